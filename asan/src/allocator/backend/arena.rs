@@ -0,0 +1,143 @@
+//! # arena
+//! This allocator is a simple bump allocator which serves allocations from a
+//! single large region mapped up-front. Individual buffers are never released
+//! (dealloc only checks that the buffer belongs to the arena), instead the
+//! whole arena can be discarded at once using `reset`. This is intended for
+//! persistent-mode fuzzing, where the heap can be discarded in its entirety
+//! between iterations, rather than leaking (or having to free) any buffers
+//! allocated by the target.
+//!
+//! The region is mapped with `MAP_NORESERVE` and so the size of the arena
+//! need only be bounded by the available address space.
+use {
+    crate::{
+        allocator::backend::{AllocatorBackend, ResettableBackend},
+        mmap::Mmap,
+        options::Options,
+        GuestAddr,
+    },
+    alloc::fmt::{self, Debug, Formatter},
+    core::ops::Range,
+    log::debug,
+    thiserror::Error,
+};
+
+pub struct ArenaBackend<M: Mmap> {
+    map: M,
+    next: usize,
+}
+
+impl<M: Mmap> Debug for ArenaBackend<M> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "ArenaBackend")
+    }
+}
+
+impl<M: Mmap> AllocatorBackend for ArenaBackend<M> {
+    type Error = ArenaBackendError<M>;
+
+    fn alloc(&mut self, len: usize, align: usize) -> Result<GuestAddr, Self::Error> {
+        let align = align.max(1);
+        if !align.is_power_of_two() {
+            Err(ArenaBackendError::InvalidAlignment(align))?;
+        }
+        let range = self.range();
+        let addr = (range.start + self.next)
+            .checked_add(align - 1)
+            .map(|a| a & !(align - 1))
+            .ok_or(ArenaBackendError::OutOfMemory(len))?;
+        let end = addr
+            .checked_add(len)
+            .filter(|end| *end <= range.end)
+            .ok_or(ArenaBackendError::OutOfMemory(len))?;
+        self.next = end - range.start;
+        debug!("alloc - addr: 0x{:x}, len: 0x{:x}", addr, len);
+        Ok(addr)
+    }
+
+    fn dealloc(&mut self, addr: GuestAddr, _len: usize, _align: usize) -> Result<(), Self::Error> {
+        let range = self.range();
+        if addr < range.start || addr >= range.start + self.next {
+            Err(ArenaBackendError::InvalidAddress(addr))?;
+        }
+        Ok(())
+    }
+}
+
+impl<M: Mmap> ResettableBackend for ArenaBackend<M> {
+    fn reset(&mut self) -> Result<Range<GuestAddr>, Self::Error> {
+        debug!("reset - used: 0x{:x}", self.next);
+        let start = self.range().start;
+        let used = start..start + self.next;
+        self.next = 0;
+        Ok(used)
+    }
+
+    fn range(&self) -> Range<GuestAddr> {
+        let slice = self.map.as_slice();
+        let base = slice.as_ptr() as GuestAddr;
+        base..base + slice.len()
+    }
+}
+
+impl<M: Mmap> ArenaBackend<M> {
+    #[cfg(target_pointer_width = "32")]
+    pub const DEFAULT_ARENA_SIZE: usize = 256 << 20;
+
+    #[cfg(target_pointer_width = "64")]
+    pub const DEFAULT_ARENA_SIZE: usize = 16 << 30;
+
+    /// Create an arena of the given size
+    pub fn new(len: usize) -> Result<ArenaBackend<M>, ArenaBackendError<M>> {
+        let map = M::map(len).map_err(|e| ArenaBackendError::MmapError(e))?;
+        Ok(ArenaBackend { map, next: 0 })
+    }
+
+    /// Create an arena of the given size at the given (page aligned) address
+    pub fn new_at(addr: GuestAddr, len: usize) -> Result<ArenaBackend<M>, ArenaBackendError<M>> {
+        let map = M::map_at(addr, len).map_err(|e| ArenaBackendError::MmapError(e))?;
+        Ok(ArenaBackend { map, next: 0 })
+    }
+
+    /// Create an arena whose size is taken from the `arena_size_mb` option (or
+    /// the default size if it isn't specified), placed at the address given by
+    /// the `heap_base` option (if specified), which must be page aligned
+    pub fn with_options(
+        page_size: usize,
+        options: &Options,
+    ) -> Result<ArenaBackend<M>, ArenaBackendError<M>> {
+        let len = match options.arena_size_mb {
+            Some(mb) => mb
+                .checked_mul(1 << 20)
+                .ok_or(ArenaBackendError::InvalidArenaSize(mb))?,
+            None => Self::DEFAULT_ARENA_SIZE,
+        };
+        match options.heap_base {
+            Some(addr) if addr % page_size != 0 => Err(ArenaBackendError::InvalidHeapBase(addr)),
+            Some(addr) => Self::new_at(addr, len),
+            None => Self::new(len),
+        }
+    }
+
+    /// The number of bytes of the arena which have been allocated since it
+    /// was created (or last reset)
+    pub fn used(&self) -> usize {
+        self.next
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ArenaBackendError<M: Mmap> {
+    #[error("Invalid arena_size_mb: {0}")]
+    InvalidArenaSize(usize),
+    #[error("Invalid heap_base (not page aligned): {0:x}")]
+    InvalidHeapBase(GuestAddr),
+    #[error("Invalid alignment: {0}")]
+    InvalidAlignment(usize),
+    #[error("Out of memory - len: {0}")]
+    OutOfMemory(usize),
+    #[error("Invalid address: {0:x}")]
+    InvalidAddress(GuestAddr),
+    #[error("Mmap error: {0:?}")]
+    MmapError(M::Error),
+}
@@ -2,27 +2,102 @@
 //! This allocator makes use of the `dlmalloc` crate to manage memory. It in
 //! turn uses pages of memory allocated by one of the implementations of the
 //! `Mmap` trait described in the `mmap` module.
+//!
+//! Each region mapped on behalf of `dlmalloc` is prefixed with a page holding
+//! a small header so that all of the regions owned by the allocator can be
+//! enumerated using `regions` (e.g. to exclude them from the roots scanned by
+//! the leak checker). Since `dlmalloc` never releases these regions, they are
+//! simply pushed onto a global lock-free list.
+//!
+//! The regions may optionally be placed consecutively from a fixed base
+//! address (see the `heap_base` option), rather than wherever the `Mmap`
+//! implementation chooses, so that the addresses of the allocations depend
+//! only upon the sequence of requests made by the application.
 use {
-    crate::{allocator::backend::AllocatorBackend, mmap::Mmap, GuestAddr},
+    crate::{allocator::backend::AllocatorBackend, mmap::Mmap, options::Options, GuestAddr},
     alloc::fmt::{self, Debug, Formatter},
-    core::{marker::PhantomData, mem::forget, ptr::null_mut},
+    core::{
+        iter::successors,
+        marker::PhantomData,
+        mem::forget,
+        ops::Range,
+        ptr::null_mut,
+        sync::atomic::{AtomicUsize, Ordering},
+    },
     dlmalloc::{Allocator, Dlmalloc},
     log::debug,
     thiserror::Error,
 };
 
+struct RegionHeader {
+    next: GuestAddr,
+    len: usize,
+}
+
+static REGIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Enumerate the regions which have been mapped on behalf of any instance of
+/// `DlmallocBackend`
+pub fn regions() -> impl Iterator<Item = Range<GuestAddr>> {
+    let head = REGIONS.load(Ordering::Acquire);
+    successors((head != 0).then_some(head), |addr| {
+        let next = unsafe { (*(*addr as *const RegionHeader)).next };
+        (next != 0).then_some(next)
+    })
+    .map(|addr| addr..addr + unsafe { (*(addr as *const RegionHeader)).len })
+}
+
 pub struct DlmallocBackendMap<M: Mmap> {
     page_size: usize,
+    /// The address at which the next region is placed, or zero if the
+    /// regions are not placed at fixed addresses
+    next: AtomicUsize,
     _phantom: PhantomData<M>,
 }
 
 unsafe impl<M: Mmap + Send> Allocator for DlmallocBackendMap<M> {
     fn alloc(&self, size: usize) -> (*mut u8, usize, u32) {
-        let map = M::map(size);
+        let Some(len) = size
+            .checked_add(self.page_size)
+            .and_then(|len| len.checked_next_multiple_of(self.page_size))
+        else {
+            return (null_mut(), 0, 0);
+        };
+        let next = self.next.load(Ordering::Acquire);
+        let map = if next == 0 {
+            M::map(len)
+        } else {
+            M::map_at(next, len).inspect(|_| self.next.store(next + len, Ordering::Release))
+        };
         match map {
             Ok(mut map) => {
                 let slice = map.as_mut_slice();
-                let result = (slice.as_mut_ptr(), slice.len(), 0);
+                let base = slice.as_mut_ptr() as GuestAddr;
+                let header = base as *mut RegionHeader;
+                unsafe {
+                    header.write(RegionHeader {
+                        next: 0,
+                        len: slice.len(),
+                    })
+                };
+                let mut head = REGIONS.load(Ordering::Acquire);
+                loop {
+                    unsafe { (*header).next = head };
+                    match REGIONS.compare_exchange_weak(
+                        head,
+                        base,
+                        Ordering::AcqRel,
+                        Ordering::Acquire,
+                    ) {
+                        Ok(_) => break,
+                        Err(current) => head = current,
+                    }
+                }
+                let result = (
+                    unsafe { slice.as_mut_ptr().add(self.page_size) },
+                    slice.len() - self.page_size,
+                    0,
+                );
                 forget(map);
                 result
             }
@@ -60,8 +135,15 @@ unsafe impl<M: Mmap + Send> Allocator for DlmallocBackendMap<M> {
 
 impl<M: Mmap> DlmallocBackendMap<M> {
     pub const fn new(page_size: usize) -> DlmallocBackendMap<M> {
+        Self::new_at(page_size, 0)
+    }
+
+    /// Place the regions consecutively starting at the given (page aligned)
+    /// address
+    pub const fn new_at(page_size: usize, base: GuestAddr) -> DlmallocBackendMap<M> {
         DlmallocBackendMap {
             page_size,
+            next: AtomicUsize::new(base),
             _phantom: PhantomData,
         }
     }
@@ -100,10 +182,34 @@ impl<M: Mmap + Send> DlmallocBackend<M> {
         let dlmalloc = Dlmalloc::<DlmallocBackendMap<M>>::new_with_allocator(backend);
         Self { dlmalloc }
     }
+
+    /// Create a backend whose heap is placed at the given (page aligned)
+    /// address
+    pub const fn new_at(page_size: usize, base: GuestAddr) -> DlmallocBackend<M> {
+        let backend = DlmallocBackendMap::new_at(page_size, base);
+        let dlmalloc = Dlmalloc::<DlmallocBackendMap<M>>::new_with_allocator(backend);
+        Self { dlmalloc }
+    }
+
+    /// Create a backend whose heap is placed at the address given by the
+    /// `heap_base` option (or wherever the `Mmap` implementation chooses if it
+    /// isn't specified). The `heap_base` must be page aligned.
+    pub fn with_options(
+        page_size: usize,
+        options: &Options,
+    ) -> Result<DlmallocBackend<M>, DlmallocBackendError> {
+        match options.heap_base {
+            Some(base) if base % page_size != 0 => Err(DlmallocBackendError::InvalidHeapBase(base)),
+            Some(base) => Ok(Self::new_at(page_size, base)),
+            None => Ok(Self::new(page_size)),
+        }
+    }
 }
 
 #[derive(Error, Debug, PartialEq)]
 pub enum DlmallocBackendError {
     #[error("Failed to allocate - size: {0}, align: {1}")]
     FailedToAllocate(usize, usize),
+    #[error("Invalid heap_base (not page aligned): {0:x}")]
+    InvalidHeapBase(GuestAddr),
 }
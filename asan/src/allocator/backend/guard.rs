@@ -0,0 +1,169 @@
+//! # guard
+//! This allocator places each allocation in its own mapping, positioned such
+//! that it ends immediately before an inaccessible guard page (in the manner
+//! of Electric Fence). Any overflow beyond the end of the buffer is therefore
+//! caught by the MMU, even in code paths which are not instrumented. When a
+//! buffer is freed, the whole of its mapping is made inaccessible, but it is
+//! never unmapped and hence its pages are never re-used, so that any
+//! subsequent use after free also faults.
+//!
+//! This comes at the cost of consuming at least two pages of address space
+//! (and one page of memory) for every allocation and so is best suited to
+//! debugging rather than fuzzing at scale.
+//!
+//! The mappings may optionally be placed consecutively from a fixed base
+//! address (see the `heap_base` option) so that the addresses of the
+//! allocations are reproducible between runs.
+use {
+    crate::{
+        allocator::backend::AllocatorBackend,
+        mmap::{Mmap, MmapProt},
+        options::Options,
+        GuestAddr,
+    },
+    alloc::{
+        collections::BTreeMap,
+        fmt::{self, Debug, Formatter},
+    },
+    core::{mem::forget, ops::Range},
+    log::debug,
+    thiserror::Error,
+};
+
+pub struct GuardBackend<M: Mmap> {
+    page_size: usize,
+    next: Option<GuestAddr>,
+    mappings: BTreeMap<GuestAddr, M>,
+}
+
+impl<M: Mmap> Debug for GuardBackend<M> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "GuardBackend")
+    }
+}
+
+impl<M: Mmap> AllocatorBackend for GuardBackend<M> {
+    type Error = GuardBackendError<M>;
+
+    fn alloc(&mut self, len: usize, align: usize) -> Result<GuestAddr, Self::Error> {
+        let align = align.max(1);
+        if !align.is_power_of_two() || align > self.page_size {
+            Err(GuardBackendError::InvalidAlignment(align))?;
+        }
+        let aligned_len = Self::align_up(len, align).ok_or(GuardBackendError::SizeOverflow(len))?;
+        let pages_len = Self::align_up(aligned_len, self.page_size)
+            .ok_or(GuardBackendError::SizeOverflow(len))?;
+        let map_len = pages_len
+            .checked_add(self.page_size)
+            .ok_or(GuardBackendError::SizeOverflow(len))?;
+
+        let map = match self.next {
+            Some(next) => {
+                let map = M::map_at(next, map_len).map_err(|e| GuardBackendError::MmapError(e))?;
+                self.next = Some(
+                    next.checked_add(map_len)
+                        .ok_or(GuardBackendError::SizeOverflow(len))?,
+                );
+                map
+            }
+            None => M::map(map_len).map_err(|e| GuardBackendError::MmapError(e))?,
+        };
+        let base = map.as_slice().as_ptr() as GuestAddr;
+        let guard = base + pages_len;
+        M::protect(guard, self.page_size, MmapProt::empty())
+            .map_err(|e| GuardBackendError::MmapError(e))?;
+
+        let addr = guard - aligned_len;
+        debug!(
+            "alloc - addr: 0x{:x}, len: 0x{:x}, guard: 0x{:x}",
+            addr, len, guard
+        );
+        self.mappings.insert(addr, map);
+        Ok(addr)
+    }
+
+    fn dealloc(&mut self, addr: GuestAddr, _len: usize, _align: usize) -> Result<(), Self::Error> {
+        let map = self
+            .mappings
+            .remove(&addr)
+            .ok_or(GuardBackendError::InvalidAddress(addr))?;
+        let slice = map.as_slice();
+        M::protect(slice.as_ptr() as GuestAddr, slice.len(), MmapProt::empty())
+            .map_err(|e| GuardBackendError::MmapError(e))?;
+        debug!("dealloc - addr: 0x{:x}", addr);
+        /* The mapping is deliberately leaked so that its pages are never re-used */
+        forget(map);
+        Ok(())
+    }
+}
+
+impl<M: Mmap> GuardBackend<M> {
+    pub const fn new(page_size: usize) -> GuardBackend<M> {
+        GuardBackend {
+            page_size,
+            next: None,
+            mappings: BTreeMap::new(),
+        }
+    }
+
+    /// Create a backend whose mappings are placed consecutively starting at
+    /// the given (page aligned) address
+    pub const fn new_at(page_size: usize, base: GuestAddr) -> GuardBackend<M> {
+        GuardBackend {
+            page_size,
+            next: Some(base),
+            mappings: BTreeMap::new(),
+        }
+    }
+
+    /// Create a backend whose mappings are placed at the address given by the
+    /// `heap_base` option (if specified), which must be page aligned
+    pub fn with_options(
+        page_size: usize,
+        options: &Options,
+    ) -> Result<GuardBackend<M>, GuardBackendError<M>> {
+        match options.heap_base {
+            Some(base) if base % page_size != 0 => Err(GuardBackendError::InvalidHeapBase(base)),
+            Some(base) => Ok(Self::new_at(page_size, base)),
+            None => Ok(Self::new(page_size)),
+        }
+    }
+
+    /// The number of live allocations
+    pub fn len(&self) -> usize {
+        self.mappings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mappings.is_empty()
+    }
+
+    /// Enumerate the (accessible portion of the) mappings of the live
+    /// allocations, e.g. to exclude them from the roots scanned by the leak
+    /// checker
+    pub fn regions(&self) -> impl Iterator<Item = Range<GuestAddr>> + '_ {
+        self.mappings.values().map(|map| {
+            let slice = map.as_slice();
+            let base = slice.as_ptr() as GuestAddr;
+            base..base + slice.len() - self.page_size
+        })
+    }
+
+    fn align_up(len: usize, align: usize) -> Option<usize> {
+        len.checked_add(align - 1).map(|l| l & !(align - 1))
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum GuardBackendError<M: Mmap> {
+    #[error("Invalid alignment: {0}")]
+    InvalidAlignment(usize),
+    #[error("Size overflow: {0}")]
+    SizeOverflow(usize),
+    #[error("Invalid heap_base (not page aligned): {0:x}")]
+    InvalidHeapBase(GuestAddr),
+    #[error("Invalid address: {0:x}")]
+    InvalidAddress(GuestAddr),
+    #[error("Mmap error: {0:?}")]
+    MmapError(M::Error),
+}
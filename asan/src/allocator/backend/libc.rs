@@ -0,0 +1,164 @@
+//! # libc
+//! This allocator calls down into the original `libc` implementation of
+//! `malloc` (found using the `Symbols` lookup rather than being linked
+//! directly, since the sanitizer itself provides `malloc`). The frontend
+//! still adds red zones and a quarantine around each allocation, but the
+//! underlying memory is managed by the `libc` heap, so applications which
+//! depend on its behaviour (e.g. by calling `mallinfo` or `malloc_trim`)
+//! continue to work.
+//!
+//! Note that since the `libc` heap is not a region of memory managed by the
+//! sanitizer, it cannot be excluded when searching for the roots during leak
+//! detection. Any leaked buffers which are referenced by other leaked buffers
+//! may therefore not be reported.
+use {
+    crate::{
+        allocator::backend::AllocatorBackend,
+        asan_swap,
+        symbols::{
+            AtomicGuestAddr, Function, FunctionPointer, FunctionPointerError, Symbols,
+            SymbolsLookupStr,
+        },
+        GuestAddr,
+    },
+    core::{
+        ffi::{c_void, CStr},
+        marker::PhantomData,
+        mem::size_of,
+    },
+    libc::size_t,
+    log::debug,
+    thiserror::Error,
+};
+
+#[derive(Debug)]
+struct FunctionMalloc;
+
+impl Function for FunctionMalloc {
+    type Func = unsafe extern "C" fn(size_t) -> *mut c_void;
+    const NAME: &'static CStr = c"malloc";
+}
+
+#[derive(Debug)]
+struct FunctionFree;
+
+impl Function for FunctionFree {
+    type Func = unsafe extern "C" fn(*mut c_void);
+    const NAME: &'static CStr = c"free";
+}
+
+#[derive(Debug)]
+struct FunctionMemalign;
+
+impl Function for FunctionMemalign {
+    type Func = unsafe extern "C" fn(size_t, size_t) -> *mut c_void;
+    const NAME: &'static CStr = c"memalign";
+}
+
+static MALLOC_ADDR: AtomicGuestAddr = AtomicGuestAddr::new();
+static FREE_ADDR: AtomicGuestAddr = AtomicGuestAddr::new();
+static MEMALIGN_ADDR: AtomicGuestAddr = AtomicGuestAddr::new();
+
+#[derive(Debug)]
+pub struct LibcBackend<S: Symbols> {
+    _phantom: PhantomData<S>,
+}
+
+impl<S: Symbols> LibcBackend<S> {
+    /// The alignment guaranteed by `malloc`, larger alignments require the
+    /// use of `memalign`
+    pub const MALLOC_ALIGNMENT: usize = 2 * size_of::<usize>();
+
+    pub const fn new() -> LibcBackend<S> {
+        LibcBackend {
+            _phantom: PhantomData,
+        }
+    }
+
+    fn get_malloc() -> Result<<FunctionMalloc as Function>::Func, LibcBackendError<S>> {
+        let addr = MALLOC_ADDR.try_get_or_insert_with(|| {
+            S::lookup_str(FunctionMalloc::NAME).map_err(|e| LibcBackendError::FailedToFindSymbol(e))
+        })?;
+        let f =
+            FunctionMalloc::as_ptr(addr).map_err(|e| LibcBackendError::InvalidPointerType(e))?;
+        Ok(f)
+    }
+
+    fn get_free() -> Result<<FunctionFree as Function>::Func, LibcBackendError<S>> {
+        let addr = FREE_ADDR.try_get_or_insert_with(|| {
+            S::lookup_str(FunctionFree::NAME).map_err(|e| LibcBackendError::FailedToFindSymbol(e))
+        })?;
+        let f = FunctionFree::as_ptr(addr).map_err(|e| LibcBackendError::InvalidPointerType(e))?;
+        Ok(f)
+    }
+
+    fn get_memalign() -> Result<<FunctionMemalign as Function>::Func, LibcBackendError<S>> {
+        let addr = MEMALIGN_ADDR.try_get_or_insert_with(|| {
+            S::lookup_str(FunctionMemalign::NAME)
+                .map_err(|e| LibcBackendError::FailedToFindSymbol(e))
+        })?;
+        let f =
+            FunctionMemalign::as_ptr(addr).map_err(|e| LibcBackendError::InvalidPointerType(e))?;
+        Ok(f)
+    }
+}
+
+impl<S: Symbols> Default for LibcBackend<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: Symbols> AllocatorBackend for LibcBackend<S> {
+    type Error = LibcBackendError<S>;
+
+    fn alloc(&mut self, len: usize, align: usize) -> Result<GuestAddr, Self::Error> {
+        let align = align.max(1);
+        if !align.is_power_of_two() {
+            Err(LibcBackendError::InvalidAlignment(align))?;
+        }
+        let p = if align <= Self::MALLOC_ALIGNMENT {
+            let fn_malloc = Self::get_malloc()?;
+            unsafe { asan_swap(false) };
+            let p = unsafe { fn_malloc(len) };
+            unsafe { asan_swap(true) };
+            p
+        } else {
+            let fn_memalign = Self::get_memalign()?;
+            unsafe { asan_swap(false) };
+            let p = unsafe { fn_memalign(align, len) };
+            unsafe { asan_swap(true) };
+            p
+        };
+        if p.is_null() {
+            Err(LibcBackendError::FailedToAllocate(len, align))?;
+        }
+        let addr = p as GuestAddr;
+        debug!(
+            "alloc - addr: 0x{:x}, len: 0x{:x}, align: 0x{:x}",
+            addr, len, align
+        );
+        Ok(addr)
+    }
+
+    fn dealloc(&mut self, addr: GuestAddr, _len: usize, _align: usize) -> Result<(), Self::Error> {
+        let fn_free = Self::get_free()?;
+        debug!("dealloc - addr: 0x{:x}", addr);
+        unsafe { asan_swap(false) };
+        unsafe { fn_free(addr as *mut c_void) };
+        unsafe { asan_swap(true) };
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum LibcBackendError<S: Symbols> {
+    #[error("Failed to find allocator functions")]
+    FailedToFindSymbol(S::Error),
+    #[error("Invalid pointer type: {0:?}")]
+    InvalidPointerType(FunctionPointerError),
+    #[error("Invalid alignment: {0}")]
+    InvalidAlignment(usize),
+    #[error("Failed to allocate - len: {0}, align: {1}")]
+    FailedToAllocate(usize, usize),
+}
@@ -1,18 +1,33 @@
 //! # backend
 //! The backend is responsible for allocating the underlying memory used by the
-//! application. At present there is only one implemented allocator:
+//! application. The following allocators are implemented:
 //!
 //! - `dlmalloc` - A pure rust allocator based on the `dlmalloc` crate.
-//!
-//! A number other of possible implementations could be considered:
-//! - A simple bump allocator allocating from a fixed memory buffer
-//! - An allocator which calls down into the original `libc` implementation of `malloc`
+//! - `mimalloc` - A pure rust allocator based on the `baby-mimalloc` crate.
+//! - `guard` - An allocator which places each allocation against an
+//!   inaccessible guard page and never re-uses freed pages.
+//! - `arena` - A bump allocator over a single region which can be discarded
+//!   in its entirety (see `ResettableBackend`).
+//! - `libc` - An allocator which calls down into the original `libc`
+//!   implementation of `malloc`.
+
+use {
+    crate::GuestAddr,
+    alloc::fmt::Debug,
+    core::{alloc::GlobalAlloc, ops::Range},
+    spin::Mutex,
+};
 
-use {crate::GuestAddr, alloc::fmt::Debug, core::alloc::GlobalAlloc, spin::Mutex};
+pub mod arena;
 
 #[cfg(feature = "dlmalloc")]
 pub mod dlmalloc;
 
+pub mod guard;
+
+#[cfg(feature = "libc")]
+pub mod libc;
+
 #[cfg(feature = "mimalloc")]
 pub mod mimalloc;
 
@@ -22,6 +37,15 @@ pub trait AllocatorBackend: Sized + Debug + Send {
     fn dealloc(&mut self, addr: GuestAddr, len: usize, align: usize) -> Result<(), Self::Error>;
 }
 
+/// A backend whose allocations can all be released at once
+pub trait ResettableBackend: AllocatorBackend {
+    /// Release all of the allocations made by the backend, returning the
+    /// range of memory which they occupied
+    fn reset(&mut self) -> Result<Range<GuestAddr>, Self::Error>;
+    /// The region of memory from which allocations are made
+    fn range(&self) -> Range<GuestAddr>;
+}
+
 #[derive(Debug)]
 pub struct GlobalAllocator<A: AllocatorBackend> {
     backend: Mutex<A>,
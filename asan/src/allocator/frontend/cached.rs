@@ -0,0 +1,844 @@
+//! # cached
+//! This frontend sits in front of a `DefaultFrontend` and adds per-thread
+//! caches, so that most requests can be satisfied without taking the lock
+//! which guards the shared frontend (and hence without serializing every
+//! allocation made by a multi-threaded target).
+//!
+//! Small allocations are rounded up to one of `NUM_CLASSES` size classes (and
+//! to the granularity of the shadow, if that is larger). Each thread keeps a
+//! cache of chunks of each class, which is refilled from the shared frontend a
+//! batch at a time. Likewise, freed chunks are poisoned
+//! immediately, but are collected in a per-thread batch which is handed off to
+//! the quarantine of the shared frontend only once it is full. Larger (or more
+//! strictly aligned) allocations are passed directly to the shared frontend.
+//!
+//! The metadata for each allocation (its size, state and the stacks at which
+//! it was allocated and freed) is stored in a header in the poisoned left
+//! red-zone immediately before the user's buffer, so that it can be read and
+//! updated without any lock. Stacks are stored in the `StackDepot` of the
+//! cache of the thread which captured them. Since the shadow map is updated
+//! without holding the lock of the shared frontend, a shadow which may be
+//! used by several threads at once (e.g. a `&GuestShadow`) is required.
+//!
+//! As there is no thread local storage, each thread's cache is identified by
+//! the value of its thread pointer. Caches are not released when a thread
+//! exits, but are instead re-used by any subsequent thread which is given the
+//! same thread pointer. Otherwise, when a new thread is first seen, the cache
+//! of a thread which has since exited is re-used (after its batch of freed
+//! chunks is handed to the shared frontend) in preference to creating another.
+//!
+//! Where several locks are held at once, they are always taken in the same
+//! order: the table of caches, then the cache of a thread and finally the
+//! shared frontend.
+//!
+//! As with the `DefaultFrontend`, the type of each allocation is recorded (in
+//! its header) so that alloc-dealloc mismatches can be reported, and the
+//! statistics of the heap are maintained (these are atomic counters, so that
+//! they too can be updated without any lock). Leaks are found by
+//! scanning the live chunks of the shared frontend whose headers mark them as
+//! allocated to the application.
+use {
+    crate::{
+        allocator::{
+            backend::AllocatorBackend,
+            backend::ResettableBackend,
+            frontend::{
+                default::{DefaultFrontend, DefaultFrontendError},
+                AllocationInfo, AllocationType, AllocatorFrontend,
+            },
+        },
+        backtrace::{
+            capture,
+            depot::{StackDepot, StackId},
+            MAX_DEPTH,
+        },
+        leak::{registers, Chunk, Leak, LeakChecker},
+        options::Options,
+        shadow::{PoisonType, Shadow},
+        stats::{AtomicHeapStats, HeapStats},
+        thread::{thread_exited, thread_id, thread_pointer},
+        tracking::Tracking,
+        GuestAddr,
+    },
+    alloc::{collections::BTreeMap, vec::Vec},
+    core::{
+        mem::{offset_of, replace, take},
+        ops::Range,
+        sync::atomic::{AtomicU8, Ordering},
+    },
+    log::debug,
+    spin::{Mutex, MutexGuard, RwLock},
+    thiserror::Error,
+};
+
+/// The number of size classes of cached chunks
+pub const NUM_CLASSES: usize = 8;
+
+/// The size of the chunks in the smallest size class
+pub const MIN_CLASS_SIZE: usize = 16;
+
+/// The size of the largest allocation which is served from the caches
+pub const MAX_CACHED_SIZE: usize = class_size(NUM_CLASSES - 1);
+
+/// The size of the chunks of the given size class
+pub const fn class_size(class: usize) -> usize {
+    MIN_CLASS_SIZE << class
+}
+
+const CHUNK_MAGIC: u32 = 0xcac4ed;
+
+const CLASS_DIRECT: u8 = u8::MAX;
+
+const STATE_CACHED: u8 = 1;
+const STATE_ALLOCATED: u8 = 2;
+const STATE_QUARANTINED: u8 = 3;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct ChunkHeader {
+    data: GuestAddr,
+    len: usize,
+    offset: u32,
+    magic: u32,
+    alloc_stack: Option<StackId>,
+    free_stack: Option<StackId>,
+    alloc_cache: u16,
+    free_cache: u16,
+    class: u8,
+    state: u8,
+    alloc_type: AllocationType,
+}
+
+impl ChunkHeader {
+    /// The size of the header, it is placed immediately before the user's
+    /// buffer (at the end of the space reserved for it, see `header_size`)
+    const SIZE: usize = size_of::<ChunkHeader>().next_multiple_of(16);
+
+    fn read(data: GuestAddr) -> ChunkHeader {
+        unsafe { ((data - Self::SIZE) as *const ChunkHeader).read() }
+    }
+
+    fn write(self) {
+        unsafe { ((self.data - Self::SIZE) as *mut ChunkHeader).write(self) }
+    }
+
+    /// The state of the header of the buffer at the given address, this is
+    /// updated atomically when the buffer is freed, so that only one of
+    /// several threads freeing the same buffer succeeds
+    fn state(data: GuestAddr) -> &'static AtomicU8 {
+        let addr = data - Self::SIZE + offset_of!(ChunkHeader, state);
+        unsafe { AtomicU8::from_ptr(addr as *mut u8) }
+    }
+
+    /// The address of the chunk allocated from the shared frontend
+    fn chunk(&self) -> GuestAddr {
+        self.data - self.offset as usize
+    }
+
+    /// The length of the user's buffer, rounded up to its size class (and to
+    /// the given alignment, that of the chunks)
+    fn capacity(&self, align: usize) -> usize {
+        match self.class {
+            CLASS_DIRECT => self.len.next_multiple_of(align),
+            class => class_size(class as usize).next_multiple_of(align),
+        }
+    }
+
+    /// The length of the chunk (as recorded in the statistics of the heap)
+    fn chunk_len(&self, align: usize) -> usize {
+        self.offset as usize + self.capacity(align)
+    }
+}
+
+struct ThreadCache {
+    id: u16,
+    tp: GuestAddr,
+    tid: usize,
+    chunks: [Vec<GuestAddr>; NUM_CLASSES],
+    quarantine: Vec<GuestAddr>,
+    depot: StackDepot,
+}
+
+#[derive(Default)]
+struct ThreadCaches {
+    threads: BTreeMap<GuestAddr, usize>,
+    caches: Vec<Mutex<ThreadCache>>,
+}
+
+pub struct CachedFrontend<B: AllocatorBackend, S: Shadow + Clone, T: Tracking> {
+    frontend: Mutex<DefaultFrontend<B, S, T>>,
+    shadow: S,
+    caches: RwLock<ThreadCaches>,
+    align: usize,
+    header_size: usize,
+    batch_size: usize,
+    caching: bool,
+    stack_depth: usize,
+    alloc_dealloc_mismatch: bool,
+    new_delete_type_mismatch: bool,
+    stats: AtomicHeapStats,
+}
+
+impl<B: AllocatorBackend, S: Shadow + Clone, T: Tracking> AllocatorFrontend
+    for CachedFrontend<B, S, T>
+{
+    type Error = CachedFrontendError<B, S, T>;
+
+    fn alloc(&mut self, len: usize, align: usize) -> Result<GuestAddr, Self::Error> {
+        CachedFrontend::alloc(self, len, align)
+    }
+
+    fn dealloc(&mut self, addr: GuestAddr) -> Result<(), Self::Error> {
+        CachedFrontend::dealloc(self, addr)
+    }
+
+    fn get_size(&self, addr: GuestAddr) -> Result<usize, Self::Error> {
+        CachedFrontend::get_size(self, addr)
+    }
+}
+
+impl<B: AllocatorBackend, S: Shadow + Clone, T: Tracking> CachedFrontend<B, S, T> {
+    /// The minimum alignment of the user's buffers, this is increased to the
+    /// granularity of the shadow if it is larger
+    const MIN_ALIGN: usize = 16;
+
+    pub const DEFAULT_BATCH_SIZE: usize = 16;
+    pub const DEFAULT_STACK_DEPTH: usize = 30;
+
+    /// Create a frontend which caches allocations made from the given shared
+    /// frontend, `shadow` must refer to the same shadow map as that used by
+    /// `frontend`.
+    pub fn new(
+        frontend: DefaultFrontend<B, S, T>,
+        shadow: S,
+        batch_size: usize,
+    ) -> Result<CachedFrontend<B, S, T>, CachedFrontendError<B, S, T>> {
+        if batch_size == 0 {
+            Err(CachedFrontendError::InvalidBatchSize(batch_size))?;
+        }
+        let align = Self::MIN_ALIGN.max(shadow.granularity());
+        Ok(CachedFrontend {
+            frontend: Mutex::new(frontend),
+            shadow,
+            caches: RwLock::new(ThreadCaches::default()),
+            align,
+            header_size: ChunkHeader::SIZE.next_multiple_of(align),
+            batch_size,
+            caching: true,
+            stack_depth: Self::DEFAULT_STACK_DEPTH,
+            alloc_dealloc_mismatch: true,
+            new_delete_type_mismatch: true,
+            stats: AtomicHeapStats::default(),
+        })
+    }
+
+    /// Create a frontend configured by the given `Options` (those which
+    /// configure the shared frontend should already have been applied to it)
+    pub fn with_options(
+        frontend: DefaultFrontend<B, S, T>,
+        shadow: S,
+        options: &Options,
+    ) -> Result<CachedFrontend<B, S, T>, CachedFrontendError<B, S, T>> {
+        let mut cached = Self::new(frontend, shadow, Self::DEFAULT_BATCH_SIZE)?;
+        if let Some(depth) = options.malloc_context_size {
+            cached.set_stack_depth(depth)?;
+        }
+        if let Some(detect) = options.alloc_dealloc_mismatch {
+            cached.set_alloc_dealloc_mismatch(detect);
+        }
+        if let Some(detect) = options.new_delete_type_mismatch {
+            cached.set_new_delete_type_mismatch(detect);
+        }
+        Ok(cached)
+    }
+
+    /// Set the maximum number of frames recorded in the allocation and free
+    /// stacks (a depth of zero disables their capture).
+    pub fn set_stack_depth(
+        &mut self,
+        stack_depth: usize,
+    ) -> Result<(), CachedFrontendError<B, S, T>> {
+        if stack_depth > MAX_DEPTH {
+            Err(CachedFrontendError::InvalidStackDepth(stack_depth))?;
+        }
+        self.stack_depth = stack_depth;
+        Ok(())
+    }
+
+    /// Set whether small buffers are allocated from the caches. When disabled,
+    /// every buffer is allocated directly from the shared frontend at the
+    /// requested size rather than being rounded up to a size class (e.g. so
+    /// that a buffer ends as close as possible to a guard page).
+    pub fn set_caching(&mut self, caching: bool) {
+        self.caching = caching;
+    }
+
+    /// Set whether buffers released by a different family of functions to
+    /// that which allocated them are reported
+    pub fn set_alloc_dealloc_mismatch(&mut self, alloc_dealloc_mismatch: bool) {
+        self.alloc_dealloc_mismatch = alloc_dealloc_mismatch;
+    }
+
+    /// Set whether buffers released by a sized `operator delete` with the
+    /// wrong size are reported
+    pub fn set_new_delete_type_mismatch(&mut self, new_delete_type_mismatch: bool) {
+        self.new_delete_type_mismatch = new_delete_type_mismatch;
+    }
+
+    pub fn alloc(
+        &self,
+        len: usize,
+        align: usize,
+    ) -> Result<GuestAddr, CachedFrontendError<B, S, T>> {
+        self.alloc_typed(len, align, AllocationType::Malloc)
+    }
+
+    /// Allocate a buffer on behalf of the given family of allocation
+    /// functions
+    pub fn alloc_typed(
+        &self,
+        len: usize,
+        align: usize,
+        alloc_type: AllocationType,
+    ) -> Result<GuestAddr, CachedFrontendError<B, S, T>> {
+        debug!(
+            "alloc - len: 0x{:x}, align: 0x{:x}, type: {:?}",
+            len, align, alloc_type
+        );
+        let mut frames = [0; MAX_DEPTH];
+        let depth = capture(&mut frames[..self.stack_depth]);
+        let frames = &frames[..depth];
+
+        let mut header = if self.caching && len <= MAX_CACHED_SIZE && align <= self.align {
+            let class = Self::class(len);
+            self.with_cache(|cache| {
+                let data = match cache.chunks[class].pop() {
+                    Some(data) => data,
+                    None => {
+                        self.refill(cache, class)?;
+                        cache.chunks[class]
+                            .pop()
+                            .ok_or(CachedFrontendError::CacheCorruption)?
+                    }
+                };
+                let mut header = ChunkHeader::read(data);
+                header.alloc_stack = Some(cache.depot.insert(frames));
+                header.alloc_cache = cache.id;
+                Ok(header)
+            })??
+        } else {
+            let mut header = self.alloc_direct(len, align)?;
+            self.with_cache(|cache| {
+                header.alloc_stack = Some(cache.depot.insert(frames));
+                header.alloc_cache = cache.id;
+            })?;
+            header
+        };
+
+        header.len = len;
+        header.state = STATE_ALLOCATED;
+        header.alloc_type = alloc_type;
+        header.write();
+        self.stats.record_alloc(len, header.chunk_len(self.align));
+
+        let mut shadow = self.shadow.clone();
+        shadow
+            .unpoison(header.data, len)
+            .map_err(|e| CachedFrontendError::ShadowError(e))?;
+        shadow
+            .poison(
+                header.data + len,
+                header.capacity(self.align) - len,
+                PoisonType::AsanHeapRightRz,
+            )
+            .map_err(|e| CachedFrontendError::ShadowError(e))?;
+        Ok(header.data)
+    }
+
+    pub fn dealloc(&self, addr: GuestAddr) -> Result<(), CachedFrontendError<B, S, T>> {
+        self.dealloc_typed(addr, AllocationType::Malloc, None)
+    }
+
+    /// Release a buffer on behalf of the given family of deallocation
+    /// functions. If the buffer was allocated by a different family, or the
+    /// given size (from a sized `operator delete`) doesn't match that of the
+    /// allocation, then the buffer is left allocated and an error returned.
+    pub fn dealloc_typed(
+        &self,
+        addr: GuestAddr,
+        dealloc_type: AllocationType,
+        len: Option<usize>,
+    ) -> Result<(), CachedFrontendError<B, S, T>> {
+        debug!(
+            "dealloc - addr: 0x{:x}, type: {:?}, len: {:?}",
+            addr, dealloc_type, len
+        );
+        if addr == 0 {
+            return Ok(());
+        }
+
+        let mut header = self.header(addr)?;
+        if header.state == STATE_ALLOCATED {
+            if self.alloc_dealloc_mismatch && header.alloc_type != dealloc_type {
+                Err(CachedFrontendError::AllocDeallocMismatch(
+                    addr,
+                    header.alloc_type,
+                    dealloc_type,
+                ))?;
+            }
+            match len {
+                Some(len) if self.new_delete_type_mismatch && len != header.len => {
+                    Err(CachedFrontendError::NewDeleteSizeMismatch(addr, len))?;
+                }
+                _ => (),
+            }
+        }
+        match ChunkHeader::state(addr).compare_exchange(
+            STATE_ALLOCATED,
+            STATE_QUARANTINED,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => header.state = STATE_QUARANTINED,
+            Err(STATE_QUARANTINED) => Err(CachedFrontendError::DoubleFree(addr))?,
+            Err(_) => Err(CachedFrontendError::InvalidAddress(addr))?,
+        }
+        self.stats
+            .record_free(header.len, header.chunk_len(self.align));
+
+        let mut frames = [0; MAX_DEPTH];
+        let depth = capture(&mut frames[..self.stack_depth]);
+
+        self.shadow
+            .clone()
+            .poison(addr, header.capacity(self.align), PoisonType::AsanHeapFreed)
+            .map_err(|e| CachedFrontendError::ShadowError(e))?;
+
+        let batch = self.with_cache(|cache| {
+            header.free_stack = Some(cache.depot.insert(&frames[..depth]));
+            header.free_cache = cache.id;
+            header.write();
+            cache.quarantine.push(addr);
+            if cache.quarantine.len() >= self.batch_size {
+                take(&mut cache.quarantine)
+            } else {
+                Vec::new()
+            }
+        })?;
+        self.release(&batch)
+    }
+
+    pub fn get_size(&self, addr: GuestAddr) -> Result<usize, CachedFrontendError<B, S, T>> {
+        debug!("get_size - addr: 0x{:x}", addr);
+        let header = self.header(addr)?;
+        if header.state != STATE_ALLOCATED {
+            Err(CachedFrontendError::InvalidAddress(addr))?;
+        }
+        Ok(header.len)
+    }
+
+    /// Whether the given address is the start of a live allocation
+    pub fn owns(&self, addr: GuestAddr) -> bool {
+        self.header(addr)
+            .is_ok_and(|header| header.state == STATE_ALLOCATED)
+    }
+
+    /// The current statistics of the heap. The size of the heap is that of
+    /// the shared frontend and so includes the chunks held by the caches.
+    pub fn stats(&self) -> HeapStats {
+        let heap_size = self.frontend.lock().stats().heap_size;
+        HeapStats {
+            heap_size,
+            ..self.stats.load()
+        }
+    }
+
+    /// Return the chunks held by the caches of all threads (and their batches
+    /// of freed chunks) to the shared frontend
+    pub fn flush(&self) -> Result<(), CachedFrontendError<B, S, T>> {
+        let chunks = {
+            let caches = self.caches.read();
+            caches
+                .caches
+                .iter()
+                .flat_map(|c| {
+                    let mut cache = c.lock();
+                    let mut chunks = take(&mut cache.quarantine);
+                    cache.chunks.iter_mut().for_each(|v| chunks.append(v));
+                    chunks
+                })
+                .collect::<Vec<GuestAddr>>()
+        };
+        self.release(&chunks)
+    }
+
+    /// Find the allocation (either live or in the quarantine) whose backing
+    /// buffer, including its red-zones, contains the given address.
+    pub fn find_allocation(&self, addr: GuestAddr) -> Option<AllocationInfo> {
+        let chunk = self.frontend.lock().find_allocation(addr)?.addr;
+        let header = self.chunk_header(chunk)?;
+        let data = header.data;
+        if header.state == STATE_CACHED {
+            return None;
+        }
+
+        let caches = self.caches.read();
+        let frames = |cache: u16, id: Option<StackId>| {
+            id.and_then(|id| {
+                let cache = caches.caches.get(cache as usize)?.lock();
+                cache.depot.get(id).map(|f| f.to_vec())
+            })
+            .unwrap_or_default()
+        };
+        let freed = header.state == STATE_QUARANTINED;
+        Some(AllocationInfo {
+            addr: data,
+            len: header.len,
+            freed,
+            alloc_stack: frames(header.alloc_cache, header.alloc_stack),
+            free_stack: freed.then(|| frames(header.free_cache, header.free_stack)),
+        })
+    }
+
+    /// Find the live allocations which are not reachable from either the
+    /// given roots or the registers of the current thread.
+    ///
+    /// # Safety
+    /// The given roots must be readable
+    pub unsafe fn find_leaks(&self, roots: &[Range<GuestAddr>]) -> Vec<Leak> {
+        /*
+         * The headers are copied before the caches are locked, since the
+         * caches are locked before the shared frontend when they are refilled
+         */
+        let headers = self
+            .frontend
+            .lock()
+            .allocations()
+            .filter_map(|chunk| self.chunk_header(chunk))
+            .filter(|header| header.state == STATE_ALLOCATED)
+            .collect::<Vec<ChunkHeader>>();
+        let caches = self.caches.read();
+        /* The stacks are gathered from the depots of each thread into one */
+        let mut depot = StackDepot::new();
+        let chunks = headers
+            .into_iter()
+            .map(|header| {
+                let frames = header
+                    .alloc_stack
+                    .and_then(|id| {
+                        let cache = caches.caches.get(header.alloc_cache as usize)?.lock();
+                        cache.depot.get(id).map(|f| f.to_vec())
+                    })
+                    .unwrap_or_default();
+                Chunk {
+                    addr: header.data,
+                    len: header.len,
+                    stack: depot.insert(&frames),
+                }
+            })
+            .collect();
+        drop(caches);
+        let mut checker = LeakChecker::new(chunks);
+        checker.scan_words(&registers());
+        roots
+            .iter()
+            .for_each(|r| checker.scan_range(r.start, r.end));
+        checker.leaks(&depot)
+    }
+
+    /// Read the header of the buffer held in the given chunk of the shared
+    /// frontend
+    fn chunk_header(&self, chunk: GuestAddr) -> Option<ChunkHeader> {
+        /*
+         * The address of the user's buffer is the first word of the chunk,
+         * either as the first field of the header or written there separately
+         * if the buffer is more strictly aligned
+         */
+        let data = unsafe { (chunk as *const GuestAddr).read() };
+        self.header(data).ok()
+    }
+
+    /// Record the address of the user's buffer in the first word of its
+    /// chunk (see `chunk_header`), unless the header is already there
+    fn write_chunk_data(chunk: GuestAddr, data: GuestAddr) {
+        if data - chunk > ChunkHeader::SIZE {
+            unsafe { (chunk as *mut GuestAddr).write(data) };
+        }
+    }
+
+    fn class(len: usize) -> usize {
+        let len = len.max(MIN_CLASS_SIZE);
+        (len.next_power_of_two() / MIN_CLASS_SIZE).trailing_zeros() as usize
+    }
+
+    /// Read and validate the header of the buffer at the given address. The
+    /// shadow map is checked first, so that we don't read arbitrary memory.
+    fn header(&self, addr: GuestAddr) -> Result<ChunkHeader, CachedFrontendError<B, S, T>> {
+        if addr % self.align != 0 || addr < ChunkHeader::SIZE {
+            Err(CachedFrontendError::InvalidAddress(addr))?;
+        }
+        match self
+            .shadow
+            .is_poison(addr - ChunkHeader::SIZE, ChunkHeader::SIZE)
+        {
+            Ok(true) => (),
+            _ => Err(CachedFrontendError::InvalidAddress(addr))?,
+        }
+        let header = ChunkHeader::read(addr);
+        if header.magic != CHUNK_MAGIC || header.data != addr {
+            Err(CachedFrontendError::InvalidAddress(addr))?;
+        }
+        Ok(header)
+    }
+
+    /// Allocate a batch of chunks of the given size class from the shared
+    /// frontend
+    fn refill(
+        &self,
+        cache: &mut ThreadCache,
+        class: usize,
+    ) -> Result<(), CachedFrontendError<B, S, T>> {
+        let len = self.header_size + class_size(class).next_multiple_of(self.align);
+        let chunks = {
+            let mut frontend = self.frontend.lock();
+            (0..self.batch_size)
+                .map(|_| frontend.alloc(len, self.align))
+                .collect::<Result<Vec<GuestAddr>, _>>()
+                .map_err(|e| CachedFrontendError::FrontendError(e))?
+        };
+        debug!(
+            "refill - class: {}, len: 0x{:x}, count: {}",
+            class,
+            len,
+            chunks.len()
+        );
+
+        let mut shadow = self.shadow.clone();
+        for chunk in chunks {
+            let data = chunk + self.header_size;
+            shadow
+                .poison(chunk, len, PoisonType::AsanHeapLeftRz)
+                .map_err(|e| CachedFrontendError::ShadowError(e))?;
+            Self::write_chunk_data(chunk, data);
+            ChunkHeader {
+                data,
+                len: 0,
+                offset: self.header_size as u32,
+                magic: CHUNK_MAGIC,
+                alloc_stack: None,
+                free_stack: None,
+                alloc_cache: 0,
+                free_cache: 0,
+                class: class as u8,
+                state: STATE_CACHED,
+                alloc_type: AllocationType::Malloc,
+            }
+            .write();
+            cache.chunks[class].push(data);
+        }
+        Ok(())
+    }
+
+    /// Allocate a chunk directly from the shared frontend for a buffer which
+    /// is too large (or too strictly aligned) to be cached
+    fn alloc_direct(
+        &self,
+        len: usize,
+        align: usize,
+    ) -> Result<ChunkHeader, CachedFrontendError<B, S, T>> {
+        let align = align.max(self.align);
+        let offset = self.header_size.next_multiple_of(align);
+        let offset32 =
+            u32::try_from(offset).map_err(|_| CachedFrontendError::InvalidAlignment(align))?;
+        let chunk = {
+            let mut frontend = self.frontend.lock();
+            let max = frontend.max_allocation_size();
+            let chunk_len = offset
+                .checked_add(len)
+                .filter(|_| len <= max)
+                .ok_or(CachedFrontendError::AllocationSizeTooBig(len, max))?;
+            frontend
+                .alloc(chunk_len, align)
+                .map_err(|e| CachedFrontendError::FrontendError(e))?
+        };
+        let data = chunk + offset;
+        self.shadow
+            .clone()
+            .poison(chunk, offset, PoisonType::AsanHeapLeftRz)
+            .map_err(|e| CachedFrontendError::ShadowError(e))?;
+        Self::write_chunk_data(chunk, data);
+        Ok(ChunkHeader {
+            data,
+            len,
+            offset: offset32,
+            magic: CHUNK_MAGIC,
+            alloc_stack: None,
+            free_stack: None,
+            alloc_cache: 0,
+            free_cache: 0,
+            class: CLASS_DIRECT,
+            state: STATE_CACHED,
+            alloc_type: AllocationType::Malloc,
+        })
+    }
+
+    /// Hand a batch of chunks back to the shared frontend
+    fn release(&self, batch: &[GuestAddr]) -> Result<(), CachedFrontendError<B, S, T>> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        debug!("release - count: {}", batch.len());
+        let headers = batch
+            .iter()
+            .map(|data| ChunkHeader::read(*data))
+            .collect::<Vec<ChunkHeader>>();
+        headers
+            .iter()
+            .filter(|h| h.state == STATE_QUARANTINED)
+            .for_each(|h| self.stats.record_release(h.chunk_len(self.align)));
+        /* The header is read (e.g. by `find_allocation`) after the chunk is freed */
+        let mut frontend = self.frontend.lock();
+        for header in headers {
+            frontend
+                .dealloc_preserving(header.chunk(), header.offset as usize)
+                .map_err(|e| CachedFrontendError::FrontendError(e))?;
+        }
+        Ok(())
+    }
+
+    /// Run the given function with the cache of the current thread, assigning
+    /// it one if required
+    fn with_cache<R>(
+        &self,
+        f: impl FnOnce(&mut ThreadCache) -> R,
+    ) -> Result<R, CachedFrontendError<B, S, T>> {
+        let tp = thread_pointer();
+        {
+            let caches = self.caches.read();
+            if let Some(idx) = caches.threads.get(&tp) {
+                return Ok(f(&mut caches.caches[*idx].lock()));
+            }
+        }
+
+        let mut caches = self.caches.write();
+        let idx = match caches.threads.get(&tp) {
+            Some(idx) => *idx,
+            None => {
+                let idx = self.assign_cache(&mut caches, tp)?;
+                caches.threads.insert(tp, idx);
+                idx
+            }
+        };
+        let caches = caches.downgrade();
+        let result = f(&mut caches.caches[idx].lock());
+        Ok(result)
+    }
+
+    /// Choose the cache for a thread which doesn't yet have one. The cache of
+    /// an exited thread is re-used if there is one (its depot is retained,
+    /// since the headers of its chunks still refer to it), otherwise a new
+    /// cache is created. Should the identifiers of the caches be exhausted,
+    /// the thread instead shares the cache of another.
+    fn assign_cache(
+        &self,
+        caches: &mut ThreadCaches,
+        tp: GuestAddr,
+    ) -> Result<usize, CachedFrontendError<B, S, T>> {
+        let tid = thread_id();
+        let exited = caches
+            .caches
+            .iter_mut()
+            .position(|cache| thread_exited(cache.get_mut().tid));
+        if let Some(idx) = exited {
+            let cache = caches.caches[idx].get_mut();
+            debug!(
+                "thread cache - tp: 0x{:x}, id: {}, exited tp: 0x{:x}",
+                tp, cache.id, cache.tp
+            );
+            let owner = replace(&mut cache.tp, tp);
+            cache.tid = tid;
+            let batch = take(&mut cache.quarantine);
+            if caches.threads.get(&owner) == Some(&idx) {
+                caches.threads.remove(&owner);
+            }
+            self.release(&batch)?;
+            return Ok(idx);
+        }
+
+        let idx = caches.caches.len();
+        match u16::try_from(idx) {
+            Ok(id) => {
+                debug!("thread cache - tp: 0x{:x}, id: {}", tp, id);
+                caches.caches.push(Mutex::new(ThreadCache {
+                    id,
+                    tp,
+                    tid,
+                    chunks: Default::default(),
+                    quarantine: Vec::new(),
+                    depot: StackDepot::new(),
+                }));
+                Ok(idx)
+            }
+            Err(_) => Ok((tp / size_of::<GuestAddr>()) % idx),
+        }
+    }
+
+    /// Lock the shared frontend
+    pub fn frontend(&self) -> MutexGuard<'_, DefaultFrontend<B, S, T>> {
+        self.frontend.lock()
+    }
+
+    pub fn shadow(&self) -> &S {
+        &self.shadow
+    }
+
+    /// The number of threads which have caches
+    pub fn threads(&self) -> usize {
+        self.caches.read().caches.len()
+    }
+}
+
+impl<B: ResettableBackend, S: Shadow + Clone, T: Tracking> CachedFrontend<B, S, T> {
+    /// Discard all of the buffers (live, cached and quarantined) at once, see
+    /// `DefaultFrontend::reset`
+    pub fn reset(&self) -> Result<(), CachedFrontendError<B, S, T>> {
+        let caches = self.caches.write();
+        for cache in &caches.caches {
+            let mut cache = cache.lock();
+            cache.chunks.iter_mut().for_each(|v| v.clear());
+            cache.quarantine.clear();
+        }
+        self.frontend
+            .lock()
+            .reset()
+            .map_err(|e| CachedFrontendError::FrontendError(e))?;
+        self.stats.record_reset();
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum CachedFrontendError<B: AllocatorBackend, S: Shadow, T: Tracking> {
+    #[error("Invalid batch_size: {0}")]
+    InvalidBatchSize(usize),
+    #[error("Invalid stack_depth: {0}")]
+    InvalidStackDepth(usize),
+    #[error("Invalid alignment: {0}")]
+    InvalidAlignment(usize),
+    #[error("Frontend error: {0:?}")]
+    FrontendError(DefaultFrontendError<B, S, T>),
+    #[error("Shadow error: {0:?}")]
+    ShadowError(S::Error),
+    #[error("Invalid address: {0:x}")]
+    InvalidAddress(GuestAddr),
+    #[error("Double free: {0:x}")]
+    DoubleFree(GuestAddr),
+    #[error("Alloc-dealloc mismatch: {0:x} ({1:?} vs {2:?})")]
+    AllocDeallocMismatch(GuestAddr, AllocationType, AllocationType),
+    #[error("New-delete size mismatch: {0:x} ({1})")]
+    NewDeleteSizeMismatch(GuestAddr, usize),
+    #[error("Allocation size too big: 0x{0:x} (max 0x{1:x})")]
+    AllocationSizeTooBig(usize, usize),
+    #[error("Cache corruption")]
+    CacheCorruption,
+}
@@ -5,67 +5,261 @@
 //!
 //! This frontend stores all of it's metadata out-of-band, that is no meta-data
 //! is stored adjacent to the user's buffers. The size of the red-zone applied
-//! to each allocation is configurable. The frontend also supports the use of a
+//! to each allocation is configurable, either as a fixed size, or scaled with
+//! the size of the allocation (within given bounds) in the same manner as
+//! compiler-rt, so that small allocations don't pay for a large red-zone and
+//! large ones receive more protection. The frontend also supports the use of a
 //! quarantine (whose size is configurable) to prevent user buffers from being
-//! re-used for a period of time.
+//! re-used for a period of time. Its configuration may be taken from the
+//! runtime `Options`.
+//!
+//! Newly allocated buffers are filled (up to a configurable size) with a fill
+//! byte. Freed buffers may also be filled with a (different) fill byte, in
+//! which case the frontend can optionally check that their contents are
+//! unchanged when they leave the quarantine. This detects writes to freed
+//! memory which were not instrumented (e.g. when running without QEMU).
+//!
+//! Attempts to free an address which is not the start of a live allocation
+//! are classified as either a double free (the address is that of a buffer
+//! in the quarantine), a free of an interior pointer (the address lies within
+//! a live or quarantined buffer or its red-zones), or a free of a foreign
+//! address (memory which was never allocated by the frontend, e.g. on the
+//! stack, a global, or allocated before the runtime was initialized).
+//!
+//! The type of each allocation (whether it was made by `malloc` or by the C++
+//! `operator new` or `operator new []`) is recorded so that buffers released
+//! by the wrong deallocation function, or by a sized `operator delete` with
+//! the wrong size, can be reported as an alloc-dealloc-mismatch.
+//!
+//! Requests for buffers larger than a configurable maximum (or whose size
+//! overflows once the red-zones are added) fail without reaching the
+//! backend, so that the caller can report them as allocation-size-too-big.
+//!
+//! When used with a `ResettableBackend` (e.g. the arena), the whole heap can
+//! be discarded at once using `reset`, e.g. between the iterations of a
+//! persistent fuzzing loop.
+//!
+//! The frontend also maintains the statistics of the heap (see `stats`).
+//!
+//! The call stacks at which each buffer is allocated and freed are captured
+//! (up to a configurable depth) and stored in a de-duplicating `StackDepot`
+//! so that they may be included in subsequent error reports.
 use {
     crate::{
-        allocator::{backend::AllocatorBackend, frontend::AllocatorFrontend},
+        allocator::{
+            backend::{AllocatorBackend, ResettableBackend},
+            frontend::{AllocationInfo, AllocationType, AllocatorFrontend},
+        },
+        backtrace::{
+            capture,
+            depot::{StackDepot, StackId},
+            MAX_DEPTH,
+        },
+        leak::{registers, Chunk, Leak, LeakChecker},
+        options::Options,
         shadow::{PoisonType, Shadow},
+        stats::HeapStats,
         tracking::Tracking,
         GuestAddr,
     },
     alloc::{
         collections::{BTreeMap, VecDeque},
         fmt::Debug,
+        vec::Vec,
+    },
+    core::{
+        ops::Range,
+        slice::{from_raw_parts, from_raw_parts_mut},
     },
-    core::slice::from_raw_parts_mut,
     log::debug,
     thiserror::Error,
 };
 
 struct Allocation {
+    frontend_addr: GuestAddr,
     frontend_len: usize,
     backend_addr: GuestAddr,
     backend_len: usize,
     backend_align: usize,
+    red_zone_size: usize,
+    alloc_type: AllocationType,
+    alloc_stack: StackId,
+    free_stack: Option<StackId>,
+    /// The number of bytes at the start of the freed buffer which are left
+    /// out of the free fill
+    fill_offset: usize,
+}
+
+impl Allocation {
+    fn contains(&self, addr: GuestAddr) -> bool {
+        addr >= self.backend_addr && addr - self.backend_addr < self.backend_len
+    }
+
+    fn info(&self, depot: &StackDepot, freed: bool) -> AllocationInfo {
+        let frames = |id| depot.get(id).unwrap_or_default().to_vec();
+        AllocationInfo {
+            addr: self.frontend_addr,
+            len: self.frontend_len,
+            freed,
+            alloc_stack: frames(self.alloc_stack),
+            free_stack: self.free_stack.map(frames),
+        }
+    }
+}
+
+/// How the size of the red-zone applied to each allocation is chosen
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RedZonePolicy {
+    /// The same red-zone is applied to every allocation
+    Fixed(usize),
+    /// The red-zone is scaled with the size of the allocation, but kept
+    /// within the given bounds
+    Adaptive { min: usize, max: usize },
+}
+
+impl RedZonePolicy {
+    pub const MIN_RED_ZONE_SIZE: usize = 16;
+    pub const MAX_RED_ZONE_SIZE: usize = 2048;
+
+    /// The size of the red-zone for an allocation of the given length. As
+    /// with compiler-rt, the adaptive red-zone is chosen such that it is at
+    /// least (roughly) a quarter of the size of the allocation for smaller
+    /// allocations.
+    pub fn red_zone_size(&self, len: usize) -> usize {
+        match *self {
+            RedZonePolicy::Fixed(size) => size,
+            RedZonePolicy::Adaptive { min, max } => {
+                let rz_log = match len {
+                    0..=48 => 0,
+                    49..=96 => 1,
+                    97..=448 => 2,
+                    449..=3968 => 3,
+                    3969..=16128 => 4,
+                    16129..=32256 => 5,
+                    32257..=64512 => 6,
+                    _ => 7,
+                };
+                (Self::MIN_RED_ZONE_SIZE << rz_log).clamp(min, max)
+            }
+        }
+    }
 }
 
 pub struct DefaultFrontend<B: AllocatorBackend, S: Shadow, T: Tracking> {
     backend: B,
     shadow: S,
     tracking: T,
-    red_zone_size: usize,
+    red_zone: RedZonePolicy,
+    alloc_align_size: usize,
     allocations: BTreeMap<GuestAddr, Allocation>,
     quarantine: VecDeque<Allocation>,
     quarantine_size: usize,
     quaratine_used: usize,
+    depot: StackDepot,
+    stack_depth: usize,
+    malloc_fill_byte: u8,
+    max_malloc_fill_size: usize,
+    free_fill_byte: Option<u8>,
+    check_free_fill: bool,
+    alloc_dealloc_mismatch: bool,
+    new_delete_type_mismatch: bool,
+    max_allocation_size: usize,
+    stats: HeapStats,
 }
 
 impl<B: AllocatorBackend, S: Shadow, T: Tracking> AllocatorFrontend for DefaultFrontend<B, S, T> {
     type Error = DefaultFrontendError<B, S, T>;
 
     fn alloc(&mut self, len: usize, align: usize) -> Result<GuestAddr, Self::Error> {
-        debug!("alloc - len: 0x{:x}, align: 0x{:x}", len, align);
+        self.alloc_typed(len, align, AllocationType::Malloc)
+    }
+
+    fn dealloc(&mut self, addr: GuestAddr) -> Result<(), Self::Error> {
+        self.dealloc_typed(addr, AllocationType::Malloc, None)
+    }
+
+    fn get_size(&self, addr: GuestAddr) -> Result<usize, Self::Error> {
+        debug!("get_size - addr: 0x{:x}", addr);
+        let alloc = self
+            .allocations
+            .get(&addr)
+            .ok_or_else(|| DefaultFrontendError::InvalidAddress(addr))?;
+        Ok(alloc.frontend_len)
+    }
+}
+
+impl<B: AllocatorBackend, S: Shadow, T: Tracking> DefaultFrontend<B, S, T> {
+    /// The minimum alignment of the buffers obtained from the backend, this
+    /// is increased to the granularity of the shadow if it is larger
+    #[cfg(target_pointer_width = "32")]
+    const MIN_ALLOC_ALIGN_SIZE: usize = 8;
+
+    #[cfg(target_pointer_width = "64")]
+    const MIN_ALLOC_ALIGN_SIZE: usize = 16;
+
+    pub const DEFAULT_REDZONE_SIZE: usize = 128;
+    pub const DEFAULT_QUARANTINE_SIZE: usize = 50 << 20;
+    pub const DEFAULT_STACK_DEPTH: usize = 30;
+    pub const DEFAULT_MALLOC_FILL_BYTE: u8 = 0xff;
+    pub const DEFAULT_MAX_MALLOC_FILL_SIZE: usize = 0x1000;
+
+    #[cfg(target_pointer_width = "32")]
+    pub const DEFAULT_MAX_ALLOCATION_SIZE: usize = 3 << 30;
+
+    #[cfg(target_pointer_width = "64")]
+    pub const DEFAULT_MAX_ALLOCATION_SIZE: usize = 1 << 40;
+
+    /// Allocate a buffer on behalf of the given family of allocation
+    /// functions
+    pub fn alloc_typed(
+        &mut self,
+        len: usize,
+        align: usize,
+        alloc_type: AllocationType,
+    ) -> Result<GuestAddr, DefaultFrontendError<B, S, T>> {
+        debug!(
+            "alloc - len: 0x{:x}, align: 0x{:x}, type: {:?}",
+            len, align, alloc_type
+        );
         if align % size_of::<GuestAddr>() != 0 {
             Err(DefaultFrontendError::InvalidAlignment(align))?;
         }
-        let size = len + align;
-        let allocated_size = (self.red_zone_size * 2) + Self::align_up(size);
-        assert!(allocated_size % Self::ALLOC_ALIGN_SIZE == 0);
+        let max = self.max_allocation_size;
+        let too_big = || DefaultFrontendError::AllocationSizeTooBig(len, max);
+        if len > max {
+            Err(too_big())?;
+        }
+        /* The buffer must begin on a granule of the shadow */
+        let granularity = self.shadow.granularity();
+        let align = match align {
+            0 => 0,
+            _ => {
+                align
+                    << granularity
+                        .trailing_zeros()
+                        .saturating_sub(align.trailing_zeros())
+            }
+        };
+        let red_zone_size = self
+            .align_up(self.red_zone.red_zone_size(len))
+            .ok_or_else(too_big)?;
+        let allocated_size = len
+            .checked_add(align)
+            .and_then(|size| self.align_up(size))
+            .and_then(|size| size.checked_add(red_zone_size.checked_mul(2)?))
+            .ok_or_else(too_big)?;
+        assert!(allocated_size % self.alloc_align_size == 0);
         let orig = self
             .backend
-            .alloc(allocated_size, Self::ALLOC_ALIGN_SIZE)
+            .alloc(allocated_size, self.alloc_align_size)
             .map_err(|e| DefaultFrontendError::AllocatorError(e))?;
 
         debug!(
             "alloc - buffer: 0x{:x}, len: 0x{:x}, align: 0x{:x}",
-            orig,
-            allocated_size,
-            Self::ALLOC_ALIGN_SIZE
+            orig, allocated_size, self.alloc_align_size
         );
 
-        let rz = orig + self.red_zone_size;
+        let rz = orig + red_zone_size;
         let data = if align == 0 {
             rz
         } else {
@@ -74,13 +268,20 @@ impl<B: AllocatorBackend, S: Shadow, T: Tracking> AllocatorFrontend for DefaultF
         assert!(align == 0 || data % align == 0);
         assert!(data + len <= orig + allocated_size);
 
+        let alloc_stack = self.capture_stack();
         self.allocations.insert(
             data,
             Allocation {
+                frontend_addr: data,
                 frontend_len: len,
                 backend_addr: orig,
                 backend_len: allocated_size,
-                backend_align: Self::ALLOC_ALIGN_SIZE,
+                backend_align: self.alloc_align_size,
+                red_zone_size,
+                alloc_type,
+                alloc_stack,
+                free_stack: None,
+                fill_offset: 0,
             },
         );
 
@@ -93,26 +294,89 @@ impl<B: AllocatorBackend, S: Shadow, T: Tracking> AllocatorFrontend for DefaultF
         self.shadow
             .unpoison(data, len)
             .map_err(|e| DefaultFrontendError::ShadowError(e))?;
-        let poison_len = Self::align_up(len) - len + self.red_zone_size;
+        let poison_len = self.align_up(len).ok_or_else(too_big)? - len + red_zone_size;
         self.shadow
-            .poison(data + len, poison_len, PoisonType::AsanStackRightRz)
+            .poison(data + len, poison_len, PoisonType::AsanHeapRightRz)
             .map_err(|e| DefaultFrontendError::ShadowError(e))?;
 
-        let buffer = unsafe { from_raw_parts_mut(data as *mut u8, len) };
-        buffer.iter_mut().for_each(|b| *b = 0xff);
+        let fill_len = len.min(self.max_malloc_fill_size);
+        let buffer = unsafe { from_raw_parts_mut(data as *mut u8, fill_len) };
+        buffer.fill(self.malloc_fill_byte);
+        self.stats.record_alloc(len, allocated_size);
         Ok(data)
     }
 
-    fn dealloc(&mut self, addr: GuestAddr) -> Result<(), Self::Error> {
-        debug!("dealloc - addr: 0x{:x}", addr);
+    /// Release a buffer on behalf of the given family of deallocation
+    /// functions. If the buffer was allocated by a different family, or the
+    /// given size (from a sized `operator delete`) doesn't match that of the
+    /// allocation, then the buffer is left allocated and an error returned.
+    pub fn dealloc_typed(
+        &mut self,
+        addr: GuestAddr,
+        dealloc_type: AllocationType,
+        len: Option<usize>,
+    ) -> Result<(), DefaultFrontendError<B, S, T>> {
+        self.free(addr, dealloc_type, len, 0)
+    }
+
+    /// Release a buffer, leaving its first `preserved` bytes untouched by the
+    /// free fill (e.g. a header which is still read once the buffer is freed)
+    pub fn dealloc_preserving(
+        &mut self,
+        addr: GuestAddr,
+        preserved: usize,
+    ) -> Result<(), DefaultFrontendError<B, S, T>> {
+        self.free(addr, AllocationType::Malloc, None, preserved)
+    }
+
+    fn free(
+        &mut self,
+        addr: GuestAddr,
+        dealloc_type: AllocationType,
+        len: Option<usize>,
+        preserved: usize,
+    ) -> Result<(), DefaultFrontendError<B, S, T>> {
+        debug!(
+            "dealloc - addr: 0x{:x}, type: {:?}, len: {:?}",
+            addr, dealloc_type, len
+        );
         if addr == 0 {
             return Ok(());
         }
 
         let alloc = self
+            .allocations
+            .get(&addr)
+            .ok_or_else(|| self.invalid_free(addr))?;
+        if self.alloc_dealloc_mismatch && alloc.alloc_type != dealloc_type {
+            Err(DefaultFrontendError::AllocDeallocMismatch(
+                addr,
+                alloc.alloc_type,
+                dealloc_type,
+                alloc.info(&self.depot, false),
+            ))?;
+        }
+        match len {
+            Some(len) if self.new_delete_type_mismatch && len != alloc.frontend_len => {
+                Err(DefaultFrontendError::NewDeleteSizeMismatch(
+                    addr,
+                    len,
+                    alloc.info(&self.depot, false),
+                ))?;
+            }
+            _ => (),
+        }
+
+        let mut alloc = self
             .allocations
             .remove(&addr)
             .ok_or_else(|| DefaultFrontendError::InvalidAddress(addr))?;
+        alloc.free_stack = Some(self.capture_stack());
+        alloc.fill_offset = preserved.min(alloc.frontend_len);
+        if let Some(fill) = self.free_fill_byte {
+            let buffer = unsafe { from_raw_parts_mut(addr as *mut u8, alloc.frontend_len) };
+            buffer[alloc.fill_offset..].fill(fill);
+        }
         self.shadow
             .poison(
                 alloc.backend_addr,
@@ -123,32 +387,14 @@ impl<B: AllocatorBackend, S: Shadow, T: Tracking> AllocatorFrontend for DefaultF
         self.tracking
             .dealloc(addr)
             .map_err(|e| DefaultFrontendError::TrackingError(e))?;
+        self.stats
+            .record_free(alloc.frontend_len, alloc.backend_len);
         self.quaratine_used += alloc.backend_len;
         self.quarantine.push_back(alloc);
         self.purge_quarantine()?;
         Ok(())
     }
 
-    fn get_size(&self, addr: GuestAddr) -> Result<usize, Self::Error> {
-        debug!("get_size - addr: 0x{:x}", addr);
-        let alloc = self
-            .allocations
-            .get(&addr)
-            .ok_or_else(|| DefaultFrontendError::InvalidAddress(addr))?;
-        Ok(alloc.frontend_len)
-    }
-}
-
-impl<B: AllocatorBackend, S: Shadow, T: Tracking> DefaultFrontend<B, S, T> {
-    #[cfg(target_pointer_width = "32")]
-    const ALLOC_ALIGN_SIZE: usize = 8;
-
-    #[cfg(target_pointer_width = "64")]
-    const ALLOC_ALIGN_SIZE: usize = 16;
-
-    pub const DEFAULT_REDZONE_SIZE: usize = 128;
-    pub const DEFAULT_QUARANTINE_SIZE: usize = 50 << 20;
-
     pub fn new(
         backend: B,
         shadow: S,
@@ -156,39 +402,321 @@ impl<B: AllocatorBackend, S: Shadow, T: Tracking> DefaultFrontend<B, S, T> {
         red_zone_size: usize,
         quarantine_size: usize,
     ) -> Result<DefaultFrontend<B, S, T>, DefaultFrontendError<B, S, T>> {
-        if red_zone_size % Self::ALLOC_ALIGN_SIZE != 0 {
-            Err(DefaultFrontendError::InvalidRedZoneSize(red_zone_size))?;
-        }
+        Self::with_red_zone_policy(
+            backend,
+            shadow,
+            tracking,
+            RedZonePolicy::Fixed(red_zone_size),
+            quarantine_size,
+        )
+    }
+
+    /// Create a frontend whose red-zones are sized according to the given
+    /// policy. The bounds of the policy are rounded up to a multiple of the
+    /// granularity of the shadow (e.g. the default minimum of 16 bytes becomes
+    /// 32 when each shadow byte describes 32 bytes).
+    pub fn with_red_zone_policy(
+        backend: B,
+        shadow: S,
+        tracking: T,
+        red_zone: RedZonePolicy,
+        quarantine_size: usize,
+    ) -> Result<DefaultFrontend<B, S, T>, DefaultFrontendError<B, S, T>> {
+        let alloc_align_size = Self::MIN_ALLOC_ALIGN_SIZE.max(shadow.granularity());
+        let round_up = |size: usize| {
+            size.checked_next_multiple_of(alloc_align_size)
+                .ok_or(DefaultFrontendError::InvalidRedZoneSize(size))
+        };
+        let red_zone = match red_zone {
+            RedZonePolicy::Fixed(size) => RedZonePolicy::Fixed(round_up(size)?),
+            RedZonePolicy::Adaptive { min, max } => {
+                if min > max {
+                    Err(DefaultFrontendError::InvalidRedZoneRange(min, max))?;
+                }
+                RedZonePolicy::Adaptive {
+                    min: round_up(min)?,
+                    max: round_up(max)?,
+                }
+            }
+        };
         Ok(DefaultFrontend::<B, S, T> {
             backend,
             shadow,
             tracking,
-            red_zone_size,
+            red_zone,
+            alloc_align_size,
             allocations: BTreeMap::new(),
             quarantine: VecDeque::new(),
             quarantine_size,
             quaratine_used: 0,
+            depot: StackDepot::new(),
+            stack_depth: Self::DEFAULT_STACK_DEPTH,
+            malloc_fill_byte: Self::DEFAULT_MALLOC_FILL_BYTE,
+            max_malloc_fill_size: Self::DEFAULT_MAX_MALLOC_FILL_SIZE,
+            free_fill_byte: None,
+            check_free_fill: false,
+            alloc_dealloc_mismatch: true,
+            new_delete_type_mismatch: true,
+            max_allocation_size: Self::DEFAULT_MAX_ALLOCATION_SIZE,
+            stats: HeapStats::default(),
         })
     }
 
+    /// Create a frontend configured by the given `Options`, any which are not
+    /// specified take their default values.
+    pub fn with_options(
+        backend: B,
+        shadow: S,
+        tracking: T,
+        options: &Options,
+    ) -> Result<DefaultFrontend<B, S, T>, DefaultFrontendError<B, S, T>> {
+        let quarantine_size = match options.quarantine_size_mb {
+            Some(mb) => mb
+                .checked_mul(1 << 20)
+                .ok_or(DefaultFrontendError::InvalidQuarantineSize(mb))?,
+            None => Self::DEFAULT_QUARANTINE_SIZE,
+        };
+        let red_zone = match options.max_redzone {
+            Some(max) => RedZonePolicy::Adaptive {
+                min: options.redzone.unwrap_or(RedZonePolicy::MIN_RED_ZONE_SIZE),
+                max,
+            },
+            None => RedZonePolicy::Fixed(options.redzone.unwrap_or(Self::DEFAULT_REDZONE_SIZE)),
+        };
+        let mut frontend =
+            Self::with_red_zone_policy(backend, shadow, tracking, red_zone, quarantine_size)?;
+        if let Some(depth) = options.malloc_context_size {
+            frontend.set_stack_depth(depth)?;
+        }
+        if let Some(fill) = options.malloc_fill_byte {
+            frontend.set_malloc_fill_byte(fill);
+        }
+        if let Some(size) = options.max_malloc_fill_size {
+            frontend.set_max_malloc_fill_size(size);
+        }
+        frontend.set_free_fill_byte(options.free_fill_byte);
+        if let Some(check) = options.check_free_fill {
+            frontend.set_check_free_fill(check);
+        }
+        if let Some(detect) = options.alloc_dealloc_mismatch {
+            frontend.set_alloc_dealloc_mismatch(detect);
+        }
+        if let Some(detect) = options.new_delete_type_mismatch {
+            frontend.set_new_delete_type_mismatch(detect);
+        }
+        if let Some(mb) = options.max_allocation_size_mb {
+            let size = mb
+                .checked_mul(1 << 20)
+                .ok_or(DefaultFrontendError::InvalidMaxAllocationSize(mb))?;
+            frontend.set_max_allocation_size(size);
+        }
+        Ok(frontend)
+    }
+
+    /// Set the maximum number of frames recorded in the allocation and free
+    /// stacks (a depth of zero disables their capture).
+    pub fn set_stack_depth(
+        &mut self,
+        stack_depth: usize,
+    ) -> Result<(), DefaultFrontendError<B, S, T>> {
+        if stack_depth > MAX_DEPTH {
+            Err(DefaultFrontendError::InvalidStackDepth(stack_depth))?;
+        }
+        self.stack_depth = stack_depth;
+        Ok(())
+    }
+
+    pub fn red_zone_policy(&self) -> RedZonePolicy {
+        self.red_zone
+    }
+
+    /// The size of the red-zone applied to the live allocation at the given
+    /// address
+    pub fn get_red_zone_size(
+        &self,
+        addr: GuestAddr,
+    ) -> Result<usize, DefaultFrontendError<B, S, T>> {
+        let alloc = self
+            .allocations
+            .get(&addr)
+            .ok_or_else(|| DefaultFrontendError::InvalidAddress(addr))?;
+        Ok(alloc.red_zone_size)
+    }
+
+    /// Set the value used to fill newly allocated buffers
+    pub fn set_malloc_fill_byte(&mut self, malloc_fill_byte: u8) {
+        self.malloc_fill_byte = malloc_fill_byte;
+    }
+
+    /// Set the maximum number of bytes of each new buffer which are filled
+    pub fn set_max_malloc_fill_size(&mut self, max_malloc_fill_size: usize) {
+        self.max_malloc_fill_size = max_malloc_fill_size;
+    }
+
+    /// Set the value used to fill freed buffers (or `None` to leave their
+    /// contents untouched)
+    pub fn set_free_fill_byte(&mut self, free_fill_byte: Option<u8>) {
+        self.free_fill_byte = free_fill_byte;
+    }
+
+    /// Set whether the contents of freed buffers are checked against the
+    /// free fill byte when they leave the quarantine. This has no effect
+    /// unless a free fill byte is set.
+    pub fn set_check_free_fill(&mut self, check_free_fill: bool) {
+        self.check_free_fill = check_free_fill;
+    }
+
+    /// Set whether buffers released by a different family of functions to
+    /// that which allocated them are reported
+    pub fn set_alloc_dealloc_mismatch(&mut self, alloc_dealloc_mismatch: bool) {
+        self.alloc_dealloc_mismatch = alloc_dealloc_mismatch;
+    }
+
+    /// Set whether buffers released by a sized `operator delete` with the
+    /// wrong size are reported
+    pub fn set_new_delete_type_mismatch(&mut self, new_delete_type_mismatch: bool) {
+        self.new_delete_type_mismatch = new_delete_type_mismatch;
+    }
+
+    /// Set the largest buffer which may be requested, larger requests fail
+    /// with `AllocationSizeTooBig` without reaching the backend
+    pub fn set_max_allocation_size(&mut self, max_allocation_size: usize) {
+        self.max_allocation_size = max_allocation_size;
+    }
+
+    #[inline(always)]
+    fn capture_stack(&mut self) -> StackId {
+        let mut frames = [0; MAX_DEPTH];
+        let depth = capture(&mut frames[..self.stack_depth]);
+        self.depot.insert(&frames[..depth])
+    }
+
+    /// Release buffers from the quarantine until it is within its size limit.
+    /// If a buffer is found to have been written to since it was freed, then
+    /// the purge is completed before the first such write is returned as an
+    /// error.
     fn purge_quarantine(&mut self) -> Result<(), DefaultFrontendError<B, S, T>> {
+        let mut modified = None;
         while self.quaratine_used > self.quarantine_size {
             let alloc = self
                 .quarantine
                 .pop_front()
                 .ok_or(DefaultFrontendError::QuarantineCorruption)?;
+            if modified.is_none() {
+                modified = self
+                    .find_free_fill_mismatch(&alloc)
+                    .map(|addr| (addr, alloc.info(&self.depot, true)));
+            }
             self.backend
                 .dealloc(alloc.backend_addr, alloc.backend_len, alloc.backend_align)
                 .map_err(|e| DefaultFrontendError::AllocatorError(e))?;
             self.quaratine_used -= alloc.backend_len;
+            self.stats.record_release(alloc.backend_len);
         }
-        Ok(())
+        match modified {
+            Some((addr, info)) => Err(DefaultFrontendError::WriteAfterFree(addr, info)),
+            None => Ok(()),
+        }
+    }
+
+    /// Find the first byte of a freed buffer which no longer matches the free
+    /// fill byte
+    fn find_free_fill_mismatch(&self, alloc: &Allocation) -> Option<GuestAddr> {
+        let fill = self.free_fill_byte.filter(|_| self.check_free_fill)?;
+        let buffer =
+            unsafe { from_raw_parts(alloc.frontend_addr as *const u8, alloc.frontend_len) };
+        buffer[alloc.fill_offset..]
+            .iter()
+            .position(|b| *b != fill)
+            .map(|i| alloc.frontend_addr + alloc.fill_offset + i)
+    }
+
+    /// Classify an attempt to free an address which isn't the start of a live
+    /// allocation
+    fn invalid_free(&self, addr: GuestAddr) -> DefaultFrontendError<B, S, T> {
+        if let Some(alloc) = self.quarantine.iter().find(|a| a.frontend_addr == addr) {
+            return DefaultFrontendError::DoubleFree(addr, alloc.info(&self.depot, true));
+        }
+        match self.find_allocation(addr) {
+            Some(info) => DefaultFrontendError::InteriorPointer(addr, info),
+            None => DefaultFrontendError::ForeignAddress(addr),
+        }
+    }
+
+    /// Whether the given address is the start of a live allocation
+    pub fn owns(&self, addr: GuestAddr) -> bool {
+        self.allocations.contains_key(&addr)
+    }
+
+    /// The current statistics of the heap
+    pub fn stats(&self) -> HeapStats {
+        self.stats
+    }
+
+    /// The addresses of the live allocations
+    pub fn allocations(&self) -> impl Iterator<Item = GuestAddr> + '_ {
+        self.allocations.keys().copied()
+    }
+
+    /// The largest buffer which may be requested
+    pub fn max_allocation_size(&self) -> usize {
+        self.max_allocation_size
+    }
+
+    /// Find the allocation (either live or in the quarantine) whose backing
+    /// buffer, including its red-zones, contains the given address.
+    pub fn find_allocation(&self, addr: GuestAddr) -> Option<AllocationInfo> {
+        let live = self
+            .allocations
+            .range(..=addr)
+            .next_back()
+            .into_iter()
+            .chain(self.allocations.range(addr..).next())
+            .map(|(_, a)| a)
+            .find(|a| a.contains(addr))
+            .map(|a| a.info(&self.depot, false));
+
+        live.or_else(|| {
+            self.quarantine
+                .iter()
+                .rev()
+                .find(|a| a.contains(addr))
+                .map(|a| a.info(&self.depot, true))
+        })
+    }
+
+    /// Find the live allocations which are not reachable from either the
+    /// given roots or the registers of the current thread.
+    ///
+    /// # Safety
+    /// The given roots must be readable
+    pub unsafe fn find_leaks(&self, roots: &[Range<GuestAddr>]) -> Vec<Leak> {
+        let chunks = self
+            .allocations
+            .values()
+            .map(|a| Chunk {
+                addr: a.frontend_addr,
+                len: a.frontend_len,
+                stack: a.alloc_stack,
+            })
+            .collect();
+        let mut checker = LeakChecker::new(chunks);
+        checker.scan_words(&registers());
+        roots
+            .iter()
+            .for_each(|r| checker.scan_range(r.start, r.end));
+        checker.leaks(&self.depot)
     }
 
-    fn align_up(size: usize) -> usize {
-        assert!(size <= GuestAddr::MAX - (Self::ALLOC_ALIGN_SIZE - 1));
-        let val = size + (Self::ALLOC_ALIGN_SIZE - 1);
-        val & !(Self::ALLOC_ALIGN_SIZE - 1)
+    fn align_up(&self, size: usize) -> Option<usize> {
+        let val = size.checked_add(self.alloc_align_size - 1)?;
+        Some(val & !(self.alloc_align_size - 1))
+    }
+
+    /// The alignment of the buffers obtained from the backend (and hence the
+    /// granularity of the red-zones)
+    pub fn alloc_align_size(&self) -> usize {
+        self.alloc_align_size
     }
 
     pub fn shadow(&self) -> &S {
@@ -199,6 +727,10 @@ impl<B: AllocatorBackend, S: Shadow, T: Tracking> DefaultFrontend<B, S, T> {
         &mut self.shadow
     }
 
+    pub fn depot(&self) -> &StackDepot {
+        &self.depot
+    }
+
     pub fn tracking(&self) -> &T {
         &self.tracking
     }
@@ -207,15 +739,57 @@ impl<B: AllocatorBackend, S: Shadow, T: Tracking> DefaultFrontend<B, S, T> {
         &mut self.tracking
     }
 
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
     pub fn backend_mut(&mut self) -> &mut B {
         &mut self.backend
     }
 }
 
+impl<B: ResettableBackend, S: Shadow, T: Tracking> DefaultFrontend<B, S, T> {
+    /// Discard all of the buffers (both live and quarantined) at once. The
+    /// backend is reset, the memory it had allocated poisoned and the live
+    /// buffers are removed from the tracking.
+    pub fn reset(&mut self) -> Result<(), DefaultFrontendError<B, S, T>> {
+        debug!(
+            "reset - allocations: {}, quarantine: {}",
+            self.allocations.len(),
+            self.quarantine.len()
+        );
+        for addr in self.allocations.keys() {
+            self.tracking
+                .dealloc(*addr)
+                .map_err(|e| DefaultFrontendError::TrackingError(e))?;
+        }
+        self.allocations.clear();
+        self.quarantine.clear();
+        self.quaratine_used = 0;
+        let range = self
+            .backend
+            .reset()
+            .map_err(|e| DefaultFrontendError::AllocatorError(e))?;
+        self.shadow
+            .poison(range.start, range.len(), PoisonType::AsanHeapFreed)
+            .map_err(|e| DefaultFrontendError::ShadowError(e))?;
+        self.stats.record_reset();
+        Ok(())
+    }
+}
+
 #[derive(Error, Debug, PartialEq)]
 pub enum DefaultFrontendError<B: AllocatorBackend, S: Shadow, T: Tracking> {
     #[error("Invalid red_zone_size: {0}")]
     InvalidRedZoneSize(usize),
+    #[error("Invalid red-zone range: {0}-{1}")]
+    InvalidRedZoneRange(usize, usize),
+    #[error("Invalid quarantine_size_mb: {0}")]
+    InvalidQuarantineSize(usize),
+    #[error("Invalid stack_depth: {0}")]
+    InvalidStackDepth(usize),
+    #[error("Invalid max_allocation_size_mb: {0}")]
+    InvalidMaxAllocationSize(usize),
     #[error("Invalid alignment: {0}")]
     InvalidAlignment(usize),
     #[error("Allocator error: {0:?}")]
@@ -226,6 +800,20 @@ pub enum DefaultFrontendError<B: AllocatorBackend, S: Shadow, T: Tracking> {
     TrackingError(T::Error),
     #[error("Invalid address: {0:x}")]
     InvalidAddress(GuestAddr),
+    #[error("Double free: {0:x}")]
+    DoubleFree(GuestAddr, AllocationInfo),
+    #[error("Interior pointer: {0:x}")]
+    InteriorPointer(GuestAddr, AllocationInfo),
+    #[error("Foreign address: {0:x}")]
+    ForeignAddress(GuestAddr),
     #[error("Quarantine corruption")]
     QuarantineCorruption,
+    #[error("Write after free: {0:x}")]
+    WriteAfterFree(GuestAddr, AllocationInfo),
+    #[error("Alloc-dealloc mismatch: {0:x} ({1:?} vs {2:?})")]
+    AllocDeallocMismatch(GuestAddr, AllocationType, AllocationType, AllocationInfo),
+    #[error("New-delete size mismatch: {0:x} ({1})")]
+    NewDeleteSizeMismatch(GuestAddr, usize, AllocationInfo),
+    #[error("Allocation size too big: 0x{0:x} (max 0x{1:x})")]
+    AllocationSizeTooBig(usize, usize),
 }
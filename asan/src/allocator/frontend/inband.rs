@@ -0,0 +1,541 @@
+//! # inband
+//! This frontend stores the metadata of each allocation in-band, that is in a
+//! compact header placed in the left red-zone immediately before the user's
+//! buffer. This means that `dealloc` and `get_size` need only read the header
+//! rather than searching a map of all allocations. Since the header lies
+//! within the red-zone, it is poisoned and hence any access to it by the
+//! application is reported.
+//!
+//! Each header includes a magic value and a checksum (which covers its
+//! contents and its address), so that a pointer which does not refer to the
+//! start of a buffer, or a header which has been corrupted, is detected. The
+//! shadow map is checked before any header is read, so that an arbitrary
+//! pointer passed to `dealloc` does not cause us to read unmapped memory.
+//! Freed buffers are placed in a quarantine (whose size is configurable) and
+//! their headers are marked as such, allowing double frees to be detected.
+//!
+//! Live allocations are additionally linked together through their headers,
+//! so that they can be enumerated (e.g. to check for leaks, or to find the
+//! allocation which owns a faulting address when reporting an error) without
+//! the need for a separate index.
+use {
+    crate::{
+        allocator::{
+            backend::AllocatorBackend,
+            frontend::{AllocationInfo, AllocatorFrontend},
+        },
+        backtrace::{
+            capture,
+            depot::{StackDepot, StackId},
+            MAX_DEPTH,
+        },
+        leak::{registers, Chunk, Leak, LeakChecker},
+        options::Options,
+        shadow::{PoisonType, Shadow},
+        tracking::Tracking,
+        GuestAddr,
+    },
+    alloc::{collections::VecDeque, fmt::Debug, vec::Vec},
+    core::{ops::Range, slice::from_raw_parts_mut},
+    log::debug,
+    thiserror::Error,
+};
+
+const CHUNK_MAGIC: u16 = 0xa5a5;
+
+const STATE_ALLOCATED: u8 = 1;
+const STATE_QUARANTINED: u8 = 2;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct ChunkHeader {
+    prev: GuestAddr,
+    next: GuestAddr,
+    size: usize,
+    backend_len: usize,
+    offset: u32,
+    checksum: u32,
+    alloc_stack: StackId,
+    free_stack: StackId,
+    magic: u16,
+    state: u8,
+    reserved: u8,
+}
+
+impl ChunkHeader {
+    const SIZE: usize = size_of::<ChunkHeader>();
+
+    fn addr(data: GuestAddr) -> GuestAddr {
+        data - Self::SIZE
+    }
+
+    /// The checksum excludes the links to the neighbouring chunks, since
+    /// these are updated as other chunks are allocated and freed
+    fn compute_checksum(&self, data: GuestAddr) -> u32 {
+        [
+            data,
+            self.size,
+            self.backend_len,
+            self.offset as usize,
+            self.magic as usize,
+            self.state as usize,
+        ]
+        .iter()
+        .flat_map(|v| v.to_ne_bytes())
+        .chain(
+            [self.alloc_stack, self.free_stack]
+                .iter()
+                .flat_map(|s| s.value().to_ne_bytes()),
+        )
+        .fold(0x811c9dc5, |h, b| (h ^ b as u32).wrapping_mul(0x01000193))
+    }
+
+    fn seal(&mut self, data: GuestAddr) {
+        self.checksum = self.compute_checksum(data);
+    }
+
+    fn is_valid(&self, data: GuestAddr) -> bool {
+        self.magic == CHUNK_MAGIC && self.checksum == self.compute_checksum(data)
+    }
+
+    fn backend_addr(&self, data: GuestAddr) -> GuestAddr {
+        data - self.offset as usize
+    }
+
+    fn contains(&self, data: GuestAddr, addr: GuestAddr) -> bool {
+        let base = self.backend_addr(data);
+        addr >= base && addr - base < self.backend_len
+    }
+
+    fn info(&self, data: GuestAddr, depot: &StackDepot) -> AllocationInfo {
+        let frames = |id| depot.get(id).unwrap_or_default().to_vec();
+        let freed = self.state == STATE_QUARANTINED;
+        AllocationInfo {
+            addr: data,
+            len: self.size,
+            freed,
+            alloc_stack: frames(self.alloc_stack),
+            free_stack: freed.then(|| frames(self.free_stack)),
+        }
+    }
+}
+
+pub struct InbandFrontend<B: AllocatorBackend, S: Shadow, T: Tracking> {
+    backend: B,
+    shadow: S,
+    tracking: T,
+    red_zone_size: usize,
+    alloc_align_size: usize,
+    head: GuestAddr,
+    quarantine: VecDeque<GuestAddr>,
+    quarantine_size: usize,
+    quarantine_used: usize,
+    depot: StackDepot,
+    stack_depth: usize,
+    malloc_fill_byte: u8,
+}
+
+impl<B: AllocatorBackend, S: Shadow, T: Tracking> AllocatorFrontend for InbandFrontend<B, S, T> {
+    type Error = InbandFrontendError<B, S, T>;
+
+    fn alloc(&mut self, len: usize, align: usize) -> Result<GuestAddr, Self::Error> {
+        debug!("alloc - len: 0x{:x}, align: 0x{:x}", len, align);
+        if align % size_of::<GuestAddr>() != 0 || (align != 0 && !align.is_power_of_two()) {
+            Err(InbandFrontendError::InvalidAlignment(align))?;
+        }
+        let align = align.max(self.alloc_align_size);
+        let padding = align - self.alloc_align_size;
+        let allocated_size = self
+            .align_up(len)
+            .and_then(|size| size.checked_add(padding))
+            .and_then(|size| size.checked_add(self.red_zone_size * 2))
+            .ok_or(InbandFrontendError::AllocationSizeTooBig(len))?;
+        let orig = self
+            .backend
+            .alloc(allocated_size, self.alloc_align_size)
+            .map_err(|e| InbandFrontendError::AllocatorError(e))?;
+
+        debug!(
+            "alloc - buffer: 0x{:x}, len: 0x{:x}, align: 0x{:x}",
+            orig, allocated_size, self.alloc_align_size
+        );
+
+        let data = (orig + self.red_zone_size).next_multiple_of(align);
+        assert!(data + len <= orig + allocated_size);
+
+        let offset =
+            u32::try_from(data - orig).map_err(|_| InbandFrontendError::InvalidAlignment(align))?;
+        let alloc_stack = self.capture_stack();
+        let mut header = ChunkHeader {
+            prev: 0,
+            next: self.head,
+            size: len,
+            backend_len: allocated_size,
+            offset,
+            checksum: 0,
+            alloc_stack,
+            free_stack: alloc_stack,
+            magic: CHUNK_MAGIC,
+            state: STATE_ALLOCATED,
+            reserved: 0,
+        };
+        header.seal(data);
+        unsafe { Self::write_header(data, header) };
+        if self.head != 0 {
+            unsafe { (*Self::header_mut(self.head)).prev = data };
+        }
+        self.head = data;
+
+        self.tracking
+            .alloc(data, len)
+            .map_err(|e| InbandFrontendError::TrackingError(e))?;
+        self.shadow
+            .poison(orig, data - orig, PoisonType::AsanHeapLeftRz)
+            .map_err(|e| InbandFrontendError::ShadowError(e))?;
+        self.shadow
+            .unpoison(data, len)
+            .map_err(|e| InbandFrontendError::ShadowError(e))?;
+        let poison_len = orig + allocated_size - (data + len);
+        self.shadow
+            .poison(data + len, poison_len, PoisonType::AsanHeapRightRz)
+            .map_err(|e| InbandFrontendError::ShadowError(e))?;
+
+        let buffer = unsafe { from_raw_parts_mut(data as *mut u8, len) };
+        buffer.iter_mut().for_each(|b| *b = self.malloc_fill_byte);
+        Ok(data)
+    }
+
+    fn dealloc(&mut self, addr: GuestAddr) -> Result<(), Self::Error> {
+        debug!("dealloc - addr: 0x{:x}", addr);
+        if addr == 0 {
+            return Ok(());
+        }
+
+        let mut header = self.header(addr)?;
+        if header.state == STATE_QUARANTINED {
+            Err(InbandFrontendError::DoubleFree(addr))?;
+        }
+
+        self.unlink(addr, &header);
+        header.state = STATE_QUARANTINED;
+        header.free_stack = self.capture_stack();
+        header.seal(addr);
+        unsafe { Self::write_header(addr, header) };
+
+        self.shadow
+            .poison(
+                header.backend_addr(addr),
+                header.backend_len,
+                PoisonType::AsanHeapFreed,
+            )
+            .map_err(|e| InbandFrontendError::ShadowError(e))?;
+        self.tracking
+            .dealloc(addr)
+            .map_err(|e| InbandFrontendError::TrackingError(e))?;
+        self.quarantine_used += header.backend_len;
+        self.quarantine.push_back(addr);
+        self.purge_quarantine()?;
+        Ok(())
+    }
+
+    fn get_size(&self, addr: GuestAddr) -> Result<usize, Self::Error> {
+        debug!("get_size - addr: 0x{:x}", addr);
+        let header = self.header(addr)?;
+        if header.state != STATE_ALLOCATED {
+            Err(InbandFrontendError::InvalidAddress(addr))?;
+        }
+        Ok(header.size)
+    }
+}
+
+impl<B: AllocatorBackend, S: Shadow, T: Tracking> InbandFrontend<B, S, T> {
+    /// The minimum alignment of the buffers obtained from the backend, this
+    /// is increased to the granularity of the shadow if it is larger
+    #[cfg(target_pointer_width = "32")]
+    const MIN_ALLOC_ALIGN_SIZE: usize = 8;
+
+    #[cfg(target_pointer_width = "64")]
+    const MIN_ALLOC_ALIGN_SIZE: usize = 16;
+
+    /// The size of the header stored in the left red-zone of each allocation
+    pub const HEADER_SIZE: usize = ChunkHeader::SIZE;
+
+    pub const DEFAULT_REDZONE_SIZE: usize = 128;
+    pub const DEFAULT_QUARANTINE_SIZE: usize = 50 << 20;
+    pub const DEFAULT_STACK_DEPTH: usize = 30;
+    pub const DEFAULT_MALLOC_FILL_BYTE: u8 = 0xff;
+
+    pub fn new(
+        backend: B,
+        shadow: S,
+        tracking: T,
+        red_zone_size: usize,
+        quarantine_size: usize,
+    ) -> Result<InbandFrontend<B, S, T>, InbandFrontendError<B, S, T>> {
+        let alloc_align_size = Self::MIN_ALLOC_ALIGN_SIZE.max(shadow.granularity());
+        if red_zone_size % alloc_align_size != 0 || red_zone_size < Self::HEADER_SIZE {
+            Err(InbandFrontendError::InvalidRedZoneSize(red_zone_size))?;
+        }
+        Ok(InbandFrontend::<B, S, T> {
+            backend,
+            shadow,
+            tracking,
+            red_zone_size,
+            alloc_align_size,
+            head: 0,
+            quarantine: VecDeque::new(),
+            quarantine_size,
+            quarantine_used: 0,
+            depot: StackDepot::new(),
+            stack_depth: Self::DEFAULT_STACK_DEPTH,
+            malloc_fill_byte: Self::DEFAULT_MALLOC_FILL_BYTE,
+        })
+    }
+
+    /// Create a frontend configured by the given `Options`, any which are not
+    /// specified take their default values.
+    pub fn with_options(
+        backend: B,
+        shadow: S,
+        tracking: T,
+        options: &Options,
+    ) -> Result<InbandFrontend<B, S, T>, InbandFrontendError<B, S, T>> {
+        let quarantine_size = match options.quarantine_size_mb {
+            Some(mb) => mb
+                .checked_mul(1 << 20)
+                .ok_or(InbandFrontendError::InvalidQuarantineSize(mb))?,
+            None => Self::DEFAULT_QUARANTINE_SIZE,
+        };
+        let mut frontend = Self::new(
+            backend,
+            shadow,
+            tracking,
+            options.redzone.unwrap_or(Self::DEFAULT_REDZONE_SIZE),
+            quarantine_size,
+        )?;
+        if let Some(depth) = options.malloc_context_size {
+            frontend.set_stack_depth(depth)?;
+        }
+        if let Some(fill) = options.malloc_fill_byte {
+            frontend.set_malloc_fill_byte(fill);
+        }
+        Ok(frontend)
+    }
+
+    /// Set the maximum number of frames recorded in the allocation and free
+    /// stacks (a depth of zero disables their capture).
+    pub fn set_stack_depth(
+        &mut self,
+        stack_depth: usize,
+    ) -> Result<(), InbandFrontendError<B, S, T>> {
+        if stack_depth > MAX_DEPTH {
+            Err(InbandFrontendError::InvalidStackDepth(stack_depth))?;
+        }
+        self.stack_depth = stack_depth;
+        Ok(())
+    }
+
+    /// Set the value used to fill newly allocated buffers
+    pub fn set_malloc_fill_byte(&mut self, malloc_fill_byte: u8) {
+        self.malloc_fill_byte = malloc_fill_byte;
+    }
+
+    #[inline(always)]
+    fn capture_stack(&mut self) -> StackId {
+        let mut frames = [0; MAX_DEPTH];
+        let depth = capture(&mut frames[..self.stack_depth]);
+        self.depot.insert(&frames[..depth])
+    }
+
+    /// Read and validate the header of the buffer at the given address. The
+    /// header must lie in poisoned memory (a red-zone or a freed buffer),
+    /// otherwise the address cannot be the start of one of our buffers.
+    fn header(&self, addr: GuestAddr) -> Result<ChunkHeader, InbandFrontendError<B, S, T>> {
+        if addr % self.alloc_align_size != 0 || addr < ChunkHeader::SIZE {
+            Err(InbandFrontendError::InvalidAddress(addr))?;
+        }
+        let poisoned = self
+            .shadow
+            .is_poison(ChunkHeader::addr(addr), ChunkHeader::SIZE)
+            .unwrap_or(false);
+        if !poisoned {
+            Err(InbandFrontendError::InvalidAddress(addr))?;
+        }
+        let header = unsafe { Self::read_header(addr) };
+        if header.magic != CHUNK_MAGIC {
+            Err(InbandFrontendError::InvalidAddress(addr))?;
+        }
+        if !header.is_valid(addr) {
+            Err(InbandFrontendError::CorruptHeader(addr))?;
+        }
+        Ok(header)
+    }
+
+    unsafe fn read_header(data: GuestAddr) -> ChunkHeader {
+        (ChunkHeader::addr(data) as *const ChunkHeader).read()
+    }
+
+    unsafe fn write_header(data: GuestAddr, header: ChunkHeader) {
+        (ChunkHeader::addr(data) as *mut ChunkHeader).write(header)
+    }
+
+    unsafe fn header_mut(data: GuestAddr) -> *mut ChunkHeader {
+        ChunkHeader::addr(data) as *mut ChunkHeader
+    }
+
+    /// Remove the given chunk from the list of live chunks
+    fn unlink(&mut self, data: GuestAddr, header: &ChunkHeader) {
+        if header.prev == 0 {
+            self.head = header.next;
+        } else {
+            unsafe { (*Self::header_mut(header.prev)).next = header.next };
+        }
+        if header.next != 0 {
+            unsafe { (*Self::header_mut(header.next)).prev = header.prev };
+        }
+        debug!("unlink - data: 0x{:x}", data);
+    }
+
+    /// Iterate the live chunks (most recently allocated first)
+    fn live(&self) -> impl Iterator<Item = (GuestAddr, ChunkHeader)> + '_ {
+        let mut next = self.head;
+        core::iter::from_fn(move || {
+            if next == 0 {
+                return None;
+            }
+            let data = next;
+            let header = unsafe { Self::read_header(data) };
+            next = header.next;
+            Some((data, header))
+        })
+    }
+
+    fn purge_quarantine(&mut self) -> Result<(), InbandFrontendError<B, S, T>> {
+        while self.quarantine_used > self.quarantine_size {
+            let data = self
+                .quarantine
+                .pop_front()
+                .ok_or(InbandFrontendError::QuarantineCorruption)?;
+            let mut header = unsafe { Self::read_header(data) };
+            if !header.is_valid(data) || header.state != STATE_QUARANTINED {
+                Err(InbandFrontendError::CorruptHeader(data))?;
+            }
+            /* Invalidate the header so that a stale copy is never mistaken for a live chunk */
+            header.magic = 0;
+            unsafe { Self::write_header(data, header) };
+            self.backend
+                .dealloc(
+                    header.backend_addr(data),
+                    header.backend_len,
+                    self.alloc_align_size,
+                )
+                .map_err(|e| InbandFrontendError::AllocatorError(e))?;
+            self.quarantine_used -= header.backend_len;
+        }
+        Ok(())
+    }
+
+    /// Find the allocation (either live or in the quarantine) whose backing
+    /// buffer, including its red-zones, contains the given address. Since no
+    /// index is maintained, this requires a linear search, but is only
+    /// required when reporting an error.
+    pub fn find_allocation(&self, addr: GuestAddr) -> Option<AllocationInfo> {
+        let live = self
+            .live()
+            .find(|(data, h)| h.contains(*data, addr))
+            .map(|(data, h)| h.info(data, &self.depot));
+
+        live.or_else(|| {
+            self.quarantine
+                .iter()
+                .rev()
+                .map(|data| (*data, unsafe { Self::read_header(*data) }))
+                .find(|(data, h)| h.contains(*data, addr))
+                .map(|(data, h)| h.info(data, &self.depot))
+        })
+    }
+
+    /// Find the live allocations which are not reachable from either the
+    /// given roots or the registers of the current thread.
+    ///
+    /// # Safety
+    /// The given roots must be readable
+    pub unsafe fn find_leaks(&self, roots: &[Range<GuestAddr>]) -> Vec<Leak> {
+        let chunks = self
+            .live()
+            .map(|(data, h)| Chunk {
+                addr: data,
+                len: h.size,
+                stack: h.alloc_stack,
+            })
+            .collect();
+        let mut checker = LeakChecker::new(chunks);
+        checker.scan_words(&registers());
+        roots
+            .iter()
+            .for_each(|r| checker.scan_range(r.start, r.end));
+        checker.leaks(&self.depot)
+    }
+
+    fn align_up(&self, size: usize) -> Option<usize> {
+        size.checked_next_multiple_of(self.alloc_align_size)
+    }
+
+    /// The alignment of the buffers obtained from the backend (and hence the
+    /// granularity of the red-zones)
+    pub fn alloc_align_size(&self) -> usize {
+        self.alloc_align_size
+    }
+
+    pub fn shadow(&self) -> &S {
+        &self.shadow
+    }
+
+    pub fn shadow_mut(&mut self) -> &mut S {
+        &mut self.shadow
+    }
+
+    pub fn depot(&self) -> &StackDepot {
+        &self.depot
+    }
+
+    pub fn tracking(&self) -> &T {
+        &self.tracking
+    }
+
+    pub fn tracking_mut(&mut self) -> &mut T {
+        &mut self.tracking
+    }
+
+    pub fn backend_mut(&mut self) -> &mut B {
+        &mut self.backend
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum InbandFrontendError<B: AllocatorBackend, S: Shadow, T: Tracking> {
+    #[error("Invalid red_zone_size: {0}")]
+    InvalidRedZoneSize(usize),
+    #[error("Invalid quarantine_size_mb: {0}")]
+    InvalidQuarantineSize(usize),
+    #[error("Invalid stack_depth: {0}")]
+    InvalidStackDepth(usize),
+    #[error("Invalid alignment: {0}")]
+    InvalidAlignment(usize),
+    #[error("Allocation size too big: 0x{0:x}")]
+    AllocationSizeTooBig(usize),
+    #[error("Allocator error: {0:?}")]
+    AllocatorError(B::Error),
+    #[error("Shadow error: {0:?}")]
+    ShadowError(S::Error),
+    #[error("Tracking error: {0:?}")]
+    TrackingError(T::Error),
+    #[error("Invalid address: {0:x}")]
+    InvalidAddress(GuestAddr),
+    #[error("Double free: {0:x}")]
+    DoubleFree(GuestAddr),
+    #[error("Corrupt chunk header: {0:x}")]
+    CorruptHeader(GuestAddr),
+    #[error("Quarantine corruption")]
+    QuarantineCorruption,
+}
@@ -2,9 +2,60 @@
 //! The frontend of the allocator is responsible for applying the value-added
 //! asan features on behalf of incoming user requests for allocations including
 //! red-zones, poisoning and memory tracking.
-use {crate::GuestAddr, alloc::fmt::Debug};
+use {
+    crate::GuestAddr,
+    alloc::{fmt::Debug, vec::Vec},
+};
 
+pub mod cached;
 pub mod default;
+pub mod inband;
+
+/// Describes a user allocation known to the frontend, used when reporting
+/// errors to describe the allocation which owns a faulting address. The call
+/// stacks of the allocation (and free, if the allocation has been freed) are
+/// included where they were recorded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AllocationInfo {
+    pub addr: GuestAddr,
+    pub len: usize,
+    pub freed: bool,
+    pub alloc_stack: Vec<GuestAddr>,
+    pub free_stack: Option<Vec<GuestAddr>>,
+}
+
+/// The family of functions used to make an allocation, each allocation must
+/// be released by the corresponding deallocation function
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AllocationType {
+    /// `malloc`, `calloc`, `realloc` etc., released by `free`
+    Malloc,
+    /// `operator new`, released by `operator delete`
+    New,
+    /// `operator new []`, released by `operator delete []`
+    NewArray,
+}
+
+impl AllocationType {
+    /// The name of the function which makes an allocation of this type
+    pub fn alloc_name(&self) -> &'static str {
+        match self {
+            AllocationType::Malloc => "malloc",
+            AllocationType::New => "operator new",
+            AllocationType::NewArray => "operator new []",
+        }
+    }
+
+    /// The name of the function which releases an allocation of this type
+    pub fn dealloc_name(&self) -> &'static str {
+        match self {
+            AllocationType::Malloc => "free",
+            AllocationType::New => "operator delete",
+            AllocationType::NewArray => "operator delete []",
+        }
+    }
+}
 
 pub trait AllocatorFrontend: Sized + Send {
     type Error: Debug;
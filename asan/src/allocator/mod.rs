@@ -7,3 +7,17 @@
 //!   red-zones, poisoning and memory tracking.
 pub mod backend;
 pub mod frontend;
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+static MAY_RETURN_NULL: AtomicBool = AtomicBool::new(false);
+
+/// Set whether allocation functions should return `NULL` when an allocation
+/// fails (e.g. because it is too large), rather than reporting an error
+pub fn set_allocator_may_return_null(may_return_null: bool) {
+    MAY_RETURN_NULL.store(may_return_null, Ordering::Relaxed);
+}
+
+pub fn allocator_may_return_null() -> bool {
+    MAY_RETURN_NULL.load(Ordering::Relaxed)
+}
@@ -0,0 +1,87 @@
+//! # depot
+//! The stack depot stores captured backtraces, de-duplicating identical
+//! stacks so that the (typically small) set of distinct call sites through
+//! which allocations are made is stored only once. Each stack is referred to
+//! by a compact `StackId`.
+use {
+    crate::GuestAddr,
+    alloc::{collections::BTreeMap, vec::Vec},
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StackId(u32);
+
+impl StackId {
+    /// The raw value of the identifier
+    pub fn value(self) -> u32 {
+        self.0
+    }
+}
+
+struct Stack {
+    offset: usize,
+    len: usize,
+}
+
+#[derive(Default)]
+pub struct StackDepot {
+    frames: Vec<GuestAddr>,
+    stacks: Vec<Stack>,
+    index: BTreeMap<u64, StackId>,
+}
+
+impl StackDepot {
+    pub const fn new() -> StackDepot {
+        StackDepot {
+            frames: Vec::new(),
+            stacks: Vec::new(),
+            index: BTreeMap::new(),
+        }
+    }
+
+    /// Store the given stack (if an identical one is not already present) and
+    /// return its identifier
+    pub fn insert(&mut self, frames: &[GuestAddr]) -> StackId {
+        let hash = Self::hash(frames);
+        if let Some(id) = self.index.get(&hash) {
+            if self.get(*id) == Some(frames) {
+                return *id;
+            }
+        }
+
+        let id = StackId(self.stacks.len() as u32);
+        self.stacks.push(Stack {
+            offset: self.frames.len(),
+            len: frames.len(),
+        });
+        self.frames.extend_from_slice(frames);
+        // In the (unlikely) event of a collision, the new stack is simply not indexed
+        self.index.entry(hash).or_insert(id);
+        id
+    }
+
+    /// Retrieve the frames of a previously stored stack
+    pub fn get(&self, id: StackId) -> Option<&[GuestAddr]> {
+        let stack = self.stacks.get(id.0 as usize)?;
+        self.frames.get(stack.offset..stack.offset + stack.len)
+    }
+
+    /// The number of distinct stacks stored
+    pub fn len(&self) -> usize {
+        self.stacks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stacks.is_empty()
+    }
+
+    /// FNV-1a
+    fn hash(frames: &[GuestAddr]) -> u64 {
+        frames
+            .iter()
+            .flat_map(|f| f.to_ne_bytes())
+            .fold(0xcbf29ce484222325, |h, b| {
+                (h ^ b as u64).wrapping_mul(0x100000001b3)
+            })
+    }
+}
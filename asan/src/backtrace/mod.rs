@@ -0,0 +1,166 @@
+//! # backtrace
+//! This module is responsible for capturing the call stack of the current
+//! thread so that it can be recorded (e.g. when a buffer is allocated or
+//! freed) and subsequently included in error reports.
+//!
+//! Since we cannot rely upon the availability of an unwinder (or of libc),
+//! stacks are captured by walking the chain of frame records maintained by
+//! code compiled with frame pointers. Frames are only followed while they
+//! appear sane (aligned, within the bounds of the stack and moving
+//! monotonically up it by a bounded amount), so code compiled without frame
+//! pointers results in a truncated (rather than invalid) backtrace.
+//!
+//! The top of the stack is taken from the mapping which contains it (see
+//! `asan_stack_top`), this is remembered for each thread so that the memory
+//! map needn't be read each time a stack is captured.
+//!
+//! Captured stacks are stored in a `StackDepot` which de-duplicates them so
+//! that each allocation need only record a compact `StackId`.
+use {
+    crate::{asan_stack_top, thread::thread_pointer, GuestAddr},
+    alloc::vec::Vec,
+    spin::Mutex,
+};
+
+pub mod depot;
+
+/// The maximum number of frames which may be captured in a single backtrace
+pub const MAX_DEPTH: usize = 64;
+
+/// The maximum distance between two consecutive frame records for the chain
+/// to be considered valid
+const MAX_FRAME_SIZE: GuestAddr = 0x10000;
+
+/// The number of threads whose stack tops are remembered
+const NUM_STACK_TOPS: usize = 64;
+
+/// The thread pointer and stack top of recently seen threads, indexed by a
+/// hash of the thread pointer. A thread whose entry is displaced by another
+/// must read the memory map again.
+static STACK_TOPS: Mutex<[(GuestAddr, GuestAddr); NUM_STACK_TOPS]> =
+    Mutex::new([(0, 0); NUM_STACK_TOPS]);
+
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+fn frame_address() -> GuestAddr {
+    let fp: GuestAddr;
+    unsafe { core::arch::asm!("mov {}, rbp", out(reg) fp) };
+    fp
+}
+
+#[cfg(target_arch = "x86")]
+#[inline(always)]
+fn frame_address() -> GuestAddr {
+    let fp: GuestAddr;
+    unsafe { core::arch::asm!("mov {}, ebp", out(reg) fp) };
+    fp
+}
+
+#[cfg(target_arch = "aarch64")]
+#[inline(always)]
+fn frame_address() -> GuestAddr {
+    let fp: GuestAddr;
+    unsafe { core::arch::asm!("mov {}, x29", out(reg) fp) };
+    fp
+}
+
+#[cfg(target_arch = "arm")]
+#[inline(always)]
+fn frame_address() -> GuestAddr {
+    let fp: GuestAddr;
+    unsafe { core::arch::asm!("mov {}, r11", out(reg) fp) };
+    fp
+}
+
+/// On PowerPC, we walk the back-chain of stack frames starting with the
+/// current stack pointer.
+#[cfg(target_arch = "powerpc")]
+#[inline(always)]
+fn frame_address() -> GuestAddr {
+    let fp: GuestAddr;
+    unsafe { core::arch::asm!("mr {}, 1", out(reg) fp) };
+    fp
+}
+
+/// Read the frame record at the given address returning the address of the
+/// next frame record and the return address.
+#[cfg(not(target_arch = "powerpc"))]
+unsafe fn read_frame(fp: GuestAddr) -> (GuestAddr, GuestAddr) {
+    let record = fp as *const GuestAddr;
+    (record.read(), record.add(1).read())
+}
+
+/// On PowerPC, the return address is saved by the callee in the LR save word
+/// of the caller's frame.
+#[cfg(target_arch = "powerpc")]
+unsafe fn read_frame(fp: GuestAddr) -> (GuestAddr, GuestAddr) {
+    let next = (fp as *const GuestAddr).read();
+    if next <= fp || next - fp > MAX_FRAME_SIZE {
+        return (next, 0);
+    }
+    (next, (next as *const GuestAddr).add(1).read())
+}
+
+/// Capture the return addresses of the callers of the current function into
+/// `frames`, returning the number of frames captured. The number of frames
+/// captured is limited by the length of `frames`.
+#[inline(never)]
+pub fn capture(frames: &mut [GuestAddr]) -> usize {
+    if frames.is_empty() {
+        return 0;
+    }
+    let marker = 0u8;
+    let sp = &marker as *const u8 as GuestAddr;
+    let mut fp = frame_address();
+    if fp < sp || fp - sp > MAX_FRAME_SIZE {
+        return 0;
+    }
+
+    /* Each frame record must lie wholly between the stack pointer and the top of the stack */
+    let top = stack_top(sp);
+    let record_size = 2 * size_of::<GuestAddr>();
+    let in_bounds = |fp: GuestAddr| fp >= sp && fp < top && top - fp >= record_size;
+
+    let mut depth = 0;
+    while depth < frames.len() {
+        if fp % size_of::<GuestAddr>() != 0 || !in_bounds(fp) {
+            break;
+        }
+        let (next, ret) = unsafe { read_frame(fp) };
+        if ret == 0 {
+            break;
+        }
+        frames[depth] = ret;
+        depth += 1;
+        if next <= fp || next - fp > MAX_FRAME_SIZE {
+            break;
+        }
+        fp = next;
+    }
+    depth
+}
+
+/// The top of the stack of the current thread (or zero if it can't be
+/// found), given the current stack pointer
+fn stack_top(sp: GuestAddr) -> GuestAddr {
+    let tp = thread_pointer();
+    let idx = (tp >> 12) % NUM_STACK_TOPS;
+    match STACK_TOPS.lock()[idx] {
+        (owner, top) if owner == tp && sp < top => return top,
+        _ => (),
+    }
+    let top = unsafe { asan_stack_top(sp) };
+    STACK_TOPS.lock()[idx] = (tp, top);
+    top
+}
+
+/// Capture the call stack of the current function, omitting the given number
+/// of innermost frames. Since this function is always inlined, the first
+/// frame lies within the current function and hence a `skip` of one yields a
+/// stack starting at its caller.
+#[inline(always)]
+pub fn stack(skip: usize) -> Vec<GuestAddr> {
+    let mut frames = [0; MAX_DEPTH];
+    let depth = capture(&mut frames);
+    frames[..depth].iter().skip(skip).copied().collect()
+}
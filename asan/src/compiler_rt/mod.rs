@@ -0,0 +1,649 @@
+//! # compiler_rt
+//! This module provides the entry points used by code instrumented by the
+//! compiler (e.g. `-fsanitize=address`) so that such binaries can be linked
+//! against the runtime in place of compiler-rt. Instrumented code performs
+//! most of its checks inline by reading the shadow map directly and so these
+//! functions are only suitable for variants whose shadow map lives in the
+//! guest and whose layout matches that assumed by the compiler.
+//!
+//! The following functions are provided:
+//! - `__asan_load{1,2,4,8,16,N}` / `__asan_store{1,2,4,8,16,N}` - Outlined
+//!   checks used in place of inline checks (e.g. for large functions)
+//! - `__asan_report_{load,store}{1,2,4,8,16,_n}` - Called by inline checks
+//!   once an access has been found to be poisoned
+//! - `__asan_poison_memory_region` / `__asan_unpoison_memory_region` - Manual
+//!   poisoning by the application
+//! - `__asan_region_is_poisoned` / `__asan_address_is_poisoned` - Queries of
+//!   the shadow map by the application
+//! - `__asan_register_globals` / `__asan_unregister_globals` - Called by the
+//!   constructor (and destructor) of each instrumented module to describe its
+//!   global variables so that their red-zones can be poisoned
+//! - `__asan_register_elf_globals` / `__asan_unregister_elf_globals` - As
+//!   above, but for modules whose globals are described by the entries of
+//!   their `asan_globals` section
+//! - `__asan_stack_malloc_{0..10}` / `__asan_stack_free_{0..10}` - Allocate
+//!   (and release) fake stack frames to detect stack-use-after-return (see
+//!   `fake_stack`), only when `__asan_option_detect_stack_use_after_return`
+//!   is set (unless the `_always` form is used)
+//! - `__asan_alloca_poison` / `__asan_allocas_unpoison` - Poison the
+//!   red-zones of dynamic allocas
+//! - `__asan_set_shadow_{00,f1,f2,f3,f5,f8}` - Fill the shadow of stack
+//!   frames for large frames (rather than doing so inline)
+//! - `__asan_poison_stack_memory` / `__asan_unpoison_stack_memory` - Mark
+//!   variables as going out of (or into) scope
+//! - `__asan_handle_no_return` - Unpoison the stack before calling a function
+//!   which doesn't return (e.g. `longjmp` or `__cxa_throw`)
+//! - `__asan_init` - Called by the constructor of each instrumented module
+//!
+//! Each of the checks and reports is also provided in a `_noabort` form used
+//! by code compiled with `-fsanitize-recover=address`. The other forms are
+//! considered fatal by the compiler (it assumes that they don't return) and
+//! so terminate the process once the error is reported even if the runtime
+//! is configured to recover from errors.
+use {
+    crate::{
+        asan_fake_stack_alloc, asan_fake_stack_dealloc, asan_find_poison, asan_init,
+        asan_is_poison, asan_load, asan_page_size, asan_poison, asan_shadow_granularity,
+        asan_stack_top, asan_store, asan_unpoison,
+        fake_stack::frame_size,
+        globals::{register_global, unregister_global, Global},
+        report::die,
+        shadow::PoisonType,
+        GuestAddr,
+    },
+    alloc::string::{String, ToString},
+    core::{
+        ffi::{c_char, c_int, c_void, CStr},
+        ptr::{null_mut, write_bytes},
+        slice,
+        sync::atomic::{AtomicI32, Ordering},
+    },
+    log::{trace, warn},
+};
+
+/// The largest region of the stack which `__asan_handle_no_return` will
+/// unpoison, anything larger suggests that the stack has been switched (e.g.
+/// by `swapcontext`)
+const MAX_NO_RETURN_CLEANUP: usize = 64 << 20;
+
+macro_rules! check_functions {
+    ($($size:literal => $load:ident, $store:ident, $load_noabort:ident, $store_noabort:ident;)*) => {
+        $(
+            /// # Safety
+            /// Called by instrumented code
+            #[no_mangle]
+            pub unsafe extern "C" fn $load(addr: *const c_void) {
+                __asan_loadN(addr, $size);
+            }
+
+            /// # Safety
+            /// Called by instrumented code
+            #[no_mangle]
+            pub unsafe extern "C" fn $store(addr: *const c_void) {
+                __asan_storeN(addr, $size);
+            }
+
+            /// # Safety
+            /// Called by instrumented code
+            #[no_mangle]
+            pub unsafe extern "C" fn $load_noabort(addr: *const c_void) {
+                asan_load(addr, $size);
+            }
+
+            /// # Safety
+            /// Called by instrumented code
+            #[no_mangle]
+            pub unsafe extern "C" fn $store_noabort(addr: *const c_void) {
+                asan_store(addr, $size);
+            }
+        )*
+    };
+}
+
+check_functions! {
+    1 => __asan_load1, __asan_store1, __asan_load1_noabort, __asan_store1_noabort;
+    2 => __asan_load2, __asan_store2, __asan_load2_noabort, __asan_store2_noabort;
+    4 => __asan_load4, __asan_store4, __asan_load4_noabort, __asan_store4_noabort;
+    8 => __asan_load8, __asan_store8, __asan_load8_noabort, __asan_store8_noabort;
+    16 => __asan_load16, __asan_store16, __asan_load16_noabort, __asan_store16_noabort;
+}
+
+/// # Safety
+/// Called by instrumented code
+#[no_mangle]
+#[allow(non_snake_case)]
+pub unsafe extern "C" fn __asan_loadN(addr: *const c_void, size: usize) {
+    if asan_is_poison(addr, size) {
+        __asan_report_load_n(addr, size);
+    }
+}
+
+/// # Safety
+/// Called by instrumented code
+#[no_mangle]
+#[allow(non_snake_case)]
+pub unsafe extern "C" fn __asan_storeN(addr: *const c_void, size: usize) {
+    if asan_is_poison(addr, size) {
+        __asan_report_store_n(addr, size);
+    }
+}
+
+/// # Safety
+/// Called by instrumented code
+#[no_mangle]
+#[allow(non_snake_case)]
+pub unsafe extern "C" fn __asan_loadN_noabort(addr: *const c_void, size: usize) {
+    asan_load(addr, size);
+}
+
+/// # Safety
+/// Called by instrumented code
+#[no_mangle]
+#[allow(non_snake_case)]
+pub unsafe extern "C" fn __asan_storeN_noabort(addr: *const c_void, size: usize) {
+    asan_store(addr, size);
+}
+
+macro_rules! report_functions {
+    ($($size:literal => $load:ident, $store:ident, $load_noabort:ident, $store_noabort:ident;)*) => {
+        $(
+            /// # Safety
+            /// Called by instrumented code
+            #[no_mangle]
+            pub unsafe extern "C" fn $load(addr: *const c_void) -> ! {
+                __asan_report_load_n(addr, $size);
+            }
+
+            /// # Safety
+            /// Called by instrumented code
+            #[no_mangle]
+            pub unsafe extern "C" fn $store(addr: *const c_void) -> ! {
+                __asan_report_store_n(addr, $size);
+            }
+
+            /// # Safety
+            /// Called by instrumented code
+            #[no_mangle]
+            pub unsafe extern "C" fn $load_noabort(addr: *const c_void) {
+                asan_load(addr, $size);
+            }
+
+            /// # Safety
+            /// Called by instrumented code
+            #[no_mangle]
+            pub unsafe extern "C" fn $store_noabort(addr: *const c_void) {
+                asan_store(addr, $size);
+            }
+        )*
+    };
+}
+
+report_functions! {
+    1 => __asan_report_load1, __asan_report_store1,
+        __asan_report_load1_noabort, __asan_report_store1_noabort;
+    2 => __asan_report_load2, __asan_report_store2,
+        __asan_report_load2_noabort, __asan_report_store2_noabort;
+    4 => __asan_report_load4, __asan_report_store4,
+        __asan_report_load4_noabort, __asan_report_store4_noabort;
+    8 => __asan_report_load8, __asan_report_store8,
+        __asan_report_load8_noabort, __asan_report_store8_noabort;
+    16 => __asan_report_load16, __asan_report_store16,
+        __asan_report_load16_noabort, __asan_report_store16_noabort;
+}
+
+/// # Safety
+/// Called by instrumented code
+#[no_mangle]
+pub unsafe extern "C" fn __asan_report_load_n(addr: *const c_void, size: usize) -> ! {
+    asan_load(addr, size);
+    die();
+}
+
+/// # Safety
+/// Called by instrumented code
+#[no_mangle]
+pub unsafe extern "C" fn __asan_report_store_n(addr: *const c_void, size: usize) -> ! {
+    asan_store(addr, size);
+    die();
+}
+
+/// # Safety
+/// Called by instrumented code
+#[no_mangle]
+pub unsafe extern "C" fn __asan_report_load_n_noabort(addr: *const c_void, size: usize) {
+    asan_load(addr, size);
+}
+
+/// # Safety
+/// Called by instrumented code
+#[no_mangle]
+pub unsafe extern "C" fn __asan_report_store_n_noabort(addr: *const c_void, size: usize) {
+    asan_store(addr, size);
+}
+
+/// Poison the given region. Since a shadow byte can only describe a granule
+/// whose leading bytes are addressable, any bytes at the unaligned end of the
+/// region are left unpoisoned (as with compiler-rt).
+///
+/// # Safety
+/// Called by the application
+#[no_mangle]
+pub unsafe extern "C" fn __asan_poison_memory_region(addr: *const c_void, size: usize) {
+    trace!("poison_memory_region - addr: {:p}, size: {:#x}", addr, size);
+    let start = addr as GuestAddr;
+    let end = align_down(start.saturating_add(size));
+    if end > start {
+        asan_poison(addr as *mut c_void, end - start, PoisonType::AsanUser);
+    }
+}
+
+/// Unpoison the given region. Since a shadow byte can only describe a granule
+/// whose leading bytes are addressable, any bytes at the unaligned start of
+/// the region are also unpoisoned (as with compiler-rt).
+///
+/// # Safety
+/// Called by the application
+#[no_mangle]
+pub unsafe extern "C" fn __asan_unpoison_memory_region(addr: *const c_void, size: usize) {
+    trace!(
+        "unpoison_memory_region - addr: {:p}, size: {:#x}",
+        addr,
+        size
+    );
+    let start = align_down(addr as GuestAddr);
+    let end = (addr as GuestAddr).saturating_add(size);
+    if end > start {
+        asan_unpoison(start as *mut c_void, end - start);
+    }
+}
+
+/// Return the address of the first poisoned byte in the given region, or
+/// null if the whole region is addressable
+///
+/// # Safety
+/// Called by the application
+#[no_mangle]
+pub unsafe extern "C" fn __asan_region_is_poisoned(addr: *mut c_void, size: usize) -> *mut c_void {
+    trace!("region_is_poisoned - addr: {:p}, size: {:#x}", addr, size);
+    if size == 0 {
+        return null_mut();
+    }
+    asan_find_poison(addr, size)
+}
+
+/// # Safety
+/// Called by the application
+#[no_mangle]
+pub unsafe extern "C" fn __asan_address_is_poisoned(addr: *const c_void) -> c_int {
+    trace!("address_is_poisoned - addr: {:p}", addr);
+    asan_is_poison(addr, 1) as c_int
+}
+
+/// Initialize the runtime (and hence map the shadow) before any instrumented
+/// code is run
+///
+/// # Safety
+/// Called by the constructor of each instrumented module
+#[no_mangle]
+pub unsafe extern "C" fn __asan_init() {
+    trace!("init");
+    asan_init();
+}
+
+/// Called by the constructor of each instrumented module, linking fails if
+/// the module was instrumented for a different version of the ABI
+///
+/// # Safety
+/// Called by the constructor of each instrumented module
+#[no_mangle]
+pub unsafe extern "C" fn __asan_version_mismatch_check_v8() {}
+
+/// Called before calls to functions which don't return (e.g. `longjmp`).
+/// Since the frames being unwound won't unpoison their own red-zones, the
+/// stack of the current thread is unpoisoned from the current stack pointer
+/// (less a page, as with compiler-rt) to its top.
+///
+/// # Safety
+/// Called by instrumented code
+#[no_mangle]
+pub unsafe extern "C" fn __asan_handle_no_return() {
+    let local = 0u8;
+    let sp = &local as *const u8 as GuestAddr;
+    let page_size = asan_page_size();
+    let bottom = sp.saturating_sub(page_size) & !(page_size - 1);
+    let top = asan_stack_top(sp);
+    trace!(
+        "handle_no_return - bottom: 0x{:x}, top: 0x{:x}",
+        bottom,
+        top
+    );
+    if top <= bottom {
+        return;
+    }
+    if top - bottom > MAX_NO_RETURN_CLEANUP {
+        warn!(
+            "handle_no_return - stack too large, skipping: 0x{:x}-0x{:x}",
+            bottom, top
+        );
+        return;
+    }
+    asan_unpoison(bottom as *mut c_void, top - bottom);
+}
+
+/// The description of an instrumented global emitted by the compiler (see
+/// `__asan_global` in compiler-rt)
+#[repr(C)]
+#[derive(Debug)]
+pub struct AsanGlobal {
+    pub beg: GuestAddr,
+    pub size: usize,
+    pub size_with_redzone: usize,
+    pub name: *const c_char,
+    pub module_name: *const c_char,
+    pub has_dynamic_init: usize,
+    pub location: *const c_void,
+    pub odr_indicator: GuestAddr,
+}
+
+impl AsanGlobal {
+    unsafe fn to_global(&self) -> Global {
+        Global {
+            addr: self.beg,
+            size: self.size,
+            size_with_redzone: self.size_with_redzone,
+            name: c_string(self.name),
+            module: c_string(self.module_name),
+        }
+    }
+}
+
+unsafe fn c_string(s: *const c_char) -> String {
+    if s.is_null() {
+        return String::new();
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .unwrap_or("<invalid>")
+        .to_string()
+}
+
+/// Poison the red-zones of the given globals and record them so that they
+/// can be described in reports
+///
+/// # Safety
+/// Called by the constructor of each instrumented module
+#[no_mangle]
+pub unsafe extern "C" fn __asan_register_globals(globals: *const AsanGlobal, n: usize) {
+    trace!("register_globals - globals: {:p}, n: {:#x}", globals, n);
+    if globals.is_null() {
+        return;
+    }
+    for g in slice::from_raw_parts(globals, n) {
+        trace!(
+            "register_global - beg: 0x{:x}, size: {:#x}, size_with_redzone: {:#x}",
+            g.beg,
+            g.size,
+            g.size_with_redzone
+        );
+        if g.size_with_redzone > g.size {
+            asan_poison(
+                (g.beg + g.size) as *mut c_void,
+                g.size_with_redzone - g.size,
+                PoisonType::AsanGlobalRz,
+            );
+        }
+        register_global(g.to_global());
+    }
+}
+
+/// # Safety
+/// Called by the destructor of each instrumented module
+#[no_mangle]
+pub unsafe extern "C" fn __asan_unregister_globals(globals: *const AsanGlobal, n: usize) {
+    trace!("unregister_globals - globals: {:p}, n: {:#x}", globals, n);
+    if globals.is_null() {
+        return;
+    }
+    for g in slice::from_raw_parts(globals, n) {
+        if unregister_global(g.beg).is_some() {
+            asan_unpoison(g.beg as *mut c_void, g.size_with_redzone);
+        }
+    }
+}
+
+/// Register the globals described by the entries of a module's
+/// `asan_globals` section (bounded by `start` and `stop`). The flag is used
+/// to ensure that the globals of each module are only registered once.
+///
+/// # Safety
+/// Called by the constructor of each instrumented module
+#[no_mangle]
+pub unsafe extern "C" fn __asan_register_elf_globals(
+    flag: *mut usize,
+    start: *const AsanGlobal,
+    stop: *const AsanGlobal,
+) {
+    trace!(
+        "register_elf_globals - flag: {:p}, start: {:p}, stop: {:p}",
+        flag,
+        start,
+        stop
+    );
+    if *flag != 0 || start == stop {
+        return;
+    }
+    *flag = 1;
+    __asan_register_globals(start, stop.offset_from(start) as usize);
+}
+
+/// # Safety
+/// Called by the destructor of each instrumented module
+#[no_mangle]
+pub unsafe extern "C" fn __asan_unregister_elf_globals(
+    flag: *mut usize,
+    start: *const AsanGlobal,
+    stop: *const AsanGlobal,
+) {
+    trace!(
+        "unregister_elf_globals - flag: {:p}, start: {:p}, stop: {:p}",
+        flag,
+        start,
+        stop
+    );
+    if *flag == 0 || start == stop {
+        return;
+    }
+    *flag = 0;
+    __asan_unregister_globals(start, stop.offset_from(start) as usize);
+}
+
+/// Read by instrumented code to determine whether to allocate its frames
+/// from the fake stack
+#[no_mangle]
+pub static __asan_option_detect_stack_use_after_return: AtomicI32 = AtomicI32::new(0);
+
+pub fn set_detect_stack_use_after_return(detect: bool) {
+    __asan_option_detect_stack_use_after_return.store(detect as i32, Ordering::Relaxed);
+}
+
+pub fn detect_stack_use_after_return() -> bool {
+    __asan_option_detect_stack_use_after_return.load(Ordering::Relaxed) != 0
+}
+
+/// The size of the red-zones either side of a dynamic alloca
+pub const ALLOCA_REDZONE_SIZE: usize = 32;
+
+#[inline(never)]
+unsafe fn stack_malloc(class: usize) -> GuestAddr {
+    let marker = 0u8;
+    let real = &marker as *const u8 as GuestAddr;
+    let frame = asan_fake_stack_alloc(class, real);
+    if !frame.is_null() {
+        asan_unpoison(frame, frame_size(class));
+    }
+    frame as GuestAddr
+}
+
+unsafe fn stack_free(class: usize, addr: GuestAddr) {
+    asan_fake_stack_dealloc(addr as *mut c_void, class);
+    asan_poison(
+        addr as *mut c_void,
+        frame_size(class),
+        PoisonType::AsanStackFreed,
+    );
+}
+
+macro_rules! stack_functions {
+    ($($class:literal => $malloc:ident, $malloc_always:ident, $free:ident;)*) => {
+        $(
+            /// # Safety
+            /// Called by instrumented code
+            #[no_mangle]
+            pub unsafe extern "C" fn $malloc(size: usize) -> GuestAddr {
+                trace!("stack_malloc - class: {}, size: {:#x}", $class, size);
+                if detect_stack_use_after_return() {
+                    stack_malloc($class)
+                } else {
+                    0
+                }
+            }
+
+            /// # Safety
+            /// Called by instrumented code
+            #[no_mangle]
+            pub unsafe extern "C" fn $malloc_always(size: usize) -> GuestAddr {
+                trace!("stack_malloc_always - class: {}, size: {:#x}", $class, size);
+                stack_malloc($class)
+            }
+
+            /// # Safety
+            /// Called by instrumented code
+            #[no_mangle]
+            pub unsafe extern "C" fn $free(addr: GuestAddr, size: usize) {
+                trace!("stack_free - class: {}, addr: 0x{:x}, size: {:#x}", $class, addr, size);
+                stack_free($class, addr);
+            }
+        )*
+    };
+}
+
+stack_functions! {
+    0 => __asan_stack_malloc_0, __asan_stack_malloc_always_0, __asan_stack_free_0;
+    1 => __asan_stack_malloc_1, __asan_stack_malloc_always_1, __asan_stack_free_1;
+    2 => __asan_stack_malloc_2, __asan_stack_malloc_always_2, __asan_stack_free_2;
+    3 => __asan_stack_malloc_3, __asan_stack_malloc_always_3, __asan_stack_free_3;
+    4 => __asan_stack_malloc_4, __asan_stack_malloc_always_4, __asan_stack_free_4;
+    5 => __asan_stack_malloc_5, __asan_stack_malloc_always_5, __asan_stack_free_5;
+    6 => __asan_stack_malloc_6, __asan_stack_malloc_always_6, __asan_stack_free_6;
+    7 => __asan_stack_malloc_7, __asan_stack_malloc_always_7, __asan_stack_free_7;
+    8 => __asan_stack_malloc_8, __asan_stack_malloc_always_8, __asan_stack_free_8;
+    9 => __asan_stack_malloc_9, __asan_stack_malloc_always_9, __asan_stack_free_9;
+    10 => __asan_stack_malloc_10, __asan_stack_malloc_always_10, __asan_stack_free_10;
+}
+
+/// Poison the red-zones either side of a dynamic alloca of the given size
+/// (the left red-zone precedes `addr` and the right red-zone follows the
+/// alloca, rounded up to the red-zone size)
+///
+/// # Safety
+/// Called by instrumented code
+#[no_mangle]
+pub unsafe extern "C" fn __asan_alloca_poison(addr: GuestAddr, size: usize) {
+    trace!("alloca_poison - addr: 0x{:x}, size: {:#x}", addr, size);
+    asan_poison(
+        (addr - ALLOCA_REDZONE_SIZE) as *mut c_void,
+        ALLOCA_REDZONE_SIZE,
+        PoisonType::AsanAllocaLeftRz,
+    );
+    let end = addr + size;
+    let right = end.next_multiple_of(ALLOCA_REDZONE_SIZE) + ALLOCA_REDZONE_SIZE;
+    asan_poison(
+        end as *mut c_void,
+        right - end,
+        PoisonType::AsanAllocaRightRz,
+    );
+}
+
+/// Unpoison the dynamic allocas between `top` and `bottom` when they are
+/// released (e.g. on `stackrestore`)
+///
+/// # Safety
+/// Called by instrumented code
+#[no_mangle]
+pub unsafe extern "C" fn __asan_allocas_unpoison(top: GuestAddr, bottom: GuestAddr) {
+    trace!(
+        "allocas_unpoison - top: 0x{:x}, bottom: 0x{:x}",
+        top,
+        bottom
+    );
+    if top == 0 || top > bottom {
+        return;
+    }
+    asan_unpoison(top as *mut c_void, bottom - top);
+}
+
+macro_rules! set_shadow_functions {
+    ($($value:literal => $name:ident;)*) => {
+        $(
+            /// Fill the given range of the shadow map
+            ///
+            /// # Safety
+            /// Called by instrumented code with the address of the shadow
+            #[no_mangle]
+            pub unsafe extern "C" fn $name(addr: GuestAddr, size: usize) {
+                write_bytes(addr as *mut u8, $value, size);
+            }
+        )*
+    };
+}
+
+set_shadow_functions! {
+    0x00 => __asan_set_shadow_00;
+    0xf1 => __asan_set_shadow_f1;
+    0xf2 => __asan_set_shadow_f2;
+    0xf3 => __asan_set_shadow_f3;
+    0xf5 => __asan_set_shadow_f5;
+    0xf8 => __asan_set_shadow_f8;
+}
+
+/// Mark the given variable as having gone out of scope
+///
+/// # Safety
+/// Called by instrumented code
+#[no_mangle]
+pub unsafe extern "C" fn __asan_poison_stack_memory(addr: GuestAddr, size: usize) {
+    trace!(
+        "poison_stack_memory - addr: 0x{:x}, size: {:#x}",
+        addr,
+        size
+    );
+    let end = align_down(addr.saturating_add(size));
+    if end > addr {
+        asan_poison(
+            addr as *mut c_void,
+            end - addr,
+            PoisonType::AsanStackOoscope,
+        );
+    }
+}
+
+/// Mark the given variable as having come into scope
+///
+/// # Safety
+/// Called by instrumented code
+#[no_mangle]
+pub unsafe extern "C" fn __asan_unpoison_stack_memory(addr: GuestAddr, size: usize) {
+    trace!(
+        "unpoison_stack_memory - addr: 0x{:x}, size: {:#x}",
+        addr,
+        size
+    );
+    __asan_unpoison_memory_region(addr as *const c_void, size);
+}
+
+/// Align the address down to the granularity of the shadow map (that is the
+/// number of bytes of memory described by each shadow byte, which must match
+/// that assumed by the compiler)
+fn align_down(addr: GuestAddr) -> GuestAddr {
+    addr & !(unsafe { asan_shadow_granularity() } - 1)
+}
@@ -0,0 +1,107 @@
+//! # libc
+//! This implementation makes use of the `getenv` and `getauxval` functions
+//! provided by `libc`.
+use {
+    crate::{
+        asan_swap,
+        env::{Env, AT_PAGESZ},
+        symbols::{
+            AtomicGuestAddr, Function, FunctionPointer, FunctionPointerError, Symbols,
+            SymbolsLookupStr,
+        },
+    },
+    alloc::string::String,
+    core::{
+        ffi::{c_char, c_ulong, CStr},
+        marker::PhantomData,
+    },
+    thiserror::Error,
+};
+
+#[derive(Debug)]
+struct FunctionGetenv;
+
+impl Function for FunctionGetenv {
+    type Func = unsafe extern "C" fn(*const c_char) -> *const c_char;
+    const NAME: &'static CStr = c"getenv";
+}
+
+#[derive(Debug)]
+struct FunctionGetauxval;
+
+impl Function for FunctionGetauxval {
+    type Func = unsafe extern "C" fn(c_ulong) -> c_ulong;
+    const NAME: &'static CStr = c"getauxval";
+}
+
+static GETENV_ADDR: AtomicGuestAddr = AtomicGuestAddr::new();
+static GETAUXVAL_ADDR: AtomicGuestAddr = AtomicGuestAddr::new();
+
+#[derive(Debug)]
+pub struct LibcEnv<S: Symbols> {
+    _phantom: PhantomData<S>,
+}
+
+impl<S: Symbols> LibcEnv<S> {
+    fn get_getenv() -> Result<<FunctionGetenv as Function>::Func, LibcEnvError<S>> {
+        let addr = GETENV_ADDR.try_get_or_insert_with(|| {
+            S::lookup_str(FunctionGetenv::NAME).map_err(|e| LibcEnvError::FailedToFindSymbol(e))
+        })?;
+        let f = FunctionGetenv::as_ptr(addr).map_err(|e| LibcEnvError::InvalidPointerType(e))?;
+        Ok(f)
+    }
+
+    fn get_getauxval() -> Result<<FunctionGetauxval as Function>::Func, LibcEnvError<S>> {
+        let addr = GETAUXVAL_ADDR.try_get_or_insert_with(|| {
+            S::lookup_str(FunctionGetauxval::NAME).map_err(|e| LibcEnvError::FailedToFindSymbol(e))
+        })?;
+        let f = FunctionGetauxval::as_ptr(addr).map_err(|e| LibcEnvError::InvalidPointerType(e))?;
+        Ok(f)
+    }
+}
+
+impl<S: Symbols> Env for LibcEnv<S> {
+    type Error = LibcEnvError<S>;
+
+    fn get(name: &str) -> Result<Option<String>, Self::Error> {
+        let fn_getenv = Self::get_getenv()?;
+        let mut buf = [0 as c_char; 256];
+        if name.len() >= buf.len() {
+            return Err(LibcEnvError::NameTooLong(name.len()));
+        }
+        buf.iter_mut()
+            .zip(name.bytes())
+            .for_each(|(d, s)| *d = s as c_char);
+        unsafe { asan_swap(false) };
+        let value = unsafe { fn_getenv(buf.as_ptr()) };
+        unsafe { asan_swap(true) };
+        if value.is_null() {
+            return Ok(None);
+        }
+        let value = unsafe { CStr::from_ptr(value) };
+        Ok(Some(String::from_utf8_lossy(value.to_bytes()).into_owned()))
+    }
+
+    fn page_size() -> Result<usize, Self::Error> {
+        let fn_getauxval = Self::get_getauxval()?;
+        unsafe { asan_swap(false) };
+        let page_size = unsafe { fn_getauxval(AT_PAGESZ as c_ulong) };
+        unsafe { asan_swap(true) };
+        if page_size == 0 {
+            return Err(LibcEnvError::MissingAuxValue(AT_PAGESZ));
+        }
+        Ok(page_size as usize)
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum LibcEnvError<S: Symbols> {
+    #[error("Failed to find environment functions")]
+    FailedToFindSymbol(S::Error),
+    #[error("Invalid pointer type: {0:?}")]
+    InvalidPointerType(FunctionPointerError),
+    #[error("Variable name too long: {0}")]
+    NameTooLong(usize),
+    #[error("Missing auxiliary vector entry: {0}")]
+    MissingAuxValue(usize),
+}
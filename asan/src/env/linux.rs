@@ -0,0 +1,75 @@
+//! # linux
+//! This implementation reads the environment and auxiliary vector of the
+//! process from `/proc/self/environ` and `/proc/self/auxv` respectively and
+//! hence has no dependency on `libc`.
+use {
+    crate::env::{Env, AT_PAGESZ},
+    alloc::{string::String, vec::Vec},
+    core::ffi::CStr,
+    rustix::{
+        fs::{open, Mode, OFlags},
+        io::{read, Errno},
+    },
+    thiserror::Error,
+};
+
+const BUFFER_SIZE: usize = 4096;
+
+#[derive(Debug)]
+pub struct LinuxEnv;
+
+impl LinuxEnv {
+    fn read_file(path: &CStr) -> Result<Vec<u8>, LinuxEnvError> {
+        let fd = open(path, OFlags::RDONLY, Mode::empty()).map_err(LinuxEnvError::FailedToOpen)?;
+        let mut contents = Vec::new();
+        let mut buffer = [0u8; BUFFER_SIZE];
+        loop {
+            let len = read(&fd, &mut buffer).map_err(LinuxEnvError::FailedToRead)?;
+            if len == 0 {
+                break;
+            }
+            contents.extend_from_slice(&buffer[..len]);
+        }
+        Ok(contents)
+    }
+}
+
+impl Env for LinuxEnv {
+    type Error = LinuxEnvError;
+
+    fn get(name: &str) -> Result<Option<String>, Self::Error> {
+        let environ = Self::read_file(c"/proc/self/environ")?;
+        let value = environ
+            .split(|c| *c == 0)
+            .filter_map(|entry| entry.strip_prefix(name.as_bytes()))
+            .find_map(|rest| rest.strip_prefix(b"="))
+            .map(|value| String::from_utf8_lossy(value).into_owned());
+        Ok(value)
+    }
+
+    fn page_size() -> Result<usize, Self::Error> {
+        let auxv = Self::read_file(c"/proc/self/auxv")?;
+        let word = size_of::<usize>();
+        auxv.chunks_exact(word * 2)
+            .map(|entry| {
+                let (key, value) = entry.split_at(word);
+                (
+                    usize::from_ne_bytes(key.try_into().unwrap()),
+                    usize::from_ne_bytes(value.try_into().unwrap()),
+                )
+            })
+            .find(|(key, _)| *key == AT_PAGESZ)
+            .map(|(_, value)| value)
+            .ok_or(LinuxEnvError::MissingAuxValue(AT_PAGESZ))
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum LinuxEnvError {
+    #[error("Failed to open - errno: {0}")]
+    FailedToOpen(Errno),
+    #[error("Failed to read - errno: {0}")]
+    FailedToRead(Errno),
+    #[error("Missing auxiliary vector entry: {0}")]
+    MissingAuxValue(usize),
+}
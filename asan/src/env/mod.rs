@@ -0,0 +1,24 @@
+//! # env
+//! This module provides access to the environment of the process, that is the
+//! environment variables and the auxiliary vector supplied by the kernel.
+//! These are used to configure the runtime (see the `options` module). The
+//! ability to substitute this functionality allows its use in environments
+//! where `libc` is not available.
+use {alloc::string::String, core::fmt::Debug};
+
+#[cfg(feature = "libc")]
+pub mod libc;
+
+#[cfg(feature = "linux")]
+pub mod linux;
+
+/// The type of the entry in the auxiliary vector giving the page size
+pub const AT_PAGESZ: usize = 6;
+
+pub trait Env: Sized {
+    type Error: Debug;
+    /// Look up the value of the given environment variable
+    fn get(name: &str) -> Result<Option<String>, Self::Error>;
+    /// Look up the system page size in the auxiliary vector
+    fn page_size() -> Result<usize, Self::Error>;
+}
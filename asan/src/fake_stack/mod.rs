@@ -0,0 +1,307 @@
+//! # fake_stack
+//! This module provides the fake stack frames used to detect
+//! stack-use-after-return. When enabled, instrumented code requests a frame
+//! for its local variables from the runtime (rather than using the real
+//! stack) on entry and returns it on exit. Returned frames are poisoned and
+//! are re-used in a round-robin fashion so that they remain poisoned (and
+//! hence dangling references to them are detected) for as long as possible.
+//!
+//! As with compiler-rt, frames are divided into `NUM_CLASSES` size classes
+//! (from 64 bytes to 64 KiB) and each class of each thread is backed by a
+//! fixed region of memory. The last word of each frame is set to the address
+//! of a flag byte recording whether the frame is in use, since instrumented
+//! code releases the smaller frames inline by clearing this flag (rather
+//! than by calling the runtime). If a class is exhausted, frames which were
+//! abandoned (e.g. by `longjmp`) are reclaimed, failing that, no frame is
+//! returned and instrumented code falls back to using the real stack.
+//!
+//! Since no thread local storage is available to us, the fake stack of the
+//! current thread is identified by the value of its thread pointer. When a
+//! new thread is first seen, the fake stack of a thread which has since
+//! exited is re-used in preference to mapping another. Fake stacks are not
+//! unmapped, since a new thread given the same thread pointer as one which
+//! has exited continues to use its fake stack.
+use {
+    crate::{
+        mmap::Mmap,
+        thread::{thread_exited, thread_id, thread_pointer},
+        GuestAddr,
+    },
+    alloc::{collections::BTreeMap, vec, vec::Vec},
+    core::mem::replace,
+    log::debug,
+    spin::{Mutex, RwLock},
+    thiserror::Error,
+};
+
+/// The number of size classes of fake frames
+pub const NUM_CLASSES: usize = 11;
+
+/// The size of the frames in the smallest size class
+pub const MIN_FRAME_SIZE: usize = 64;
+
+/// The default size of the region backing each size class of each thread
+pub const DEFAULT_CLASS_SIZE: usize = 1 << 18;
+
+/// The size of the frames of the given size class
+pub const fn frame_size(class: usize) -> usize {
+    MIN_FRAME_SIZE << class
+}
+
+/// The fake stack frames of a single thread
+#[derive(Debug)]
+pub struct FakeStack<M: Mmap> {
+    mmap: M,
+    class_size: usize,
+    flags: Vec<u8>,
+    real_frames: Vec<GuestAddr>,
+    next: [usize; NUM_CLASSES],
+}
+
+impl<M: Mmap> FakeStack<M> {
+    pub fn new(class_size: usize) -> Result<FakeStack<M>, FakeStackError<M>> {
+        if class_size < frame_size(NUM_CLASSES - 1) || !class_size.is_power_of_two() {
+            Err(FakeStackError::InvalidClassSize(class_size))?;
+        }
+        let mmap = M::map(class_size * NUM_CLASSES).map_err(|e| FakeStackError::MmapError(e))?;
+        let frames = (0..NUM_CLASSES)
+            .map(|c| class_size / frame_size(c))
+            .sum::<usize>();
+        Ok(FakeStack {
+            mmap,
+            class_size,
+            flags: vec![0; frames],
+            real_frames: vec![0; frames],
+            next: [0; NUM_CLASSES],
+        })
+    }
+
+    /// Allocate a frame of the given size class on behalf of a function whose
+    /// real stack frame is at `real`, returning `None` if none are available
+    pub fn alloc(&mut self, class: usize, real: GuestAddr) -> Option<GuestAddr> {
+        if class >= NUM_CLASSES {
+            return None;
+        }
+        if let Some(addr) = self.try_alloc(class, real) {
+            return Some(addr);
+        }
+        self.reclaim(class, real);
+        self.try_alloc(class, real)
+    }
+
+    /// Release the frame at the given address
+    pub fn dealloc(&mut self, class: usize, addr: GuestAddr) -> Result<(), FakeStackError<M>> {
+        if class >= NUM_CLASSES || !self.contains(addr) {
+            Err(FakeStackError::InvalidAddress(addr))?;
+        }
+        let offset = addr - self.class_base(class);
+        if offset >= self.class_size || offset % frame_size(class) != 0 {
+            Err(FakeStackError::InvalidAddress(addr))?;
+        }
+        let idx = self.first_flag(class) + offset / frame_size(class);
+        self.flags[idx] = 0;
+        Ok(())
+    }
+
+    /// Whether the given address lies within one of the frames
+    pub fn contains(&self, addr: GuestAddr) -> bool {
+        let base = self.base();
+        addr >= base && addr - base < self.class_size * NUM_CLASSES
+    }
+
+    fn try_alloc(&mut self, class: usize, real: GuestAddr) -> Option<GuestAddr> {
+        let n = self.class_size / frame_size(class);
+        let first = self.first_flag(class);
+        let i = (0..n)
+            .map(|i| (self.next[class] + i) % n)
+            .find(|i| self.flags[first + i] == 0)?;
+        self.flags[first + i] = 1;
+        self.real_frames[first + i] = real;
+        self.next[class] = (i + 1) % n;
+
+        let addr = self.class_base(class) + i * frame_size(class);
+        let flag = &mut self.flags[first + i] as *mut u8 as GuestAddr;
+        let last = addr + frame_size(class) - size_of::<GuestAddr>();
+        unsafe { (last as *mut GuestAddr).write(flag) };
+        debug!(
+            "fake stack alloc - class: {}, addr: 0x{:x}, real: 0x{:x}",
+            class, addr, real
+        );
+        Some(addr)
+    }
+
+    /// Since the stack grows down, any frame allocated on behalf of a
+    /// function at the same depth as (or deeper in the real stack than) the
+    /// current one must have been abandoned
+    fn reclaim(&mut self, class: usize, real: GuestAddr) {
+        let n = self.class_size / frame_size(class);
+        let first = self.first_flag(class);
+        for i in first..first + n {
+            if self.flags[i] != 0 && self.real_frames[i] <= real {
+                self.flags[i] = 0;
+            }
+        }
+    }
+
+    /// Release all of the frames, e.g. when the stack is re-used by another
+    /// thread
+    fn reset(&mut self) {
+        self.flags.fill(0);
+        self.real_frames.fill(0);
+        self.next = [0; NUM_CLASSES];
+    }
+
+    fn base(&self) -> GuestAddr {
+        self.mmap.as_slice().as_ptr() as GuestAddr
+    }
+
+    fn class_base(&self, class: usize) -> GuestAddr {
+        self.base() + class * self.class_size
+    }
+
+    fn first_flag(&self, class: usize) -> usize {
+        (0..class).map(|c| self.class_size / frame_size(c)).sum()
+    }
+}
+
+/// The fake stack of a thread, along with the thread to which it belongs
+#[derive(Debug)]
+struct ThreadStack<M: Mmap> {
+    tp: GuestAddr,
+    tid: usize,
+    stack: Mutex<FakeStack<M>>,
+}
+
+#[derive(Debug)]
+struct ThreadStacks<M: Mmap> {
+    /// The index of the stack of each thread, by thread pointer
+    threads: BTreeMap<GuestAddr, usize>,
+    /// The index of each stack, by its base address
+    bases: BTreeMap<GuestAddr, usize>,
+    stacks: Vec<ThreadStack<M>>,
+}
+
+/// The fake stacks of all threads. Only the table of stacks is shared by all
+/// threads (and it is only locked for writing when a new thread is seen),
+/// each stack is locked separately.
+#[derive(Debug)]
+pub struct FakeStacks<M: Mmap> {
+    class_size: usize,
+    stacks: RwLock<ThreadStacks<M>>,
+}
+
+impl<M: Mmap> FakeStacks<M> {
+    pub const fn new(class_size: usize) -> FakeStacks<M> {
+        FakeStacks {
+            class_size,
+            stacks: RwLock::new(ThreadStacks {
+                threads: BTreeMap::new(),
+                bases: BTreeMap::new(),
+                stacks: Vec::new(),
+            }),
+        }
+    }
+
+    /// Allocate a frame from the fake stack of the current thread (assigning
+    /// it one if required)
+    pub fn alloc(
+        &self,
+        class: usize,
+        real: GuestAddr,
+    ) -> Result<Option<GuestAddr>, FakeStackError<M>> {
+        let tp = thread_pointer();
+        {
+            let stacks = self.stacks.read();
+            if let Some(idx) = stacks.threads.get(&tp) {
+                return Ok(stacks.stacks[*idx].stack.lock().alloc(class, real));
+            }
+        }
+
+        let mut stacks = self.stacks.write();
+        let idx = match stacks.threads.get(&tp) {
+            Some(idx) => *idx,
+            None => {
+                let idx = self.assign_stack(&mut stacks, tp)?;
+                stacks.threads.insert(tp, idx);
+                idx
+            }
+        };
+        let stacks = stacks.downgrade();
+        let addr = stacks.stacks[idx].stack.lock().alloc(class, real);
+        Ok(addr)
+    }
+
+    /// Release a frame allocated from the fake stack of any thread
+    pub fn dealloc(&self, class: usize, addr: GuestAddr) -> Result<(), FakeStackError<M>> {
+        let stacks = self.stacks.read();
+        let stack = stacks
+            .find(addr)
+            .ok_or(FakeStackError::InvalidAddress(addr))?;
+        let result = stack.stack.lock().dealloc(class, addr);
+        result
+    }
+
+    pub fn contains(&self, addr: GuestAddr) -> bool {
+        self.stacks.read().find(addr).is_some()
+    }
+
+    /// The number of fake stacks which have been mapped
+    pub fn len(&self) -> usize {
+        self.stacks.read().stacks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Choose the fake stack for a thread which doesn't yet have one, that of
+    /// an exited thread if there is one, otherwise a new one
+    fn assign_stack(
+        &self,
+        stacks: &mut ThreadStacks<M>,
+        tp: GuestAddr,
+    ) -> Result<usize, FakeStackError<M>> {
+        let tid = thread_id();
+        if let Some(idx) = stacks.stacks.iter().position(|s| thread_exited(s.tid)) {
+            let stack = &mut stacks.stacks[idx];
+            debug!("fake stack - tp: 0x{:x}, exited tp: 0x{:x}", tp, stack.tp);
+            let owner = replace(&mut stack.tp, tp);
+            stack.tid = tid;
+            stack.stack.get_mut().reset();
+            if stacks.threads.get(&owner) == Some(&idx) {
+                stacks.threads.remove(&owner);
+            }
+            return Ok(idx);
+        }
+
+        let stack = FakeStack::new(self.class_size)?;
+        let idx = stacks.stacks.len();
+        debug!("fake stack - tp: 0x{:x}, base: 0x{:x}", tp, stack.base());
+        stacks.bases.insert(stack.base(), idx);
+        stacks.stacks.push(ThreadStack {
+            tp,
+            tid,
+            stack: Mutex::new(stack),
+        });
+        Ok(idx)
+    }
+}
+
+impl<M: Mmap> ThreadStacks<M> {
+    /// Find the stack containing the given address
+    fn find(&self, addr: GuestAddr) -> Option<&ThreadStack<M>> {
+        let (_, idx) = self.bases.range(..=addr).next_back()?;
+        let stack = &self.stacks[*idx];
+        stack.stack.lock().contains(addr).then_some(stack)
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum FakeStackError<M: Mmap> {
+    #[error("Invalid class size: {0:x}")]
+    InvalidClassSize(usize),
+    #[error("Invalid fake frame address: {0:x}")]
+    InvalidAddress(GuestAddr),
+    #[error("Mmap error: {0:?}")]
+    MmapError(M::Error),
+}
@@ -0,0 +1,85 @@
+//! # globals
+//! This module keeps track of the global variables which have been registered
+//! by compiler-instrumented modules. The compiler pads each instrumented
+//! global with a trailing red-zone, which is poisoned in the shadow map when
+//! the global is registered. The registry is then used to describe the
+//! global (and the module which defines it) when an access to its red-zone
+//! is reported.
+use {
+    crate::GuestAddr,
+    alloc::{collections::BTreeMap, string::String},
+    spin::Mutex,
+};
+
+/// A global variable registered by an instrumented module
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Global {
+    pub addr: GuestAddr,
+    pub size: usize,
+    pub size_with_redzone: usize,
+    pub name: String,
+    pub module: String,
+}
+
+impl Global {
+    /// Whether the given address lies within the global or its red-zone
+    pub fn contains(&self, addr: GuestAddr) -> bool {
+        addr >= self.addr && addr - self.addr < self.size_with_redzone
+    }
+}
+
+/// The registered globals, indexed by address
+#[derive(Debug, Default)]
+pub struct Globals {
+    globals: BTreeMap<GuestAddr, Global>,
+}
+
+impl Globals {
+    pub const fn new() -> Globals {
+        Globals {
+            globals: BTreeMap::new(),
+        }
+    }
+
+    /// Register the given global, replacing any previously registered at the
+    /// same address
+    pub fn register(&mut self, global: Global) {
+        self.globals.insert(global.addr, global);
+    }
+
+    pub fn unregister(&mut self, addr: GuestAddr) -> Option<Global> {
+        self.globals.remove(&addr)
+    }
+
+    /// Find the global whose extent (including its red-zone) contains the
+    /// given address
+    pub fn find(&self, addr: GuestAddr) -> Option<&Global> {
+        self.globals
+            .range(..=addr)
+            .next_back()
+            .map(|(_, g)| g)
+            .filter(|g| g.contains(addr))
+    }
+
+    pub fn len(&self) -> usize {
+        self.globals.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.globals.is_empty()
+    }
+}
+
+static GLOBALS: Mutex<Globals> = Mutex::new(Globals::new());
+
+pub fn register_global(global: Global) {
+    GLOBALS.lock().register(global);
+}
+
+pub fn unregister_global(addr: GuestAddr) -> Option<Global> {
+    GLOBALS.lock().unregister(addr)
+}
+
+pub fn find_global(addr: GuestAddr) -> Option<Global> {
+    GLOBALS.lock().find(addr).cloned()
+}
@@ -0,0 +1,185 @@
+//! # delete
+//! Replacements for the C++ `operator delete` and `operator delete []`
+//! (including their sized, aligned and nothrow variants). The frontend checks
+//! that each buffer is released by the counterpart of the function which
+//! allocated it and, for the sized variants, that the size is correct.
+use {
+    crate::{allocator::frontend::AllocationType, asan_delete, size_t},
+    core::ffi::c_void,
+    log::trace,
+};
+
+/// # Safety
+/// See the C++ standard
+#[cfg_attr(not(feature = "test"), export_name = "_ZdlPv")]
+#[cfg_attr(feature = "test", export_name = "patch_operator_delete")]
+pub unsafe extern "C" fn operator_delete(p: *mut c_void) {
+    trace!("operator delete - p: {:p}", p);
+    asan_delete(p, AllocationType::New, 0);
+}
+
+/// # Safety
+/// See the C++ standard
+#[cfg_attr(not(feature = "test"), export_name = "_ZdaPv")]
+#[cfg_attr(feature = "test", export_name = "patch_operator_delete_array")]
+pub unsafe extern "C" fn operator_delete_array(p: *mut c_void) {
+    trace!("operator delete [] - p: {:p}", p);
+    asan_delete(p, AllocationType::NewArray, 0);
+}
+
+/// # Safety
+/// See the C++ standard
+#[cfg_attr(
+    all(not(feature = "test"), target_pointer_width = "64"),
+    export_name = "_ZdlPvm"
+)]
+#[cfg_attr(
+    all(not(feature = "test"), target_pointer_width = "32"),
+    export_name = "_ZdlPvj"
+)]
+#[cfg_attr(feature = "test", export_name = "patch_operator_delete_sized")]
+pub unsafe extern "C" fn operator_delete_sized(p: *mut c_void, size: size_t) {
+    trace!("operator delete (sized) - p: {:p}, size: {:#x}", p, size);
+    asan_delete(p, AllocationType::New, size);
+}
+
+/// # Safety
+/// See the C++ standard
+#[cfg_attr(
+    all(not(feature = "test"), target_pointer_width = "64"),
+    export_name = "_ZdaPvm"
+)]
+#[cfg_attr(
+    all(not(feature = "test"), target_pointer_width = "32"),
+    export_name = "_ZdaPvj"
+)]
+#[cfg_attr(feature = "test", export_name = "patch_operator_delete_array_sized")]
+pub unsafe extern "C" fn operator_delete_array_sized(p: *mut c_void, size: size_t) {
+    trace!("operator delete [] (sized) - p: {:p}, size: {:#x}", p, size);
+    asan_delete(p, AllocationType::NewArray, size);
+}
+
+/// # Safety
+/// See the C++ standard
+#[cfg_attr(not(feature = "test"), export_name = "_ZdlPvRKSt9nothrow_t")]
+#[cfg_attr(feature = "test", export_name = "patch_operator_delete_nothrow")]
+pub unsafe extern "C" fn operator_delete_nothrow(p: *mut c_void, _nothrow: *const c_void) {
+    trace!("operator delete (nothrow) - p: {:p}", p);
+    asan_delete(p, AllocationType::New, 0);
+}
+
+/// # Safety
+/// See the C++ standard
+#[cfg_attr(not(feature = "test"), export_name = "_ZdaPvRKSt9nothrow_t")]
+#[cfg_attr(feature = "test", export_name = "patch_operator_delete_array_nothrow")]
+pub unsafe extern "C" fn operator_delete_array_nothrow(p: *mut c_void, _nothrow: *const c_void) {
+    trace!("operator delete [] (nothrow) - p: {:p}", p);
+    asan_delete(p, AllocationType::NewArray, 0);
+}
+
+/// # Safety
+/// See the C++ standard
+#[cfg_attr(not(feature = "test"), export_name = "_ZdlPvSt11align_val_t")]
+#[cfg_attr(feature = "test", export_name = "patch_operator_delete_aligned")]
+pub unsafe extern "C" fn operator_delete_aligned(p: *mut c_void, _alignment: size_t) {
+    trace!("operator delete (aligned) - p: {:p}", p);
+    asan_delete(p, AllocationType::New, 0);
+}
+
+/// # Safety
+/// See the C++ standard
+#[cfg_attr(not(feature = "test"), export_name = "_ZdaPvSt11align_val_t")]
+#[cfg_attr(feature = "test", export_name = "patch_operator_delete_array_aligned")]
+pub unsafe extern "C" fn operator_delete_array_aligned(p: *mut c_void, _alignment: size_t) {
+    trace!("operator delete [] (aligned) - p: {:p}", p);
+    asan_delete(p, AllocationType::NewArray, 0);
+}
+
+/// # Safety
+/// See the C++ standard
+#[cfg_attr(
+    all(not(feature = "test"), target_pointer_width = "64"),
+    export_name = "_ZdlPvmSt11align_val_t"
+)]
+#[cfg_attr(
+    all(not(feature = "test"), target_pointer_width = "32"),
+    export_name = "_ZdlPvjSt11align_val_t"
+)]
+#[cfg_attr(feature = "test", export_name = "patch_operator_delete_sized_aligned")]
+pub unsafe extern "C" fn operator_delete_sized_aligned(
+    p: *mut c_void,
+    size: size_t,
+    _alignment: size_t,
+) {
+    trace!(
+        "operator delete (sized, aligned) - p: {:p}, size: {:#x}",
+        p,
+        size
+    );
+    asan_delete(p, AllocationType::New, size);
+}
+
+/// # Safety
+/// See the C++ standard
+#[cfg_attr(
+    all(not(feature = "test"), target_pointer_width = "64"),
+    export_name = "_ZdaPvmSt11align_val_t"
+)]
+#[cfg_attr(
+    all(not(feature = "test"), target_pointer_width = "32"),
+    export_name = "_ZdaPvjSt11align_val_t"
+)]
+#[cfg_attr(
+    feature = "test",
+    export_name = "patch_operator_delete_array_sized_aligned"
+)]
+pub unsafe extern "C" fn operator_delete_array_sized_aligned(
+    p: *mut c_void,
+    size: size_t,
+    _alignment: size_t,
+) {
+    trace!(
+        "operator delete [] (sized, aligned) - p: {:p}, size: {:#x}",
+        p,
+        size
+    );
+    asan_delete(p, AllocationType::NewArray, size);
+}
+
+/// # Safety
+/// See the C++ standard
+#[cfg_attr(
+    not(feature = "test"),
+    export_name = "_ZdlPvSt11align_val_tRKSt9nothrow_t"
+)]
+#[cfg_attr(
+    feature = "test",
+    export_name = "patch_operator_delete_aligned_nothrow"
+)]
+pub unsafe extern "C" fn operator_delete_aligned_nothrow(
+    p: *mut c_void,
+    _alignment: size_t,
+    _nothrow: *const c_void,
+) {
+    trace!("operator delete (aligned, nothrow) - p: {:p}", p);
+    asan_delete(p, AllocationType::New, 0);
+}
+
+/// # Safety
+/// See the C++ standard
+#[cfg_attr(
+    not(feature = "test"),
+    export_name = "_ZdaPvSt11align_val_tRKSt9nothrow_t"
+)]
+#[cfg_attr(
+    feature = "test",
+    export_name = "patch_operator_delete_array_aligned_nothrow"
+)]
+pub unsafe extern "C" fn operator_delete_array_aligned_nothrow(
+    p: *mut c_void,
+    _alignment: size_t,
+    _nothrow: *const c_void,
+) {
+    trace!("operator delete [] (aligned, nothrow) - p: {:p}", p);
+    asan_delete(p, AllocationType::NewArray, 0);
+}
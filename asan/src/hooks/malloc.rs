@@ -1,5 +1,5 @@
 use {
-    crate::{asan_alloc, size_t},
+    crate::{asan_alloc, hooks::check_alloc, size_t},
     core::{ffi::c_void, ptr::null_mut},
     log::trace,
 };
@@ -13,6 +13,6 @@ pub unsafe extern "C" fn malloc(size: size_t) -> *mut c_void {
     if size == 0 {
         null_mut()
     } else {
-        asan_alloc(size, 0)
+        check_alloc(asan_alloc(size, 0))
     }
 }
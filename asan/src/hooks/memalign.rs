@@ -1,5 +1,5 @@
 use {
-    crate::{asan_alloc, asan_panic, size_t, GuestAddr},
+    crate::{asan_alloc, asan_panic, hooks::check_alloc, size_t, GuestAddr},
     core::{
         ffi::{c_char, c_void},
         mem::size_of,
@@ -25,6 +25,6 @@ pub unsafe extern "C" fn memalign(align: size_t, size: size_t) -> *mut c_void {
     } else if size == 0 {
         null_mut()
     } else {
-        asan_alloc(size, align)
+        check_alloc(asan_alloc(size, align))
     }
 }
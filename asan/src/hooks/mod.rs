@@ -11,6 +11,7 @@ pub mod atoll;
 pub mod bcmp;
 pub mod bzero;
 pub mod calloc;
+pub mod delete;
 pub mod explicit_bzero;
 pub mod free;
 pub mod malloc;
@@ -26,6 +27,7 @@ pub mod memrchr;
 pub mod memset;
 pub mod mmap;
 pub mod munmap;
+pub mod new;
 pub mod posix_memalign;
 pub mod pvalloc;
 pub mod read;
@@ -56,12 +58,59 @@ pub mod write;
 #[cfg(feature = "libc")]
 pub mod fgets;
 
+#[cfg(feature = "libc")]
+use crate::{
+    asan_swap, asan_sym,
+    symbols::{AtomicGuestAddr, Function, FunctionPointer},
+};
 use {
     crate::{hooks, size_t, wchar_t, GuestAddr},
     alloc::vec::Vec,
     core::ffi::{c_char, c_int, c_void, CStr},
 };
 
+/// The `errno` value indicating that there was insufficient memory
+pub const ENOMEM: c_int = 12;
+
+#[cfg(feature = "libc")]
+#[derive(Debug)]
+struct FunctionErrnoLocation;
+
+#[cfg(feature = "libc")]
+impl Function for FunctionErrnoLocation {
+    type Func = unsafe extern "C" fn() -> *mut c_int;
+    const NAME: &'static CStr = c"__errno_location";
+}
+
+#[cfg(feature = "libc")]
+static ERRNO_LOCATION_ADDR: AtomicGuestAddr = AtomicGuestAddr::new();
+
+/// Set `errno` to the given value (where libc is available)
+#[cfg(feature = "libc")]
+pub fn set_errno(errno: c_int) {
+    let addr = ERRNO_LOCATION_ADDR
+        .get_or_insert_with(|| unsafe { asan_sym(FunctionErrnoLocation::NAME.as_ptr()) });
+    let fn_errno_location = FunctionErrnoLocation::as_ptr(addr).unwrap();
+    unsafe {
+        asan_swap(false);
+        *fn_errno_location() = errno;
+        asan_swap(true);
+    }
+}
+
+#[cfg(not(feature = "libc"))]
+pub fn set_errno(_errno: c_int) {}
+
+/// Allocation hooks return `NULL` (and set `errno` to `ENOMEM`) if the
+/// runtime was unable to satisfy the allocation (see
+/// `allocator_may_return_null`)
+pub fn check_alloc(p: *mut c_void) -> *mut c_void {
+    if p.is_null() {
+        set_errno(ENOMEM);
+    }
+    p
+}
+
 extern "C" {
     pub fn asprintf(strp: *mut *mut c_char, fmt: *const c_char, ...) -> c_int;
     pub fn vasprintf(strp: *mut *mut c_char, fmt: *const c_char, va: *const c_void) -> c_int;
@@ -0,0 +1,197 @@
+//! # new
+//! Replacements for the C++ `operator new` and `operator new []` (including
+//! their aligned and nothrow variants). The type of each allocation is
+//! recorded so that it can be checked when the buffer is released. Since the
+//! runtime cannot throw `std::bad_alloc`, failure to allocate is always
+//! reported, unless using one of the nothrow variants and the runtime is
+//! configured to return null (see `allocator_may_return_null`).
+use {
+    crate::{allocator::frontend::AllocationType, asan_new, asan_panic, size_t, GuestAddr},
+    core::{
+        ffi::{c_char, c_void},
+        mem::size_of,
+    },
+    log::trace,
+};
+
+/// C++ requires that a zero-sized allocation returns a unique pointer
+fn len(size: size_t) -> usize {
+    size.max(1)
+}
+
+fn align(align: size_t) -> usize {
+    if !align.is_power_of_two() {
+        unsafe {
+            asan_panic(c"operator new - align is not a power of two".as_ptr() as *const c_char)
+        };
+    }
+    if align <= size_of::<GuestAddr>() {
+        0
+    } else {
+        align
+    }
+}
+
+/// # Safety
+/// See the C++ standard
+#[cfg_attr(
+    all(not(feature = "test"), target_pointer_width = "64"),
+    export_name = "_Znwm"
+)]
+#[cfg_attr(
+    all(not(feature = "test"), target_pointer_width = "32"),
+    export_name = "_Znwj"
+)]
+#[cfg_attr(feature = "test", export_name = "patch_operator_new")]
+pub unsafe extern "C" fn operator_new(size: size_t) -> *mut c_void {
+    trace!("operator new - size: {:#x}", size);
+    asan_new(len(size), 0, AllocationType::New, false)
+}
+
+/// # Safety
+/// See the C++ standard
+#[cfg_attr(
+    all(not(feature = "test"), target_pointer_width = "64"),
+    export_name = "_Znam"
+)]
+#[cfg_attr(
+    all(not(feature = "test"), target_pointer_width = "32"),
+    export_name = "_Znaj"
+)]
+#[cfg_attr(feature = "test", export_name = "patch_operator_new_array")]
+pub unsafe extern "C" fn operator_new_array(size: size_t) -> *mut c_void {
+    trace!("operator new [] - size: {:#x}", size);
+    asan_new(len(size), 0, AllocationType::NewArray, false)
+}
+
+/// # Safety
+/// See the C++ standard
+#[cfg_attr(
+    all(not(feature = "test"), target_pointer_width = "64"),
+    export_name = "_ZnwmRKSt9nothrow_t"
+)]
+#[cfg_attr(
+    all(not(feature = "test"), target_pointer_width = "32"),
+    export_name = "_ZnwjRKSt9nothrow_t"
+)]
+#[cfg_attr(feature = "test", export_name = "patch_operator_new_nothrow")]
+pub unsafe extern "C" fn operator_new_nothrow(
+    size: size_t,
+    _nothrow: *const c_void,
+) -> *mut c_void {
+    trace!("operator new (nothrow) - size: {:#x}", size);
+    asan_new(len(size), 0, AllocationType::New, true)
+}
+
+/// # Safety
+/// See the C++ standard
+#[cfg_attr(
+    all(not(feature = "test"), target_pointer_width = "64"),
+    export_name = "_ZnamRKSt9nothrow_t"
+)]
+#[cfg_attr(
+    all(not(feature = "test"), target_pointer_width = "32"),
+    export_name = "_ZnajRKSt9nothrow_t"
+)]
+#[cfg_attr(feature = "test", export_name = "patch_operator_new_array_nothrow")]
+pub unsafe extern "C" fn operator_new_array_nothrow(
+    size: size_t,
+    _nothrow: *const c_void,
+) -> *mut c_void {
+    trace!("operator new [] (nothrow) - size: {:#x}", size);
+    asan_new(len(size), 0, AllocationType::NewArray, true)
+}
+
+/// # Safety
+/// See the C++ standard
+#[cfg_attr(
+    all(not(feature = "test"), target_pointer_width = "64"),
+    export_name = "_ZnwmSt11align_val_t"
+)]
+#[cfg_attr(
+    all(not(feature = "test"), target_pointer_width = "32"),
+    export_name = "_ZnwjSt11align_val_t"
+)]
+#[cfg_attr(feature = "test", export_name = "patch_operator_new_aligned")]
+pub unsafe extern "C" fn operator_new_aligned(size: size_t, alignment: size_t) -> *mut c_void {
+    trace!(
+        "operator new (aligned) - size: {:#x}, align: {:#x}",
+        size,
+        alignment
+    );
+    asan_new(len(size), align(alignment), AllocationType::New, false)
+}
+
+/// # Safety
+/// See the C++ standard
+#[cfg_attr(
+    all(not(feature = "test"), target_pointer_width = "64"),
+    export_name = "_ZnamSt11align_val_t"
+)]
+#[cfg_attr(
+    all(not(feature = "test"), target_pointer_width = "32"),
+    export_name = "_ZnajSt11align_val_t"
+)]
+#[cfg_attr(feature = "test", export_name = "patch_operator_new_array_aligned")]
+pub unsafe extern "C" fn operator_new_array_aligned(
+    size: size_t,
+    alignment: size_t,
+) -> *mut c_void {
+    trace!(
+        "operator new [] (aligned) - size: {:#x}, align: {:#x}",
+        size,
+        alignment
+    );
+    asan_new(len(size), align(alignment), AllocationType::NewArray, false)
+}
+
+/// # Safety
+/// See the C++ standard
+#[cfg_attr(
+    all(not(feature = "test"), target_pointer_width = "64"),
+    export_name = "_ZnwmSt11align_val_tRKSt9nothrow_t"
+)]
+#[cfg_attr(
+    all(not(feature = "test"), target_pointer_width = "32"),
+    export_name = "_ZnwjSt11align_val_tRKSt9nothrow_t"
+)]
+#[cfg_attr(feature = "test", export_name = "patch_operator_new_aligned_nothrow")]
+pub unsafe extern "C" fn operator_new_aligned_nothrow(
+    size: size_t,
+    alignment: size_t,
+    _nothrow: *const c_void,
+) -> *mut c_void {
+    trace!(
+        "operator new (aligned, nothrow) - size: {:#x}, align: {:#x}",
+        size,
+        alignment
+    );
+    asan_new(len(size), align(alignment), AllocationType::New, true)
+}
+
+/// # Safety
+/// See the C++ standard
+#[cfg_attr(
+    all(not(feature = "test"), target_pointer_width = "64"),
+    export_name = "_ZnamSt11align_val_tRKSt9nothrow_t"
+)]
+#[cfg_attr(
+    all(not(feature = "test"), target_pointer_width = "32"),
+    export_name = "_ZnajSt11align_val_tRKSt9nothrow_t"
+)]
+#[cfg_attr(
+    feature = "test",
+    export_name = "patch_operator_new_array_aligned_nothrow"
+)]
+pub unsafe extern "C" fn operator_new_array_aligned_nothrow(
+    size: size_t,
+    alignment: size_t,
+    _nothrow: *const c_void,
+) -> *mut c_void {
+    trace!(
+        "operator new [] (aligned, nothrow) - size: {:#x}, align: {:#x}",
+        size,
+        alignment
+    );
+    asan_new(len(size), align(alignment), AllocationType::NewArray, true)
+}
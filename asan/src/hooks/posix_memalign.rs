@@ -1,5 +1,5 @@
 use {
-    crate::{asan_alloc, asan_panic, size_t, GuestAddr},
+    crate::{asan_alloc, asan_panic, hooks::ENOMEM, size_t, GuestAddr},
     core::{
         ffi::{c_char, c_int, c_void},
         mem::size_of,
@@ -43,6 +43,10 @@ pub unsafe extern "C" fn posix_memalign(
         0
     } else {
         let p = asan_alloc(size, align);
+        /* On failure, memptr is left untouched */
+        if p.is_null() {
+            return ENOMEM;
+        }
         *memptr = p;
         0
     }
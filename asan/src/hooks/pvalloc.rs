@@ -1,5 +1,5 @@
 use {
-    crate::{asan_alloc, asan_page_size, size_t},
+    crate::{asan_alloc, asan_page_size, hooks::check_alloc, size_t},
     core::ffi::c_void,
     log::trace,
 };
@@ -14,8 +14,9 @@ pub unsafe extern "C" fn pvalloc(size: size_t) -> *mut c_void {
     let aligned_size = if size == 0 {
         page_size
     } else {
-        (size + page_size - 1) & !(page_size - 1)
+        /* An overflowing size is saturated so that it is rejected as too big */
+        size.saturating_add(page_size - 1) & !(page_size - 1)
     };
     assert_ne!(aligned_size, 0);
-    asan_alloc(aligned_size, page_size)
+    check_alloc(asan_alloc(aligned_size, page_size))
 }
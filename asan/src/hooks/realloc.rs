@@ -1,5 +1,5 @@
 use {
-    crate::{asan_alloc, asan_dealloc, asan_get_size, asan_load, size_t},
+    crate::{asan_alloc, asan_dealloc, asan_get_size, asan_load, hooks::check_alloc, size_t},
     core::{
         ffi::c_void,
         ptr::{copy_nonoverlapping, null_mut},
@@ -16,14 +16,18 @@ pub unsafe extern "C" fn realloc(p: *mut c_void, size: size_t) -> *mut c_void {
     if p.is_null() && size == 0 {
         null_mut()
     } else if p.is_null() {
-        asan_alloc(size, 0)
+        check_alloc(asan_alloc(size, 0))
     } else if size == 0 {
         asan_dealloc(p);
         null_mut()
     } else {
         let old_size = asan_get_size(p);
         asan_load(p, old_size);
-        let q = asan_alloc(size, 0);
+        let q = check_alloc(asan_alloc(size, 0));
+        /* On failure, the original buffer is left untouched */
+        if q.is_null() {
+            return q;
+        }
         let min = old_size.min(size);
         unsafe { copy_nonoverlapping(p as *const u8, q as *mut u8, min) };
         asan_dealloc(p);
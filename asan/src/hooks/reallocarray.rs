@@ -1,7 +1,7 @@
 use {
-    crate::{asan_alloc, asan_dealloc, asan_get_size, asan_load, asan_panic, size_t},
+    crate::{asan_alloc, asan_dealloc, asan_get_size, asan_load, hooks::check_alloc, size_t},
     core::{
-        ffi::{c_char, c_void},
+        ffi::c_void,
         ptr::{copy_nonoverlapping, null_mut},
     },
     log::trace,
@@ -22,25 +22,26 @@ pub unsafe extern "C" fn reallocarray(
         nmemb,
         size
     );
-    match nmemb.checked_mul(size) {
-        Some(size) => {
-            if ptr.is_null() && size == 0 {
-                null_mut()
-            } else if ptr.is_null() {
-                asan_alloc(size, 0)
-            } else if size == 0 {
-                asan_dealloc(ptr);
-                null_mut()
-            } else {
-                let old_size = asan_get_size(ptr);
-                asan_load(ptr, old_size);
-                let q = asan_alloc(size, 0);
-                let min = old_size.min(size);
-                unsafe { copy_nonoverlapping(ptr as *const u8, q as *mut u8, min) };
-                asan_dealloc(ptr);
-                q
-            }
+    /* An overflowing size is saturated so that it is rejected as too big */
+    let size = nmemb.saturating_mul(size);
+    if ptr.is_null() && size == 0 {
+        null_mut()
+    } else if ptr.is_null() {
+        check_alloc(asan_alloc(size, 0))
+    } else if size == 0 {
+        asan_dealloc(ptr);
+        null_mut()
+    } else {
+        let old_size = asan_get_size(ptr);
+        asan_load(ptr, old_size);
+        let q = check_alloc(asan_alloc(size, 0));
+        /* On failure, the original buffer is left untouched */
+        if q.is_null() {
+            return q;
         }
-        None => asan_panic(c"reallocarray - size would overflow".as_ptr() as *const c_char),
+        let min = old_size.min(size);
+        unsafe { copy_nonoverlapping(ptr as *const u8, q as *mut u8, min) };
+        asan_dealloc(ptr);
+        q
     }
 }
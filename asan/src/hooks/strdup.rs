@@ -1,5 +1,5 @@
 use {
-    crate::{asan_alloc, asan_load, asan_panic},
+    crate::{asan_alloc, asan_load, asan_panic, hooks::check_alloc},
     core::{
         ffi::{c_char, c_void},
         ptr::copy,
@@ -23,7 +23,10 @@ pub unsafe extern "C" fn strdup(cs: *const c_char) -> *mut c_char {
     }
     asan_load(cs as *const c_void, len + 1);
 
-    let dest = asan_alloc(len + 1, 0) as *mut c_char;
+    let dest = check_alloc(asan_alloc(len + 1, 0)) as *mut c_char;
+    if dest.is_null() {
+        return dest;
+    }
     copy(cs, dest, len + 1);
     dest
 }
@@ -1,5 +1,5 @@
 use {
-    crate::{asan_alloc, asan_load, asan_panic, size_t},
+    crate::{asan_alloc, asan_load, asan_panic, hooks::check_alloc, size_t},
     core::{
         ffi::{c_char, c_void},
         ptr::copy,
@@ -15,7 +15,10 @@ pub unsafe extern "C" fn strndup(cs: *const c_char, n: size_t) -> *mut c_char {
 
     if cs.is_null() {
         if n == 0 {
-            let dest = asan_alloc(1, 0) as *mut c_char;
+            let dest = check_alloc(asan_alloc(1, 0)) as *mut c_char;
+            if dest.is_null() {
+                return dest;
+            }
             *dest = 0;
             return dest;
         } else {
@@ -29,7 +32,10 @@ pub unsafe extern "C" fn strndup(cs: *const c_char, n: size_t) -> *mut c_char {
     }
     asan_load(cs as *const c_void, len + 1);
 
-    let dest = asan_alloc(len + 1, 0) as *mut c_char;
+    let dest = check_alloc(asan_alloc(len + 1, 0)) as *mut c_char;
+    if dest.is_null() {
+        return dest;
+    }
     copy(cs, dest, len + 1);
     *dest.add(len) = 0;
     dest
@@ -1,5 +1,5 @@
 use {
-    crate::{asan_alloc, asan_page_size, size_t},
+    crate::{asan_alloc, asan_page_size, hooks::check_alloc, size_t},
     core::{ffi::c_void, ptr::null_mut},
     log::trace,
 };
@@ -14,6 +14,6 @@ pub unsafe extern "C" fn valloc(size: size_t) -> *mut c_void {
     if size == 0 {
         null_mut()
     } else {
-        asan_alloc(size, asan_page_size())
+        check_alloc(asan_alloc(size, asan_page_size()))
     }
 }
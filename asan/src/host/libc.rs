@@ -131,6 +131,23 @@ impl<S: Symbols> Host for LibcHost<S> {
         }
         Ok(())
     }
+
+    fn read_shadow(start: GuestAddr, buf: &mut [u8]) -> Result<(), LibcHostError<S>> {
+        let syscall = Self::get_syscall()?;
+        let ret = unsafe {
+            syscall(
+                Self::SYSCALL_NO,
+                HostAction::ReadShadow as usize,
+                start,
+                buf.as_mut_ptr(),
+                buf.len(),
+            )
+        };
+        if ret != 0 {
+            return Err(LibcHostError::SyscallError(ret));
+        }
+        Ok(())
+    }
 }
 
 static SYSCALL_ADDR: AtomicGuestAddr = AtomicGuestAddr::new();
@@ -77,6 +77,19 @@ impl Host for LinuxHost {
         unsafe { syscall2(Self::sysno(), HostAction::Dealloc as usize, start)? };
         Ok(())
     }
+
+    fn read_shadow(start: GuestAddr, buf: &mut [u8]) -> LinuxHostResult<()> {
+        unsafe {
+            syscall4(
+                Self::sysno(),
+                HostAction::ReadShadow as usize,
+                start,
+                buf.as_mut_ptr() as usize,
+                buf.len(),
+            )?;
+        };
+        Ok(())
+    }
 }
 
 impl LinuxHost {
@@ -29,6 +29,7 @@ enum HostAction {
     Enable,
     Disable,
     SwapState,
+    ReadShadow,
 }
 
 pub trait Host: Debug + Send {
@@ -41,4 +42,7 @@ pub trait Host: Debug + Send {
     fn swap(enabled: bool) -> Result<(), Self::Error>;
     fn alloc(start: GuestAddr, len: usize) -> Result<(), Self::Error>;
     fn dealloc(start: GuestAddr) -> Result<(), Self::Error>;
+    /// Copy the shadow bytes describing the memory starting at `start` into
+    /// `buf`
+    fn read_shadow(start: GuestAddr, buf: &mut [u8]) -> Result<(), Self::Error>;
 }
@@ -0,0 +1,331 @@
+//! # leak
+//! This module is responsible for detecting memory leaks, typically when the
+//! process exits. In the absence of LeakSanitizer (which is unavailable when
+//! running under QEMU), we conservatively scan a set of roots for values
+//! which point into (or to the start of) any live allocation. Allocations
+//! which are transitively reachable from the roots are not considered to be
+//! leaked. The remaining allocations are reported as leaked, grouped by the
+//! call stack at which they were allocated.
+//!
+//! As with LeakSanitizer, a leaked allocation which is referenced by another
+//! leaked allocation is reported as an indirect leak, the others are reported
+//! as direct leaks.
+//!
+//! The roots are gathered from the writable mappings of the process (which
+//! includes the stacks of all threads, as well as the data and bss sections
+//! of each module) along with the callee-saved registers of the current
+//! thread. Any internal regions of the runtime (e.g. the shadow map and the
+//! heaps used by the allocator) must be excluded, since these would otherwise
+//! cause all allocations to be considered reachable.
+//!
+//! By default, leaks are only reported, but `set_exit_code` can be used to
+//! cause the process to terminate with a given exit code if any leaks are
+//! found.
+use {
+    crate::{
+        backtrace::depot::{StackDepot, StackId},
+        exit::exit,
+        maps::{iterator::MapIterator, MapReader},
+        report::fmt_stack,
+        GuestAddr,
+    },
+    alloc::{collections::BTreeMap, vec::Vec},
+    core::{
+        ffi::c_int,
+        fmt::{self, Display, Formatter},
+        ops::Range,
+    },
+    log::error,
+    spin::Mutex,
+};
+
+static DETECT_LEAKS: Mutex<bool> = Mutex::new(true);
+
+static EXIT_CODE: Mutex<Option<c_int>> = Mutex::new(None);
+
+pub fn set_detect_leaks(detect_leaks: bool) {
+    *DETECT_LEAKS.lock() = detect_leaks;
+}
+
+pub fn detect_leaks() -> bool {
+    *DETECT_LEAKS.lock()
+}
+
+/// Set the exit code used to terminate the process if leaks are detected (or
+/// `None` to simply report them)
+pub fn set_exit_code(exit_code: Option<c_int>) {
+    *EXIT_CODE.lock() = exit_code;
+}
+
+pub fn exit_code() -> Option<c_int> {
+    *EXIT_CODE.lock()
+}
+
+/// A live allocation to be checked for leaks
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Chunk {
+    pub addr: GuestAddr,
+    pub len: usize,
+    pub stack: StackId,
+}
+
+/// A group of leaked allocations made from the same call stack
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Leak {
+    pub direct: bool,
+    pub count: usize,
+    pub bytes: usize,
+    pub stack: Vec<GuestAddr>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ChunkState {
+    Unreachable,
+    Reachable,
+    Indirect,
+}
+
+pub struct LeakChecker {
+    chunks: Vec<Chunk>,
+    states: Vec<ChunkState>,
+    pending: Vec<usize>,
+}
+
+impl LeakChecker {
+    pub fn new(mut chunks: Vec<Chunk>) -> LeakChecker {
+        chunks.sort_by_key(|c| c.addr);
+        let states = chunks.iter().map(|_| ChunkState::Unreachable).collect();
+        LeakChecker {
+            chunks,
+            states,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Mark any chunks (and those transitively reachable from them) referenced
+    /// by the given values as reachable
+    pub fn scan_words(&mut self, words: &[GuestAddr]) {
+        words
+            .iter()
+            .for_each(|w| self.mark(*w, usize::MAX, ChunkState::Reachable));
+        self.flood();
+    }
+
+    /// Mark any chunks (and those transitively reachable from them) referenced
+    /// by the aligned words in the given range of memory as reachable
+    ///
+    /// # Safety
+    /// The given range of memory must be readable
+    pub unsafe fn scan_range(&mut self, start: GuestAddr, end: GuestAddr) {
+        self.scan(start, end, usize::MAX, ChunkState::Reachable);
+        self.flood();
+    }
+
+    /// Classify the chunks which were not found to be reachable and group them
+    /// by allocation stack
+    pub fn leaks(mut self, depot: &StackDepot) -> Vec<Leak> {
+        for i in 0..self.chunks.len() {
+            if self.states[i] != ChunkState::Reachable {
+                let chunk = self.chunks[i];
+                unsafe { self.scan(chunk.addr, chunk.addr + chunk.len, i, ChunkState::Indirect) };
+            }
+        }
+
+        let mut groups = BTreeMap::<(bool, StackId), (usize, usize)>::new();
+        self.chunks
+            .iter()
+            .zip(self.states.iter())
+            .filter(|(_, state)| **state != ChunkState::Reachable)
+            .for_each(|(chunk, state)| {
+                let group = groups
+                    .entry((*state == ChunkState::Unreachable, chunk.stack))
+                    .or_default();
+                group.0 += 1;
+                group.1 += chunk.len;
+            });
+
+        let mut leaks = groups
+            .into_iter()
+            .map(|((direct, stack), (count, bytes))| Leak {
+                direct,
+                count,
+                bytes,
+                stack: depot.get(stack).unwrap_or_default().to_vec(),
+            })
+            .collect::<Vec<Leak>>();
+        leaks.sort_by(|a, b| b.direct.cmp(&a.direct).then(b.bytes.cmp(&a.bytes)));
+        leaks
+    }
+
+    unsafe fn scan(&mut self, start: GuestAddr, end: GuestAddr, owner: usize, state: ChunkState) {
+        let align = size_of::<GuestAddr>();
+        let mut addr = start.next_multiple_of(align);
+        while addr + align <= end {
+            let value = (addr as *const GuestAddr).read_volatile();
+            self.mark(value, owner, state);
+            addr += align;
+        }
+    }
+
+    fn flood(&mut self) {
+        while let Some(i) = self.pending.pop() {
+            let chunk = self.chunks[i];
+            unsafe { self.scan(chunk.addr, chunk.addr + chunk.len, i, ChunkState::Reachable) };
+        }
+    }
+
+    fn mark(&mut self, value: GuestAddr, owner: usize, state: ChunkState) {
+        let Some(i) = self.find(value) else {
+            return;
+        };
+        if i == owner || self.states[i] != ChunkState::Unreachable {
+            return;
+        }
+        self.states[i] = state;
+        if state == ChunkState::Reachable {
+            self.pending.push(i);
+        }
+    }
+
+    fn find(&self, value: GuestAddr) -> Option<usize> {
+        let idx = self.chunks.partition_point(|c| c.addr <= value);
+        let i = idx.checked_sub(1)?;
+        let chunk = &self.chunks[i];
+        (value - chunk.addr < chunk.len.max(1)).then_some(i)
+    }
+}
+
+/// The result of a leak check
+pub struct LeakReport {
+    leaks: Vec<Leak>,
+}
+
+impl LeakReport {
+    pub fn new(leaks: Vec<Leak>) -> LeakReport {
+        LeakReport { leaks }
+    }
+
+    pub fn leaks(&self) -> &[Leak] {
+        &self.leaks
+    }
+
+    /// Log the report (if any leaks were found) and terminate the process if
+    /// an exit code has been configured
+    pub fn emit(&self) {
+        if self.leaks.is_empty() {
+            return;
+        }
+        error!("{}", self);
+        if let Some(code) = exit_code() {
+            exit(code);
+        }
+    }
+}
+
+impl Display for LeakReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "LeakSanitizer: detected memory leaks")?;
+        for leak in &self.leaks {
+            writeln!(
+                f,
+                "{} leak of {} byte(s) in {} object(s) allocated from:",
+                if leak.direct { "Direct" } else { "Indirect" },
+                leak.bytes,
+                leak.count
+            )?;
+            fmt_stack(f, &leak.stack)?;
+        }
+        write!(
+            f,
+            "SUMMARY: AddressSanitizer: {} byte(s) leaked in {} allocation(s).",
+            self.leaks.iter().map(|l| l.bytes).sum::<usize>(),
+            self.leaks.iter().map(|l| l.count).sum::<usize>()
+        )
+    }
+}
+
+/// Collect the readable and writable mappings of the process, less any
+/// excluded ranges, to be used as roots for the leak check
+pub fn roots<R: MapReader>(
+    exclude: &[Range<GuestAddr>],
+) -> Result<Vec<Range<GuestAddr>>, R::Error> {
+    let mut exclude = exclude.to_vec();
+    exclude.sort_by_key(|r| r.start);
+    let roots = MapIterator::new(R::new()?)
+        .filter(|m| m.is_readable() && m.is_writeable())
+        .filter(|m| !m.path().starts_with("/dev/"))
+        .flat_map(|m| subtract(m.base()..m.limit(), &exclude))
+        .collect();
+    Ok(roots)
+}
+
+fn subtract(range: Range<GuestAddr>, exclude: &[Range<GuestAddr>]) -> Vec<Range<GuestAddr>> {
+    let mut remaining = Vec::new();
+    let mut start = range.start;
+    for e in exclude
+        .iter()
+        .filter(|e| e.start < range.end && e.end > range.start)
+    {
+        if e.start > start {
+            remaining.push(start..e.start);
+        }
+        start = start.max(e.end);
+    }
+    if start < range.end {
+        remaining.push(start..range.end);
+    }
+    remaining
+}
+
+macro_rules! read_registers {
+    ($insn:literal, $($reg:literal),*) => {
+        [$({
+            let value: GuestAddr;
+            unsafe {
+                core::arch::asm!(
+                    concat!($insn, " {}, ", $reg),
+                    out(reg) value,
+                    options(nomem, nostack, preserves_flags)
+                )
+            };
+            value
+        }),*]
+    };
+}
+
+/// Read the callee-saved registers of the current thread (the others will
+/// have been spilled to the stack by our callers)
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+pub fn registers() -> [GuestAddr; 6] {
+    read_registers!("mov", "rbx", "rbp", "r12", "r13", "r14", "r15")
+}
+
+#[cfg(target_arch = "x86")]
+#[inline(always)]
+pub fn registers() -> [GuestAddr; 4] {
+    read_registers!("mov", "ebx", "esi", "edi", "ebp")
+}
+
+#[cfg(target_arch = "aarch64")]
+#[inline(always)]
+pub fn registers() -> [GuestAddr; 11] {
+    read_registers!(
+        "mov", "x19", "x20", "x21", "x22", "x23", "x24", "x25", "x26", "x27", "x28", "x29"
+    )
+}
+
+#[cfg(target_arch = "arm")]
+#[inline(always)]
+pub fn registers() -> [GuestAddr; 8] {
+    read_registers!("mov", "r4", "r5", "r6", "r7", "r8", "r9", "r10", "r11")
+}
+
+#[cfg(target_arch = "powerpc")]
+#[inline(always)]
+pub fn registers() -> [GuestAddr; 18] {
+    read_registers!(
+        "mr", "14", "15", "16", "17", "18", "19", "20", "21", "22", "23", "24", "25", "26", "27",
+        "28", "29", "30", "31"
+    )
+}
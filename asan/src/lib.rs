@@ -31,6 +31,8 @@
 //! combining alternative implementations of the various key components.
 //!
 //! ## Features
+//! - `compiler_rt` - Export the entry points used by compiler instrumented
+//!   code (e.g. `__asan_load4`) so that it can be linked against the runtime
 //! - `dlmalloc` - Enable support for the dlmalloc allocator backend.
 //! - `guest` - Enable support for shadow memory and tracking in the guest
 //! - `hooks` - Enable support for hooking functions in the guest
@@ -51,14 +53,28 @@ pub mod allocator;
 #[cfg(not(feature = "test"))]
 pub mod arch;
 
+pub mod backtrace;
+
+#[cfg(feature = "compiler_rt")]
+pub mod compiler_rt;
+
+pub mod env;
+
 pub mod exit;
 
+#[cfg(feature = "compiler_rt")]
+pub mod fake_stack;
+
+pub mod globals;
+
 #[cfg(feature = "hooks")]
 pub mod hooks;
 
 #[cfg(feature = "host")]
 pub mod host;
 
+pub mod leak;
+
 pub mod logger;
 
 pub mod maps;
@@ -71,12 +87,20 @@ pub mod mmap;
 #[cfg(not(feature = "test"))]
 mod nostd;
 
+pub mod options;
+
 pub mod patch;
 
+pub mod report;
+
 pub mod shadow;
 
+pub mod stats;
+
 pub mod symbols;
 
+pub mod thread;
+
 #[cfg(feature = "test")]
 pub mod test;
 
@@ -130,11 +154,39 @@ extern "C" {
     pub fn asan_store(addr: *const c_void, size: usize);
     pub fn asan_alloc(len: usize, align: usize) -> *mut c_void;
     pub fn asan_dealloc(addr: *const c_void);
+    pub fn asan_new(
+        len: usize,
+        align: usize,
+        alloc_type: allocator::frontend::AllocationType,
+        nothrow: bool,
+    ) -> *mut c_void;
+    pub fn asan_delete(
+        addr: *const c_void,
+        dealloc_type: allocator::frontend::AllocationType,
+        len: usize,
+    );
     pub fn asan_get_size(addr: *const c_void) -> usize;
+    pub fn asan_get_ownership(addr: *const c_void) -> bool;
+    pub fn asan_heap_stats() -> stats::HeapStats;
     #[cfg(feature = "libc")]
     pub fn asan_sym(name: *const c_char) -> GuestAddr;
     pub fn asan_page_size() -> usize;
     pub fn asan_unpoison(addr: *mut c_void, len: usize);
+    #[cfg(feature = "compiler_rt")]
+    pub fn asan_poison(addr: *mut c_void, len: usize, poison: shadow::PoisonType);
+    #[cfg(feature = "compiler_rt")]
+    pub fn asan_is_poison(addr: *const c_void, len: usize) -> bool;
+    #[cfg(feature = "compiler_rt")]
+    pub fn asan_shadow_granularity() -> usize;
+    #[cfg(feature = "compiler_rt")]
+    pub fn asan_find_poison(addr: *const c_void, len: usize) -> *mut c_void;
+    pub fn asan_stack_top(addr: GuestAddr) -> GuestAddr;
+    #[cfg(feature = "compiler_rt")]
+    pub fn asan_init();
+    #[cfg(feature = "compiler_rt")]
+    pub fn asan_fake_stack_alloc(class: usize, real: GuestAddr) -> *mut c_void;
+    #[cfg(feature = "compiler_rt")]
+    pub fn asan_fake_stack_dealloc(addr: *mut c_void, class: usize);
     pub fn asan_track(addr: *mut c_void, len: usize);
     pub fn asan_untrack(addr: *mut c_void);
     pub fn asan_panic(msg: *const c_char) -> !;
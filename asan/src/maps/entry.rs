@@ -82,10 +82,22 @@ impl MapEntry {
         addr >= self.base && addr < self.limit
     }
 
-    fn base(&self) -> GuestAddr {
+    pub fn base(&self) -> GuestAddr {
         self.base
     }
 
+    pub fn limit(&self) -> GuestAddr {
+        self.limit
+    }
+
+    pub fn is_readable(&self) -> bool {
+        self.read
+    }
+
+    pub fn is_writeable(&self) -> bool {
+        self.write
+    }
+
     fn len(&self) -> usize {
         self.limit - self.base
     }
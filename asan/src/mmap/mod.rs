@@ -17,9 +17,9 @@ pub mod linux;
 bitflags! {
     #[derive(PartialEq, Eq)]
     pub struct MmapProt: u32 {
-        const READ = 0;
-        const WRITE = 1;
-        const EXEC = 2;
+        const READ = 1;
+        const WRITE = 2;
+        const EXEC = 4;
     }
 }
 
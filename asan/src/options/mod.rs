@@ -0,0 +1,283 @@
+//! # options
+//! This module is responsible for parsing the runtime configuration of the
+//! sanitizer. In the same manner as compiler-rt, options are provided by an
+//! environment variable (`ASAN_OPTIONS`) containing a list of `key=value`
+//! pairs separated by colons, commas or whitespace, e.g.:
+//!
+//! ```text
+//! ASAN_OPTIONS=redzone=64:quarantine_size_mb=16:log_level=debug
+//! ```
+//!
+//! The following keys are supported:
+//! - `redzone` - The size of the red-zone applied to each allocation (or the
+//!   minimum size, if `max_redzone` is given)
+//! - `max_redzone` - The maximum size of the red-zone, when given, red-zones
+//!   are scaled with the size of each allocation
+//! - `quarantine_size_mb` - The size of the quarantine (in MiB)
+//! - `malloc_context_size` - The depth of the recorded allocation stacks
+//! - `malloc_fill_byte` - The value used to fill new allocations
+//! - `max_malloc_fill_size` - The maximum number of bytes of each new
+//!   allocation which are filled
+//! - `free_fill_byte` - The value used to fill freed allocations (by default
+//!   they are not filled)
+//! - `check_free_fill` - Whether to check that freed allocations still
+//!   contain the `free_fill_byte` when they leave the quarantine
+//! - `alloc_dealloc_mismatch` - Whether to report buffers released by a
+//!   different family of functions (e.g. `free` or `operator delete`) to
+//!   that which allocated them
+//! - `new_delete_type_mismatch` - Whether to report buffers released by a
+//!   sized `operator delete` with the wrong size
+//! - `forward_foreign_free` - Whether to pass buffers which weren't allocated
+//!   by the runtime (e.g. those allocated by the dynamic loader before it was
+//!   initialized) to the original libc `free` rather than reporting an error
+//!   (only where libc is available)
+//! - `max_allocation_size_mb` - The largest allocation (in MiB) which may be
+//!   requested by the application
+//! - `allocator_may_return_null` - Whether allocation functions should return
+//!   `NULL` on failure rather than reporting an error
+//! - `arena_size_mb` - The size of the heap (in MiB) when using the arena
+//!   backend
+//! - `heap_base` - The (page aligned) address at which the heap is placed.
+//!   Since the backends themselves are deterministic, this means the
+//!   addresses of the allocations depend only upon the sequence of requests
+//!   made by the application and so are reproducible between runs (not
+//!   supported by the libc backend)
+//! - `shadow_layout` - The layout of the shadow maps (in `guest` mode), one
+//!   of `x86_64`, `x86_64_high`, `aarch64_39`, `aarch64_42` or `aarch64_48`
+//!   (`i386` or `ppc32` on 32-bit targets). The `x86_64`, `i386` and `ppc32`
+//!   layouts may also be given a suffix of `_g16` or `_g32` to select 16 or
+//!   32-byte shadow granules (the instrumentation must match). If not given,
+//!   the layout is chosen to fit the existing mappings of the process
+//! - `print_stats` - Whether to log the statistics of the heap at exit
+//! - `log_level` - One of `error`, `warn`, `info`, `debug` or `trace`
+//! - `halt_on_error` - Whether to terminate on the first error
+//! - `exitcode` - Exit with the given code (rather than aborting) on error
+//! - `detect_leaks` - Whether to check for leaks at exit
+//! - `leak_exitcode` - Exit with the given code if any leaks are found
+//! - `detect_stack_use_after_return` - Whether instrumented code should
+//!   allocate its frames from the fake stack
+//!
+//! Numeric values may be given in decimal or hexadecimal (prefixed with
+//! `0x`), boolean values as `0`, `1`, `false` or `true`. Any option which is
+//! not specified is left as `None` so that the default of the component it
+//! configures is used. As with compiler-rt, unknown keys (e.g. those only
+//! supported by compiler-rt itself) are ignored with a warning, whereas
+//! malformed values are an error.
+use {
+    crate::{
+        allocator,
+        env::Env,
+        leak,
+        report::{set_exit_action, set_halt_on_error, ExitAction},
+        stats, GuestAddr,
+    },
+    alloc::{
+        string::{String, ToString},
+        vec::Vec,
+    },
+    core::{ffi::c_int, fmt::Debug, str::FromStr},
+    log::{warn, Level},
+    thiserror::Error,
+};
+
+#[cfg(feature = "guest")]
+use crate::shadow::dynamic::ShadowLayoutKind;
+
+/// The name of the environment variable containing the options
+pub const OPTIONS_ENV: &str = "ASAN_OPTIONS";
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Options {
+    pub redzone: Option<usize>,
+    pub max_redzone: Option<usize>,
+    pub quarantine_size_mb: Option<usize>,
+    pub malloc_context_size: Option<usize>,
+    pub malloc_fill_byte: Option<u8>,
+    pub max_malloc_fill_size: Option<usize>,
+    pub free_fill_byte: Option<u8>,
+    pub check_free_fill: Option<bool>,
+    pub alloc_dealloc_mismatch: Option<bool>,
+    pub new_delete_type_mismatch: Option<bool>,
+    pub forward_foreign_free: Option<bool>,
+    pub max_allocation_size_mb: Option<usize>,
+    pub allocator_may_return_null: Option<bool>,
+    pub arena_size_mb: Option<usize>,
+    pub heap_base: Option<GuestAddr>,
+    #[cfg(feature = "guest")]
+    pub shadow_layout: Option<ShadowLayoutKind>,
+    pub print_stats: Option<bool>,
+    pub log_level: Option<Level>,
+    pub halt_on_error: Option<bool>,
+    pub exitcode: Option<c_int>,
+    pub detect_leaks: Option<bool>,
+    pub leak_exitcode: Option<c_int>,
+    pub detect_stack_use_after_return: Option<bool>,
+    /// Keys which were not recognised (and hence ignored)
+    pub unknown_keys: Vec<String>,
+}
+
+impl Options {
+    /// Parse the given options string
+    pub fn parse(options: &str) -> Result<Options, ParseError> {
+        let mut result = Options::default();
+        options
+            .split([':', ',', ' ', '\t', '\n'])
+            .filter(|entry| !entry.is_empty())
+            .try_for_each(|entry| result.parse_entry(entry))?;
+        Ok(result)
+    }
+
+    /// Parse the options provided by the `ASAN_OPTIONS` environment variable
+    pub fn from_env<E: Env>() -> Result<Options, OptionsError<E>> {
+        match E::get(OPTIONS_ENV).map_err(|e| OptionsError::EnvError(e))? {
+            Some(options) => Options::parse(&options).map_err(|e| OptionsError::ParseError(e)),
+            None => Ok(Options::default()),
+        }
+    }
+
+    /// Apply those options which configure the global state of the runtime
+    /// (rather than an individual component)
+    pub fn apply(&self) {
+        for key in &self.unknown_keys {
+            warn!("options - ignoring unknown option: {}", key);
+        }
+        if let Some(halt_on_error) = self.halt_on_error {
+            set_halt_on_error(halt_on_error);
+        }
+        if let Some(code) = self.exitcode {
+            set_exit_action(ExitAction::Exit(code));
+        }
+        if let Some(detect_leaks) = self.detect_leaks {
+            leak::set_detect_leaks(detect_leaks);
+        }
+        if let Some(code) = self.leak_exitcode {
+            leak::set_exit_code(Some(code));
+        }
+        if let Some(may_return_null) = self.allocator_may_return_null {
+            allocator::set_allocator_may_return_null(may_return_null);
+        }
+        if let Some(print_stats) = self.print_stats {
+            stats::set_print_stats(print_stats);
+        }
+        #[cfg(feature = "compiler_rt")]
+        if let Some(detect) = self.detect_stack_use_after_return {
+            crate::compiler_rt::set_detect_stack_use_after_return(detect);
+        }
+    }
+
+    fn parse_entry(&mut self, entry: &str) -> Result<(), ParseError> {
+        let (key, value) = entry
+            .split_once('=')
+            .ok_or_else(|| ParseError::MissingValue(entry.to_string()))?;
+        match key {
+            "redzone" => self.redzone = Some(Self::parse_value(key, value)?),
+            "max_redzone" => self.max_redzone = Some(Self::parse_value(key, value)?),
+            "quarantine_size_mb" => self.quarantine_size_mb = Some(Self::parse_value(key, value)?),
+            "malloc_context_size" => {
+                self.malloc_context_size = Some(Self::parse_value(key, value)?)
+            }
+            "malloc_fill_byte" => self.malloc_fill_byte = Some(Self::parse_value(key, value)?),
+            "max_malloc_fill_size" => {
+                self.max_malloc_fill_size = Some(Self::parse_value(key, value)?)
+            }
+            "free_fill_byte" => self.free_fill_byte = Some(Self::parse_value(key, value)?),
+            "check_free_fill" => self.check_free_fill = Some(Self::parse_bool(key, value)?),
+            "alloc_dealloc_mismatch" => {
+                self.alloc_dealloc_mismatch = Some(Self::parse_bool(key, value)?)
+            }
+            "new_delete_type_mismatch" => {
+                self.new_delete_type_mismatch = Some(Self::parse_bool(key, value)?)
+            }
+            "forward_foreign_free" => {
+                self.forward_foreign_free = Some(Self::parse_bool(key, value)?)
+            }
+            "max_allocation_size_mb" => {
+                self.max_allocation_size_mb = Some(Self::parse_value(key, value)?)
+            }
+            "allocator_may_return_null" => {
+                self.allocator_may_return_null = Some(Self::parse_bool(key, value)?)
+            }
+            "arena_size_mb" => self.arena_size_mb = Some(Self::parse_value(key, value)?),
+            "heap_base" => match Self::parse_value(key, value)? {
+                0 => Err(ParseError::invalid_value(key, value))?,
+                base => self.heap_base = Some(base),
+            },
+            #[cfg(feature = "guest")]
+            "shadow_layout" => {
+                self.shadow_layout = Some(
+                    ShadowLayoutKind::from_str(value)
+                        .map_err(|_| ParseError::invalid_value(key, value))?,
+                )
+            }
+            "print_stats" => self.print_stats = Some(Self::parse_bool(key, value)?),
+            "log_level" => {
+                self.log_level = Some(
+                    Level::from_str(value).map_err(|_| ParseError::invalid_value(key, value))?,
+                )
+            }
+            "halt_on_error" => self.halt_on_error = Some(Self::parse_bool(key, value)?),
+            "exitcode" => self.exitcode = Some(Self::parse_value(key, value)?),
+            "detect_leaks" => self.detect_leaks = Some(Self::parse_bool(key, value)?),
+            "leak_exitcode" => self.leak_exitcode = Some(Self::parse_value(key, value)?),
+            "detect_stack_use_after_return" => {
+                self.detect_stack_use_after_return = Some(Self::parse_bool(key, value)?)
+            }
+            _ => self.unknown_keys.push(key.to_string()),
+        }
+        Ok(())
+    }
+
+    fn parse_value<T: FromStrRadix>(key: &str, value: &str) -> Result<T, ParseError> {
+        match value.strip_prefix("0x") {
+            Some(hex) => T::from_str_radix(hex, 16),
+            None => T::from_str_radix(value, 10),
+        }
+        .ok_or_else(|| ParseError::invalid_value(key, value))
+    }
+
+    fn parse_bool(key: &str, value: &str) -> Result<bool, ParseError> {
+        match value {
+            "1" | "true" => Ok(true),
+            "0" | "false" => Ok(false),
+            _ => Err(ParseError::invalid_value(key, value)),
+        }
+    }
+}
+
+trait FromStrRadix: Sized {
+    fn from_str_radix(src: &str, radix: u32) -> Option<Self>;
+}
+
+macro_rules! impl_from_str_radix {
+    ($($t:ty),*) => {
+        $(impl FromStrRadix for $t {
+            fn from_str_radix(src: &str, radix: u32) -> Option<Self> {
+                <$t>::from_str_radix(src, radix).ok()
+            }
+        })*
+    };
+}
+
+impl_from_str_radix!(u8, c_int, usize);
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    #[error("Missing value for option: {0}")]
+    MissingValue(String),
+    #[error("Invalid value for option: {0}, value: {1}")]
+    InvalidValue(String, String),
+}
+
+impl ParseError {
+    fn invalid_value(key: &str, value: &str) -> ParseError {
+        ParseError::InvalidValue(key.to_string(), value.to_string())
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum OptionsError<E: Env> {
+    #[error("Environment error: {0:?}")]
+    EnvError(E::Error),
+    #[error("Parse error: {0}")]
+    ParseError(ParseError),
+}
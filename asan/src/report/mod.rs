@@ -0,0 +1,751 @@
+//! # report
+//! This module is responsible for reporting the memory safety errors detected
+//! by the runtime. Errors are classified (e.g. heap-buffer-overflow or
+//! heap-use-after-free) using the `PoisonType` found in the shadow map at the
+//! faulting address, supplemented by any information the allocator frontend
+//! has about the allocation (or the registry has about the global variable)
+//! which owns it. The report is then logged in a
+//! format resembling that used by compiler-rt before the process is
+//! terminated.
+//!
+//! By default the process is terminated using `exit::abort` (so that fuzzers
+//! observe a crash), but this can be changed to `exit::exit` with a given exit
+//! code using `set_exit_action`.
+//!
+//! Alternatively, the runtime can be configured to recover from errors (see
+//! `set_halt_on_error`) in which case execution continues after an error is
+//! reported. Reports are then de-duplicated by their bug type, the program
+//! counter at which they occurred and the call stack at which the affected
+//! allocation was made, so that each distinct error is only logged once. A
+//! summary of the errors detected can be logged at exit using `emit_summary`.
+use {
+    crate::{
+        allocator::frontend::{AllocationInfo, AllocationType},
+        exit::{abort, exit},
+        globals::Global,
+        shadow::{dump::ShadowDump, PoisonType, Shadow},
+        GuestAddr,
+    },
+    alloc::{collections::BTreeMap, vec::Vec},
+    core::{
+        ffi::c_int,
+        fmt::{self, Arguments, Display, Formatter},
+    },
+    log::error,
+    spin::Mutex,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ErrorType {
+    HeapBufferOverflow,
+    HeapUseAfterFree,
+    GlobalBufferOverflow,
+    StackBufferOverflow,
+    DynamicStackBufferOverflow,
+    StackUseAfterReturn,
+    StackUseAfterScope,
+    DoubleFree,
+    InvalidFree,
+    WriteAfterFree,
+    AllocDeallocMismatch,
+    AllocationSizeTooBig,
+    OutOfMemory,
+    WildAccess,
+}
+
+impl ErrorType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorType::HeapBufferOverflow => "heap-buffer-overflow",
+            ErrorType::HeapUseAfterFree => "heap-use-after-free",
+            ErrorType::GlobalBufferOverflow => "global-buffer-overflow",
+            ErrorType::StackBufferOverflow => "stack-buffer-overflow",
+            ErrorType::DynamicStackBufferOverflow => "dynamic-stack-buffer-overflow",
+            ErrorType::StackUseAfterReturn => "stack-use-after-return",
+            ErrorType::StackUseAfterScope => "stack-use-after-scope",
+            ErrorType::DoubleFree => "double-free",
+            ErrorType::InvalidFree => "bad-free",
+            ErrorType::WriteAfterFree => "write-after-free",
+            ErrorType::AllocDeallocMismatch => "alloc-dealloc-mismatch",
+            ErrorType::AllocationSizeTooBig => "allocation-size-too-big",
+            ErrorType::OutOfMemory => "out-of-memory",
+            ErrorType::WildAccess => "wild-access",
+        }
+    }
+}
+
+impl Display for ErrorType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AccessType {
+    Read,
+    Write,
+}
+
+impl Display for AccessType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            AccessType::Read => write!(f, "READ"),
+            AccessType::Write => write!(f, "WRITE"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Report {
+    Access {
+        addr: GuestAddr,
+        len: usize,
+        access: AccessType,
+        fault: GuestAddr,
+        poison: Option<PoisonType>,
+        allocation: Option<AllocationInfo>,
+        global: Option<Global>,
+        shadow: Option<ShadowDump>,
+        stack: Vec<GuestAddr>,
+    },
+    Free {
+        addr: GuestAddr,
+        poison: Option<PoisonType>,
+        allocation: Option<AllocationInfo>,
+        global: Option<Global>,
+        shadow: Option<ShadowDump>,
+        stack: Vec<GuestAddr>,
+    },
+    Recycle {
+        addr: GuestAddr,
+        allocation: Option<AllocationInfo>,
+        shadow: Option<ShadowDump>,
+        stack: Vec<GuestAddr>,
+    },
+    Mismatch {
+        addr: GuestAddr,
+        alloc_type: AllocationType,
+        dealloc_type: AllocationType,
+        allocation: Option<AllocationInfo>,
+        shadow: Option<ShadowDump>,
+        stack: Vec<GuestAddr>,
+    },
+    SizeMismatch {
+        addr: GuestAddr,
+        len: usize,
+        allocation: Option<AllocationInfo>,
+        shadow: Option<ShadowDump>,
+        stack: Vec<GuestAddr>,
+    },
+    AllocationSizeTooBig {
+        len: usize,
+        max: usize,
+        stack: Vec<GuestAddr>,
+    },
+    OutOfMemory {
+        len: usize,
+        stack: Vec<GuestAddr>,
+    },
+}
+
+impl Report {
+    pub fn access(
+        addr: GuestAddr,
+        len: usize,
+        access: AccessType,
+        fault: GuestAddr,
+        poison: Option<PoisonType>,
+        allocation: Option<AllocationInfo>,
+    ) -> Report {
+        Report::Access {
+            addr,
+            len,
+            access,
+            fault,
+            poison,
+            allocation,
+            global: None,
+            shadow: None,
+            stack: Vec::new(),
+        }
+    }
+
+    pub fn free(
+        addr: GuestAddr,
+        poison: Option<PoisonType>,
+        allocation: Option<AllocationInfo>,
+    ) -> Report {
+        Report::Free {
+            addr,
+            poison,
+            allocation,
+            global: None,
+            shadow: None,
+            stack: Vec::new(),
+        }
+    }
+
+    /// A write to the freed buffer described by `allocation` (at `addr`),
+    /// detected when the buffer was recycled
+    pub fn recycle(addr: GuestAddr, allocation: AllocationInfo) -> Report {
+        Report::Recycle {
+            addr,
+            allocation: Some(allocation),
+            shadow: None,
+            stack: Vec::new(),
+        }
+    }
+
+    /// A buffer allocated by one family of functions (`alloc_type`) being
+    /// released by another (`dealloc_type`)
+    pub fn mismatch(
+        addr: GuestAddr,
+        alloc_type: AllocationType,
+        dealloc_type: AllocationType,
+        allocation: AllocationInfo,
+    ) -> Report {
+        Report::Mismatch {
+            addr,
+            alloc_type,
+            dealloc_type,
+            allocation: Some(allocation),
+            shadow: None,
+            stack: Vec::new(),
+        }
+    }
+
+    /// A buffer being released by a sized `operator delete` with the wrong
+    /// size (`len`)
+    pub fn size_mismatch(addr: GuestAddr, len: usize, allocation: AllocationInfo) -> Report {
+        Report::SizeMismatch {
+            addr,
+            len,
+            allocation: Some(allocation),
+            shadow: None,
+            stack: Vec::new(),
+        }
+    }
+
+    /// A request for an allocation of `len` bytes which exceeds the maximum
+    /// supported size (`max`)
+    pub fn allocation_size_too_big(len: usize, max: usize) -> Report {
+        Report::AllocationSizeTooBig {
+            len,
+            max,
+            stack: Vec::new(),
+        }
+    }
+
+    /// A request for an allocation of `len` bytes which the backend was unable
+    /// to satisfy
+    pub fn out_of_memory(len: usize) -> Report {
+        Report::OutOfMemory {
+            len,
+            stack: Vec::new(),
+        }
+    }
+
+    /// Attach the call stack at which the error occurred
+    pub fn with_stack(mut self, frames: Vec<GuestAddr>) -> Report {
+        match &mut self {
+            Report::Access { stack, .. }
+            | Report::Free { stack, .. }
+            | Report::Recycle { stack, .. }
+            | Report::Mismatch { stack, .. }
+            | Report::SizeMismatch { stack, .. }
+            | Report::AllocationSizeTooBig { stack, .. }
+            | Report::OutOfMemory { stack, .. } => *stack = frames,
+        }
+        self
+    }
+
+    /// Attach the shadow bytes surrounding the faulting address (only
+    /// applicable to reports of memory errors)
+    pub fn with_shadow(mut self, dump: Option<ShadowDump>) -> Report {
+        match &mut self {
+            Report::Access { shadow, .. }
+            | Report::Free { shadow, .. }
+            | Report::Recycle { shadow, .. }
+            | Report::Mismatch { shadow, .. }
+            | Report::SizeMismatch { shadow, .. } => *shadow = dump,
+            Report::AllocationSizeTooBig { .. } | Report::OutOfMemory { .. } => (),
+        }
+        self
+    }
+
+    /// Attach the global variable which owns the faulting address (only
+    /// applicable to access and free reports)
+    pub fn with_global(mut self, owner: Option<Global>) -> Report {
+        if let Report::Access { global, .. } | Report::Free { global, .. } = &mut self {
+            *global = owner;
+        }
+        self
+    }
+
+    pub fn stack(&self) -> &[GuestAddr] {
+        match self {
+            Report::Access { stack, .. }
+            | Report::Free { stack, .. }
+            | Report::Recycle { stack, .. }
+            | Report::Mismatch { stack, .. }
+            | Report::SizeMismatch { stack, .. }
+            | Report::AllocationSizeTooBig { stack, .. }
+            | Report::OutOfMemory { stack, .. } => stack,
+        }
+    }
+
+    /// The program counter at which the error occurred (if known)
+    pub fn pc(&self) -> Option<GuestAddr> {
+        self.stack().first().copied()
+    }
+
+    pub fn shadow(&self) -> Option<&ShadowDump> {
+        match self {
+            Report::Access { shadow, .. }
+            | Report::Free { shadow, .. }
+            | Report::Recycle { shadow, .. }
+            | Report::Mismatch { shadow, .. }
+            | Report::SizeMismatch { shadow, .. } => shadow.as_ref(),
+            Report::AllocationSizeTooBig { .. } | Report::OutOfMemory { .. } => None,
+        }
+    }
+
+    fn allocation(&self) -> Option<&AllocationInfo> {
+        match self {
+            Report::Access { allocation, .. }
+            | Report::Free { allocation, .. }
+            | Report::Recycle { allocation, .. }
+            | Report::Mismatch { allocation, .. }
+            | Report::SizeMismatch { allocation, .. } => allocation.as_ref(),
+            Report::AllocationSizeTooBig { .. } | Report::OutOfMemory { .. } => None,
+        }
+    }
+
+    fn key(&self) -> ReportKey {
+        ReportKey {
+            error_type: self.error_type(),
+            pc: self.pc(),
+            alloc_stack: self
+                .allocation()
+                .map(|a| a.alloc_stack.clone())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn error_type(&self) -> ErrorType {
+        match self {
+            Report::Access {
+                poison, allocation, ..
+            } => match (poison, allocation) {
+                (Some(PoisonType::AsanHeapFreed), _) => ErrorType::HeapUseAfterFree,
+                (
+                    Some(
+                        PoisonType::AsanHeapLeftRz
+                        | PoisonType::AsanHeapRightRz
+                        | PoisonType::AsanHeapRz,
+                    ),
+                    _,
+                ) => ErrorType::HeapBufferOverflow,
+                (Some(PoisonType::AsanGlobalRz), _) => ErrorType::GlobalBufferOverflow,
+                (
+                    Some(
+                        PoisonType::AsanStackLeftRz
+                        | PoisonType::AsanStackMidRz
+                        | PoisonType::AsanStackRightRz
+                        | PoisonType::AsanStackRz,
+                    ),
+                    _,
+                ) => ErrorType::StackBufferOverflow,
+                (Some(PoisonType::AsanAllocaLeftRz | PoisonType::AsanAllocaRightRz), _) => {
+                    ErrorType::DynamicStackBufferOverflow
+                }
+                (Some(PoisonType::AsanStackFreed), _) => ErrorType::StackUseAfterReturn,
+                (Some(PoisonType::AsanStackOoscope), _) => ErrorType::StackUseAfterScope,
+                (_, Some(AllocationInfo { freed: true, .. })) => ErrorType::HeapUseAfterFree,
+                (_, Some(AllocationInfo { freed: false, .. })) => ErrorType::HeapBufferOverflow,
+                (_, None) => ErrorType::WildAccess,
+            },
+            Report::Free {
+                addr,
+                poison,
+                allocation,
+                ..
+            } => match (poison, allocation) {
+                (_, Some(a)) if a.freed && a.addr == *addr => ErrorType::DoubleFree,
+                (Some(PoisonType::AsanHeapFreed), None) => ErrorType::DoubleFree,
+                _ => ErrorType::InvalidFree,
+            },
+            Report::Recycle { .. } => ErrorType::WriteAfterFree,
+            Report::Mismatch { .. } | Report::SizeMismatch { .. } => {
+                ErrorType::AllocDeallocMismatch
+            }
+            Report::AllocationSizeTooBig { .. } => ErrorType::AllocationSizeTooBig,
+            Report::OutOfMemory { .. } => ErrorType::OutOfMemory,
+        }
+    }
+
+    /// Log the report and terminate the process, or if recovering from errors,
+    /// log the report only if an equivalent one has not already been logged
+    pub fn emit(&self) {
+        if halt_on_error() {
+            error!("{}", self);
+            die();
+        }
+
+        let mut reported = REPORTED.lock();
+        let count = reported.entry(self.key()).or_default();
+        *count += 1;
+        if *count == 1 {
+            error!("{}", self);
+        }
+    }
+
+    fn fmt_location(
+        f: &mut Formatter,
+        addr: GuestAddr,
+        allocation: &AllocationInfo,
+    ) -> fmt::Result {
+        let start = allocation.addr;
+        let end = allocation.addr + allocation.len;
+        write!(f, "0x{:x} is located ", addr)?;
+        if addr < start {
+            write!(f, "{} bytes before", start - addr)?;
+        } else if addr >= end {
+            write!(f, "{} bytes after", addr - end)?;
+        } else {
+            write!(f, "{} bytes inside of", addr - start)?;
+        }
+        writeln!(
+            f,
+            " {}-byte region [0x{:x},0x{:x})",
+            allocation.len, start, end
+        )?;
+        if allocation.freed {
+            writeln!(f, "The region has been freed")?;
+        }
+        if let Some(free_stack) = &allocation.free_stack {
+            writeln!(f, "freed here:")?;
+            fmt_stack(f, free_stack)?;
+            writeln!(f, "previously allocated here:")?;
+        } else {
+            writeln!(f, "allocated here:")?;
+        }
+        fmt_stack(f, &allocation.alloc_stack)
+    }
+
+    fn fmt_global(f: &mut Formatter, addr: GuestAddr, global: &Global) -> fmt::Result {
+        let end = global.addr + global.size;
+        write!(f, "0x{:x} is located ", addr)?;
+        if addr >= end {
+            write!(f, "{} bytes after", addr - end)?;
+        } else {
+            write!(f, "{} bytes inside of", addr - global.addr)?;
+        }
+        writeln!(
+            f,
+            " global variable '{}' defined in '{}' (0x{:x}) of size {}",
+            global.name, global.module, global.addr, global.size
+        )
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let error_type = self.error_type();
+        match self {
+            Report::Access {
+                addr,
+                len,
+                access,
+                fault,
+                poison,
+                allocation,
+                global,
+                stack,
+                ..
+            } => {
+                writeln!(
+                    f,
+                    "AddressSanitizer: {} on address 0x{:x}",
+                    error_type, fault
+                )?;
+                writeln!(
+                    f,
+                    "{} of size {} at 0x{:x} (access: 0x{:x}-0x{:x})",
+                    access,
+                    len,
+                    fault,
+                    addr,
+                    addr + len
+                )?;
+                if !stack.is_empty() {
+                    fmt_stack(f, stack)?;
+                }
+                if let Some(poison) = poison {
+                    writeln!(f, "Shadow value: {:?} (0x{:02x})", poison, *poison as u8)?;
+                }
+                if let Some(allocation) = allocation {
+                    Self::fmt_location(f, *fault, allocation)?;
+                }
+                if let Some(global) = global {
+                    Self::fmt_global(f, *fault, global)?;
+                }
+            }
+            Report::Free {
+                addr,
+                poison,
+                allocation,
+                global,
+                stack,
+                ..
+            } => {
+                match error_type {
+                    ErrorType::DoubleFree => {
+                        writeln!(f, "AddressSanitizer: attempting double-free on 0x{:x}", addr)?
+                    }
+                    _ => writeln!(
+                        f,
+                        "AddressSanitizer: attempting free on address which was not malloc()-ed: 0x{:x}",
+                        addr
+                    )?,
+                }
+                if !stack.is_empty() {
+                    fmt_stack(f, stack)?;
+                }
+                match (allocation, poison) {
+                    (Some(allocation), _) => Self::fmt_location(f, *addr, allocation)?,
+                    (None, Some(poison)) => {
+                        writeln!(f, "Shadow value: {:?} (0x{:02x})", poison, *poison as u8)?
+                    }
+                    (None, None) => (),
+                }
+                if let Some(global) = global {
+                    Self::fmt_global(f, *addr, global)?;
+                }
+            }
+            Report::Recycle {
+                addr,
+                allocation,
+                stack,
+                ..
+            } => {
+                writeln!(
+                    f,
+                    "AddressSanitizer: write-after-free detected on recycle at 0x{:x}",
+                    addr
+                )?;
+                if !stack.is_empty() {
+                    fmt_stack(f, stack)?;
+                }
+                if let Some(allocation) = allocation {
+                    Self::fmt_location(f, *addr, allocation)?;
+                }
+            }
+            Report::Mismatch {
+                addr,
+                alloc_type,
+                dealloc_type,
+                allocation,
+                stack,
+                ..
+            } => {
+                writeln!(
+                    f,
+                    "AddressSanitizer: {} ({} vs {}) on 0x{:x}",
+                    error_type,
+                    alloc_type.alloc_name(),
+                    dealloc_type.dealloc_name(),
+                    addr
+                )?;
+                if !stack.is_empty() {
+                    fmt_stack(f, stack)?;
+                }
+                if let Some(allocation) = allocation {
+                    Self::fmt_location(f, *addr, allocation)?;
+                }
+            }
+            Report::SizeMismatch {
+                addr,
+                len,
+                allocation,
+                stack,
+                ..
+            } => {
+                writeln!(f, "AddressSanitizer: {} on 0x{:x}", error_type, addr)?;
+                if let Some(allocation) = allocation {
+                    writeln!(
+                        f,
+                        "object passed to delete has wrong type: size of the allocated type: {} bytes, size of the deallocated type: {} bytes",
+                        allocation.len, len
+                    )?;
+                }
+                if !stack.is_empty() {
+                    fmt_stack(f, stack)?;
+                }
+                if let Some(allocation) = allocation {
+                    Self::fmt_location(f, *addr, allocation)?;
+                }
+            }
+            Report::AllocationSizeTooBig { len, max, stack } => {
+                writeln!(
+                    f,
+                    "AddressSanitizer: requested allocation size 0x{:x} exceeds maximum supported size of 0x{:x}",
+                    len, max
+                )?;
+                if !stack.is_empty() {
+                    fmt_stack(f, stack)?;
+                }
+            }
+            Report::OutOfMemory { len, stack } => {
+                writeln!(
+                    f,
+                    "AddressSanitizer: out of memory: allocator is trying to allocate 0x{:x} bytes",
+                    len
+                )?;
+                if !stack.is_empty() {
+                    fmt_stack(f, stack)?;
+                }
+            }
+        }
+        if let Some(shadow) = self.shadow() {
+            writeln!(f, "{}", shadow)?;
+        }
+        write!(f, "SUMMARY: AddressSanitizer: {}", error_type)
+    }
+}
+
+pub(crate) fn fmt_stack(f: &mut Formatter, frames: &[GuestAddr]) -> fmt::Result {
+    if frames.is_empty() {
+        return writeln!(f, "    <empty stack>");
+    }
+    frames
+        .iter()
+        .enumerate()
+        .try_for_each(|(i, pc)| writeln!(f, "    #{} 0x{:x}", i, pc))
+}
+
+/// Find the address of the first poisoned byte within the given range. If no
+/// individual byte is found to be poisoned (or the shadow could not be
+/// queried), then the start of the range is returned.
+pub fn find_fault<S: Shadow>(shadow: &S, start: GuestAddr, len: usize) -> GuestAddr {
+    shadow
+        .find_poison(start, len)
+        .ok()
+        .flatten()
+        .map_or(start, |fault| fault.addr)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct ReportKey {
+    error_type: ErrorType,
+    pc: Option<GuestAddr>,
+    alloc_stack: Vec<GuestAddr>,
+}
+
+static REPORTED: Mutex<BTreeMap<ReportKey, usize>> = Mutex::new(BTreeMap::new());
+
+/// A distinct error reported whilst recovering from errors
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReportedError {
+    pub error_type: ErrorType,
+    pub pc: Option<GuestAddr>,
+    pub count: usize,
+}
+
+/// The distinct errors which have been reported whilst recovering from errors
+pub struct Summary {
+    errors: Vec<ReportedError>,
+}
+
+impl Summary {
+    pub fn errors(&self) -> &[ReportedError] {
+        &self.errors
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "AddressSanitizer: {} unique error(s) detected ({} in total)",
+            self.errors.len(),
+            self.errors.iter().map(|e| e.count).sum::<usize>()
+        )?;
+        for error in &self.errors {
+            write!(f, "    {}", error.error_type)?;
+            if let Some(pc) = error.pc {
+                write!(f, " at pc 0x{:x}", pc)?;
+            }
+            writeln!(f, " ({} time(s))", error.count)?;
+        }
+        write!(
+            f,
+            "SUMMARY: AddressSanitizer: {} unique error(s)",
+            self.errors.len()
+        )
+    }
+}
+
+pub fn summary() -> Summary {
+    let errors = REPORTED
+        .lock()
+        .iter()
+        .map(|(key, count)| ReportedError {
+            error_type: key.error_type,
+            pc: key.pc,
+            count: *count,
+        })
+        .collect();
+    Summary { errors }
+}
+
+/// Log the summary of the errors reported (if any)
+pub fn emit_summary() {
+    let summary = summary();
+    if !summary.errors.is_empty() {
+        error!("{}", summary);
+    }
+}
+
+static HALT_ON_ERROR: Mutex<bool> = Mutex::new(true);
+
+/// Set whether the process should be terminated when an error is reported
+/// (otherwise execution continues after the report is logged)
+pub fn set_halt_on_error(halt_on_error: bool) {
+    *HALT_ON_ERROR.lock() = halt_on_error;
+}
+
+pub fn halt_on_error() -> bool {
+    *HALT_ON_ERROR.lock()
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExitAction {
+    Abort,
+    Exit(c_int),
+}
+
+static EXIT_ACTION: Mutex<ExitAction> = Mutex::new(ExitAction::Abort);
+
+pub fn set_exit_action(action: ExitAction) {
+    *EXIT_ACTION.lock() = action;
+}
+
+pub fn exit_action() -> ExitAction {
+    *EXIT_ACTION.lock()
+}
+
+/// Report an unexpected internal error (e.g. an error returned by one of the
+/// components of the runtime) and terminate the process
+pub fn fatal(msg: Arguments) -> ! {
+    error!("AddressSanitizer: internal error: {}", msg);
+    die();
+}
+
+/// Terminate the process according to the configured `ExitAction`
+pub fn die() -> ! {
+    match exit_action() {
+        ExitAction::Abort => abort(),
+        ExitAction::Exit(code) => exit(code),
+    }
+}
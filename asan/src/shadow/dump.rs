@@ -0,0 +1,149 @@
+//! # dump
+//! This module renders the shadow bytes surrounding an address (typically that
+//! of a memory error) in the same grid as compiler-rt, e.g.:
+//!
+//! ```text
+//! Shadow bytes around the buggy address:
+//!   0x602000000f00: fa fa fa fa fa fa fa fa fa fa fa fa fa fa fa fa
+//! =>0x602000001000: fa fa 00 00 00 00[04]fb fb fb fa fa fa fa fa fa
+//!   0x602000001080: fa fa fa fa fa fa fa fa fa fa fa fa fa fa fa fa
+//! Shadow byte legend (one shadow byte represents 8 application bytes):
+//!   Addressable:           00
+//!   Partially addressable: 01 02 03 04 05 06 07
+//!   ...
+//! ```
+//!
+//! Unlike compiler-rt, each row is labelled with the address of the
+//! application memory it describes (rather than that of the shadow bytes
+//! themselves), since the shadow map may not be accessible to the guest.
+//! Rows which cannot be read (e.g. since they lie outside of the application
+//! memory) are omitted.
+use {
+    crate::{
+        shadow::{PoisonType, Shadow},
+        GuestAddr,
+    },
+    alloc::vec::Vec,
+    core::fmt::{self, Display, Formatter},
+};
+
+/// The number of shadow bytes displayed in each row
+pub const SHADOW_BYTES_PER_ROW: usize = 16;
+
+/// The number of rows displayed either side of that containing the address
+pub const SHADOW_CONTEXT_ROWS: usize = 5;
+
+const LEGEND: [(&str, PoisonType); 15] = [
+    ("Heap left redzone:", PoisonType::AsanHeapLeftRz),
+    ("Heap right redzone:", PoisonType::AsanHeapRightRz),
+    ("Heap redzone:", PoisonType::AsanHeapRz),
+    ("Freed heap region:", PoisonType::AsanHeapFreed),
+    ("Stack left redzone:", PoisonType::AsanStackLeftRz),
+    ("Stack mid redzone:", PoisonType::AsanStackMidRz),
+    ("Stack right redzone:", PoisonType::AsanStackRightRz),
+    ("Stack redzone:", PoisonType::AsanStackRz),
+    ("Stack after return:", PoisonType::AsanStackFreed),
+    ("Stack use after scope:", PoisonType::AsanStackOoscope),
+    ("Global redzone:", PoisonType::AsanGlobalRz),
+    ("Poisoned by user:", PoisonType::AsanUser),
+    ("Array cookie:", PoisonType::AsanArrayCookie),
+    ("Left alloca redzone:", PoisonType::AsanAllocaLeftRz),
+    ("Right alloca redzone:", PoisonType::AsanAllocaRightRz),
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShadowRow {
+    /// The address of the application memory described by the first byte
+    pub addr: GuestAddr,
+    pub bytes: [u8; SHADOW_BYTES_PER_ROW],
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShadowDump {
+    pub addr: GuestAddr,
+    pub granularity: usize,
+    pub rows: Vec<ShadowRow>,
+}
+
+impl ShadowDump {
+    /// Read the shadow bytes surrounding `addr` from the given shadow map. An
+    /// error is returned only if the row containing `addr` cannot be read.
+    pub fn new<S: Shadow>(shadow: &S, addr: GuestAddr) -> Result<ShadowDump, S::Error> {
+        let granularity = shadow.granularity();
+        let row_len = granularity * SHADOW_BYTES_PER_ROW;
+        let fault_row = addr & !(row_len - 1);
+        let row_addrs = (1..=SHADOW_CONTEXT_ROWS)
+            .rev()
+            .map(|i| fault_row.checked_sub(i * row_len))
+            .chain((0..=SHADOW_CONTEXT_ROWS).map(|i| fault_row.checked_add(i * row_len)))
+            .flatten();
+        let mut rows = Vec::with_capacity(2 * SHADOW_CONTEXT_ROWS + 1);
+        for row in row_addrs {
+            let mut bytes = [0u8; SHADOW_BYTES_PER_ROW];
+            match shadow.read_shadow(row, &mut bytes) {
+                Ok(()) => rows.push(ShadowRow { addr: row, bytes }),
+                Err(e) if row == fault_row => Err(e)?,
+                Err(_) => (),
+            }
+        }
+        Ok(ShadowDump {
+            addr,
+            granularity,
+            rows,
+        })
+    }
+
+    /// The value of the shadow byte describing `addr`
+    pub fn value(&self) -> Option<u8> {
+        let row_len = self.granularity * SHADOW_BYTES_PER_ROW;
+        self.rows
+            .iter()
+            .find(|r| (r.addr..r.addr + row_len).contains(&self.addr))
+            .map(|r| r.bytes[(self.addr - r.addr) / self.granularity])
+    }
+}
+
+impl Display for ShadowDump {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let row_len = self.granularity * SHADOW_BYTES_PER_ROW;
+        writeln!(f, "Shadow bytes around the buggy address:")?;
+        for row in &self.rows {
+            let marked = (row.addr..row.addr + row_len)
+                .contains(&self.addr)
+                .then(|| (self.addr - row.addr) / self.granularity);
+            let prefix = if marked.is_some() { "=>" } else { "  " };
+            write!(f, "{}0x{:x}:", prefix, row.addr)?;
+            for (i, byte) in row.bytes.iter().enumerate() {
+                let sep = match marked {
+                    Some(m) if m == i => '[',
+                    Some(m) if m + 1 == i => ']',
+                    _ => ' ',
+                };
+                write!(f, "{}{:02x}", sep, byte)?;
+            }
+            if marked == Some(SHADOW_BYTES_PER_ROW - 1) {
+                write!(f, "]")?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(
+            f,
+            "Shadow byte legend (one shadow byte represents {} application bytes):",
+            self.granularity
+        )?;
+        writeln!(
+            f,
+            "  {:<22} {:02x}",
+            "Addressable:",
+            PoisonType::AsanValid as u8
+        )?;
+        write!(f, "  {:<22}", "Partially addressable:")?;
+        for partial in 1..self.granularity {
+            write!(f, " {:02x}", partial)?;
+        }
+        for (name, poison) in LEGEND {
+            write!(f, "\n    {:<22} {:02x}", name, poison as u8)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,539 @@
+//! # dynamic
+//! The layout of a `GuestShadow` is fixed by its type. This module instead
+//! selects the layout at runtime, either as configured (see
+//! `Options::shadow_layout`) or by probing the existing mappings of the
+//! process (read from `/proc/self/maps`) for the first of the candidate
+//! layouts for the target which does not collide with them. On aarch64, for
+//! example, this determines the size of the address space configured by the
+//! kernel (39, 42 or 48 bits).
+//!
+//! Note that the candidate layouts for each target share the same shadow
+//! offset, since instrumented code (or the TCG code emitted by QEMU) computes
+//! the shadow address itself. Other layouts may be selected explicitly where
+//! the instrumentation is configured to match, including those whose shadow
+//! bytes each describe 16 or 32 bytes of memory (see `ScaledShadowLayout`),
+//! named with a suffix of `_g16` or `_g32` respectively.
+use {
+    crate::{
+        maps::{entry::MapEntry, iterator::MapIterator, MapReader},
+        mmap::Mmap,
+        options::Options,
+        shadow::{
+            guest::{GuestShadow, GuestShadowError, ShadowLayout},
+            PoisonType, Shadow, ShadowFault,
+        },
+        GuestAddr,
+    },
+    alloc::{
+        string::{String, ToString},
+        vec::Vec,
+    },
+    core::{
+        fmt::{self, Debug, Display, Formatter},
+        ops::Range,
+        str::FromStr,
+    },
+    log::{debug, info},
+    thiserror::Error,
+};
+
+#[cfg(target_pointer_width = "64")]
+use crate::shadow::guest::{
+    Aarch64Va39ShadowLayout, Aarch64Va42ShadowLayout, Aarch64Va48ShadowLayout,
+    X86_64HighShadowLayout, X86_64ShadowLayout,
+};
+
+#[cfg(target_pointer_width = "32")]
+use crate::shadow::guest::{I386ShadowLayout, Ppc32ShadowLayout};
+
+use crate::shadow::guest::ScaledShadowLayout;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ShadowLayoutKind {
+    #[cfg(target_pointer_width = "64")]
+    X86_64,
+    #[cfg(target_pointer_width = "64")]
+    X86_64High,
+    #[cfg(target_pointer_width = "64")]
+    Aarch64Va39,
+    #[cfg(target_pointer_width = "64")]
+    Aarch64Va42,
+    #[cfg(target_pointer_width = "64")]
+    Aarch64Va48,
+    #[cfg(target_pointer_width = "64")]
+    X86_64Granule16,
+    #[cfg(target_pointer_width = "64")]
+    X86_64Granule32,
+    #[cfg(target_pointer_width = "32")]
+    I386,
+    #[cfg(target_pointer_width = "32")]
+    Ppc32,
+    #[cfg(target_pointer_width = "32")]
+    I386Granule16,
+    #[cfg(target_pointer_width = "32")]
+    I386Granule32,
+    #[cfg(target_pointer_width = "32")]
+    Ppc32Granule16,
+    #[cfg(target_pointer_width = "32")]
+    Ppc32Granule32,
+}
+
+impl ShadowLayoutKind {
+    /// The layouts (in order of preference) which are probed if none is
+    /// configured
+    #[cfg(target_arch = "aarch64")]
+    pub const CANDIDATES: &'static [ShadowLayoutKind] = &[
+        ShadowLayoutKind::Aarch64Va39,
+        ShadowLayoutKind::Aarch64Va42,
+        ShadowLayoutKind::Aarch64Va48,
+    ];
+
+    #[cfg(all(target_pointer_width = "64", not(target_arch = "aarch64")))]
+    pub const CANDIDATES: &'static [ShadowLayoutKind] = &[ShadowLayoutKind::X86_64];
+
+    #[cfg(target_arch = "powerpc")]
+    pub const CANDIDATES: &'static [ShadowLayoutKind] =
+        &[ShadowLayoutKind::Ppc32, ShadowLayoutKind::I386];
+
+    #[cfg(all(target_pointer_width = "32", not(target_arch = "powerpc")))]
+    pub const CANDIDATES: &'static [ShadowLayoutKind] = &[ShadowLayoutKind::I386];
+
+    const NAMES: &'static [(&'static str, ShadowLayoutKind)] = &[
+        #[cfg(target_pointer_width = "64")]
+        ("x86_64", ShadowLayoutKind::X86_64),
+        #[cfg(target_pointer_width = "64")]
+        ("x86_64_high", ShadowLayoutKind::X86_64High),
+        #[cfg(target_pointer_width = "64")]
+        ("aarch64_39", ShadowLayoutKind::Aarch64Va39),
+        #[cfg(target_pointer_width = "64")]
+        ("aarch64_42", ShadowLayoutKind::Aarch64Va42),
+        #[cfg(target_pointer_width = "64")]
+        ("aarch64_48", ShadowLayoutKind::Aarch64Va48),
+        #[cfg(target_pointer_width = "64")]
+        ("x86_64_g16", ShadowLayoutKind::X86_64Granule16),
+        #[cfg(target_pointer_width = "64")]
+        ("x86_64_g32", ShadowLayoutKind::X86_64Granule32),
+        #[cfg(target_pointer_width = "32")]
+        ("i386", ShadowLayoutKind::I386),
+        #[cfg(target_pointer_width = "32")]
+        ("ppc32", ShadowLayoutKind::Ppc32),
+        #[cfg(target_pointer_width = "32")]
+        ("i386_g16", ShadowLayoutKind::I386Granule16),
+        #[cfg(target_pointer_width = "32")]
+        ("i386_g32", ShadowLayoutKind::I386Granule32),
+        #[cfg(target_pointer_width = "32")]
+        ("ppc32_g16", ShadowLayoutKind::Ppc32Granule16),
+        #[cfg(target_pointer_width = "32")]
+        ("ppc32_g32", ShadowLayoutKind::Ppc32Granule32),
+    ];
+
+    /// Find the first of the given mappings which collides with the layout
+    /// (see `ShadowLayout::collision`)
+    pub fn collision(self, mappings: &[MapEntry]) -> Option<ShadowCollision> {
+        let collision = match self {
+            #[cfg(target_pointer_width = "64")]
+            ShadowLayoutKind::X86_64 => X86_64ShadowLayout::collision(mappings),
+            #[cfg(target_pointer_width = "64")]
+            ShadowLayoutKind::X86_64High => X86_64HighShadowLayout::collision(mappings),
+            #[cfg(target_pointer_width = "64")]
+            ShadowLayoutKind::Aarch64Va39 => Aarch64Va39ShadowLayout::collision(mappings),
+            #[cfg(target_pointer_width = "64")]
+            ShadowLayoutKind::Aarch64Va42 => Aarch64Va42ShadowLayout::collision(mappings),
+            #[cfg(target_pointer_width = "64")]
+            ShadowLayoutKind::Aarch64Va48 => Aarch64Va48ShadowLayout::collision(mappings),
+            #[cfg(target_pointer_width = "64")]
+            ShadowLayoutKind::X86_64Granule16 => {
+                ScaledShadowLayout::<X86_64ShadowLayout, 4>::collision(mappings)
+            }
+            #[cfg(target_pointer_width = "64")]
+            ShadowLayoutKind::X86_64Granule32 => {
+                ScaledShadowLayout::<X86_64ShadowLayout, 5>::collision(mappings)
+            }
+            #[cfg(target_pointer_width = "32")]
+            ShadowLayoutKind::I386 => I386ShadowLayout::collision(mappings),
+            #[cfg(target_pointer_width = "32")]
+            ShadowLayoutKind::Ppc32 => Ppc32ShadowLayout::collision(mappings),
+            #[cfg(target_pointer_width = "32")]
+            ShadowLayoutKind::I386Granule16 => {
+                ScaledShadowLayout::<I386ShadowLayout, 4>::collision(mappings)
+            }
+            #[cfg(target_pointer_width = "32")]
+            ShadowLayoutKind::I386Granule32 => {
+                ScaledShadowLayout::<I386ShadowLayout, 5>::collision(mappings)
+            }
+            #[cfg(target_pointer_width = "32")]
+            ShadowLayoutKind::Ppc32Granule16 => {
+                ScaledShadowLayout::<Ppc32ShadowLayout, 4>::collision(mappings)
+            }
+            #[cfg(target_pointer_width = "32")]
+            ShadowLayoutKind::Ppc32Granule32 => {
+                ScaledShadowLayout::<Ppc32ShadowLayout, 5>::collision(mappings)
+            }
+        };
+        collision.map(|m| ShadowCollision {
+            layout: self,
+            base: m.base(),
+            limit: m.limit(),
+            path: m.path().to_string(),
+        })
+    }
+
+    /// Select the first of the candidate layouts which doesn't collide with
+    /// the given mappings, otherwise return the collision found for each
+    pub fn probe(
+        candidates: &[ShadowLayoutKind],
+        mappings: &[MapEntry],
+    ) -> Result<ShadowLayoutKind, Vec<ShadowCollision>> {
+        let mut collisions = Vec::with_capacity(candidates.len());
+        for candidate in candidates {
+            match candidate.collision(mappings) {
+                Some(collision) => {
+                    debug!("probe - {}", collision);
+                    collisions.push(collision);
+                }
+                None => return Ok(*candidate),
+            }
+        }
+        Err(collisions)
+    }
+}
+
+impl Display for ShadowLayoutKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let (name, _) = Self::NAMES
+            .iter()
+            .find(|(_, kind)| kind == self)
+            .ok_or(fmt::Error)?;
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for ShadowLayoutKind {
+    type Err = ShadowLayoutKindError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::NAMES
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, kind)| *kind)
+            .ok_or_else(|| ShadowLayoutKindError::UnknownLayout(s.to_string()))
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ShadowLayoutKindError {
+    #[error("Unknown shadow layout: {0}")]
+    UnknownLayout(String),
+}
+
+/// An existing mapping which prevents the use of a layout
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShadowCollision {
+    pub layout: ShadowLayoutKind,
+    pub base: GuestAddr,
+    pub limit: GuestAddr,
+    pub path: String,
+}
+
+impl Display for ShadowCollision {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "layout: {}, collides with mapping: 0x{:x}-0x{:x} {}",
+            self.layout, self.base, self.limit, self.path
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum DynamicGuestShadow<M: Mmap> {
+    #[cfg(target_pointer_width = "64")]
+    X86_64(GuestShadow<M, X86_64ShadowLayout>),
+    #[cfg(target_pointer_width = "64")]
+    X86_64High(GuestShadow<M, X86_64HighShadowLayout>),
+    #[cfg(target_pointer_width = "64")]
+    Aarch64Va39(GuestShadow<M, Aarch64Va39ShadowLayout>),
+    #[cfg(target_pointer_width = "64")]
+    Aarch64Va42(GuestShadow<M, Aarch64Va42ShadowLayout>),
+    #[cfg(target_pointer_width = "64")]
+    Aarch64Va48(GuestShadow<M, Aarch64Va48ShadowLayout>),
+    #[cfg(target_pointer_width = "64")]
+    X86_64Granule16(GuestShadow<M, ScaledShadowLayout<X86_64ShadowLayout, 4>>),
+    #[cfg(target_pointer_width = "64")]
+    X86_64Granule32(GuestShadow<M, ScaledShadowLayout<X86_64ShadowLayout, 5>>),
+    #[cfg(target_pointer_width = "32")]
+    I386(GuestShadow<M, I386ShadowLayout>),
+    #[cfg(target_pointer_width = "32")]
+    Ppc32(GuestShadow<M, Ppc32ShadowLayout>),
+    #[cfg(target_pointer_width = "32")]
+    I386Granule16(GuestShadow<M, ScaledShadowLayout<I386ShadowLayout, 4>>),
+    #[cfg(target_pointer_width = "32")]
+    I386Granule32(GuestShadow<M, ScaledShadowLayout<I386ShadowLayout, 5>>),
+    #[cfg(target_pointer_width = "32")]
+    Ppc32Granule16(GuestShadow<M, ScaledShadowLayout<Ppc32ShadowLayout, 4>>),
+    #[cfg(target_pointer_width = "32")]
+    Ppc32Granule32(GuestShadow<M, ScaledShadowLayout<Ppc32ShadowLayout, 5>>),
+}
+
+/// Apply the given expression to the `GuestShadow` of whichever layout is in
+/// use
+macro_rules! dispatch {
+    ($self:expr, $shadow:ident => $e:expr) => {
+        match $self {
+            #[cfg(target_pointer_width = "64")]
+            DynamicGuestShadow::X86_64($shadow) => $e,
+            #[cfg(target_pointer_width = "64")]
+            DynamicGuestShadow::X86_64High($shadow) => $e,
+            #[cfg(target_pointer_width = "64")]
+            DynamicGuestShadow::Aarch64Va39($shadow) => $e,
+            #[cfg(target_pointer_width = "64")]
+            DynamicGuestShadow::Aarch64Va42($shadow) => $e,
+            #[cfg(target_pointer_width = "64")]
+            DynamicGuestShadow::Aarch64Va48($shadow) => $e,
+            #[cfg(target_pointer_width = "64")]
+            DynamicGuestShadow::X86_64Granule16($shadow) => $e,
+            #[cfg(target_pointer_width = "64")]
+            DynamicGuestShadow::X86_64Granule32($shadow) => $e,
+            #[cfg(target_pointer_width = "32")]
+            DynamicGuestShadow::I386($shadow) => $e,
+            #[cfg(target_pointer_width = "32")]
+            DynamicGuestShadow::Ppc32($shadow) => $e,
+            #[cfg(target_pointer_width = "32")]
+            DynamicGuestShadow::I386Granule16($shadow) => $e,
+            #[cfg(target_pointer_width = "32")]
+            DynamicGuestShadow::I386Granule32($shadow) => $e,
+            #[cfg(target_pointer_width = "32")]
+            DynamicGuestShadow::Ppc32Granule16($shadow) => $e,
+            #[cfg(target_pointer_width = "32")]
+            DynamicGuestShadow::Ppc32Granule32($shadow) => $e,
+        }
+    };
+}
+
+impl<M: Mmap> DynamicGuestShadow<M> {
+    /// Create the shadow using the given layout, or if none is given, the
+    /// first of the candidate layouts for the target which doesn't collide
+    /// with the existing mappings of the process. In either case, an error is
+    /// returned if the layout would collide with an existing mapping.
+    pub fn new<R: MapReader>(
+        layout: Option<ShadowLayoutKind>,
+    ) -> Result<DynamicGuestShadow<M>, DynamicGuestShadowError<M, R>> {
+        let reader = R::new().map_err(|e| DynamicGuestShadowError::MapReaderError(e))?;
+        let mappings = MapIterator::new(reader).collect::<Vec<MapEntry>>();
+        let kind = match layout {
+            Some(kind) => match kind.collision(&mappings) {
+                Some(collision) => Err(DynamicGuestShadowError::LayoutCollision(collision))?,
+                None => kind,
+            },
+            None => ShadowLayoutKind::probe(ShadowLayoutKind::CANDIDATES, &mappings)
+                .map_err(|e| DynamicGuestShadowError::NoLayout(e))?,
+        };
+        info!("shadow layout: {}", kind);
+        Self::with_layout(kind).map_err(|e| DynamicGuestShadowError::GuestShadowError(e))
+    }
+
+    /// Create the shadow using the layout given by `Options::shadow_layout`,
+    /// see `new`
+    pub fn with_options<R: MapReader>(
+        options: &Options,
+    ) -> Result<DynamicGuestShadow<M>, DynamicGuestShadowError<M, R>> {
+        Self::new(options.shadow_layout)
+    }
+
+    /// Create the shadow using the given layout without checking the existing
+    /// mappings
+    pub fn with_layout(kind: ShadowLayoutKind) -> Result<Self, GuestShadowError<M>> {
+        let shadow = match kind {
+            #[cfg(target_pointer_width = "64")]
+            ShadowLayoutKind::X86_64 => DynamicGuestShadow::X86_64(GuestShadow::new()?),
+            #[cfg(target_pointer_width = "64")]
+            ShadowLayoutKind::X86_64High => DynamicGuestShadow::X86_64High(GuestShadow::new()?),
+            #[cfg(target_pointer_width = "64")]
+            ShadowLayoutKind::Aarch64Va39 => DynamicGuestShadow::Aarch64Va39(GuestShadow::new()?),
+            #[cfg(target_pointer_width = "64")]
+            ShadowLayoutKind::Aarch64Va42 => DynamicGuestShadow::Aarch64Va42(GuestShadow::new()?),
+            #[cfg(target_pointer_width = "64")]
+            ShadowLayoutKind::Aarch64Va48 => DynamicGuestShadow::Aarch64Va48(GuestShadow::new()?),
+            #[cfg(target_pointer_width = "64")]
+            ShadowLayoutKind::X86_64Granule16 => {
+                DynamicGuestShadow::X86_64Granule16(GuestShadow::new()?)
+            }
+            #[cfg(target_pointer_width = "64")]
+            ShadowLayoutKind::X86_64Granule32 => {
+                DynamicGuestShadow::X86_64Granule32(GuestShadow::new()?)
+            }
+            #[cfg(target_pointer_width = "32")]
+            ShadowLayoutKind::I386 => DynamicGuestShadow::I386(GuestShadow::new()?),
+            #[cfg(target_pointer_width = "32")]
+            ShadowLayoutKind::Ppc32 => DynamicGuestShadow::Ppc32(GuestShadow::new()?),
+            #[cfg(target_pointer_width = "32")]
+            ShadowLayoutKind::I386Granule16 => {
+                DynamicGuestShadow::I386Granule16(GuestShadow::new()?)
+            }
+            #[cfg(target_pointer_width = "32")]
+            ShadowLayoutKind::I386Granule32 => {
+                DynamicGuestShadow::I386Granule32(GuestShadow::new()?)
+            }
+            #[cfg(target_pointer_width = "32")]
+            ShadowLayoutKind::Ppc32Granule16 => {
+                DynamicGuestShadow::Ppc32Granule16(GuestShadow::new()?)
+            }
+            #[cfg(target_pointer_width = "32")]
+            ShadowLayoutKind::Ppc32Granule32 => {
+                DynamicGuestShadow::Ppc32Granule32(GuestShadow::new()?)
+            }
+        };
+        Ok(shadow)
+    }
+
+    pub fn kind(&self) -> ShadowLayoutKind {
+        match self {
+            #[cfg(target_pointer_width = "64")]
+            DynamicGuestShadow::X86_64(_) => ShadowLayoutKind::X86_64,
+            #[cfg(target_pointer_width = "64")]
+            DynamicGuestShadow::X86_64High(_) => ShadowLayoutKind::X86_64High,
+            #[cfg(target_pointer_width = "64")]
+            DynamicGuestShadow::Aarch64Va39(_) => ShadowLayoutKind::Aarch64Va39,
+            #[cfg(target_pointer_width = "64")]
+            DynamicGuestShadow::Aarch64Va42(_) => ShadowLayoutKind::Aarch64Va42,
+            #[cfg(target_pointer_width = "64")]
+            DynamicGuestShadow::Aarch64Va48(_) => ShadowLayoutKind::Aarch64Va48,
+            #[cfg(target_pointer_width = "64")]
+            DynamicGuestShadow::X86_64Granule16(_) => ShadowLayoutKind::X86_64Granule16,
+            #[cfg(target_pointer_width = "64")]
+            DynamicGuestShadow::X86_64Granule32(_) => ShadowLayoutKind::X86_64Granule32,
+            #[cfg(target_pointer_width = "32")]
+            DynamicGuestShadow::I386(_) => ShadowLayoutKind::I386,
+            #[cfg(target_pointer_width = "32")]
+            DynamicGuestShadow::Ppc32(_) => ShadowLayoutKind::Ppc32,
+            #[cfg(target_pointer_width = "32")]
+            DynamicGuestShadow::I386Granule16(_) => ShadowLayoutKind::I386Granule16,
+            #[cfg(target_pointer_width = "32")]
+            DynamicGuestShadow::I386Granule32(_) => ShadowLayoutKind::I386Granule32,
+            #[cfg(target_pointer_width = "32")]
+            DynamicGuestShadow::Ppc32Granule16(_) => ShadowLayoutKind::Ppc32Granule16,
+            #[cfg(target_pointer_width = "32")]
+            DynamicGuestShadow::Ppc32Granule32(_) => ShadowLayoutKind::Ppc32Granule32,
+        }
+    }
+
+    pub fn get_poison(&self, addr: GuestAddr) -> Result<PoisonType, GuestShadowError<M>> {
+        dispatch!(self, s => s.get_poison(addr))
+    }
+
+    /// The low and high shadow regions of the layout
+    pub fn regions(&self) -> [Range<GuestAddr>; 2] {
+        dispatch!(self, s => Self::layout_regions(s))
+    }
+
+    fn layout_regions<L: ShadowLayout>(_shadow: &GuestShadow<M, L>) -> [Range<GuestAddr>; 2] {
+        [
+            GuestShadow::<M, L>::LOW_SHADOW_OFFSET..GuestShadow::<M, L>::LOW_SHADOW_LIMIT + 1,
+            GuestShadow::<M, L>::HIGH_SHADOW_OFFSET..GuestShadow::<M, L>::HIGH_SHADOW_LIMIT + 1,
+        ]
+    }
+}
+
+impl<M: Mmap> Shadow for DynamicGuestShadow<M> {
+    type Error = GuestShadowError<M>;
+
+    fn load(&self, start: GuestAddr, len: usize) -> Result<(), Self::Error> {
+        dispatch!(self, s => s.load(start, len))
+    }
+
+    fn store(&self, start: GuestAddr, len: usize) -> Result<(), Self::Error> {
+        dispatch!(self, s => s.store(start, len))
+    }
+
+    fn poison(
+        &mut self,
+        start: GuestAddr,
+        len: usize,
+        poison: PoisonType,
+    ) -> Result<(), Self::Error> {
+        dispatch!(self, s => s.poison(start, len, poison))
+    }
+
+    fn unpoison(&mut self, start: GuestAddr, len: usize) -> Result<(), Self::Error> {
+        dispatch!(self, s => s.unpoison(start, len))
+    }
+
+    fn is_poison(&self, start: GuestAddr, len: usize) -> Result<bool, Self::Error> {
+        dispatch!(self, s => s.is_poison(start, len))
+    }
+
+    fn read_shadow(&self, start: GuestAddr, buf: &mut [u8]) -> Result<(), Self::Error> {
+        dispatch!(self, s => s.read_shadow(start, buf))
+    }
+
+    fn granularity(&self) -> usize {
+        dispatch!(self, s => s.granularity())
+    }
+
+    fn find_poison(
+        &self,
+        start: GuestAddr,
+        len: usize,
+    ) -> Result<Option<ShadowFault>, Self::Error> {
+        dispatch!(self, s => s.find_poison(start, len))
+    }
+}
+
+/// A shared reference to the shadow may itself be used as a `Shadow` (see the
+/// corresponding implementation for `GuestShadow`)
+impl<M: Mmap + Sync> Shadow for &DynamicGuestShadow<M> {
+    type Error = GuestShadowError<M>;
+
+    fn load(&self, start: GuestAddr, len: usize) -> Result<(), Self::Error> {
+        (*self).load(start, len)
+    }
+
+    fn store(&self, start: GuestAddr, len: usize) -> Result<(), Self::Error> {
+        (*self).store(start, len)
+    }
+
+    fn poison(
+        &mut self,
+        start: GuestAddr,
+        len: usize,
+        poison: PoisonType,
+    ) -> Result<(), Self::Error> {
+        dispatch!(*self, s => Shadow::poison(&mut { s }, start, len, poison))
+    }
+
+    fn unpoison(&mut self, start: GuestAddr, len: usize) -> Result<(), Self::Error> {
+        dispatch!(*self, s => Shadow::unpoison(&mut { s }, start, len))
+    }
+
+    fn is_poison(&self, start: GuestAddr, len: usize) -> Result<bool, Self::Error> {
+        (*self).is_poison(start, len)
+    }
+
+    fn read_shadow(&self, start: GuestAddr, buf: &mut [u8]) -> Result<(), Self::Error> {
+        (*self).read_shadow(start, buf)
+    }
+
+    fn granularity(&self) -> usize {
+        (*self).granularity()
+    }
+
+    fn find_poison(
+        &self,
+        start: GuestAddr,
+        len: usize,
+    ) -> Result<Option<ShadowFault>, Self::Error> {
+        (*self).find_poison(start, len)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum DynamicGuestShadowError<M: Mmap, R: MapReader> {
+    #[error("Failed to read mappings: {0:?}")]
+    MapReaderError(R::Error),
+    #[error("Shadow layout collision - {0}")]
+    LayoutCollision(ShadowCollision),
+    #[error("No shadow layout fits the existing mappings: {0:#?}")]
+    NoLayout(Vec<ShadowCollision>),
+    #[error("Guest shadow error: {0:?}")]
+    GuestShadowError(GuestShadowError<M>),
+}
@@ -4,12 +4,13 @@
 //! module are performed by reading or writing these shadow regions.
 use {
     crate::{
+        maps::entry::MapEntry,
         mmap::Mmap,
-        shadow::{PoisonType, Shadow},
+        shadow::{find_nonzero, scan_shadow, PoisonType, PoisonTypeError, Shadow, ShadowFault},
         GuestAddr,
     },
     alloc::fmt::Debug,
-    core::marker::PhantomData,
+    core::{marker::PhantomData, ptr::write_bytes},
     log::{debug, trace},
     thiserror::Error,
 };
@@ -26,20 +27,16 @@ impl<M: Mmap, L: ShadowLayout> Shadow for GuestShadow<M, L> {
     type Error = GuestShadowError<M>;
 
     fn load(&self, start: GuestAddr, len: usize) -> Result<(), Self::Error> {
-        debug!("load - start: 0x{:x}, len: 0x{:x}", start, len);
-        if self.is_poison(start, len)? {
-            Err(GuestShadowError::Poisoned(start, len))
-        } else {
-            Ok(())
+        match self.find_poison(start, len)? {
+            Some(fault) => Err(GuestShadowError::Poisoned(fault)),
+            None => Ok(()),
         }
     }
 
     fn store(&self, start: GuestAddr, len: usize) -> Result<(), Self::Error> {
-        debug!("store - start: 0x{:x}, len: 0x{:x}", start, len);
-        if self.is_poison(start, len)? {
-            Err(GuestShadowError::Poisoned(start, len))
-        } else {
-            Ok(())
+        match self.find_poison(start, len)? {
+            Some(fault) => Err(GuestShadowError::Poisoned(fault)),
+            None => Ok(()),
         }
     }
 
@@ -49,106 +46,14 @@ impl<M: Mmap, L: ShadowLayout> Shadow for GuestShadow<M, L> {
         len: usize,
         poison: PoisonType,
     ) -> Result<(), Self::Error> {
-        debug!(
-            "poison - start: 0x{:x}, len: 0x{:x}, pioson: {:?}",
-            start, len, poison
-        );
-
-        if Self::is_out_of_bounds(start, len) {
-            Err(GuestShadowError::AddressRangeOverflow(start, len))?;
-        }
-
-        if !Self::is_memory(start, len) {
-            Err(GuestShadowError::InvalidMemoryAddress(start))?;
-        }
-
-        if !Self::is_end_aligned(start, len) {
-            Err(GuestShadowError::UnalignedEndAddress(start, len))?;
-        }
-
-        if len == 0 {
-            return Ok(());
-        }
-
-        let mut remaining_len = len;
-
-        /* First poison any odd bytes from the unaligned start of the region */
-        if !Self::is_start_aligned(start) {
-            let first_unpoisoned = Self::remainder(start);
-            let poisoned = Self::ALLOC_ALIGN_SIZE - first_unpoisoned;
-            remaining_len -= poisoned;
-
-            let start_aligned_down = Self::align_down(start);
-            let first_shadow = self.get_shadow_mut(start_aligned_down, Self::ALLOC_ALIGN_SIZE)?;
-            first_shadow[0] = first_unpoisoned as u8;
-        }
-
-        /* If our range is expressed within the first byte, then we are done here */
-        if remaining_len == 0 {
-            return Ok(());
-        }
-
-        /* Now poison the rest of the range, then end is aligned */
-        let start_aligned_up = Self::align_up(start);
-        let shadow_map = self.get_shadow_mut(start_aligned_up, remaining_len)?;
-        shadow_map.iter_mut().for_each(|v| *v = poison as u8);
-
-        Ok(())
+        self.poison_shared(start, len, poison)
     }
 
     fn unpoison(&mut self, start: GuestAddr, len: usize) -> Result<(), Self::Error> {
-        debug!("unpoison - start: 0x{:x}, len: 0x{:x}", start, len);
-
-        if Self::is_out_of_bounds(start, len) {
-            Err(GuestShadowError::AddressRangeOverflow(start, len))?;
-        }
-
-        if !Self::is_memory(start, len) {
-            Err(GuestShadowError::InvalidMemoryAddress(start))?;
-        }
-
-        if !Self::is_start_aligned(start) {
-            Err(GuestShadowError::UnalignedStartAddress(start, len))?;
-        }
-
-        if len == 0 {
-            return Ok(());
-        }
-
-        let mut remaining_len = len;
-
-        let aligned_len = Self::align_down(len);
-
-        /* Handle the unaligned end of the region */
-        if !Self::is_end_aligned(start, len) {
-            let end_aligned = start + aligned_len;
-            let last_unpoisoned = Self::remainder(len);
-            remaining_len -= last_unpoisoned;
-
-            let last_shadow = self.get_shadow_mut(end_aligned, Self::ALLOC_ALIGN_SIZE)?;
-            last_shadow[0] = last_unpoisoned as u8;
-        }
-
-        /* If our region is just the unaligned end, then we are done here */
-        if remaining_len == 0 {
-            return Ok(());
-        }
-
-        /*
-         * Next unpoison the aligned portion of our allocation (the start
-         * is aligned, but the end is not)
-         */
-        let shadow_map = self.get_shadow_mut(start, aligned_len)?;
-        shadow_map
-            .iter_mut()
-            .for_each(|v| *v = PoisonType::AsanValid as u8);
-
-        Ok(())
+        self.unpoison_shared(start, len)
     }
 
     fn is_poison(&self, start: GuestAddr, len: usize) -> Result<bool, Self::Error> {
-        debug!("is_poison - start: 0x{:x}, len: 0x{:x}", start, len);
-
         if Self::is_out_of_bounds(start, len) {
             Err(GuestShadowError::AddressRangeOverflow(start, len))?;
         }
@@ -181,25 +86,7 @@ impl<M: Mmap, L: ShadowLayout> Shadow for GuestShadow<M, L> {
                  * unaligned start) plus the length of our buffer
                  */
                 let test_len = (len + skipped) as i8;
-                if first_k != 0 && test_len > first_k {
-                    trace!(
-                        "is_poison #1 - start: 0x{:x}, len: 0x{:x}, first_k: 0x{:x}, first_len: 0x{:x}",
-                        start,
-                        len,
-                        first_k,
-                        test_len
-                    );
-                    return Ok(true);
-                } else {
-                    trace!(
-                        "!is_poison #1 - start: 0x{:x}, len: 0x{:x}, first_k: 0x{:x}, first_len: 0x{:x}",
-                        start,
-                        len,
-                        first_k,
-                        test_len
-                    );
-                    return Ok(false);
-                }
+                return Ok(first_k != 0 && test_len > first_k);
             }
 
             remaining_len -= first_len;
@@ -209,12 +96,6 @@ impl<M: Mmap, L: ShadowLayout> Shadow for GuestShadow<M, L> {
              * zero
              */
             if first_k != 0 {
-                trace!(
-                    "is_poison #2 - start: 0x{:x}, len: 0x{:x}, first_k: 0x{:x}",
-                    start,
-                    len,
-                    first_k
-                );
                 return Ok(true);
             }
         }
@@ -228,13 +109,6 @@ impl<M: Mmap, L: ShadowLayout> Shadow for GuestShadow<M, L> {
 
             let last_k = k_end[0] as i8;
             if last_k != 0 && last_len as i8 > last_k {
-                trace!(
-                    "is_poison #3 - start: 0x{:x}, len: 0x{:x}, last_k: 0x{:x}, last_len: 0x{:x}",
-                    start,
-                    len,
-                    last_k,
-                    last_len
-                );
                 return Ok(true);
             }
         }
@@ -250,23 +124,114 @@ impl<M: Mmap, L: ShadowLayout> Shadow for GuestShadow<M, L> {
 
         let shadow_map = self.get_shadow(start_aligned, remaining_len)?;
 
-        let poisoned = shadow_map.iter().any(|v| *v != PoisonType::AsanValid as u8);
+        Ok(find_nonzero(shadow_map).is_some())
+    }
 
-        if poisoned {
-            trace!(
-                "is_poison #4 - start_aligned: 0x{:x}, remaining_len: 0x{:x}",
-                start_aligned,
-                remaining_len
-            );
-            Ok(true)
-        } else {
-            trace!(
-                "!is_poison #4 - start_aligned: 0x{:x}, remaining_len: 0x{:x}",
-                start_aligned,
-                remaining_len
-            );
-            Ok(false)
+    fn read_shadow(&self, start: GuestAddr, buf: &mut [u8]) -> Result<(), Self::Error> {
+        debug!("read_shadow - start: 0x{:x}, len: 0x{:x}", start, buf.len());
+        let len = buf
+            .len()
+            .checked_mul(Self::ALLOC_ALIGN_SIZE)
+            .ok_or(GuestShadowError::AddressRangeOverflow(start, buf.len()))?;
+
+        if Self::is_out_of_bounds(start, len) {
+            Err(GuestShadowError::AddressRangeOverflow(start, len))?;
+        }
+
+        if !Self::is_memory(start, len) {
+            Err(GuestShadowError::InvalidMemoryAddress(start))?;
         }
+
+        if !Self::is_start_aligned(start) {
+            Err(GuestShadowError::UnalignedStartAddress(start, len))?;
+        }
+
+        buf.copy_from_slice(self.get_shadow(start, len)?);
+        Ok(())
+    }
+
+    fn granularity(&self) -> usize {
+        Self::ALLOC_ALIGN_SIZE
+    }
+
+    fn find_poison(
+        &self,
+        start: GuestAddr,
+        len: usize,
+    ) -> Result<Option<ShadowFault>, Self::Error> {
+        if Self::is_out_of_bounds(start, len) {
+            Err(GuestShadowError::AddressRangeOverflow(start, len))?;
+        }
+
+        if !Self::is_memory(start, len) {
+            Err(GuestShadowError::InvalidMemoryAddress(start))?;
+        }
+
+        if len == 0 {
+            return Ok(None);
+        }
+
+        let base = Self::align_down(start);
+        let end = Self::align_up(start + len);
+        let shadow = self.get_shadow(base, end - base)?;
+        let next = self
+            .get_shadow(end, Self::ALLOC_ALIGN_SIZE)
+            .ok()
+            .map(|s| s[0]);
+        Ok(scan_shadow(
+            start,
+            len,
+            Self::ALLOC_ALIGN_SIZE,
+            shadow,
+            next,
+        ))
+    }
+}
+
+/// A shared reference to the guest shadow may itself be used as a `Shadow`,
+/// allowing it to be used by several frontends (or threads) at once.
+impl<M: Mmap + Sync, L: ShadowLayout + Sync> Shadow for &GuestShadow<M, L> {
+    type Error = GuestShadowError<M>;
+
+    fn load(&self, start: GuestAddr, len: usize) -> Result<(), Self::Error> {
+        (*self).load(start, len)
+    }
+
+    fn store(&self, start: GuestAddr, len: usize) -> Result<(), Self::Error> {
+        (*self).store(start, len)
+    }
+
+    fn poison(
+        &mut self,
+        start: GuestAddr,
+        len: usize,
+        poison: PoisonType,
+    ) -> Result<(), Self::Error> {
+        self.poison_shared(start, len, poison)
+    }
+
+    fn unpoison(&mut self, start: GuestAddr, len: usize) -> Result<(), Self::Error> {
+        self.unpoison_shared(start, len)
+    }
+
+    fn is_poison(&self, start: GuestAddr, len: usize) -> Result<bool, Self::Error> {
+        (*self).is_poison(start, len)
+    }
+
+    fn read_shadow(&self, start: GuestAddr, buf: &mut [u8]) -> Result<(), Self::Error> {
+        (*self).read_shadow(start, buf)
+    }
+
+    fn granularity(&self) -> usize {
+        (*self).granularity()
+    }
+
+    fn find_poison(
+        &self,
+        start: GuestAddr,
+        len: usize,
+    ) -> Result<Option<ShadowFault>, Self::Error> {
+        (*self).find_poison(start, len)
     }
 }
 
@@ -375,8 +340,133 @@ impl<M: Mmap, L: ShadowLayout> GuestShadow<M, L> {
         }
     }
 
+    /// Poison the given range. Since the shadow regions are written in place,
+    /// this requires only a shared reference.
+    pub fn poison_shared(
+        &self,
+        start: GuestAddr,
+        len: usize,
+        poison: PoisonType,
+    ) -> Result<(), GuestShadowError<M>> {
+        if Self::is_out_of_bounds(start, len) {
+            Err(GuestShadowError::AddressRangeOverflow(start, len))?;
+        }
+
+        if !Self::is_memory(start, len) {
+            Err(GuestShadowError::InvalidMemoryAddress(start))?;
+        }
+
+        if !Self::is_end_aligned(start, len) {
+            Err(GuestShadowError::UnalignedEndAddress(start, len))?;
+        }
+
+        if len == 0 {
+            return Ok(());
+        }
+
+        let mut remaining_len = len;
+
+        /* First poison any odd bytes from the unaligned start of the region */
+        if !Self::is_start_aligned(start) {
+            let first_unpoisoned = Self::remainder(start);
+            let poisoned = Self::ALLOC_ALIGN_SIZE - first_unpoisoned;
+            remaining_len -= poisoned;
+
+            let start_aligned_down = Self::align_down(start);
+            self.fill_shadow(
+                start_aligned_down,
+                Self::ALLOC_ALIGN_SIZE,
+                first_unpoisoned as u8,
+            )?;
+        }
+
+        /* If our range is expressed within the first byte, then we are done here */
+        if remaining_len == 0 {
+            return Ok(());
+        }
+
+        /* Now poison the rest of the range, then end is aligned */
+        let start_aligned_up = Self::align_up(start);
+        self.fill_shadow(start_aligned_up, remaining_len, poison as u8)?;
+
+        Ok(())
+    }
+
+    /// Unpoison the given range, see `poison_shared`
+    pub fn unpoison_shared(&self, start: GuestAddr, len: usize) -> Result<(), GuestShadowError<M>> {
+        if Self::is_out_of_bounds(start, len) {
+            Err(GuestShadowError::AddressRangeOverflow(start, len))?;
+        }
+
+        if !Self::is_memory(start, len) {
+            Err(GuestShadowError::InvalidMemoryAddress(start))?;
+        }
+
+        if !Self::is_start_aligned(start) {
+            Err(GuestShadowError::UnalignedStartAddress(start, len))?;
+        }
+
+        if len == 0 {
+            return Ok(());
+        }
+
+        let mut remaining_len = len;
+
+        let aligned_len = Self::align_down(len);
+
+        /* Handle the unaligned end of the region */
+        if !Self::is_end_aligned(start, len) {
+            let end_aligned = start + aligned_len;
+            let last_unpoisoned = Self::remainder(len);
+            remaining_len -= last_unpoisoned;
+
+            self.fill_shadow(end_aligned, Self::ALLOC_ALIGN_SIZE, last_unpoisoned as u8)?;
+        }
+
+        /* If our region is just the unaligned end, then we are done here */
+        if remaining_len == 0 {
+            return Ok(());
+        }
+
+        /*
+         * Next unpoison the aligned portion of our allocation (the start
+         * is aligned, but the end is not)
+         */
+        self.fill_shadow(start, aligned_len, PoisonType::AsanValid as u8)?;
+
+        Ok(())
+    }
+
+    /// Fill the shadow bytes of the given (aligned) range with `value`. The
+    /// shadow regions are mapped at fixed addresses, so they are written
+    /// directly (rather than through our mappings), which allows disjoint
+    /// ranges to be updated concurrently by several threads.
+    fn fill_shadow(
+        &self,
+        addr: GuestAddr,
+        len: usize,
+        value: u8,
+    ) -> Result<(), GuestShadowError<M>> {
+        assert!(addr % Self::ALLOC_ALIGN_SIZE == 0);
+        if !Self::is_memory(addr, len) {
+            Err(GuestShadowError::InvalidMemoryAddress(addr))?;
+        }
+        let shadow_addr = (addr >> Self::ALLOC_ALIGN_POW) + Self::SHADOW_OFFSET;
+        let shadow_len = Self::align_up(len) >> Self::ALLOC_ALIGN_POW;
+        unsafe { write_bytes(shadow_addr as *mut u8, value, shadow_len) };
+        Ok(())
+    }
+
+    pub fn get_poison(&self, addr: GuestAddr) -> Result<PoisonType, GuestShadowError<M>> {
+        trace!("get_poison - addr: 0x{:x}", addr);
+        if !Self::is_memory(addr, 1) {
+            Err(GuestShadowError::InvalidMemoryAddress(addr))?;
+        }
+        let shadow = self.get_shadow(Self::align_down(addr), Self::ALLOC_ALIGN_SIZE)?;
+        PoisonType::try_from(shadow[0]).map_err(GuestShadowError::InvalidPoisonType)
+    }
+
     pub fn get_shadow(&self, addr: GuestAddr, len: usize) -> Result<&[u8], GuestShadowError<M>> {
-        trace!("get_shadow - addr: 0x{:x}, len: 0x{:x}", addr, len);
         assert!(addr % Self::ALLOC_ALIGN_SIZE == 0);
         assert!(len % Self::ALLOC_ALIGN_SIZE == 0);
         let shadow_addr = (addr >> Self::ALLOC_ALIGN_POW) + Self::SHADOW_OFFSET;
@@ -397,7 +487,6 @@ impl<M: Mmap, L: ShadowLayout> GuestShadow<M, L> {
         addr: GuestAddr,
         len: usize,
     ) -> Result<&mut [u8], GuestShadowError<M>> {
-        trace!("get_shadow_mut - addr: 0x{:x}, len: 0x{:x}", addr, len);
         assert!(addr % Self::ALLOC_ALIGN_SIZE == 0);
         assert!(len % Self::ALLOC_ALIGN_SIZE == 0);
         let shadow_addr = (addr >> Self::ALLOC_ALIGN_POW) + Self::SHADOW_OFFSET;
@@ -431,13 +520,170 @@ pub trait ShadowLayout: Debug + Send {
     const SHADOW_OFFSET: usize;
     const ALLOC_ALIGN_POW: usize;
     const ALLOC_ALIGN_SIZE: usize;
+
+    /// Find the first of the given mappings which cannot coexist with the
+    /// layout, that is one which overlaps the shadow regions (or the gap
+    /// between them), or lies beyond the end of the high memory region (since
+    /// the address space is then larger than that described by the layout).
+    /// The `[vsyscall]` page is ignored, since it lies in the kernel's half of
+    /// the address space and is never accessed through the shadow.
+    fn collision(mappings: &[MapEntry]) -> Option<&MapEntry> {
+        let high_mem_limit = Self::HIGH_MEM_OFFSET + (Self::HIGH_MEM_SIZE - 1);
+        mappings
+            .iter()
+            .filter(|m| m.path() != "[vsyscall]")
+            .find(|m| {
+                let last = m.limit().saturating_sub(1);
+                let overlaps = m.base() < Self::HIGH_MEM_OFFSET && last >= Self::LOW_SHADOW_OFFSET;
+                overlaps || last > high_mem_limit
+            })
+    }
+}
+
+/// The layout used by compiler-rt for x86-64 (and that expected by code
+/// instrumented for it), with a 47-bit address space
+#[cfg(target_pointer_width = "64")]
+#[derive(Debug)]
+pub struct X86_64ShadowLayout;
+
+#[cfg(target_pointer_width = "64")]
+impl ShadowLayout for X86_64ShadowLayout {
+    // [0x10007fff8000, 0x7fffffffffff] 	HighMem
+    // [0x02008fff7000, 0x10007fff7fff] 	HighShadow
+    // [0x00008fff7000, 0x02008fff6fff] 	ShadowGap
+    // [0x00007fff8000, 0x00008fff6fff] 	LowShadow
+    // [0x000000000000, 0x00007fff7fff] 	LowMem
+    const SHADOW_OFFSET: usize = 0x7fff8000;
+    const LOW_MEM_OFFSET: GuestAddr = 0x0;
+    const LOW_MEM_SIZE: usize = 0x00007fff8000;
+    const LOW_SHADOW_OFFSET: GuestAddr = 0x00007fff8000;
+    const LOW_SHADOW_SIZE: usize = 0xffff000;
+    const HIGH_SHADOW_OFFSET: GuestAddr = 0x02008fff7000;
+    const HIGH_SHADOW_SIZE: usize = 0xdfff0001000;
+    const HIGH_MEM_OFFSET: GuestAddr = 0x10007fff8000;
+    const HIGH_MEM_SIZE: usize = 0x6fff80008000;
+
+    const ALLOC_ALIGN_POW: usize = 3;
+    const ALLOC_ALIGN_SIZE: usize = 1 << Self::ALLOC_ALIGN_POW;
+}
+
+/// A layout for a 47-bit address space with the shadow at `1 << 44` (rather
+/// than `0x7fff8000`). The low memory region then extends to 16TiB, which
+/// accommodates guests whose PIE or stack are placed within the shadow gap of
+/// the x86-64 layout. Note that instrumented code must use the same offset.
+#[cfg(target_pointer_width = "64")]
+#[derive(Debug)]
+pub struct X86_64HighShadowLayout;
+
+#[cfg(target_pointer_width = "64")]
+impl ShadowLayout for X86_64HighShadowLayout {
+    // [0x200000000000, 0x7fffffffffff] 	HighMem
+    // [0x140000000000, 0x1fffffffffff] 	HighShadow
+    // [0x120000000000, 0x13ffffffffff] 	ShadowGap
+    // [0x100000000000, 0x11ffffffffff] 	LowShadow
+    // [0x000000000000, 0x0fffffffffff] 	LowMem
+    const SHADOW_OFFSET: usize = 0x100000000000;
+    const LOW_MEM_OFFSET: GuestAddr = 0x0;
+    const LOW_MEM_SIZE: usize = 0x100000000000;
+    const LOW_SHADOW_OFFSET: GuestAddr = 0x100000000000;
+    const LOW_SHADOW_SIZE: usize = 0x20000000000;
+    const HIGH_SHADOW_OFFSET: GuestAddr = 0x140000000000;
+    const HIGH_SHADOW_SIZE: usize = 0xc0000000000;
+    const HIGH_MEM_OFFSET: GuestAddr = 0x200000000000;
+    const HIGH_MEM_SIZE: usize = 0x600000000000;
+
+    const ALLOC_ALIGN_POW: usize = 3;
+    const ALLOC_ALIGN_SIZE: usize = 1 << Self::ALLOC_ALIGN_POW;
 }
 
+/// The layout used by compiler-rt for aarch64 with a 39-bit address space.
+/// The aarch64 layouts share the same shadow offset (`1 << 36`) and differ
+/// only in the size of the address space, so the layout can be chosen at
+/// runtime without affecting instrumented code.
+#[cfg(target_pointer_width = "64")]
 #[derive(Debug)]
-pub struct DefaultShadowLayout;
+pub struct Aarch64Va39ShadowLayout;
 
-#[cfg(target_pointer_width = "32")]
-impl ShadowLayout for DefaultShadowLayout {
+#[cfg(target_pointer_width = "64")]
+impl ShadowLayout for Aarch64Va39ShadowLayout {
+    // [0x002000000000, 0x007fffffffff] 	HighMem
+    // [0x001400000000, 0x001fffffffff] 	HighShadow
+    // [0x001200000000, 0x0013ffffffff] 	ShadowGap
+    // [0x001000000000, 0x0011ffffffff] 	LowShadow
+    // [0x000000000000, 0x000fffffffff] 	LowMem
+    const SHADOW_OFFSET: usize = 0x1000000000;
+    const LOW_MEM_OFFSET: GuestAddr = 0x0;
+    const LOW_MEM_SIZE: usize = 0x1000000000;
+    const LOW_SHADOW_OFFSET: GuestAddr = 0x1000000000;
+    const LOW_SHADOW_SIZE: usize = 0x200000000;
+    const HIGH_SHADOW_OFFSET: GuestAddr = 0x1400000000;
+    const HIGH_SHADOW_SIZE: usize = 0xc00000000;
+    const HIGH_MEM_OFFSET: GuestAddr = 0x2000000000;
+    const HIGH_MEM_SIZE: usize = 0x6000000000;
+
+    const ALLOC_ALIGN_POW: usize = 3;
+    const ALLOC_ALIGN_SIZE: usize = 1 << Self::ALLOC_ALIGN_POW;
+}
+
+/// The layout used by compiler-rt for aarch64 with a 42-bit address space
+#[cfg(target_pointer_width = "64")]
+#[derive(Debug)]
+pub struct Aarch64Va42ShadowLayout;
+
+#[cfg(target_pointer_width = "64")]
+impl ShadowLayout for Aarch64Va42ShadowLayout {
+    // [0x009000000000, 0x03ffffffffff] 	HighMem
+    // [0x002200000000, 0x008fffffffff] 	HighShadow
+    // [0x001200000000, 0x0021ffffffff] 	ShadowGap
+    // [0x001000000000, 0x0011ffffffff] 	LowShadow
+    // [0x000000000000, 0x000fffffffff] 	LowMem
+    const SHADOW_OFFSET: usize = 0x1000000000;
+    const LOW_MEM_OFFSET: GuestAddr = 0x0;
+    const LOW_MEM_SIZE: usize = 0x1000000000;
+    const LOW_SHADOW_OFFSET: GuestAddr = 0x1000000000;
+    const LOW_SHADOW_SIZE: usize = 0x200000000;
+    const HIGH_SHADOW_OFFSET: GuestAddr = 0x2200000000;
+    const HIGH_SHADOW_SIZE: usize = 0x6e00000000;
+    const HIGH_MEM_OFFSET: GuestAddr = 0x9000000000;
+    const HIGH_MEM_SIZE: usize = 0x37000000000;
+
+    const ALLOC_ALIGN_POW: usize = 3;
+    const ALLOC_ALIGN_SIZE: usize = 1 << Self::ALLOC_ALIGN_POW;
+}
+
+/// The layout used by compiler-rt for aarch64 with a 48-bit address space
+#[cfg(target_pointer_width = "64")]
+#[derive(Debug)]
+pub struct Aarch64Va48ShadowLayout;
+
+#[cfg(target_pointer_width = "64")]
+impl ShadowLayout for Aarch64Va48ShadowLayout {
+    // [0x201000000000, 0xffffffffffff] 	HighMem
+    // [0x041200000000, 0x200fffffffff] 	HighShadow
+    // [0x001200000000, 0x0411ffffffff] 	ShadowGap
+    // [0x001000000000, 0x0011ffffffff] 	LowShadow
+    // [0x000000000000, 0x000fffffffff] 	LowMem
+    const SHADOW_OFFSET: usize = 0x1000000000;
+    const LOW_MEM_OFFSET: GuestAddr = 0x0;
+    const LOW_MEM_SIZE: usize = 0x1000000000;
+    const LOW_SHADOW_OFFSET: GuestAddr = 0x1000000000;
+    const LOW_SHADOW_SIZE: usize = 0x200000000;
+    const HIGH_SHADOW_OFFSET: GuestAddr = 0x41200000000;
+    const HIGH_SHADOW_SIZE: usize = 0x1bfe00000000;
+    const HIGH_MEM_OFFSET: GuestAddr = 0x201000000000;
+    const HIGH_MEM_SIZE: usize = 0xdff000000000;
+
+    const ALLOC_ALIGN_POW: usize = 3;
+    const ALLOC_ALIGN_SIZE: usize = 1 << Self::ALLOC_ALIGN_POW;
+}
+
+/// The layout used by compiler-rt for 32-bit targets, with a 4GiB address
+/// space. Since its bounds fit within any pointer width, it (like the other
+/// 32-bit layouts) is defined for 64-bit targets too.
+#[derive(Debug)]
+pub struct I386ShadowLayout;
+
+impl ShadowLayout for I386ShadowLayout {
     // [0x40000000, 0xffffffff] 	HighMem
     // [0x28000000, 0x3fffffff] 	HighShadow
     // [0x24000000, 0x27ffffff] 	ShadowGap
@@ -457,27 +703,90 @@ impl ShadowLayout for DefaultShadowLayout {
     const ALLOC_ALIGN_SIZE: usize = 1 << Self::ALLOC_ALIGN_POW;
 }
 
-#[cfg(target_pointer_width = "64")]
-impl ShadowLayout for DefaultShadowLayout {
-    // [0x10007fff8000, 0x7fffffffffff] 	HighMem
-    // [0x02008fff7000, 0x10007fff7fff] 	HighShadow
-    // [0x00008fff7000, 0x02008fff6fff] 	ShadowGap
-    // [0x00007fff8000, 0x00008fff6fff] 	LowShadow
-    // [0x000000000000, 0x00007fff7fff] 	LowMem
-    const SHADOW_OFFSET: usize = 0x7fff8000;
+/// A layout for 32-bit PowerPC, whose address space ends at `0xc0000000`
+/// (`TASK_SIZE`). It shares the shadow offset of the 32-bit layout, but the
+/// high shadow region is correspondingly smaller.
+#[derive(Debug)]
+pub struct Ppc32ShadowLayout;
+
+impl ShadowLayout for Ppc32ShadowLayout {
+    // [0x38000000, 0xbfffffff] 	HighMem
+    // [0x27000000, 0x37ffffff] 	HighShadow
+    // [0x24000000, 0x26ffffff] 	ShadowGap
+    // [0x20000000, 0x23ffffff] 	LowShadow
+    // [0x00000000, 0x1fffffff] 	LowMem
+    const SHADOW_OFFSET: usize = 0x20000000;
     const LOW_MEM_OFFSET: GuestAddr = 0x0;
-    const LOW_MEM_SIZE: usize = 0x00007fff8000;
-    const LOW_SHADOW_OFFSET: GuestAddr = 0x00007fff8000;
-    const LOW_SHADOW_SIZE: usize = 0xffff000;
-    const HIGH_SHADOW_OFFSET: GuestAddr = 0x02008fff7000;
-    const HIGH_SHADOW_SIZE: usize = 0xdfff0001000;
-    const HIGH_MEM_OFFSET: GuestAddr = 0x10007fff8000;
-    const HIGH_MEM_SIZE: usize = 0x6fff80008000;
+    const LOW_MEM_SIZE: usize = 0x20000000;
+    const LOW_SHADOW_OFFSET: GuestAddr = 0x20000000;
+    const LOW_SHADOW_SIZE: usize = 0x4000000;
+    const HIGH_SHADOW_OFFSET: GuestAddr = 0x27000000;
+    const HIGH_SHADOW_SIZE: usize = 0x11000000;
+    const HIGH_MEM_OFFSET: GuestAddr = 0x38000000;
+    const HIGH_MEM_SIZE: usize = 0x88000000;
 
     const ALLOC_ALIGN_POW: usize = 3;
     const ALLOC_ALIGN_SIZE: usize = 1 << Self::ALLOC_ALIGN_POW;
 }
 
+/// A layout derived from `L` in which each shadow byte describes `1 << SCALE`
+/// bytes of memory (rather than 8). The shadow offset and the extent of the
+/// address space are those of `L`, but the shadow regions are smaller by a
+/// factor of `1 << (SCALE - 3)`, e.g. a `SCALE` of 4 or 5 (16 or 32-byte
+/// granules) reduces the shadow memory required by 2x or 4x respectively at
+/// the expense of coarser red-zones. Note that instrumented code must use the
+/// same scale (e.g. `-asan-mapping-scale`).
+#[derive(Debug)]
+pub struct ScaledShadowLayout<L: ShadowLayout, const SCALE: usize> {
+    _phantom: PhantomData<L>,
+}
+
+impl<L: ShadowLayout, const SCALE: usize> ScaledShadowLayout<L, SCALE> {
+    /// The last address of the address space described by `L`
+    const MEM_LIMIT: usize = L::HIGH_MEM_OFFSET + (L::HIGH_MEM_SIZE - 1);
+
+    /// The page size assumed when aligning the high shadow region
+    const PAGE_SIZE: usize = 0x1000;
+
+    const fn mem_to_shadow(addr: GuestAddr) -> GuestAddr {
+        (addr >> SCALE) + L::SHADOW_OFFSET
+    }
+}
+
+impl<L: ShadowLayout, const SCALE: usize> ShadowLayout for ScaledShadowLayout<L, SCALE> {
+    const SHADOW_OFFSET: usize = L::SHADOW_OFFSET;
+    const LOW_MEM_OFFSET: GuestAddr = L::LOW_MEM_OFFSET;
+    const LOW_MEM_SIZE: usize = L::LOW_MEM_SIZE;
+    const LOW_SHADOW_OFFSET: GuestAddr = L::LOW_SHADOW_OFFSET;
+    const LOW_SHADOW_SIZE: usize = L::LOW_MEM_SIZE >> SCALE;
+    /* The high shadow begins at the page containing the shadow of the high memory */
+    const HIGH_SHADOW_OFFSET: GuestAddr =
+        Self::mem_to_shadow(Self::HIGH_MEM_OFFSET) & !(Self::PAGE_SIZE - 1);
+    const HIGH_SHADOW_SIZE: usize = Self::HIGH_MEM_OFFSET - Self::HIGH_SHADOW_OFFSET;
+    /* The high memory begins immediately after the shadow of its last byte */
+    const HIGH_MEM_OFFSET: GuestAddr = Self::mem_to_shadow(Self::MEM_LIMIT) + 1;
+    const HIGH_MEM_SIZE: usize = Self::MEM_LIMIT - Self::HIGH_MEM_OFFSET + 1;
+
+    const ALLOC_ALIGN_POW: usize = {
+        assert!(SCALE >= 3 && (1 << SCALE) <= PoisonType::MAX_GRANULARITY);
+        SCALE
+    };
+    const ALLOC_ALIGN_SIZE: usize = 1 << Self::ALLOC_ALIGN_POW;
+}
+
+/// The layout assumed by code instrumented for the target architecture
+#[cfg(all(target_pointer_width = "64", not(target_arch = "aarch64")))]
+pub type DefaultShadowLayout = X86_64ShadowLayout;
+
+#[cfg(target_arch = "aarch64")]
+pub type DefaultShadowLayout = Aarch64Va48ShadowLayout;
+
+#[cfg(all(target_pointer_width = "32", not(target_arch = "powerpc")))]
+pub type DefaultShadowLayout = I386ShadowLayout;
+
+#[cfg(target_arch = "powerpc")]
+pub type DefaultShadowLayout = Ppc32ShadowLayout;
+
 #[derive(Error, Debug, PartialEq)]
 pub enum GuestShadowError<M: Mmap> {
     #[error("Invalid shadow address: {0:x}")]
@@ -488,8 +797,10 @@ pub enum GuestShadowError<M: Mmap> {
     UnalignedStartAddress(GuestAddr, GuestAddr),
     #[error("Address overflow: {0:x}, len: {1:x}")]
     AddressRangeOverflow(GuestAddr, usize),
-    #[error("Poisoned: {0:x}, len: {1:x}")]
-    Poisoned(GuestAddr, usize),
+    #[error("Poisoned: {0:x?}")]
+    Poisoned(ShadowFault),
     #[error("Mmap error: {0:?}")]
     MmapError(M::Error),
+    #[error("Invalid poison type: {0:?}")]
+    InvalidPoisonType(PoisonTypeError),
 }
@@ -2,7 +2,8 @@
 //! This implementation of the shadow map makes use of a `Host` implementation
 //! in order to relay the requested shadow map queries or updates to the host
 //! emulator. In the case of QEMU on Linux, this will typically be by means of a
-//! bespoke `syscall`.
+//! bespoke `syscall`. The first poisoned byte within a range is found by
+//! reading back the shadow bytes from the host (see `Shadow::find_poison`).
 use {
     crate::{
         host::Host,
@@ -49,9 +50,61 @@ impl<H: Host> Shadow for HostShadow<H> {
         debug!("is_poison - start: 0x{:x}, len: 0x{:x}", start, len);
         H::is_poison(start, len).map_err(|e| HostShadowError::HostError(e))
     }
+
+    fn read_shadow(&self, start: GuestAddr, buf: &mut [u8]) -> Result<(), Self::Error> {
+        debug!("read_shadow - start: 0x{:x}, len: 0x{:x}", start, buf.len());
+        H::read_shadow(start, buf).map_err(|e| HostShadowError::HostError(e))
+    }
+
+    fn granularity(&self) -> usize {
+        Self::GRANULARITY
+    }
+}
+
+/// As the shadow map is maintained by the host, a shared reference to the
+/// shadow may equally be used to update it
+impl<H: Host + Sync> Shadow for &HostShadow<H> {
+    type Error = HostShadowError<H>;
+
+    fn load(&self, start: GuestAddr, len: usize) -> Result<(), Self::Error> {
+        (*self).load(start, len)
+    }
+
+    fn store(&self, start: GuestAddr, len: usize) -> Result<(), Self::Error> {
+        (*self).store(start, len)
+    }
+
+    fn poison(&mut self, start: GuestAddr, len: usize, val: PoisonType) -> Result<(), Self::Error> {
+        debug!(
+            "poison - start: 0x{:x}, len: 0x{:x}, pioson: {:?}",
+            start, len, val
+        );
+        H::poison(start, len, val).map_err(|e| HostShadowError::HostError(e))
+    }
+
+    fn unpoison(&mut self, start: GuestAddr, len: usize) -> Result<(), Self::Error> {
+        debug!("unpoison - start: 0x{:x}, len: 0x{:x}", start, len);
+        H::unpoison(start, len).map_err(|e| HostShadowError::HostError(e))
+    }
+
+    fn is_poison(&self, start: GuestAddr, len: usize) -> Result<bool, Self::Error> {
+        (*self).is_poison(start, len)
+    }
+
+    fn read_shadow(&self, start: GuestAddr, buf: &mut [u8]) -> Result<(), Self::Error> {
+        (*self).read_shadow(start, buf)
+    }
+
+    fn granularity(&self) -> usize {
+        (*self).granularity()
+    }
 }
 
 impl<H: Host> HostShadow<H> {
+    /// The number of bytes of memory described by each byte of the shadow map
+    /// maintained by the host
+    pub const GRANULARITY: usize = 8;
+
     pub fn new() -> Result<HostShadow<H>, HostShadowError<H>> {
         Ok(HostShadow::<H> {
             _phantom: PhantomData,
@@ -12,17 +12,28 @@
 //!   the guest memory addresses being used by the TCG code to be converted into
 //!   host addresses to be tested against the shadow maps (incurring a performance
 //!   overhead) as well as placing constraints on register usage.
-use core::fmt::Debug;
-
-use crate::GuestAddr;
+//!
+//! In `guest` mode, the layout of the shadow maps may also be selected at
+//! runtime (see `dynamic`).
+//!
+//! In either case, the shadow bytes surrounding an address can be rendered
+//! for inclusion in a report (see `dump`).
+use {
+    crate::{shadow::dump::ShadowDump, GuestAddr},
+    core::{cmp::min, fmt::Debug, mem::size_of},
+    thiserror::Error,
+};
 
+pub mod dump;
+#[cfg(feature = "guest")]
+pub mod dynamic;
 #[cfg(feature = "guest")]
 pub mod guest;
 #[cfg(feature = "host")]
 pub mod host;
 
 #[repr(u8)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum PoisonType {
     AsanValid = 0x00,
     AsanPartial1 = 0x01,
@@ -32,7 +43,33 @@ pub enum PoisonType {
     AsanPartial5 = 0x05,
     AsanPartial6 = 0x06,
     AsanPartial7 = 0x07,
+    AsanPartial8 = 0x08,
+    AsanPartial9 = 0x09,
+    AsanPartial10 = 0x0a,
+    AsanPartial11 = 0x0b,
+    AsanPartial12 = 0x0c,
+    AsanPartial13 = 0x0d,
+    AsanPartial14 = 0x0e,
+    AsanPartial15 = 0x0f,
+    AsanPartial16 = 0x10,
+    AsanPartial17 = 0x11,
+    AsanPartial18 = 0x12,
+    AsanPartial19 = 0x13,
+    AsanPartial20 = 0x14,
+    AsanPartial21 = 0x15,
+    AsanPartial22 = 0x16,
+    AsanPartial23 = 0x17,
+    AsanPartial24 = 0x18,
+    AsanPartial25 = 0x19,
+    AsanPartial26 = 0x1a,
+    AsanPartial27 = 0x1b,
+    AsanPartial28 = 0x1c,
+    AsanPartial29 = 0x1d,
+    AsanPartial30 = 0x1e,
+    AsanPartial31 = 0x1f,
     AsanArrayCookie = 0xac,
+    AsanAllocaLeftRz = 0xca,
+    AsanAllocaRightRz = 0xcb,
     AsanStackRz = 0xf0,
     AsanStackLeftRz = 0xf1,
     AsanStackMidRz = 0xf2,
@@ -47,6 +84,74 @@ pub enum PoisonType {
     AsanHeapFreed = 0xfd,
 }
 
+impl PoisonType {
+    /// The largest granularity for which partially addressable granules can
+    /// be encoded (the values `0x01` to `0x1f` denote the number of leading
+    /// bytes of a granule which are addressable)
+    pub const MAX_GRANULARITY: usize = 32;
+
+    const PARTIAL: [PoisonType; Self::MAX_GRANULARITY - 1] = [
+        PoisonType::AsanPartial1,
+        PoisonType::AsanPartial2,
+        PoisonType::AsanPartial3,
+        PoisonType::AsanPartial4,
+        PoisonType::AsanPartial5,
+        PoisonType::AsanPartial6,
+        PoisonType::AsanPartial7,
+        PoisonType::AsanPartial8,
+        PoisonType::AsanPartial9,
+        PoisonType::AsanPartial10,
+        PoisonType::AsanPartial11,
+        PoisonType::AsanPartial12,
+        PoisonType::AsanPartial13,
+        PoisonType::AsanPartial14,
+        PoisonType::AsanPartial15,
+        PoisonType::AsanPartial16,
+        PoisonType::AsanPartial17,
+        PoisonType::AsanPartial18,
+        PoisonType::AsanPartial19,
+        PoisonType::AsanPartial20,
+        PoisonType::AsanPartial21,
+        PoisonType::AsanPartial22,
+        PoisonType::AsanPartial23,
+        PoisonType::AsanPartial24,
+        PoisonType::AsanPartial25,
+        PoisonType::AsanPartial26,
+        PoisonType::AsanPartial27,
+        PoisonType::AsanPartial28,
+        PoisonType::AsanPartial29,
+        PoisonType::AsanPartial30,
+        PoisonType::AsanPartial31,
+    ];
+}
+
+impl TryFrom<u8> for PoisonType {
+    type Error = PoisonTypeError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(PoisonType::AsanValid),
+            0x01..=0x1f => Ok(PoisonType::PARTIAL[value as usize - 1]),
+            0xac => Ok(PoisonType::AsanArrayCookie),
+            0xca => Ok(PoisonType::AsanAllocaLeftRz),
+            0xcb => Ok(PoisonType::AsanAllocaRightRz),
+            0xf0 => Ok(PoisonType::AsanStackRz),
+            0xf1 => Ok(PoisonType::AsanStackLeftRz),
+            0xf2 => Ok(PoisonType::AsanStackMidRz),
+            0xf3 => Ok(PoisonType::AsanStackRightRz),
+            0xf5 => Ok(PoisonType::AsanStackFreed),
+            0xf8 => Ok(PoisonType::AsanStackOoscope),
+            0xf9 => Ok(PoisonType::AsanGlobalRz),
+            0xe9 => Ok(PoisonType::AsanHeapRz),
+            0xf7 => Ok(PoisonType::AsanUser),
+            0xfa => Ok(PoisonType::AsanHeapLeftRz),
+            0xfb => Ok(PoisonType::AsanHeapRightRz),
+            0xfd => Ok(PoisonType::AsanHeapFreed),
+            _ => Err(PoisonTypeError::InvalidValue(value)),
+        }
+    }
+}
+
 pub trait Shadow: Sized + Debug + Send {
     type Error: Debug;
     fn load(&self, start: GuestAddr, len: usize) -> Result<(), Self::Error>;
@@ -54,4 +159,137 @@ pub trait Shadow: Sized + Debug + Send {
     fn poison(&mut self, start: GuestAddr, len: usize, val: PoisonType) -> Result<(), Self::Error>;
     fn unpoison(&mut self, start: GuestAddr, len: usize) -> Result<(), Self::Error>;
     fn is_poison(&self, start: GuestAddr, len: usize) -> Result<bool, Self::Error>;
+    /// Copy the shadow bytes describing the memory starting at `start` (which
+    /// must be aligned to the granularity) into `buf`
+    fn read_shadow(&self, start: GuestAddr, buf: &mut [u8]) -> Result<(), Self::Error>;
+    /// The number of bytes of memory described by each shadow byte
+    fn granularity(&self) -> usize;
+
+    /// Read the shadow bytes surrounding `addr` for inclusion in a report
+    fn dump(&self, addr: GuestAddr) -> Result<ShadowDump, Self::Error> {
+        ShadowDump::new(self, addr)
+    }
+
+    /// Find the first poisoned byte within the given range (if any). By
+    /// default, this reads the shadow bytes covering the range a chunk at a
+    /// time using `read_shadow`.
+    fn find_poison(
+        &self,
+        start: GuestAddr,
+        len: usize,
+    ) -> Result<Option<ShadowFault>, Self::Error> {
+        const CHUNK_LEN: usize = 0x200;
+        let granularity = self.granularity();
+        let end = start.saturating_add(len);
+        let mut chunk_start = start & !(granularity - 1);
+        let mut buf = [0u8; CHUNK_LEN];
+        while chunk_start < end {
+            let chunk_len = min((end - chunk_start).div_ceil(granularity), CHUNK_LEN);
+            self.read_shadow(chunk_start, &mut buf[..chunk_len])?;
+            let chunk_end = chunk_start + chunk_len * granularity;
+            let mut next = [0u8];
+            let next = self.read_shadow(chunk_end, &mut next).ok().map(|_| next[0]);
+            let range_start = start.max(chunk_start);
+            let range_len = min(end, chunk_end) - range_start;
+            if let Some(fault) = scan_shadow(
+                range_start,
+                range_len,
+                granularity,
+                &buf[(range_start - chunk_start) / granularity..chunk_len],
+                next,
+            ) {
+                return Ok(Some(fault));
+            }
+            chunk_start = chunk_end;
+        }
+        Ok(None)
+    }
+}
+
+/// The first poisoned byte found within a range of memory
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ShadowFault {
+    pub addr: GuestAddr,
+    /// The value of the shadow byte describing `addr`
+    pub value: u8,
+    /// The type of poison at `addr`. Where the shadow byte indicates that only
+    /// part of the granule is addressable, this is taken from the shadow byte
+    /// which follows (in the same manner as compiler-rt) so that the fault can
+    /// be classified, e.g. as an overflow into a right red-zone.
+    pub poison: Option<PoisonType>,
+}
+
+/// Find the first poisoned byte within the given range from the shadow bytes
+/// (`shadow`) describing the granules which it spans, the first of which is
+/// that containing `start`. If known, the value of the shadow byte which
+/// follows those given (`next`) is used to classify a partially addressable
+/// granule at the end of the range.
+pub fn scan_shadow(
+    start: GuestAddr,
+    len: usize,
+    granularity: usize,
+    shadow: &[u8],
+    next: Option<u8>,
+) -> Option<ShadowFault> {
+    let base = start & !(granularity - 1);
+    let end = start.saturating_add(len);
+    let mut i = 0;
+    while let Some(offset) = find_nonzero(&shadow[i..]) {
+        i += offset;
+        let value = shadow[i];
+        let granule = base + i * granularity;
+        let partial = (value as usize) < granularity;
+        let first = if partial {
+            granule + value as usize
+        } else {
+            granule
+        }
+        .max(start);
+        if first < min(end, granule + granularity) {
+            let poison = if partial {
+                shadow.get(i + 1).copied().or(next)
+            } else {
+                Some(value)
+            };
+            return Some(ShadowFault {
+                addr: first,
+                value,
+                poison: poison.and_then(|v| PoisonType::try_from(v).ok()),
+            });
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Find the index of the first non-zero (i.e. not entirely addressable)
+/// shadow byte. Since most of the shadow bytes examined are typically zero,
+/// the aligned part of the slice is tested a word at a time, falling back to
+/// testing individual bytes only at its edges (or within a non-zero word).
+pub fn find_nonzero(shadow: &[u8]) -> Option<usize> {
+    const WORD_SIZE: usize = size_of::<usize>();
+    /* Safety: any bit pattern is a valid usize */
+    let (prefix, words, suffix) = unsafe { shadow.align_to::<usize>() };
+    if let Some(i) = prefix.iter().position(|v| *v != 0) {
+        return Some(i);
+    }
+    let words_start = prefix.len();
+    if let Some(w) = words.iter().position(|w| *w != 0) {
+        let word_start = words_start + w * WORD_SIZE;
+        return shadow[word_start..word_start + WORD_SIZE]
+            .iter()
+            .position(|v| *v != 0)
+            .map(|i| word_start + i);
+    }
+    let suffix_start = words_start + words.len() * WORD_SIZE;
+    suffix
+        .iter()
+        .position(|v| *v != 0)
+        .map(|i| suffix_start + i)
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum PoisonTypeError {
+    #[error("Invalid poison value: {0:x}")]
+    InvalidValue(u8),
 }
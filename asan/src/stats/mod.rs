@@ -0,0 +1,281 @@
+//! # stats
+//! This module describes the usage of the sanitizer heap. The allocator
+//! frontend maintains a `HeapStats` as buffers are allocated, freed (and
+//! placed in the quarantine) and finally released back to the backend. These
+//! comprise:
+//! - The number of bytes (and chunks) currently allocated by the application,
+//!   as well as the peak number of bytes allocated
+//! - The number of bytes (and chunks) held in the quarantine
+//! - The overhead of the red-zones (and alignment padding) of live chunks
+//! - The total number of bytes obtained from the backend
+//! - A histogram of the live chunks by (power of two) size class
+//!
+//! The statistics are exposed to the application through the same
+//! `__sanitizer_get_*` interface as compiler-rt and can be logged on demand
+//! (using `__asan_print_accumulated_stats`) or at exit (see
+//! `set_print_stats`).
+use {
+    crate::{asan_get_ownership, asan_get_size, asan_heap_stats},
+    core::{
+        ffi::{c_int, c_void},
+        fmt::{self, Display, Formatter},
+        sync::atomic::{AtomicUsize, Ordering},
+    },
+    log::error,
+    spin::Mutex,
+};
+
+/// The number of size classes in the histogram of live chunks, the last
+/// class includes all chunks larger than the preceding ones
+pub const NUM_SIZE_CLASSES: usize = 32;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    pub allocated_bytes: usize,
+    pub allocated_chunks: usize,
+    pub peak_allocated_bytes: usize,
+    pub quarantine_bytes: usize,
+    pub quarantine_chunks: usize,
+    pub red_zone_bytes: usize,
+    pub heap_size: usize,
+    pub total_allocs: usize,
+    pub total_frees: usize,
+    pub size_classes: [usize; NUM_SIZE_CLASSES],
+}
+
+impl HeapStats {
+    /// The size class of a chunk of the given length, class `n` contains the
+    /// chunks whose length is at most `2^n` bytes
+    pub fn size_class(len: usize) -> usize {
+        len.checked_next_power_of_two()
+            .map_or(NUM_SIZE_CLASSES - 1, |n| n.trailing_zeros() as usize)
+            .min(NUM_SIZE_CLASSES - 1)
+    }
+
+    /// Record the allocation of a chunk of length `len` by the application
+    /// which occupies `backend_len` bytes of the heap
+    pub fn record_alloc(&mut self, len: usize, backend_len: usize) {
+        self.allocated_bytes += len;
+        self.allocated_chunks += 1;
+        self.peak_allocated_bytes = self.peak_allocated_bytes.max(self.allocated_bytes);
+        self.red_zone_bytes += backend_len - len;
+        self.heap_size += backend_len;
+        self.total_allocs += 1;
+        self.size_classes[Self::size_class(len)] += 1;
+    }
+
+    /// Record a chunk being freed by the application and placed in the
+    /// quarantine
+    pub fn record_free(&mut self, len: usize, backend_len: usize) {
+        self.allocated_bytes -= len;
+        self.allocated_chunks -= 1;
+        self.red_zone_bytes -= backend_len - len;
+        self.quarantine_bytes += backend_len;
+        self.quarantine_chunks += 1;
+        self.total_frees += 1;
+        self.size_classes[Self::size_class(len)] -= 1;
+    }
+
+    /// Record a chunk leaving the quarantine and being released to the
+    /// backend
+    pub fn record_release(&mut self, backend_len: usize) {
+        self.quarantine_bytes -= backend_len;
+        self.quarantine_chunks -= 1;
+        self.heap_size -= backend_len;
+    }
+
+    /// Record all of the chunks (both live and quarantined) being discarded at
+    /// once. The cumulative totals and peak are retained.
+    pub fn record_reset(&mut self) {
+        *self = HeapStats {
+            peak_allocated_bytes: self.peak_allocated_bytes,
+            total_allocs: self.total_allocs,
+            total_frees: self.total_frees,
+            ..HeapStats::default()
+        };
+    }
+
+    /// The number of bytes of the heap which are not allocated to the
+    /// application (i.e. those held in the quarantine)
+    pub fn free_bytes(&self) -> usize {
+        self.quarantine_bytes
+    }
+}
+
+impl Display for HeapStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Stats: {} bytes in {} chunks allocated ({} bytes peak)",
+            self.allocated_bytes, self.allocated_chunks, self.peak_allocated_bytes
+        )?;
+        writeln!(
+            f,
+            "Stats: {} bytes in {} chunks quarantined",
+            self.quarantine_bytes, self.quarantine_chunks
+        )?;
+        writeln!(f, "Stats: {} bytes of red-zones", self.red_zone_bytes)?;
+        writeln!(f, "Stats: {} bytes of heap", self.heap_size)?;
+        writeln!(
+            f,
+            "Stats: {} allocations, {} frees",
+            self.total_allocs, self.total_frees
+        )?;
+        write!(f, "Stats: live chunks by size:")?;
+        self.size_classes
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count != 0)
+            .try_for_each(|(class, count)| write!(f, "\n    <= {}: {}", 1usize << class, count))
+    }
+}
+
+/// A `HeapStats` which may be updated by several threads at once without a
+/// lock. Each counter is updated independently, so a snapshot taken while
+/// other threads are allocating may be (briefly) inconsistent.
+#[derive(Debug, Default)]
+pub struct AtomicHeapStats {
+    allocated_bytes: AtomicUsize,
+    allocated_chunks: AtomicUsize,
+    peak_allocated_bytes: AtomicUsize,
+    quarantine_bytes: AtomicUsize,
+    quarantine_chunks: AtomicUsize,
+    red_zone_bytes: AtomicUsize,
+    heap_size: AtomicUsize,
+    total_allocs: AtomicUsize,
+    total_frees: AtomicUsize,
+    size_classes: [AtomicUsize; NUM_SIZE_CLASSES],
+}
+
+impl AtomicHeapStats {
+    /// See `HeapStats::record_alloc`
+    pub fn record_alloc(&self, len: usize, backend_len: usize) {
+        let allocated = self.allocated_bytes.fetch_add(len, Ordering::Relaxed) + len;
+        self.peak_allocated_bytes
+            .fetch_max(allocated, Ordering::Relaxed);
+        self.allocated_chunks.fetch_add(1, Ordering::Relaxed);
+        self.red_zone_bytes
+            .fetch_add(backend_len - len, Ordering::Relaxed);
+        self.heap_size.fetch_add(backend_len, Ordering::Relaxed);
+        self.total_allocs.fetch_add(1, Ordering::Relaxed);
+        self.size_classes[HeapStats::size_class(len)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// See `HeapStats::record_free`
+    pub fn record_free(&self, len: usize, backend_len: usize) {
+        self.allocated_bytes.fetch_sub(len, Ordering::Relaxed);
+        self.allocated_chunks.fetch_sub(1, Ordering::Relaxed);
+        self.red_zone_bytes
+            .fetch_sub(backend_len - len, Ordering::Relaxed);
+        self.quarantine_bytes
+            .fetch_add(backend_len, Ordering::Relaxed);
+        self.quarantine_chunks.fetch_add(1, Ordering::Relaxed);
+        self.total_frees.fetch_add(1, Ordering::Relaxed);
+        self.size_classes[HeapStats::size_class(len)].fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// See `HeapStats::record_release`
+    pub fn record_release(&self, backend_len: usize) {
+        self.quarantine_bytes
+            .fetch_sub(backend_len, Ordering::Relaxed);
+        self.quarantine_chunks.fetch_sub(1, Ordering::Relaxed);
+        self.heap_size.fetch_sub(backend_len, Ordering::Relaxed);
+    }
+
+    /// See `HeapStats::record_reset`
+    pub fn record_reset(&self) {
+        [
+            &self.allocated_bytes,
+            &self.allocated_chunks,
+            &self.quarantine_bytes,
+            &self.quarantine_chunks,
+            &self.red_zone_bytes,
+            &self.heap_size,
+        ]
+        .into_iter()
+        .chain(&self.size_classes)
+        .for_each(|counter| counter.store(0, Ordering::Relaxed));
+    }
+
+    /// A snapshot of the statistics
+    pub fn load(&self) -> HeapStats {
+        let load = |counter: &AtomicUsize| counter.load(Ordering::Relaxed);
+        HeapStats {
+            allocated_bytes: load(&self.allocated_bytes),
+            allocated_chunks: load(&self.allocated_chunks),
+            peak_allocated_bytes: load(&self.peak_allocated_bytes),
+            quarantine_bytes: load(&self.quarantine_bytes),
+            quarantine_chunks: load(&self.quarantine_chunks),
+            red_zone_bytes: load(&self.red_zone_bytes),
+            heap_size: load(&self.heap_size),
+            total_allocs: load(&self.total_allocs),
+            total_frees: load(&self.total_frees),
+            size_classes: self.size_classes.each_ref().map(load),
+        }
+    }
+}
+
+static PRINT_STATS: Mutex<bool> = Mutex::new(false);
+
+/// Set whether the statistics of the heap should be logged at exit
+pub fn set_print_stats(print_stats: bool) {
+    *PRINT_STATS.lock() = print_stats;
+}
+
+pub fn print_stats() -> bool {
+    *PRINT_STATS.lock()
+}
+
+/// Log the given statistics
+pub fn emit_stats(stats: &HeapStats) {
+    error!("{}", stats);
+}
+
+/// # Safety
+/// Called by the application
+#[no_mangle]
+pub unsafe extern "C" fn __sanitizer_get_current_allocated_bytes() -> usize {
+    asan_heap_stats().allocated_bytes
+}
+
+/// # Safety
+/// Called by the application
+#[no_mangle]
+pub unsafe extern "C" fn __sanitizer_get_heap_size() -> usize {
+    asan_heap_stats().heap_size
+}
+
+/// # Safety
+/// Called by the application
+#[no_mangle]
+pub unsafe extern "C" fn __sanitizer_get_free_bytes() -> usize {
+    asan_heap_stats().free_bytes()
+}
+
+/// # Safety
+/// Called by the application
+#[no_mangle]
+pub unsafe extern "C" fn __sanitizer_get_ownership(p: *const c_void) -> c_int {
+    asan_get_ownership(p) as c_int
+}
+
+/// Returns zero if the buffer is not owned by the allocator
+///
+/// # Safety
+/// Called by the application
+#[no_mangle]
+pub unsafe extern "C" fn __sanitizer_get_allocated_size(p: *const c_void) -> usize {
+    if asan_get_ownership(p) {
+        asan_get_size(p)
+    } else {
+        0
+    }
+}
+
+/// # Safety
+/// Called by the application
+#[no_mangle]
+pub unsafe extern "C" fn __asan_print_accumulated_stats() {
+    emit_stats(&asan_heap_stats());
+}
@@ -1,17 +1,20 @@
 use {
     crate::{
         allocator::{
+            allocator_may_return_null,
             backend::dlmalloc::DlmallocBackend,
-            frontend::{default::DefaultFrontend, AllocatorFrontend},
+            frontend::{default::DefaultFrontend, AllocationType, AllocatorFrontend},
         },
         exit::exit,
         shadow::Shadow,
+        stats::HeapStats,
         symbols::Symbols,
         tracking::Tracking,
         GuestAddr,
     },
     core::{
         ffi::{c_char, c_void, CStr},
+        ptr::null_mut,
         sync::atomic::{AtomicBool, Ordering},
     },
     log::{error, trace, Level},
@@ -41,6 +44,12 @@ type TestHost = crate::host::libc::LibcHost<TestSyms>;
 ))]
 type TestHost = crate::host::linux::LinuxHost;
 
+#[cfg(all(feature = "linux", not(feature = "libc")))]
+type TestMapReader = crate::maps::linux::LinuxMapReader;
+
+#[cfg(feature = "libc")]
+type TestMapReader = crate::maps::libc::LibcMapReader<TestSyms>;
+
 #[cfg(feature = "guest")]
 type TestShadow =
     crate::shadow::guest::GuestShadow<TestMap, crate::shadow::guest::DefaultShadowLayout>;
@@ -115,7 +124,15 @@ pub unsafe extern "C" fn asan_store(addr: *const c_void, size: usize) {
 /// # Safety
 pub unsafe extern "C" fn asan_alloc(len: usize, align: usize) -> *mut c_void {
     trace!("alloc - len: {:#x}, align: {:#x}", len, align);
-    let ptr = FRONTEND.lock().alloc(len, align).unwrap() as *mut c_void;
+    let result = FRONTEND.lock().alloc(len, align);
+    let ptr = match result {
+        Ok(addr) => addr as *mut c_void,
+        Err(_) if allocator_may_return_null() => null_mut(),
+        Err(e) => {
+            error!("alloc - {:?}", e);
+            asan_panic(c"alloc - allocation failed".as_ptr() as *const c_char)
+        }
+    };
     trace!(
         "alloc - len: {:#x}, align: {:#x}, ptr: {:p}",
         len,
@@ -132,6 +149,51 @@ pub unsafe extern "C" fn asan_dealloc(addr: *const c_void) {
     FRONTEND.lock().dealloc(addr as GuestAddr).unwrap();
 }
 
+#[no_mangle]
+/// # Safety
+pub unsafe extern "C" fn asan_new(
+    len: usize,
+    align: usize,
+    alloc_type: AllocationType,
+    nothrow: bool,
+) -> *mut c_void {
+    trace!(
+        "new - len: {:#x}, align: {:#x}, type: {:?}, nothrow: {}",
+        len,
+        align,
+        alloc_type,
+        nothrow
+    );
+    let result = FRONTEND.lock().alloc_typed(len, align, alloc_type);
+    match result {
+        Ok(addr) => addr as *mut c_void,
+        Err(_) if nothrow && allocator_may_return_null() => null_mut(),
+        Err(e) => {
+            error!("new - {:?}", e);
+            asan_panic(c"new - allocation failed".as_ptr() as *const c_char)
+        }
+    }
+}
+
+#[no_mangle]
+/// # Safety
+pub unsafe extern "C" fn asan_delete(
+    addr: *const c_void,
+    dealloc_type: AllocationType,
+    len: usize,
+) {
+    trace!(
+        "delete - addr: {:p}, type: {:?}, len: {:#x}",
+        addr,
+        dealloc_type,
+        len
+    );
+    FRONTEND
+        .lock()
+        .dealloc_typed(addr as GuestAddr, dealloc_type, (len != 0).then_some(len))
+        .unwrap();
+}
+
 #[no_mangle]
 /// # Safety
 pub unsafe extern "C" fn asan_get_size(addr: *const c_void) -> usize {
@@ -139,6 +201,19 @@ pub unsafe extern "C" fn asan_get_size(addr: *const c_void) -> usize {
     FRONTEND.lock().get_size(addr as GuestAddr).unwrap()
 }
 
+#[no_mangle]
+/// # Safety
+pub unsafe extern "C" fn asan_get_ownership(addr: *const c_void) -> bool {
+    trace!("get_ownership - addr: {:p}", addr);
+    FRONTEND.lock().owns(addr as GuestAddr)
+}
+
+#[no_mangle]
+/// # Safety
+pub unsafe extern "C" fn asan_heap_stats() -> HeapStats {
+    FRONTEND.lock().stats()
+}
+
 #[no_mangle]
 /// # Safety
 pub unsafe extern "C" fn asan_sym(name: *const c_char) -> GuestAddr {
@@ -162,6 +237,96 @@ pub unsafe extern "C" fn asan_unpoison(addr: *const c_void, len: usize) {
         .unwrap();
 }
 
+#[cfg(feature = "compiler_rt")]
+#[no_mangle]
+/// # Safety
+pub unsafe extern "C" fn asan_poison(
+    addr: *const c_void,
+    len: usize,
+    poison: crate::shadow::PoisonType,
+) {
+    trace!(
+        "poison - addr: {:p}, len: {:#x}, poison: {:?}",
+        addr,
+        len,
+        poison
+    );
+    FRONTEND
+        .lock()
+        .shadow_mut()
+        .poison(addr as GuestAddr, len, poison)
+        .unwrap();
+}
+
+#[cfg(feature = "compiler_rt")]
+#[no_mangle]
+/// # Safety
+pub unsafe extern "C" fn asan_is_poison(addr: *const c_void, len: usize) -> bool {
+    trace!("is_poison - addr: {:p}, len: {:#x}", addr, len);
+    FRONTEND
+        .lock()
+        .shadow()
+        .is_poison(addr as GuestAddr, len)
+        .unwrap_or(true)
+}
+
+#[cfg(feature = "compiler_rt")]
+#[no_mangle]
+/// # Safety
+pub unsafe extern "C" fn asan_shadow_granularity() -> usize {
+    FRONTEND.lock().shadow().granularity()
+}
+
+#[cfg(feature = "compiler_rt")]
+#[no_mangle]
+/// # Safety
+pub unsafe extern "C" fn asan_find_poison(addr: *const c_void, len: usize) -> *mut c_void {
+    trace!("find_poison - addr: {:p}, len: {:#x}", addr, len);
+    match FRONTEND.lock().shadow().find_poison(addr as GuestAddr, len) {
+        Ok(Some(fault)) => fault.addr as *mut c_void,
+        Ok(None) => null_mut(),
+        Err(_) => addr as *mut c_void,
+    }
+}
+
+#[no_mangle]
+/// # Safety
+pub unsafe extern "C" fn asan_stack_top(addr: GuestAddr) -> GuestAddr {
+    use crate::maps::{iterator::MapIterator, MapReader};
+    trace!("stack_top - addr: 0x{:x}", addr);
+    MapIterator::new(TestMapReader::new().unwrap())
+        .find(|m| m.contains(addr))
+        .map_or(0, |m| m.limit())
+}
+
+#[cfg(feature = "compiler_rt")]
+#[no_mangle]
+/// # Safety
+pub unsafe extern "C" fn asan_init() {
+    trace!("init");
+    drop(FRONTEND.lock());
+}
+
+#[cfg(feature = "compiler_rt")]
+static FAKE_STACKS: crate::fake_stack::FakeStacks<TestMap> =
+    crate::fake_stack::FakeStacks::new(crate::fake_stack::DEFAULT_CLASS_SIZE);
+
+#[cfg(feature = "compiler_rt")]
+#[no_mangle]
+/// # Safety
+pub unsafe extern "C" fn asan_fake_stack_alloc(class: usize, real: GuestAddr) -> *mut c_void {
+    trace!("fake_stack_alloc - class: {}, real: 0x{:x}", class, real);
+    FAKE_STACKS.alloc(class, real).unwrap().unwrap_or(0) as *mut c_void
+}
+
+#[cfg(feature = "compiler_rt")]
+#[no_mangle]
+/// # Safety
+pub unsafe extern "C" fn asan_fake_stack_dealloc(addr: *mut c_void, class: usize) {
+    trace!("fake_stack_dealloc - addr: {:p}, class: {}", addr, class);
+    FAKE_STACKS.dealloc(class, addr as GuestAddr).unwrap();
+}
+
 #[no_mangle]
 /// # Safety
 pub unsafe extern "C" fn asan_track(addr: *const c_void, len: usize) {
@@ -0,0 +1,44 @@
+//! # libc
+//! This implementation issues the `gettid` and `kill` system calls by means of
+//! the `syscall` function provided by `libc`.
+use {
+    crate::{
+        asan_swap, asan_sym,
+        symbols::{AtomicGuestAddr, Function, FunctionPointer},
+    },
+    core::ffi::{c_char, c_long, CStr},
+    libc::{SYS_gettid, SYS_kill},
+};
+
+#[derive(Debug)]
+struct FunctionSyscall;
+
+impl Function for FunctionSyscall {
+    type Func = unsafe extern "C" fn(num: c_long, ...) -> c_long;
+    const NAME: &'static CStr = c"syscall";
+}
+
+static SYSCALL_ADDR: AtomicGuestAddr = AtomicGuestAddr::new();
+
+fn get_syscall() -> <FunctionSyscall as Function>::Func {
+    let addr = SYSCALL_ADDR.get_or_insert_with(|| unsafe {
+        asan_sym(FunctionSyscall::NAME.as_ptr() as *const c_char)
+    });
+    FunctionSyscall::as_ptr(addr).unwrap()
+}
+
+pub fn thread_id() -> usize {
+    let fn_syscall = get_syscall();
+    unsafe { asan_swap(false) };
+    let tid = unsafe { fn_syscall(SYS_gettid) };
+    unsafe { asan_swap(true) };
+    tid as usize
+}
+
+pub fn thread_exited(tid: usize) -> bool {
+    let fn_syscall = get_syscall();
+    unsafe { asan_swap(false) };
+    let ret = unsafe { fn_syscall(SYS_kill, tid as c_long, 0 as c_long) };
+    unsafe { asan_swap(true) };
+    ret != 0
+}
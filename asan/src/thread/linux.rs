@@ -0,0 +1,12 @@
+//! # linux
+//! This implementation issues the `gettid` and `kill` system calls directly
+//! and hence has no dependency on `libc`.
+use syscalls::{syscall0, syscall2, Sysno};
+
+pub fn thread_id() -> usize {
+    unsafe { syscall0(Sysno::gettid) }.unwrap()
+}
+
+pub fn thread_exited(tid: usize) -> bool {
+    unsafe { syscall2(Sysno::kill, tid, 0) }.is_err()
+}
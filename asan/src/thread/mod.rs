@@ -0,0 +1,61 @@
+//! # thread
+//! Since no thread local storage is available to us, the current thread is
+//! identified by the value of its thread pointer, this is unique for each live
+//! thread (though it may be re-used once a thread has exited).
+//!
+//! Nor are we notified when a thread exits, so any state kept on behalf of a
+//! thread records the kernel's identifier of the thread (see `thread_id`),
+//! allowing it to be reclaimed once `thread_exited` reports that it is gone.
+use crate::GuestAddr;
+
+#[cfg(feature = "libc")]
+pub use crate::thread::libc::{thread_exited, thread_id};
+
+#[cfg(all(feature = "linux", not(feature = "libc")))]
+pub use crate::thread::linux::{thread_exited, thread_id};
+
+#[cfg(feature = "libc")]
+pub mod libc;
+
+#[cfg(feature = "linux")]
+pub mod linux;
+
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+pub fn thread_pointer() -> GuestAddr {
+    let tp: GuestAddr;
+    unsafe { core::arch::asm!("mov {}, fs:0", out(reg) tp) };
+    tp
+}
+
+#[cfg(target_arch = "x86")]
+#[inline(always)]
+pub fn thread_pointer() -> GuestAddr {
+    let tp: GuestAddr;
+    unsafe { core::arch::asm!("mov {}, gs:0", out(reg) tp) };
+    tp
+}
+
+#[cfg(target_arch = "aarch64")]
+#[inline(always)]
+pub fn thread_pointer() -> GuestAddr {
+    let tp: GuestAddr;
+    unsafe { core::arch::asm!("mrs {}, tpidr_el0", out(reg) tp) };
+    tp
+}
+
+#[cfg(target_arch = "arm")]
+#[inline(always)]
+pub fn thread_pointer() -> GuestAddr {
+    let tp: GuestAddr;
+    unsafe { core::arch::asm!("mrc p15, 0, {}, c13, c0, 3", out(reg) tp) };
+    tp
+}
+
+#[cfg(target_arch = "powerpc")]
+#[inline(always)]
+pub fn thread_pointer() -> GuestAddr {
+    let tp: GuestAddr;
+    unsafe { core::arch::asm!("mr {}, 2", out(reg) tp) };
+    tp
+}
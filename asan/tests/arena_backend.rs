@@ -0,0 +1,142 @@
+#[cfg(test)]
+#[cfg(feature = "linux")]
+mod tests {
+
+    use {
+        asan::{
+            allocator::backend::{
+                arena::{ArenaBackend, ArenaBackendError},
+                AllocatorBackend, ResettableBackend,
+            },
+            mmap::linux::LinuxMmap,
+            options::Options,
+        },
+        spin::Lazy,
+        std::sync::Mutex,
+    };
+
+    static INIT_ONCE: Lazy<Mutex<()>> = Lazy::new(|| {
+        {
+            env_logger::init();
+        };
+        Mutex::new(())
+    });
+
+    const ARENA_SIZE: usize = 1 << 20;
+
+    const PAGE_SIZE: usize = 4096;
+
+    type AB = ArenaBackend<LinuxMmap>;
+
+    fn allocator() -> AB {
+        drop(INIT_ONCE.lock().unwrap());
+        AB::new(ARENA_SIZE).unwrap()
+    }
+
+    #[test]
+    fn test_allocate() {
+        let mut allocator = allocator();
+        let range = allocator.range();
+        assert_eq!(range.len(), ARENA_SIZE);
+        let a = allocator.alloc(20, 8).unwrap();
+        let b = allocator.alloc(16, 16).unwrap();
+        assert_eq!(a, range.start);
+        assert_eq!(b, range.start + 32);
+        assert_eq!(allocator.used(), 48);
+        unsafe { (b as *mut u8).write_bytes(0xaa, 16) };
+        allocator.dealloc(a, 20, 8).unwrap();
+        allocator.dealloc(b, 16, 16).unwrap();
+        /* Freed buffers aren't re-used until the arena is reset */
+        assert_eq!(allocator.used(), 48);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut allocator = allocator();
+        let start = allocator.range().start;
+        let a = allocator.alloc(100, 8).unwrap();
+        assert_eq!(allocator.reset().unwrap(), start..start + 100);
+        assert_eq!(allocator.used(), 0);
+        let b = allocator.alloc(100, 8).unwrap();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_out_of_memory() {
+        let mut allocator = allocator();
+        allocator.alloc(ARENA_SIZE - 8, 8).unwrap();
+        assert_eq!(
+            allocator.alloc(16, 8),
+            Err(ArenaBackendError::OutOfMemory(16))
+        );
+        assert_eq!(
+            allocator.alloc(usize::MAX, 8),
+            Err(ArenaBackendError::OutOfMemory(usize::MAX))
+        );
+        allocator.alloc(8, 8).unwrap();
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut allocator = allocator();
+        assert_eq!(
+            allocator.alloc(16, 3),
+            Err(ArenaBackendError::InvalidAlignment(3))
+        );
+        let local = 0u64;
+        let addr = &local as *const u64 as usize;
+        assert_eq!(
+            allocator.dealloc(addr, 8, 8),
+            Err(ArenaBackendError::InvalidAddress(addr))
+        );
+        /* The first unused address of the arena was never allocated */
+        let buf = allocator.alloc(16, 8).unwrap();
+        assert_eq!(
+            allocator.dealloc(buf + 16, 8, 8),
+            Err(ArenaBackendError::InvalidAddress(buf + 16))
+        );
+    }
+
+    #[test]
+    fn test_with_options() {
+        drop(INIT_ONCE.lock().unwrap());
+        let options = Options::parse("arena_size_mb=2").unwrap();
+        let allocator = AB::with_options(PAGE_SIZE, &options).unwrap();
+        assert_eq!(allocator.range().len(), 2 << 20);
+        let options = Options::parse(&format!("arena_size_mb={}", usize::MAX)).unwrap();
+        assert!(matches!(
+            AB::with_options(PAGE_SIZE, &options),
+            Err(ArenaBackendError::InvalidArenaSize(usize::MAX))
+        ));
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn test_fixed_base() {
+        drop(INIT_ONCE.lock().unwrap());
+        let base = 0x6200_0000_0000;
+        let options = Options {
+            arena_size_mb: Some(1),
+            heap_base: Some(base),
+            ..Options::default()
+        };
+        let mut allocator = AB::with_options(PAGE_SIZE, &options).unwrap();
+        assert_eq!(allocator.range(), base..base + ARENA_SIZE);
+        assert_eq!(allocator.alloc(16, 8).unwrap(), base);
+        /* The region is already occupied */
+        assert!(matches!(
+            AB::new_at(base, ARENA_SIZE),
+            Err(ArenaBackendError::MmapError(_))
+        ));
+    }
+
+    #[test]
+    fn test_unaligned_heap_base() {
+        drop(INIT_ONCE.lock().unwrap());
+        let options = Options::parse("arena_size_mb=1:heap_base=0x62000010").unwrap();
+        assert!(matches!(
+            AB::with_options(PAGE_SIZE, &options),
+            Err(ArenaBackendError::InvalidHeapBase(0x6200_0010))
+        ));
+    }
+}
@@ -0,0 +1,62 @@
+#[cfg(test)]
+mod tests {
+    use asan::{
+        backtrace::{capture, depot::StackDepot, MAX_DEPTH},
+        GuestAddr,
+    };
+
+    #[test]
+    fn test_depot_insert() {
+        let mut depot = StackDepot::new();
+        assert!(depot.is_empty());
+        let a = depot.insert(&[0x1000, 0x2000, 0x3000]);
+        let b = depot.insert(&[0x1000, 0x2000]);
+        assert_ne!(a, b);
+        assert_eq!(depot.len(), 2);
+        assert_eq!(depot.get(a), Some(&[0x1000, 0x2000, 0x3000][..]));
+        assert_eq!(depot.get(b), Some(&[0x1000, 0x2000][..]));
+    }
+
+    #[test]
+    fn test_depot_deduplicate() {
+        let mut depot = StackDepot::new();
+        let a = depot.insert(&[0x1000, 0x2000]);
+        let b = depot.insert(&[0x1000, 0x2000]);
+        assert_eq!(a, b);
+        assert_eq!(depot.len(), 1);
+    }
+
+    #[test]
+    fn test_depot_empty_stack() {
+        let mut depot = StackDepot::new();
+        let a = depot.insert(&[]);
+        assert_eq!(depot.get(a), Some(&[][..]));
+    }
+
+    #[test]
+    fn test_capture_bounded() {
+        let mut frames = [0 as GuestAddr; MAX_DEPTH];
+        assert_eq!(capture(&mut frames[..0]), 0);
+        let depth = capture(&mut frames[..2]);
+        assert!(depth <= 2);
+        assert!(frames[..depth].iter().all(|f| *f != 0));
+    }
+
+    #[test]
+    fn test_capture_threads() {
+        let depths = (0..4)
+            .map(|_| {
+                std::thread::spawn(|| {
+                    let mut frames = [0 as GuestAddr; MAX_DEPTH];
+                    let depth = capture(&mut frames);
+                    assert!(frames[..depth].iter().all(|f| *f != 0));
+                    /* The stack of the thread is remembered for subsequent captures */
+                    assert_eq!(capture(&mut frames), depth);
+                    depth
+                })
+            })
+            .map(|t| t.join().unwrap())
+            .collect::<Vec<_>>();
+        assert!(depths.iter().all(|d| *d == depths[0]));
+    }
+}
@@ -0,0 +1,430 @@
+#[cfg(test)]
+#[cfg(all(feature = "linux", feature = "dlmalloc"))]
+mod tests {
+
+    use {
+        asan::{
+            allocator::{
+                backend::dlmalloc::DlmallocBackend,
+                frontend::{
+                    cached::{class_size, CachedFrontend, CachedFrontendError, MAX_CACHED_SIZE},
+                    default::DefaultFrontend,
+                    AllocationInfo, AllocationType,
+                },
+            },
+            mmap::linux::LinuxMmap,
+            shadow::{
+                guest::{DefaultShadowLayout, GuestShadow},
+                Shadow,
+            },
+            thread::{thread_exited, thread_id},
+            tracking::guest::GuestTracking,
+            GuestAddr,
+        },
+        core::{mem::size_of, slice::from_ref},
+        spin::Lazy,
+        std::{
+            sync::{Arc, Barrier},
+            thread,
+        },
+    };
+
+    const PAGE_SIZE: usize = 4096;
+
+    const BATCH_SIZE: usize = 4;
+
+    type GS = GuestShadow<LinuxMmap, DefaultShadowLayout>;
+
+    type DF = DefaultFrontend<DlmallocBackend<LinuxMmap>, &'static GS, GuestTracking>;
+
+    type CF = CachedFrontend<DlmallocBackend<LinuxMmap>, &'static GS, GuestTracking>;
+
+    static SHADOW: Lazy<GS> = Lazy::new(|| GS::new().unwrap());
+
+    static INIT_ONCE: Lazy<CF> = Lazy::new(|| {
+        env_logger::init();
+        let backend = DlmallocBackend::<LinuxMmap>::new(PAGE_SIZE);
+        let tracking = GuestTracking::new().unwrap();
+        let frontend = DF::new(
+            backend,
+            &*SHADOW,
+            tracking,
+            DF::DEFAULT_REDZONE_SIZE,
+            DF::DEFAULT_QUARANTINE_SIZE,
+        )
+        .unwrap();
+        CF::new(frontend, &*SHADOW, BATCH_SIZE).unwrap()
+    });
+
+    fn frontend() -> &'static CF {
+        &INIT_ONCE
+    }
+
+    #[test]
+    fn test_allocate() {
+        let frontend = frontend();
+        let buf = frontend.alloc(20, 8).unwrap();
+        assert_eq!(buf % 16, 0);
+        assert_eq!(frontend.get_size(buf).unwrap(), 20);
+        frontend.dealloc(buf).unwrap();
+    }
+
+    #[test]
+    fn test_allocate_is_poisoned() {
+        let frontend = frontend();
+        let len = 20;
+        let buf = frontend.alloc(len, 8).unwrap();
+        for i in buf - 64..buf + class_size(1) + 64 {
+            let expected = i < buf || i >= buf + len;
+            let poisoned = SHADOW.is_poison(i, 1).unwrap();
+            assert_eq!(expected, poisoned);
+        }
+        frontend.dealloc(buf).unwrap();
+        assert!(SHADOW.is_poison(buf, 1).unwrap());
+    }
+
+    #[test]
+    fn test_refill_batch() {
+        let frontend = frontend();
+        let bufs = (0..BATCH_SIZE)
+            .map(|_| frontend.alloc(64, 8).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(frontend.threads(), 1);
+        /* The cache is now empty, so this chunk is taken from a new batch */
+        let next = frontend.alloc(64, 8).unwrap();
+        assert!(!bufs.contains(&next));
+        for buf in bufs {
+            assert_eq!(frontend.get_size(buf).unwrap(), 64);
+            frontend.dealloc(buf).unwrap();
+        }
+        frontend.dealloc(next).unwrap();
+    }
+
+    #[test]
+    fn test_allocate_large() {
+        let frontend = frontend();
+        let len = MAX_CACHED_SIZE + 1;
+        let buf = frontend.alloc(len, 8).unwrap();
+        assert_eq!(frontend.get_size(buf).unwrap(), len);
+        assert!(!SHADOW.is_poison(buf, len).unwrap());
+        assert!(SHADOW.is_poison(buf + len, 1).unwrap());
+        assert!(SHADOW.is_poison(buf - 1, 1).unwrap());
+        frontend.dealloc(buf).unwrap();
+    }
+
+    #[test]
+    fn test_allocate_aligned() {
+        let frontend = frontend();
+        let buf = frontend.alloc(24, 0x1000).unwrap();
+        assert_eq!(buf % 0x1000, 0);
+        assert_eq!(frontend.get_size(buf).unwrap(), 24);
+        let info = frontend.find_allocation(buf).unwrap();
+        assert_eq!(info.addr, buf);
+        assert_eq!(info.len, 24);
+        frontend.dealloc(buf).unwrap();
+    }
+
+    #[test]
+    fn test_double_free() {
+        let frontend = frontend();
+        let buf = frontend.alloc(16, 8).unwrap();
+        frontend.dealloc(buf).unwrap();
+        assert!(matches!(
+            frontend.dealloc(buf),
+            Err(CachedFrontendError::DoubleFree(a)) if a == buf
+        ));
+        assert!(matches!(
+            frontend.get_size(buf),
+            Err(CachedFrontendError::InvalidAddress(a)) if a == buf
+        ));
+    }
+
+    #[test]
+    fn test_concurrent_double_free() {
+        const THREADS: usize = 4;
+        let frontend = frontend();
+        for _ in 0..100 {
+            let buf = frontend.alloc(48, 8).unwrap();
+            let barrier = Arc::new(Barrier::new(THREADS));
+            let results = (0..THREADS)
+                .map(|_| {
+                    let barrier = barrier.clone();
+                    thread::spawn(move || {
+                        barrier.wait();
+                        frontend.dealloc(buf)
+                    })
+                })
+                .collect::<Vec<_>>()
+                .into_iter()
+                .map(|t| t.join().unwrap())
+                .collect::<Vec<_>>();
+            /* Exactly one of the threads frees the buffer */
+            assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+            assert!(results.iter().all(|r| match r {
+                Ok(()) => true,
+                Err(CachedFrontendError::DoubleFree(a)) => *a == buf,
+                Err(_) => false,
+            }));
+        }
+    }
+
+    #[test]
+    fn test_invalid_free() {
+        let frontend = frontend();
+        let buf = frontend.alloc(64, 8).unwrap();
+        assert!(matches!(
+            frontend.dealloc(buf + 16),
+            Err(CachedFrontendError::InvalidAddress(a)) if a == buf + 16
+        ));
+        assert!(matches!(
+            frontend.dealloc(buf + 1),
+            Err(CachedFrontendError::InvalidAddress(a)) if a == buf + 1
+        ));
+        frontend.dealloc(buf).unwrap();
+    }
+
+    #[test]
+    fn test_batched_quarantine() {
+        let frontend = frontend();
+        let bufs = (0..BATCH_SIZE)
+            .map(|_| frontend.alloc(32, 8).unwrap())
+            .collect::<Vec<_>>();
+        for buf in &bufs[..BATCH_SIZE - 1] {
+            frontend.dealloc(*buf).unwrap();
+        }
+        /* Freed chunks remain allocated by the shared frontend until handed off */
+        let first = bufs[0];
+        let chunk = frontend.frontend().find_allocation(first).unwrap();
+        assert!(!chunk.freed);
+        assert!(SHADOW.is_poison(first, 1).unwrap());
+
+        frontend.dealloc(bufs[BATCH_SIZE - 1]).unwrap();
+        let chunk = frontend.frontend().find_allocation(first).unwrap();
+        assert!(chunk.freed);
+    }
+
+    #[test]
+    fn test_find_allocation() {
+        let frontend = frontend();
+        let len = 40;
+        let buf = frontend.alloc(len, 8).unwrap();
+        let expected = frontend.find_allocation(buf);
+        assert!(matches!(
+            expected,
+            Some(AllocationInfo {
+                addr,
+                len: 40,
+                freed: false,
+                free_stack: None,
+                ..
+            }) if addr == buf
+        ));
+        assert_eq!(frontend.find_allocation(buf + len), expected);
+        assert_eq!(frontend.find_allocation(buf - 1), expected);
+        frontend.dealloc(buf).unwrap();
+        let freed = frontend.find_allocation(buf + 4).unwrap();
+        assert_eq!(freed.addr, buf);
+        assert!(freed.freed);
+        assert!(!freed.free_stack.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_alloc_dealloc_mismatch() {
+        let frontend = frontend();
+        let len = 24;
+        let buf = frontend.alloc_typed(len, 8, AllocationType::New).unwrap();
+        assert!(matches!(
+            frontend.dealloc_typed(buf, AllocationType::Malloc, None),
+            Err(CachedFrontendError::AllocDeallocMismatch(
+                a,
+                AllocationType::New,
+                AllocationType::Malloc
+            )) if a == buf
+        ));
+        assert!(matches!(
+            frontend.dealloc_typed(buf, AllocationType::New, Some(len + 1)),
+            Err(CachedFrontendError::NewDeleteSizeMismatch(a, 25)) if a == buf
+        ));
+        /* The buffer remains allocated after a mismatch is detected */
+        assert_eq!(frontend.get_size(buf).unwrap(), len);
+        frontend
+            .dealloc_typed(buf, AllocationType::New, Some(len))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_owns() {
+        let frontend = frontend();
+        let buf = frontend.alloc(48, 8).unwrap();
+        assert!(frontend.owns(buf));
+        assert!(!frontend.owns(buf + 16));
+        frontend.dealloc(buf).unwrap();
+        assert!(!frontend.owns(buf));
+    }
+
+    #[test]
+    fn test_stats() {
+        let frontend = frontend();
+        let len = 0x123;
+        let before = frontend.stats();
+        let buf = frontend.alloc(len, 8).unwrap();
+        let allocated = frontend.stats();
+        assert!(allocated.total_allocs > before.total_allocs);
+        assert!(allocated.peak_allocated_bytes >= len);
+        assert!(allocated.heap_size > 0);
+        frontend.dealloc(buf).unwrap();
+        assert!(frontend.stats().total_frees > before.total_frees);
+    }
+
+    #[test]
+    fn test_find_leaks() {
+        let frontend = frontend();
+        let len = 0x321;
+        let root = Box::new(frontend.alloc(len, 8).unwrap());
+        let start = &*root as *const GuestAddr as GuestAddr;
+        let root_range = start..start + size_of::<GuestAddr>();
+        let leaks = unsafe { frontend.find_leaks(from_ref(&root_range)) };
+        assert!(leaks.iter().all(|l| l.bytes != len));
+        let leaks = unsafe { frontend.find_leaks(&[]) };
+        assert!(leaks.iter().any(|l| l.bytes == len));
+        frontend.dealloc(*root).unwrap();
+        let leaks = unsafe { frontend.find_leaks(&[]) };
+        assert!(leaks.iter().all(|l| l.bytes != len));
+    }
+
+    #[test]
+    fn test_flush() {
+        let frontend = frontend();
+        let buf = frontend.alloc(100, 8).unwrap();
+        frontend.dealloc(buf).unwrap();
+        frontend.flush().unwrap();
+        assert!(frontend.find_allocation(buf).unwrap().freed);
+        assert!(frontend.frontend().find_allocation(buf).unwrap().freed);
+    }
+
+    #[test]
+    fn test_threads() {
+        let frontend = frontend();
+        /* Keep the threads alive until all have allocated, lest their caches be re-used */
+        let barrier = Arc::new(Barrier::new(4));
+        let bufs = (0..4)
+            .map(|i| {
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    let bufs = (0..100)
+                        .map(|j| {
+                            let len = (i * 100 + j) % 300 + 1;
+                            let buf = frontend.alloc(len, 8).unwrap();
+                            assert_eq!(frontend.get_size(buf).unwrap(), len);
+                            buf
+                        })
+                        .collect::<Vec<_>>();
+                    barrier.wait();
+                    bufs
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .flat_map(|t| t.join().unwrap())
+            .collect::<Vec<_>>();
+        assert!(frontend.threads() >= 2);
+        /* Free the buffers on a different thread to that which allocated them */
+        for buf in bufs {
+            frontend.dealloc(buf).unwrap();
+            assert!(SHADOW.is_poison(buf, 1).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_recycle_exited_thread() {
+        let backend = DlmallocBackend::<LinuxMmap>::new(PAGE_SIZE);
+        let tracking = GuestTracking::new().unwrap();
+        let frontend = DF::new(
+            backend,
+            &*SHADOW,
+            tracking,
+            DF::DEFAULT_REDZONE_SIZE,
+            DF::DEFAULT_QUARANTINE_SIZE,
+        )
+        .unwrap();
+        let frontend: &'static CF =
+            Box::leak(Box::new(CF::new(frontend, &*SHADOW, BATCH_SIZE).unwrap()));
+        let (buf, tid) = thread::spawn(move || {
+            let buf = frontend.alloc(64, 8).unwrap();
+            frontend.dealloc(buf).unwrap();
+            (buf, thread_id())
+        })
+        .join()
+        .unwrap();
+        while !thread_exited(tid) {
+            thread::yield_now();
+        }
+        /* The freed chunk is still held in the quarantine of the exited thread */
+        assert!(!frontend.frontend().find_allocation(buf).unwrap().freed);
+
+        /* A larger stack prevents the exited thread's stack (and hence its thread pointer) being re-used */
+        thread::Builder::new()
+            .stack_size(16 << 20)
+            .spawn(move || {
+                let next = frontend.alloc(64, 8).unwrap();
+                frontend.dealloc(next).unwrap();
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(frontend.threads(), 1);
+        assert!(frontend.frontend().find_allocation(buf).unwrap().freed);
+    }
+
+    #[test]
+    fn test_caching_disabled() {
+        let backend = DlmallocBackend::<LinuxMmap>::new(PAGE_SIZE);
+        let tracking = GuestTracking::new().unwrap();
+        let frontend = DF::new(backend, &*SHADOW, tracking, 64, 0).unwrap();
+        let mut frontend = CF::new(frontend, &*SHADOW, BATCH_SIZE).unwrap();
+        frontend.set_caching(false);
+        let len = 17;
+        let buf = frontend.alloc(len, 8).unwrap();
+        /* The buffer ends at the end of the chunk, not that of its size class */
+        let chunk = frontend.frontend().find_allocation(buf).unwrap();
+        assert_eq!(chunk.addr + chunk.len, buf + len);
+        assert_eq!(frontend.get_size(buf).unwrap(), len);
+        frontend.dealloc(buf).unwrap();
+    }
+
+    #[test]
+    fn test_free_fill_keeps_header() {
+        let backend = DlmallocBackend::<LinuxMmap>::new(PAGE_SIZE);
+        let tracking = GuestTracking::new().unwrap();
+        let mut frontend = DF::new(
+            backend,
+            &*SHADOW,
+            tracking,
+            DF::DEFAULT_REDZONE_SIZE,
+            DF::DEFAULT_QUARANTINE_SIZE,
+        )
+        .unwrap();
+        frontend.set_free_fill_byte(Some(0x55));
+        frontend.set_check_free_fill(true);
+        let frontend = CF::new(frontend, &*SHADOW, BATCH_SIZE).unwrap();
+        let buf = frontend.alloc(64, 8).unwrap();
+        frontend.dealloc(buf).unwrap();
+        frontend.flush().unwrap();
+        /* The header of the chunk survives its release to the shared frontend */
+        let freed = frontend.find_allocation(buf).unwrap();
+        assert!(freed.freed);
+        assert!(!freed.alloc_stack.is_empty());
+        assert!(!freed.free_stack.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_batch_size() {
+        let backend = DlmallocBackend::<LinuxMmap>::new(PAGE_SIZE);
+        let tracking = GuestTracking::new().unwrap();
+        let frontend = DF::new(backend, &*SHADOW, tracking, 64, 0).unwrap();
+        assert!(matches!(
+            CF::new(frontend, &*SHADOW, 0),
+            Err(CachedFrontendError::InvalidBatchSize(0))
+        ));
+    }
+}
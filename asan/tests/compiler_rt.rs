@@ -0,0 +1,264 @@
+#[cfg(test)]
+#[cfg(all(feature = "compiler_rt", feature = "guest"))]
+mod tests {
+    use {
+        asan::globals::find_global,
+        asan::{
+            asan_alloc,
+            compiler_rt::{
+                __asan_address_is_poisoned, __asan_alloca_poison, __asan_allocas_unpoison,
+                __asan_handle_no_return, __asan_init, __asan_load16, __asan_load8, __asan_loadN,
+                __asan_option_detect_stack_use_after_return, __asan_poison_memory_region,
+                __asan_poison_stack_memory, __asan_region_is_poisoned, __asan_register_elf_globals,
+                __asan_register_globals, __asan_stack_free_2, __asan_stack_malloc_2,
+                __asan_stack_malloc_always_2, __asan_store4_noabort, __asan_storeN,
+                __asan_unpoison_memory_region, __asan_unpoison_stack_memory,
+                __asan_unregister_elf_globals, __asan_unregister_globals,
+                set_detect_stack_use_after_return, AsanGlobal,
+            },
+        },
+        core::{
+            ffi::{c_void, CStr},
+            ptr::{null, null_mut},
+            sync::atomic::Ordering,
+        },
+    };
+
+    const LEN: usize = 32;
+
+    fn alloc() -> *mut c_void {
+        unsafe { __asan_init() };
+        unsafe { asan_alloc(LEN, 8) }
+    }
+
+    #[test]
+    fn test_checks_valid() {
+        let p = alloc();
+        unsafe {
+            __asan_load8(p);
+            __asan_load16(p.add(LEN - 16));
+            __asan_loadN(p, LEN);
+            __asan_storeN(p, LEN);
+            __asan_store4_noabort(p.add(LEN - 4));
+        }
+    }
+
+    #[test]
+    fn test_region_is_poisoned() {
+        let p = alloc();
+        unsafe {
+            assert_eq!(__asan_region_is_poisoned(p, LEN), null_mut());
+            assert_eq!(__asan_region_is_poisoned(p, LEN + 8), p.add(LEN));
+            assert_eq!(__asan_region_is_poisoned(p, 0), null_mut());
+            assert_eq!(__asan_region_is_poisoned(p.add(4), LEN), p.add(LEN));
+        }
+        let q = unsafe { asan_alloc(LEN - 3, 8) };
+        unsafe {
+            assert_eq!(__asan_region_is_poisoned(q, LEN), q.add(LEN - 3));
+        }
+    }
+
+    #[test]
+    fn test_address_is_poisoned() {
+        let p = alloc();
+        unsafe {
+            assert_eq!(__asan_address_is_poisoned(p), 0);
+            assert_eq!(__asan_address_is_poisoned(p.add(LEN - 1)), 0);
+            assert_eq!(__asan_address_is_poisoned(p.add(LEN)), 1);
+        }
+    }
+
+    #[test]
+    fn test_poison_unpoison() {
+        let p = alloc();
+        unsafe {
+            __asan_poison_memory_region(p.add(8), 16);
+            assert_eq!(__asan_address_is_poisoned(p.add(7)), 0);
+            assert_eq!(__asan_address_is_poisoned(p.add(8)), 1);
+            assert_eq!(__asan_address_is_poisoned(p.add(23)), 1);
+            assert_eq!(__asan_address_is_poisoned(p.add(24)), 0);
+            assert_eq!(__asan_region_is_poisoned(p, LEN), p.add(8));
+
+            __asan_unpoison_memory_region(p.add(8), 16);
+            assert_eq!(__asan_region_is_poisoned(p, LEN), null_mut());
+        }
+    }
+
+    #[test]
+    fn test_poison_unaligned_start() {
+        let p = alloc();
+        unsafe {
+            __asan_poison_memory_region(p.add(3), 13);
+            assert_eq!(__asan_address_is_poisoned(p.add(2)), 0);
+            assert_eq!(__asan_address_is_poisoned(p.add(3)), 1);
+            assert_eq!(__asan_address_is_poisoned(p.add(15)), 1);
+            assert_eq!(__asan_address_is_poisoned(p.add(16)), 0);
+        }
+    }
+
+    #[test]
+    fn test_poison_unaligned_end() {
+        let p = alloc();
+        unsafe {
+            __asan_poison_memory_region(p, 13);
+            assert_eq!(__asan_address_is_poisoned(p.add(7)), 1);
+            assert_eq!(__asan_address_is_poisoned(p.add(8)), 0);
+            assert_eq!(__asan_address_is_poisoned(p.add(12)), 0);
+        }
+    }
+
+    #[test]
+    fn test_unpoison_unaligned_start() {
+        let p = alloc();
+        unsafe {
+            __asan_poison_memory_region(p, LEN);
+            __asan_unpoison_memory_region(p.add(3), 5);
+            assert_eq!(__asan_address_is_poisoned(p), 0);
+            assert_eq!(__asan_address_is_poisoned(p.add(7)), 0);
+            assert_eq!(__asan_address_is_poisoned(p.add(8)), 1);
+        }
+    }
+
+    #[test]
+    fn test_unpoison_unaligned_end() {
+        let p = alloc();
+        unsafe {
+            __asan_poison_memory_region(p, LEN);
+            __asan_unpoison_memory_region(p, 13);
+            assert_eq!(__asan_address_is_poisoned(p.add(12)), 0);
+            assert_eq!(__asan_address_is_poisoned(p.add(13)), 1);
+        }
+    }
+
+    const NAME: &CStr = c"table";
+
+    const MODULE: &CStr = c"test.c";
+
+    fn global(p: *mut c_void) -> AsanGlobal {
+        AsanGlobal {
+            beg: p as usize,
+            size: 20,
+            size_with_redzone: LEN,
+            name: NAME.as_ptr(),
+            module_name: MODULE.as_ptr(),
+            has_dynamic_init: 0,
+            location: null(),
+            odr_indicator: 0,
+        }
+    }
+
+    #[test]
+    fn test_register_globals() {
+        let p = alloc();
+        let globals = [global(p)];
+        unsafe {
+            __asan_register_globals(globals.as_ptr(), globals.len());
+            assert_eq!(__asan_region_is_poisoned(p, LEN), p.add(20));
+            assert_eq!(__asan_address_is_poisoned(p.add(19)), 0);
+            assert_eq!(__asan_address_is_poisoned(p.add(LEN - 1)), 1);
+        }
+        let registered = find_global(p as usize + 20).unwrap();
+        assert_eq!(registered.name, "table");
+        assert_eq!(registered.module, "test.c");
+        assert_eq!(registered.size, 20);
+
+        unsafe {
+            __asan_unregister_globals(globals.as_ptr(), globals.len());
+            assert_eq!(__asan_region_is_poisoned(p, LEN), null_mut());
+        }
+        assert_eq!(find_global(p as usize), None);
+    }
+
+    #[test]
+    fn test_register_elf_globals() {
+        let p = alloc();
+        let globals = [global(p)];
+        let range = globals.as_ptr_range();
+        let mut flag = 0;
+        unsafe {
+            __asan_register_elf_globals(&mut flag, range.start, range.end);
+            assert_eq!(flag, 1);
+            assert_eq!(__asan_region_is_poisoned(p, LEN), p.add(20));
+            __asan_unregister_elf_globals(&mut flag, range.start, range.end);
+            assert_eq!(flag, 0);
+            assert_eq!(__asan_region_is_poisoned(p, LEN), null_mut());
+        }
+    }
+
+    #[test]
+    fn test_alloca_poison() {
+        unsafe { __asan_init() };
+        let p = unsafe { asan_alloc(128, 32) };
+        let addr = p as usize;
+        unsafe {
+            __asan_alloca_poison(addr + 32, 5);
+            assert_eq!(__asan_address_is_poisoned(p.add(31)), 1);
+            assert_eq!(__asan_address_is_poisoned(p.add(32)), 0);
+            assert_eq!(__asan_address_is_poisoned(p.add(36)), 0);
+            assert_eq!(__asan_address_is_poisoned(p.add(37)), 1);
+            assert_eq!(__asan_address_is_poisoned(p.add(95)), 1);
+
+            __asan_allocas_unpoison(addr, addr + 96);
+            assert_eq!(__asan_region_is_poisoned(p, 96), null_mut());
+        }
+    }
+
+    #[test]
+    fn test_stack_malloc_disabled() {
+        unsafe { __asan_init() };
+        assert_eq!(
+            __asan_option_detect_stack_use_after_return.load(Ordering::Relaxed),
+            0
+        );
+        assert_eq!(unsafe { __asan_stack_malloc_2(100) }, 0);
+    }
+
+    #[test]
+    fn test_stack_malloc() {
+        unsafe { __asan_init() };
+        set_detect_stack_use_after_return(true);
+        let frame = unsafe { __asan_stack_malloc_2(200) };
+        assert_ne!(frame, 0);
+        let p = frame as *mut c_void;
+        unsafe {
+            assert_eq!(__asan_region_is_poisoned(p, 256), null_mut());
+            __asan_stack_free_2(frame, 200);
+            assert_eq!(__asan_region_is_poisoned(p, 256), p);
+            assert_eq!(__asan_address_is_poisoned(p.add(255)), 1);
+        }
+        let next = unsafe { __asan_stack_malloc_2(200) };
+        assert_ne!(next, frame);
+    }
+
+    #[test]
+    fn test_stack_malloc_always() {
+        unsafe { __asan_init() };
+        let frame = unsafe { __asan_stack_malloc_always_2(200) };
+        assert_ne!(frame, 0);
+        unsafe { __asan_stack_free_2(frame, 200) };
+    }
+
+    #[test]
+    fn test_stack_scope() {
+        let p = alloc();
+        unsafe {
+            __asan_poison_stack_memory(p as usize + 8, 16);
+            assert_eq!(__asan_region_is_poisoned(p, LEN), p.add(8));
+            __asan_unpoison_stack_memory(p as usize + 8, 16);
+            assert_eq!(__asan_region_is_poisoned(p, LEN), null_mut());
+        }
+    }
+
+    #[test]
+    fn test_handle_no_return() {
+        unsafe { __asan_init() };
+        let buf = [0u8; LEN];
+        let p = buf.as_ptr() as *const c_void;
+        unsafe {
+            __asan_poison_stack_memory(p as usize, LEN);
+            assert_eq!(__asan_address_is_poisoned(p), 1);
+            __asan_handle_no_return();
+            assert_eq!(__asan_region_is_poisoned(p as *mut c_void, LEN), null_mut());
+        }
+    }
+}
@@ -5,13 +5,17 @@ mod tests {
     use {
         asan::{
             allocator::{
-                backend::dlmalloc::DlmallocBackend,
-                frontend::{default::DefaultFrontend, AllocatorFrontend},
+                backend::{arena::ArenaBackend, dlmalloc::DlmallocBackend},
+                frontend::{
+                    default::{DefaultFrontend, DefaultFrontendError, RedZonePolicy},
+                    AllocationInfo, AllocationType, AllocatorFrontend,
+                },
             },
             mmap::linux::LinuxMmap,
+            options::Options,
             shadow::{
                 guest::{DefaultShadowLayout, GuestShadow},
-                Shadow,
+                PoisonType, Shadow,
             },
             tracking::guest::GuestTracking,
         },
@@ -66,4 +70,465 @@ mod tests {
         }
         frontend.dealloc(buf).unwrap();
     }
+
+    #[test]
+    fn test_find_allocation() {
+        let mut frontend = frontend();
+        let len = 16;
+        let buf = frontend.alloc(len, 8).unwrap();
+        let expected = frontend.find_allocation(buf);
+        assert!(matches!(
+            expected,
+            Some(AllocationInfo {
+                addr,
+                len: 16,
+                freed: false,
+                free_stack: None,
+                ..
+            }) if addr == buf
+        ));
+        assert_eq!(frontend.find_allocation(buf + len), expected);
+        assert_eq!(frontend.find_allocation(buf - 1), expected);
+        frontend.dealloc(buf).unwrap();
+        let freed = frontend.find_allocation(buf + 4).unwrap();
+        assert_eq!(freed.addr, buf);
+        assert!(freed.freed);
+    }
+
+    #[test]
+    fn test_allocation_stacks() {
+        let mut frontend = frontend();
+        let buf = frontend.alloc(16, 8).unwrap();
+        let live = frontend.find_allocation(buf).unwrap();
+        assert!(!live.alloc_stack.is_empty());
+        assert!(live.alloc_stack.len() <= DF::DEFAULT_STACK_DEPTH);
+        frontend.dealloc(buf).unwrap();
+        let freed = frontend.find_allocation(buf).unwrap();
+        assert_eq!(freed.alloc_stack, live.alloc_stack);
+        assert!(!freed.free_stack.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_allocation_stacks_deduplicated() {
+        let mut frontend = frontend();
+        let bufs = (0..2)
+            .map(|_| frontend.alloc(16, 8).unwrap())
+            .collect::<Vec<_>>();
+        let stacks = frontend.depot().len();
+        let buf = frontend.alloc(16, 8).unwrap();
+        assert_eq!(frontend.depot().len(), stacks);
+        for b in bufs.into_iter().chain([buf]) {
+            frontend.dealloc(b).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_with_options() {
+        let backend = DlmallocBackend::<LinuxMmap>::new(PAGE_SIZE);
+        let shadow = GuestShadow::<LinuxMmap, DefaultShadowLayout>::new().unwrap();
+        let tracking = GuestTracking::new().unwrap();
+        let options = Options::parse("redzone=64:malloc_fill_byte=0xbe").unwrap();
+        let mut frontend = DF::with_options(backend, shadow, tracking, &options).unwrap();
+        let len = 16;
+        let buf = frontend.alloc(len, 8).unwrap();
+        let contents = unsafe { std::slice::from_raw_parts(buf as *const u8, len) };
+        assert!(contents.iter().all(|b| *b == 0xbe));
+        assert!(frontend.shadow().is_poison(buf - 64, 1).unwrap());
+        frontend.dealloc(buf).unwrap();
+    }
+
+    #[test]
+    fn test_red_zone_policy() {
+        let fixed = RedZonePolicy::Fixed(128);
+        assert_eq!(fixed.red_zone_size(1), 128);
+        assert_eq!(fixed.red_zone_size(1 << 20), 128);
+
+        let adaptive = RedZonePolicy::Adaptive {
+            min: RedZonePolicy::MIN_RED_ZONE_SIZE,
+            max: RedZonePolicy::MAX_RED_ZONE_SIZE,
+        };
+        assert_eq!(adaptive.red_zone_size(0), 16);
+        assert_eq!(adaptive.red_zone_size(48), 16);
+        assert_eq!(adaptive.red_zone_size(49), 32);
+        assert_eq!(adaptive.red_zone_size(448), 64);
+        assert_eq!(adaptive.red_zone_size(4000), 256);
+        assert_eq!(adaptive.red_zone_size(1 << 20), 2048);
+
+        let bounded = RedZonePolicy::Adaptive { min: 64, max: 512 };
+        assert_eq!(bounded.red_zone_size(16), 64);
+        assert_eq!(bounded.red_zone_size(1 << 20), 512);
+    }
+
+    #[test]
+    fn test_adaptive_red_zone() {
+        let backend = DlmallocBackend::<LinuxMmap>::new(PAGE_SIZE);
+        let shadow = GuestShadow::<LinuxMmap, DefaultShadowLayout>::new().unwrap();
+        let tracking = GuestTracking::new().unwrap();
+        let policy = RedZonePolicy::Adaptive {
+            min: RedZonePolicy::MIN_RED_ZONE_SIZE,
+            max: RedZonePolicy::MAX_RED_ZONE_SIZE,
+        };
+        let mut frontend = DF::with_red_zone_policy(
+            backend,
+            shadow,
+            tracking,
+            policy,
+            DF::DEFAULT_QUARANTINE_SIZE,
+        )
+        .unwrap();
+        assert_eq!(frontend.red_zone_policy(), policy);
+
+        let small = frontend.alloc(16, 8).unwrap();
+        assert_eq!(frontend.get_red_zone_size(small).unwrap(), 16);
+        assert!(frontend.shadow().is_poison(small + 16, 16).unwrap());
+
+        let len = 1 << 20;
+        let large = frontend.alloc(len, 8).unwrap();
+        assert_eq!(frontend.get_red_zone_size(large).unwrap(), 2048);
+        assert!(frontend.shadow().is_poison(large - 2048, 2048).unwrap());
+        assert!(frontend.shadow().is_poison(large + len, 2048).unwrap());
+        assert!(!frontend.shadow().is_poison(large, len).unwrap());
+
+        /* The whole of each buffer (including its red-zones) is freed */
+        frontend.dealloc(large).unwrap();
+        assert!(frontend.shadow().is_poison(large + len, 2048).unwrap());
+        let freed = frontend.find_allocation(large + len + 2040).unwrap();
+        assert_eq!(freed.addr, large);
+        frontend.dealloc(small).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_red_zone_range() {
+        let backend = DlmallocBackend::<LinuxMmap>::new(PAGE_SIZE);
+        let shadow = GuestShadow::<LinuxMmap, DefaultShadowLayout>::new().unwrap();
+        let tracking = GuestTracking::new().unwrap();
+        let policy = RedZonePolicy::Adaptive { min: 256, max: 64 };
+        assert!(matches!(
+            DF::with_red_zone_policy(backend, shadow, tracking, policy, 0),
+            Err(DefaultFrontendError::InvalidRedZoneRange(256, 64))
+        ));
+    }
+
+    #[test]
+    fn test_with_options_adaptive() {
+        let backend = DlmallocBackend::<LinuxMmap>::new(PAGE_SIZE);
+        let shadow = GuestShadow::<LinuxMmap, DefaultShadowLayout>::new().unwrap();
+        let tracking = GuestTracking::new().unwrap();
+        let options = Options::parse("redzone=32:max_redzone=256").unwrap();
+        let frontend = DF::with_options(backend, shadow, tracking, &options).unwrap();
+        assert_eq!(
+            frontend.red_zone_policy(),
+            RedZonePolicy::Adaptive { min: 32, max: 256 }
+        );
+    }
+
+    #[test]
+    fn test_max_malloc_fill_size() {
+        let mut frontend = frontend();
+        let max = 64;
+        frontend.set_max_malloc_fill_size(max);
+        let len = 128;
+        let buf = frontend.alloc(len, 8).unwrap();
+        let bytes = unsafe { core::slice::from_raw_parts(buf as *const u8, len) };
+        assert!(bytes[..max]
+            .iter()
+            .all(|b| *b == DF::DEFAULT_MALLOC_FILL_BYTE));
+        frontend.dealloc(buf).unwrap();
+        frontend.set_max_malloc_fill_size(DF::DEFAULT_MAX_MALLOC_FILL_SIZE);
+    }
+
+    #[test]
+    fn test_free_fill_byte() {
+        let backend = DlmallocBackend::<LinuxMmap>::new(PAGE_SIZE);
+        let shadow = GuestShadow::<LinuxMmap, DefaultShadowLayout>::new().unwrap();
+        let tracking = GuestTracking::new().unwrap();
+        let mut frontend = DF::new(
+            backend,
+            shadow,
+            tracking,
+            DF::DEFAULT_REDZONE_SIZE,
+            DF::DEFAULT_QUARANTINE_SIZE,
+        )
+        .unwrap();
+        frontend.set_free_fill_byte(Some(0x55));
+        let len = 32;
+        let buf = frontend.alloc(len, 8).unwrap();
+        frontend.dealloc(buf).unwrap();
+        let bytes = unsafe { core::slice::from_raw_parts(buf as *const u8, len) };
+        assert!(bytes.iter().all(|b| *b == 0x55));
+    }
+
+    #[test]
+    fn test_write_after_free() {
+        let backend = DlmallocBackend::<LinuxMmap>::new(PAGE_SIZE);
+        let shadow = GuestShadow::<LinuxMmap, DefaultShadowLayout>::new().unwrap();
+        let tracking = GuestTracking::new().unwrap();
+        let mut frontend =
+            DF::new(backend, shadow, tracking, DF::DEFAULT_REDZONE_SIZE, 1024).unwrap();
+        frontend.set_free_fill_byte(Some(0x55));
+        frontend.set_check_free_fill(true);
+
+        /* The small buffer stays in the quarantine until the large one is freed */
+        let small = frontend.alloc(16, 8).unwrap();
+        let large = frontend.alloc(4096, 8).unwrap();
+        frontend.dealloc(small).unwrap();
+        unsafe { *((small + 4) as *mut u8) = 0xaa };
+        assert!(matches!(
+            frontend.dealloc(large),
+            Err(DefaultFrontendError::WriteAfterFree(addr, AllocationInfo { addr: start, len: 16, freed: true, .. }))
+                if addr == small + 4 && start == small
+        ));
+    }
+
+    #[test]
+    fn test_free_fill_preserved() {
+        let backend = DlmallocBackend::<LinuxMmap>::new(PAGE_SIZE);
+        let shadow = GuestShadow::<LinuxMmap, DefaultShadowLayout>::new().unwrap();
+        let tracking = GuestTracking::new().unwrap();
+        let mut frontend =
+            DF::new(backend, shadow, tracking, DF::DEFAULT_REDZONE_SIZE, 1024).unwrap();
+        frontend.set_free_fill_byte(Some(0x55));
+        frontend.set_check_free_fill(true);
+
+        let len = 32;
+        let small = frontend.alloc(len, 8).unwrap();
+        let large = frontend.alloc(4096, 8).unwrap();
+        let bytes = unsafe { core::slice::from_raw_parts_mut(small as *mut u8, len) };
+        bytes.fill(0xaa);
+        frontend.dealloc_preserving(small, 16).unwrap();
+        assert!(bytes[..16].iter().all(|b| *b == 0xaa));
+        assert!(bytes[16..].iter().all(|b| *b == 0x55));
+        /* The preserved bytes aren't checked as the buffer leaves the quarantine */
+        frontend.dealloc(large).unwrap();
+    }
+
+    #[test]
+    fn test_alloc_dealloc_mismatch() {
+        let mut frontend = frontend();
+        let buf = frontend
+            .alloc_typed(16, 0, AllocationType::NewArray)
+            .unwrap();
+        assert!(matches!(
+            frontend.dealloc(buf),
+            Err(DefaultFrontendError::AllocDeallocMismatch(
+                addr,
+                AllocationType::NewArray,
+                AllocationType::Malloc,
+                AllocationInfo { len: 16, freed: false, .. },
+            )) if addr == buf
+        ));
+        assert!(matches!(
+            frontend.dealloc_typed(buf, AllocationType::New, None),
+            Err(DefaultFrontendError::AllocDeallocMismatch(
+                _,
+                AllocationType::NewArray,
+                AllocationType::New,
+                _
+            ))
+        ));
+        /* The buffer remains allocated */
+        assert_eq!(frontend.get_size(buf).unwrap(), 16);
+        frontend
+            .dealloc_typed(buf, AllocationType::NewArray, None)
+            .unwrap();
+
+        let buf = frontend.alloc(16, 0).unwrap();
+        assert!(matches!(
+            frontend.dealloc_typed(buf, AllocationType::New, None),
+            Err(DefaultFrontendError::AllocDeallocMismatch(
+                _,
+                AllocationType::Malloc,
+                AllocationType::New,
+                _
+            ))
+        ));
+        frontend.dealloc(buf).unwrap();
+    }
+
+    #[test]
+    fn test_new_delete_size_mismatch() {
+        let mut frontend = frontend();
+        let buf = frontend.alloc_typed(24, 0, AllocationType::New).unwrap();
+        assert!(matches!(
+            frontend.dealloc_typed(buf, AllocationType::New, Some(16)),
+            Err(DefaultFrontendError::NewDeleteSizeMismatch(
+                addr,
+                16,
+                AllocationInfo { len: 24, .. }
+            )) if addr == buf
+        ));
+        frontend
+            .dealloc_typed(buf, AllocationType::New, Some(24))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_mismatch_disabled() {
+        let backend = DlmallocBackend::<LinuxMmap>::new(PAGE_SIZE);
+        let shadow = GuestShadow::<LinuxMmap, DefaultShadowLayout>::new().unwrap();
+        let tracking = GuestTracking::new().unwrap();
+        let options =
+            Options::parse("alloc_dealloc_mismatch=0:new_delete_type_mismatch=0").unwrap();
+        let mut frontend = DF::with_options(backend, shadow, tracking, &options).unwrap();
+        let buf = frontend
+            .alloc_typed(16, 0, AllocationType::NewArray)
+            .unwrap();
+        frontend.dealloc(buf).unwrap();
+        let buf = frontend.alloc_typed(16, 0, AllocationType::New).unwrap();
+        frontend
+            .dealloc_typed(buf, AllocationType::New, Some(8))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_invalid_free() {
+        let mut frontend = frontend();
+        let buf = frontend.alloc(64, 8).unwrap();
+        assert!(matches!(
+            frontend.dealloc(buf + 16),
+            Err(DefaultFrontendError::InteriorPointer(
+                addr,
+                AllocationInfo { addr: start, len: 64, freed: false, .. },
+            )) if addr == buf + 16 && start == buf
+        ));
+        assert!(matches!(
+            frontend.dealloc(buf - 1),
+            Err(DefaultFrontendError::InteriorPointer(_, AllocationInfo { addr: start, .. }))
+                if start == buf
+        ));
+        frontend.dealloc(buf).unwrap();
+        assert!(matches!(
+            frontend.dealloc(buf),
+            Err(DefaultFrontendError::DoubleFree(
+                addr,
+                AllocationInfo { freed: true, .. },
+            )) if addr == buf
+        ));
+        assert!(matches!(
+            frontend.dealloc(buf + 8),
+            Err(DefaultFrontendError::InteriorPointer(
+                _,
+                AllocationInfo { freed: true, .. },
+            ))
+        ));
+        let local = [0u64; 4];
+        let addr = local.as_ptr() as usize;
+        assert!(matches!(
+            frontend.dealloc(addr),
+            Err(DefaultFrontendError::ForeignAddress(a)) if a == addr
+        ));
+    }
+
+    #[test]
+    fn test_allocation_size_too_big() {
+        let mut frontend = frontend();
+        let max = DF::DEFAULT_MAX_ALLOCATION_SIZE;
+        assert!(matches!(
+            frontend.alloc(max + 1, 8),
+            Err(DefaultFrontendError::AllocationSizeTooBig(len, m)) if len == max + 1 && m == max
+        ));
+        /* Sizes which would overflow once the red-zones are added */
+        frontend.set_max_allocation_size(usize::MAX);
+        assert!(matches!(
+            frontend.alloc(usize::MAX - 8, 0),
+            Err(DefaultFrontendError::AllocationSizeTooBig(_, _))
+        ));
+        assert!(matches!(
+            frontend.alloc(usize::MAX - 0x1000, 0x1000),
+            Err(DefaultFrontendError::AllocationSizeTooBig(_, _))
+        ));
+        frontend.set_max_allocation_size(DF::DEFAULT_MAX_ALLOCATION_SIZE);
+    }
+
+    #[test]
+    fn test_red_zone_size_too_big() {
+        let backend = DlmallocBackend::<LinuxMmap>::new(PAGE_SIZE);
+        let shadow = GuestShadow::<LinuxMmap, DefaultShadowLayout>::new().unwrap();
+        let tracking = GuestTracking::new().unwrap();
+        let options =
+            Options::parse(&format!("redzone={:#x}", 1usize << (usize::BITS - 1))).unwrap();
+        let mut frontend = DF::with_options(backend, shadow, tracking, &options).unwrap();
+        assert!(matches!(
+            frontend.alloc(16, 8),
+            Err(DefaultFrontendError::AllocationSizeTooBig(16, _))
+        ));
+    }
+
+    #[test]
+    fn test_max_allocation_size() {
+        let backend = DlmallocBackend::<LinuxMmap>::new(PAGE_SIZE);
+        let shadow = GuestShadow::<LinuxMmap, DefaultShadowLayout>::new().unwrap();
+        let tracking = GuestTracking::new().unwrap();
+        let options = Options::parse("max_allocation_size_mb=1").unwrap();
+        let mut frontend = DF::with_options(backend, shadow, tracking, &options).unwrap();
+        let buf = frontend.alloc(1 << 20, 8).unwrap();
+        frontend.dealloc(buf).unwrap();
+        assert!(matches!(
+            frontend.alloc((1 << 20) + 1, 8),
+            Err(DefaultFrontendError::AllocationSizeTooBig(_, 0x100000))
+        ));
+        assert_eq!(frontend.stats().total_allocs, 1);
+    }
+
+    #[test]
+    fn test_reset() {
+        type AF = DefaultFrontend<
+            ArenaBackend<LinuxMmap>,
+            GuestShadow<LinuxMmap, DefaultShadowLayout>,
+            GuestTracking,
+        >;
+        let backend = ArenaBackend::<LinuxMmap>::new(1 << 20).unwrap();
+        let shadow = GuestShadow::<LinuxMmap, DefaultShadowLayout>::new().unwrap();
+        let tracking = GuestTracking::new().unwrap();
+        let mut frontend = AF::new(backend, shadow, tracking, 64, 1 << 20).unwrap();
+        let live = frontend.alloc(32, 8).unwrap();
+        let freed = frontend.alloc(32, 8).unwrap();
+        frontend.dealloc(freed).unwrap();
+        frontend.reset().unwrap();
+
+        assert_eq!(frontend.find_allocation(live), None);
+        assert_eq!(frontend.find_allocation(freed), None);
+        assert!(frontend.shadow().is_poison(live, 32).unwrap());
+        assert_eq!(
+            frontend.shadow().get_poison(live),
+            Ok(PoisonType::AsanHeapFreed)
+        );
+        let stats = frontend.stats();
+        assert_eq!(stats.allocated_chunks, 0);
+        assert_eq!(stats.quarantine_chunks, 0);
+        assert_eq!(stats.heap_size, 0);
+        assert_eq!(stats.total_allocs, 2);
+
+        /* The arena is re-used from the start and the tracking was reset */
+        let buf = frontend.alloc(32, 8).unwrap();
+        assert_eq!(buf, live);
+        assert!(!frontend.shadow().is_poison(buf, 32).unwrap());
+        frontend.dealloc(buf).unwrap();
+    }
+
+    #[test]
+    fn test_stats() {
+        let backend = DlmallocBackend::<LinuxMmap>::new(PAGE_SIZE);
+        let shadow = GuestShadow::<LinuxMmap, DefaultShadowLayout>::new().unwrap();
+        let tracking = GuestTracking::new().unwrap();
+        let mut frontend = DF::new(backend, shadow, tracking, 64, 0).unwrap();
+        let buf = frontend.alloc(24, 8).unwrap();
+        let stats = frontend.stats();
+        assert!(frontend.owns(buf));
+        assert_eq!(stats.allocated_bytes, 24);
+        assert_eq!(stats.allocated_chunks, 1);
+        assert_eq!(stats.red_zone_bytes, stats.heap_size - 24);
+        assert_eq!(stats.size_classes[5], 1);
+
+        /* The quarantine is empty, so the buffer is released immediately */
+        frontend.dealloc(buf).unwrap();
+        let stats = frontend.stats();
+        assert!(!frontend.owns(buf));
+        assert_eq!(stats.allocated_bytes, 0);
+        assert_eq!(stats.peak_allocated_bytes, 24);
+        assert_eq!(stats.red_zone_bytes, 0);
+        assert_eq!(stats.quarantine_bytes, 0);
+        assert_eq!(stats.heap_size, 0);
+        assert_eq!(stats.total_frees, 1);
+    }
 }
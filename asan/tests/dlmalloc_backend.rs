@@ -4,8 +4,12 @@ mod tests {
 
     use {
         asan::{
-            allocator::backend::{dlmalloc::DlmallocBackend, AllocatorBackend},
+            allocator::backend::{
+                dlmalloc::{regions, DlmallocBackend, DlmallocBackendError},
+                AllocatorBackend,
+            },
             mmap::linux::LinuxMmap,
+            options::Options,
         },
         spin::Lazy,
         std::sync::Mutex,
@@ -31,4 +35,45 @@ mod tests {
         let buf = allocator.alloc(16, 8).unwrap();
         allocator.dealloc(buf, 16, 8).unwrap();
     }
+
+    #[test]
+    fn test_regions() {
+        let mut allocator = allocator();
+        let buf = allocator.alloc(16, 8).unwrap();
+        assert!(regions().any(|r| r.contains(&buf)));
+        allocator.dealloc(buf, 16, 8).unwrap();
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn test_fixed_base() {
+        drop(INIT_ONCE.lock().unwrap());
+        let layout = |base| {
+            let options = Options {
+                heap_base: Some(base),
+                ..Options::default()
+            };
+            let mut allocator =
+                DlmallocBackend::<LinuxMmap>::with_options(PAGE_SIZE, &options).unwrap();
+            let bufs = [(16, 8), (100, 16), (0x3000, 0x1000), (1 << 20, 8), (24, 64)]
+                .into_iter()
+                .map(|(len, align)| allocator.alloc(len, align).unwrap())
+                .collect::<Vec<_>>();
+            assert!(bufs.iter().all(|buf| *buf >= base));
+            assert!(regions().any(|r| r.start == base));
+            /* The backend never releases its regions, so they are deliberately leaked */
+            bufs.into_iter().map(|buf| buf - base).collect::<Vec<_>>()
+        };
+        assert_eq!(layout(0x6000_0000_0000), layout(0x6100_0000_0000));
+    }
+
+    #[test]
+    fn test_unaligned_heap_base() {
+        drop(INIT_ONCE.lock().unwrap());
+        let options = Options::parse("heap_base=0x60000010").unwrap();
+        assert!(matches!(
+            DlmallocBackend::<LinuxMmap>::with_options(PAGE_SIZE, &options),
+            Err(DlmallocBackendError::InvalidHeapBase(0x6000_0010))
+        ));
+    }
 }
@@ -0,0 +1,25 @@
+#[cfg(test)]
+#[cfg(feature = "libc")]
+mod tests {
+    use asan::{
+        env::{libc::LibcEnv, Env},
+        symbols::dlsym::{DlSymSymbols, LookupTypeNext},
+    };
+
+    type Syms = DlSymSymbols<LookupTypeNext>;
+
+    #[test]
+    fn test_libc_env_get() {
+        let path = std::env::var("PATH").ok();
+        assert_eq!(LibcEnv::<Syms>::get("PATH").unwrap(), path);
+        assert_eq!(
+            LibcEnv::<Syms>::get("ASAN_TEST_DOES_NOT_EXIST").unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_libc_env_page_size() {
+        assert_eq!(LibcEnv::<Syms>::page_size().unwrap(), 4096);
+    }
+}
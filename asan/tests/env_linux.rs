@@ -0,0 +1,17 @@
+#[cfg(test)]
+#[cfg(feature = "linux")]
+mod tests {
+    use asan::env::{linux::LinuxEnv, Env};
+
+    #[test]
+    fn test_linux_env_get() {
+        let path = std::env::var("PATH").ok();
+        assert_eq!(LinuxEnv::get("PATH").unwrap(), path);
+        assert_eq!(LinuxEnv::get("ASAN_TEST_DOES_NOT_EXIST").unwrap(), None);
+    }
+
+    #[test]
+    fn test_linux_env_page_size() {
+        assert_eq!(LinuxEnv::page_size().unwrap(), 4096);
+    }
+}
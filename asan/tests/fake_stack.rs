@@ -0,0 +1,144 @@
+#[cfg(test)]
+#[cfg(all(feature = "compiler_rt", feature = "linux"))]
+mod tests {
+    use {
+        asan::{
+            fake_stack::{
+                frame_size, FakeStack, FakeStackError, FakeStacks, MIN_FRAME_SIZE, NUM_CLASSES,
+            },
+            mmap::linux::LinuxMmap,
+            thread::{thread_exited, thread_id},
+            GuestAddr,
+        },
+        std::{sync::Arc, thread},
+    };
+
+    const CLASS_SIZE: usize = 1 << 16;
+
+    fn fake_stack() -> FakeStack<LinuxMmap> {
+        FakeStack::new(CLASS_SIZE).unwrap()
+    }
+
+    fn flag(addr: GuestAddr, class: usize) -> u8 {
+        let last = addr + frame_size(class) - size_of::<GuestAddr>();
+        unsafe { *(*(last as *const GuestAddr) as *const u8) }
+    }
+
+    #[test]
+    fn test_invalid_class_size() {
+        assert_eq!(
+            FakeStack::<LinuxMmap>::new(0x1000).unwrap_err(),
+            FakeStackError::InvalidClassSize(0x1000)
+        );
+        assert_eq!(
+            FakeStack::<LinuxMmap>::new(CLASS_SIZE + 1).unwrap_err(),
+            FakeStackError::InvalidClassSize(CLASS_SIZE + 1)
+        );
+    }
+
+    #[test]
+    fn test_alloc() {
+        let mut stack = fake_stack();
+        for class in 0..NUM_CLASSES {
+            let addr = stack.alloc(class, 0x1000).unwrap();
+            assert_eq!(addr % MIN_FRAME_SIZE, 0);
+            assert!(stack.contains(addr));
+            assert!(stack.contains(addr + frame_size(class) - 1));
+            assert_eq!(flag(addr, class), 1);
+        }
+        assert_eq!(stack.alloc(NUM_CLASSES, 0x1000), None);
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let mut stack = fake_stack();
+        let a = stack.alloc(0, 0x1000).unwrap();
+        stack.dealloc(0, a).unwrap();
+        assert_eq!(flag(a, 0), 0);
+        let b = stack.alloc(0, 0x1000).unwrap();
+        assert_eq!(b, a + frame_size(0));
+    }
+
+    #[test]
+    fn test_exhausted() {
+        let mut stack = fake_stack();
+        let class = NUM_CLASSES - 1;
+        let frames = CLASS_SIZE / frame_size(class);
+        let addrs = (0..frames)
+            .map(|_| stack.alloc(class, 0x2000).unwrap())
+            .collect::<Vec<_>>();
+        /* Frames allocated by shallower functions are still live */
+        assert_eq!(stack.alloc(class, 0x1000), None);
+        stack.dealloc(class, addrs[0]).unwrap();
+        assert_eq!(stack.alloc(class, 0x1000), Some(addrs[0]));
+    }
+
+    #[test]
+    fn test_reclaim() {
+        let mut stack = fake_stack();
+        let class = NUM_CLASSES - 1;
+        let frames = CLASS_SIZE / frame_size(class);
+        let addrs = (0..frames)
+            .map(|_| stack.alloc(class, 0x1000).unwrap())
+            .collect::<Vec<_>>();
+        /* Frames allocated by deeper functions have been abandoned */
+        assert_eq!(stack.alloc(class, 0x2000), Some(addrs[0]));
+    }
+
+    #[test]
+    fn test_dealloc_invalid() {
+        let mut stack = fake_stack();
+        let addr = stack.alloc(1, 0x1000).unwrap();
+        assert_eq!(
+            stack.dealloc(1, addr + 8),
+            Err(FakeStackError::InvalidAddress(addr + 8))
+        );
+        assert_eq!(
+            stack.dealloc(0, addr),
+            Err(FakeStackError::InvalidAddress(addr))
+        );
+        assert_eq!(
+            stack.dealloc(1, 0x10),
+            Err(FakeStackError::InvalidAddress(0x10))
+        );
+    }
+
+    #[test]
+    fn test_fake_stacks() {
+        let stacks = Arc::new(FakeStacks::<LinuxMmap>::new(CLASS_SIZE));
+        let addr = stacks.alloc(2, 0x1000).unwrap().unwrap();
+        assert!(stacks.contains(addr));
+
+        let shared = stacks.clone();
+        let (other, tid) = thread::spawn(move || {
+            let other = shared.alloc(2, 0x1000).unwrap().unwrap();
+            (other, thread_id())
+        })
+        .join()
+        .unwrap();
+        assert_ne!(other, addr);
+        assert_ne!(other, addr + frame_size(2));
+        assert_eq!(stacks.len(), 2);
+
+        stacks.dealloc(2, addr).unwrap();
+        stacks.dealloc(2, other).unwrap();
+        assert_eq!(
+            stacks.dealloc(2, 0x10),
+            Err(FakeStackError::InvalidAddress(0x10))
+        );
+
+        /* The stack of the exited thread is re-used by the next new thread */
+        while !thread_exited(tid) {
+            thread::yield_now();
+        }
+        let shared = stacks.clone();
+        let next = thread::Builder::new()
+            .stack_size(16 << 20)
+            .spawn(move || shared.alloc(2, 0x1000).unwrap().unwrap())
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(stacks.len(), 2);
+        assert_eq!(next, other);
+    }
+}
@@ -0,0 +1,46 @@
+#[cfg(test)]
+mod tests {
+    use asan::globals::{find_global, register_global, unregister_global, Global, Globals};
+
+    fn global(addr: usize, name: &str) -> Global {
+        Global {
+            addr,
+            size: 0x14,
+            size_with_redzone: 0x40,
+            name: name.to_string(),
+            module: "test.c".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_find() {
+        let mut globals = Globals::new();
+        assert!(globals.is_empty());
+        globals.register(global(0x1000, "a"));
+        globals.register(global(0x1040, "b"));
+        assert_eq!(globals.len(), 2);
+        assert_eq!(globals.find(0xfff), None);
+        assert_eq!(globals.find(0x1000).unwrap().name, "a");
+        assert_eq!(globals.find(0x1014).unwrap().name, "a");
+        assert_eq!(globals.find(0x103f).unwrap().name, "a");
+        assert_eq!(globals.find(0x1040).unwrap().name, "b");
+        assert_eq!(globals.find(0x1080), None);
+    }
+
+    #[test]
+    fn test_unregister() {
+        let mut globals = Globals::new();
+        globals.register(global(0x1000, "a"));
+        assert_eq!(globals.unregister(0x1000), Some(global(0x1000, "a")));
+        assert_eq!(globals.unregister(0x1000), None);
+        assert_eq!(globals.find(0x1000), None);
+    }
+
+    #[test]
+    fn test_registry() {
+        register_global(global(0x2000, "c"));
+        assert_eq!(find_global(0x2020), Some(global(0x2000, "c")));
+        assert_eq!(unregister_global(0x2000), Some(global(0x2000, "c")));
+        assert_eq!(find_global(0x2020), None);
+    }
+}
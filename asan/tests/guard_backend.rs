@@ -0,0 +1,148 @@
+#[cfg(test)]
+#[cfg(feature = "linux")]
+mod tests {
+
+    use {
+        asan::{
+            allocator::backend::{
+                guard::{GuardBackend, GuardBackendError},
+                AllocatorBackend,
+            },
+            maps::{entry::MapEntry, iterator::MapIterator, linux::LinuxMapReader, MapReader},
+            mmap::{linux::LinuxMmap, MmapProt},
+            options::Options,
+            GuestAddr,
+        },
+        spin::Lazy,
+        std::sync::Mutex,
+    };
+
+    static INIT_ONCE: Lazy<Mutex<()>> = Lazy::new(|| {
+        {
+            env_logger::init();
+        };
+        Mutex::new(())
+    });
+
+    const PAGE_SIZE: usize = 4096;
+
+    fn allocator() -> GuardBackend<LinuxMmap> {
+        drop(INIT_ONCE.lock().unwrap());
+        GuardBackend::<LinuxMmap>::new(PAGE_SIZE)
+    }
+
+    fn prot(addr: GuestAddr) -> MmapProt {
+        let reader = LinuxMapReader::new().unwrap();
+        MapIterator::new(reader)
+            .collect::<Vec<MapEntry>>()
+            .iter()
+            .find(|e| e.contains(addr))
+            .unwrap()
+            .prot()
+    }
+
+    #[test]
+    fn test_allocate() {
+        let mut allocator = allocator();
+        let buf = allocator.alloc(16, 8).unwrap();
+        unsafe { (buf as *mut u8).write_bytes(0xaa, 16) };
+        assert_eq!(allocator.len(), 1);
+        allocator.dealloc(buf, 16, 8).unwrap();
+        assert!(allocator.is_empty());
+    }
+
+    #[test]
+    fn test_guard_page() {
+        let mut allocator = allocator();
+        let len = 100;
+        let buf = allocator.alloc(len, 4).unwrap();
+        assert_eq!(buf % 4, 0);
+        let end = buf + len;
+        assert_eq!(end % PAGE_SIZE, 0);
+        assert_eq!(prot(buf), MmapProt::READ | MmapProt::WRITE);
+        assert_eq!(prot(end), MmapProt::empty());
+        allocator.dealloc(buf, len, 4).unwrap();
+        assert_eq!(prot(buf), MmapProt::empty());
+    }
+
+    #[test]
+    fn test_aligned() {
+        let mut allocator = allocator();
+        let len = 20;
+        let buf = allocator.alloc(len, 16).unwrap();
+        assert_eq!(buf % 16, 0);
+        /* The end of the buffer is padded to the alignment */
+        assert_eq!(PAGE_SIZE - buf % PAGE_SIZE, 32);
+        allocator.dealloc(buf, len, 16).unwrap();
+    }
+
+    #[test]
+    fn test_large() {
+        let mut allocator = allocator();
+        let len = PAGE_SIZE * 3 + 8;
+        let buf = allocator.alloc(len, 8).unwrap();
+        assert_eq!((buf + len) % PAGE_SIZE, 0);
+        unsafe { (buf as *mut u8).write_bytes(0xaa, len) };
+        let region = allocator.regions().next().unwrap();
+        assert_eq!(region.end, buf + len);
+        assert!(region.contains(&buf));
+        allocator.dealloc(buf, len, 8).unwrap();
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn test_fixed_base() {
+        drop(INIT_ONCE.lock().unwrap());
+        let base = 0x6300_0000_0000;
+        let mut allocator = GuardBackend::<LinuxMmap>::new_at(PAGE_SIZE, base);
+        let a = allocator.alloc(16, 8).unwrap();
+        assert_eq!(a, base + PAGE_SIZE - 16);
+        let b = allocator.alloc(PAGE_SIZE + 8, 8).unwrap();
+        assert_eq!(b, base + 2 * PAGE_SIZE + 2 * PAGE_SIZE - (PAGE_SIZE + 8));
+        allocator.dealloc(a, 16, 8).unwrap();
+        allocator.dealloc(b, PAGE_SIZE + 8, 8).unwrap();
+    }
+
+    #[test]
+    fn test_not_reused() {
+        let mut allocator = allocator();
+        let buf = allocator.alloc(64, 8).unwrap();
+        allocator.dealloc(buf, 64, 8).unwrap();
+        let next = allocator.alloc(64, 8).unwrap();
+        assert_ne!(buf, next);
+        allocator.dealloc(next, 64, 8).unwrap();
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut allocator = allocator();
+        assert_eq!(
+            allocator.alloc(16, 3),
+            Err(GuardBackendError::InvalidAlignment(3))
+        );
+        assert_eq!(
+            allocator.alloc(16, PAGE_SIZE * 2),
+            Err(GuardBackendError::InvalidAlignment(PAGE_SIZE * 2))
+        );
+        assert_eq!(
+            allocator.alloc(usize::MAX, 8),
+            Err(GuardBackendError::SizeOverflow(usize::MAX))
+        );
+        let buf = allocator.alloc(16, 8).unwrap();
+        allocator.dealloc(buf, 16, 8).unwrap();
+        assert_eq!(
+            allocator.dealloc(buf, 16, 8),
+            Err(GuardBackendError::InvalidAddress(buf))
+        );
+    }
+
+    #[test]
+    fn test_unaligned_heap_base() {
+        drop(INIT_ONCE.lock().unwrap());
+        let options = Options::parse("heap_base=0x63000010").unwrap();
+        assert!(matches!(
+            GuardBackend::<LinuxMmap>::with_options(PAGE_SIZE, &options),
+            Err(GuardBackendError::InvalidHeapBase(0x6300_0010))
+        ));
+    }
+}
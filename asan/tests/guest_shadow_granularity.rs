@@ -0,0 +1,352 @@
+#[cfg(test)]
+#[cfg(all(feature = "guest", feature = "linux", target_pointer_width = "64"))]
+mod tests {
+    use {
+        asan::{
+            mmap::linux::LinuxMmap,
+            shadow::{
+                guest::{
+                    GuestShadow, I386ShadowLayout, Ppc32ShadowLayout, ScaledShadowLayout,
+                    ShadowLayout, X86_64ShadowLayout,
+                },
+                PoisonType, Shadow, ShadowFault,
+            },
+            GuestAddr,
+        },
+        core::mem::size_of,
+    };
+
+    type L8 = ScaledShadowLayout<X86_64ShadowLayout, 3>;
+    type L16 = ScaledShadowLayout<X86_64ShadowLayout, 4>;
+    type L32 = ScaledShadowLayout<X86_64ShadowLayout, 5>;
+
+    type GS16 = GuestShadow<LinuxMmap, L16>;
+    type GS32 = GuestShadow<LinuxMmap, L32>;
+
+    const BASE: GuestAddr = 0x7fff2c000000;
+
+    fn read_shadow<S: Shadow>(shadow: &S, addr: GuestAddr) -> [u8; 4] {
+        let mut buf = [0u8; 4];
+        shadow.read_shadow(addr, &mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_layout_scale_3() {
+        assert_eq!(L8::SHADOW_OFFSET, X86_64ShadowLayout::SHADOW_OFFSET);
+        assert_eq!(L8::LOW_SHADOW_SIZE, X86_64ShadowLayout::LOW_SHADOW_SIZE);
+        assert_eq!(
+            L8::HIGH_SHADOW_OFFSET,
+            X86_64ShadowLayout::HIGH_SHADOW_OFFSET
+        );
+        assert_eq!(L8::HIGH_SHADOW_SIZE, X86_64ShadowLayout::HIGH_SHADOW_SIZE);
+        assert_eq!(L8::HIGH_MEM_OFFSET, X86_64ShadowLayout::HIGH_MEM_OFFSET);
+        assert_eq!(L8::HIGH_MEM_SIZE, X86_64ShadowLayout::HIGH_MEM_SIZE);
+        assert_eq!(L8::ALLOC_ALIGN_SIZE, 8);
+    }
+
+    #[test]
+    fn test_layout_scale_4_and_5() {
+        assert_eq!(L16::ALLOC_ALIGN_SIZE, 16);
+        assert_eq!(L16::LOW_SHADOW_SIZE, 0x7fff800);
+        assert_eq!(L16::HIGH_SHADOW_OFFSET, 0x8087ff7000);
+        assert_eq!(L16::HIGH_MEM_OFFSET, 0x8007fff8000);
+        assert_eq!(
+            L16::HIGH_MEM_OFFSET + (L16::HIGH_MEM_SIZE - 1),
+            0x7fffffffffff
+        );
+
+        assert_eq!(L32::ALLOC_ALIGN_SIZE, 32);
+        assert_eq!(L32::LOW_SHADOW_SIZE, 0x3fffc00);
+        assert_eq!(L32::HIGH_SHADOW_OFFSET, 0x2083ff7000);
+        assert_eq!(L32::HIGH_MEM_OFFSET, 0x4007fff8000);
+        assert_eq!(
+            L32::HIGH_MEM_OFFSET + (L32::HIGH_MEM_SIZE - 1),
+            0x7fffffffffff
+        );
+
+        /* The high shadow describes the whole of the high memory */
+        for (offset, size, mem, pow) in [
+            (
+                L16::HIGH_SHADOW_OFFSET,
+                L16::HIGH_SHADOW_SIZE,
+                L16::HIGH_MEM_OFFSET,
+                4,
+            ),
+            (
+                L32::HIGH_SHADOW_OFFSET,
+                L32::HIGH_SHADOW_SIZE,
+                L32::HIGH_MEM_OFFSET,
+                5,
+            ),
+        ] {
+            let shadow_of = |addr: GuestAddr| (addr >> pow) + X86_64ShadowLayout::SHADOW_OFFSET;
+            assert!(shadow_of(mem) >= offset);
+            assert_eq!(offset + size, mem);
+            assert!(shadow_of(0x7fffffffffff) < mem);
+        }
+    }
+
+    #[test]
+    fn test_layout_scaled_32_bit() {
+        type I16 = ScaledShadowLayout<I386ShadowLayout, 4>;
+        type I32 = ScaledShadowLayout<I386ShadowLayout, 5>;
+        type P16 = ScaledShadowLayout<Ppc32ShadowLayout, 4>;
+        type P32 = ScaledShadowLayout<Ppc32ShadowLayout, 5>;
+
+        assert_eq!(I16::LOW_SHADOW_SIZE, 0x2000000);
+        assert_eq!(I16::HIGH_SHADOW_OFFSET, 0x23000000);
+        assert_eq!(I16::HIGH_MEM_OFFSET, 0x30000000);
+        assert_eq!(I32::LOW_SHADOW_SIZE, 0x1000000);
+        assert_eq!(I32::HIGH_SHADOW_OFFSET, 0x21400000);
+        assert_eq!(I32::HIGH_MEM_OFFSET, 0x28000000);
+        assert_eq!(P16::HIGH_SHADOW_OFFSET, 0x22c00000);
+        assert_eq!(P16::HIGH_MEM_OFFSET, 0x2c000000);
+        assert_eq!(P32::HIGH_SHADOW_OFFSET, 0x21300000);
+        assert_eq!(P32::HIGH_MEM_OFFSET, 0x26000000);
+
+        /* The address space is unchanged and its high memory fully shadowed */
+        for (shadow_offset, mem_offset, mem_limit, pow) in [
+            (I16::HIGH_SHADOW_OFFSET, I16::HIGH_MEM_OFFSET, 0xffffffff, 4),
+            (I32::HIGH_SHADOW_OFFSET, I32::HIGH_MEM_OFFSET, 0xffffffff, 5),
+            (P16::HIGH_SHADOW_OFFSET, P16::HIGH_MEM_OFFSET, 0xbfffffff, 4),
+            (P32::HIGH_SHADOW_OFFSET, P32::HIGH_MEM_OFFSET, 0xbfffffff, 5),
+        ] {
+            let shadow_of = |addr: GuestAddr| (addr >> pow) + I386ShadowLayout::SHADOW_OFFSET;
+            assert!(shadow_of(mem_offset) >= shadow_offset);
+            assert!(shadow_of(mem_limit) < mem_offset);
+        }
+        assert_eq!(I32::HIGH_MEM_OFFSET + (I32::HIGH_MEM_SIZE - 1), 0xffffffff);
+        assert_eq!(P32::HIGH_MEM_OFFSET + (P32::HIGH_MEM_SIZE - 1), 0xbfffffff);
+        assert_eq!(P32::ALLOC_ALIGN_SIZE, 32);
+    }
+
+    #[test]
+    fn test_partial_poison_types() {
+        assert_eq!(PoisonType::MAX_GRANULARITY, 32);
+        assert_eq!(PoisonType::try_from(0x07), Ok(PoisonType::AsanPartial7));
+        assert_eq!(PoisonType::try_from(0x08), Ok(PoisonType::AsanPartial8));
+        assert_eq!(PoisonType::try_from(0x1f), Ok(PoisonType::AsanPartial31));
+        assert!(PoisonType::try_from(0x20).is_err());
+        assert_eq!(size_of::<PoisonType>(), 1);
+    }
+
+    #[test]
+    fn test_granularity_16() {
+        let mut shadow = GS16::new().unwrap();
+        assert_eq!(shadow.granularity(), 16);
+        shadow.unpoison(BASE, 0x15).unwrap();
+        shadow
+            .poison(BASE + 0x20, 0x20, PoisonType::AsanHeapRightRz)
+            .unwrap();
+        assert_eq!(read_shadow(&shadow, BASE), [0x00, 0x05, 0xfb, 0xfb]);
+        assert!(!shadow.is_poison(BASE, 0x15).unwrap());
+        assert!(shadow.is_poison(BASE, 0x16).unwrap());
+        assert!(shadow.is_poison(BASE + 0x15, 1).unwrap());
+        assert!(shadow.load(BASE + 0x8, 0x8).is_ok());
+        assert!(shadow.store(BASE + 0x10, 0x8).is_err());
+        assert_eq!(
+            shadow.find_poison(BASE, 0x40),
+            Ok(Some(ShadowFault {
+                addr: BASE + 0x15,
+                value: 0x05,
+                poison: Some(PoisonType::AsanHeapRightRz),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_granularity_32() {
+        let mut shadow = GS32::new().unwrap();
+        assert_eq!(shadow.granularity(), 32);
+        shadow
+            .poison(BASE, 0x20, PoisonType::AsanHeapLeftRz)
+            .unwrap();
+        shadow.unpoison(BASE + 0x20, 0x3d).unwrap();
+        shadow
+            .poison(BASE + 0x60, 0x20, PoisonType::AsanHeapRightRz)
+            .unwrap();
+        assert_eq!(read_shadow(&shadow, BASE), [0xfa, 0x00, 0x1d, 0xfb]);
+        assert!(!shadow.is_poison(BASE + 0x20, 0x3d).unwrap());
+        assert!(shadow.is_poison(BASE + 0x1f, 0x2).unwrap());
+        assert!(shadow.is_poison(BASE + 0x5d, 0x1).unwrap());
+        assert_eq!(
+            shadow.find_poison(BASE + 0x30, 0x40),
+            Ok(Some(ShadowFault {
+                addr: BASE + 0x5d,
+                value: 0x1d,
+                poison: Some(PoisonType::AsanHeapRightRz),
+            }))
+        );
+        assert_eq!(
+            PoisonType::try_from(read_shadow(&shadow, BASE)[2]),
+            Ok(PoisonType::AsanPartial29)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "dlmalloc")]
+    fn test_frontend_granularity_32() {
+        use asan::{
+            allocator::{
+                backend::dlmalloc::DlmallocBackend,
+                frontend::{
+                    default::{DefaultFrontend, DefaultFrontendError, RedZonePolicy},
+                    AllocatorFrontend,
+                },
+            },
+            tracking::guest::GuestTracking,
+        };
+
+        type DF = DefaultFrontend<DlmallocBackend<LinuxMmap>, GS32, GuestTracking>;
+
+        let new_frontend = |red_zone_size| {
+            DF::new(
+                DlmallocBackend::<LinuxMmap>::new(0x1000),
+                GS32::new().unwrap(),
+                GuestTracking::new().unwrap(),
+                red_zone_size,
+                DF::DEFAULT_QUARANTINE_SIZE,
+            )
+        };
+        /* The red-zone is rounded up to the granularity of the shadow */
+        let frontend = new_frontend(0x30).unwrap();
+        assert_eq!(frontend.red_zone_policy(), RedZonePolicy::Fixed(0x40));
+        drop(frontend);
+        assert!(matches!(
+            new_frontend(usize::MAX),
+            Err(DefaultFrontendError::InvalidRedZoneSize(usize::MAX))
+        ));
+
+        /* As are the bounds of an adaptive red-zone (e.g. the default minimum) */
+        let frontend = DF::with_red_zone_policy(
+            DlmallocBackend::<LinuxMmap>::new(0x1000),
+            GS32::new().unwrap(),
+            GuestTracking::new().unwrap(),
+            RedZonePolicy::Adaptive {
+                min: RedZonePolicy::MIN_RED_ZONE_SIZE,
+                max: 0x50,
+            },
+            DF::DEFAULT_QUARANTINE_SIZE,
+        )
+        .unwrap();
+        assert_eq!(
+            frontend.red_zone_policy(),
+            RedZonePolicy::Adaptive {
+                min: 0x20,
+                max: 0x60
+            }
+        );
+        drop(frontend);
+
+        let mut frontend = new_frontend(0x40).unwrap();
+        assert_eq!(frontend.alloc_align_size(), 32);
+        let buf = frontend.alloc(20, 8).unwrap();
+        assert_eq!(buf % 32, 0);
+        assert_eq!(
+            read_shadow(frontend.shadow(), buf - 0x40),
+            [0xfa, 0xfa, 0x14, 0xfb]
+        );
+        for i in buf - 0x40..buf + 0x60 {
+            let expected = i < buf || i >= buf + 20;
+            assert_eq!(frontend.shadow().is_poison(i, 1).unwrap(), expected);
+        }
+        frontend.dealloc(buf).unwrap();
+        assert!(frontend.shadow().is_poison(buf, 20).unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "dlmalloc")]
+    fn test_inband_frontend_granularity_32() {
+        use asan::{
+            allocator::{
+                backend::dlmalloc::DlmallocBackend,
+                frontend::{
+                    inband::{InbandFrontend, InbandFrontendError},
+                    AllocatorFrontend,
+                },
+            },
+            tracking::guest::GuestTracking,
+        };
+
+        type IF = InbandFrontend<DlmallocBackend<LinuxMmap>, GS32, GuestTracking>;
+
+        let new_frontend = |red_zone_size| {
+            IF::new(
+                DlmallocBackend::<LinuxMmap>::new(0x1000),
+                GS32::new().unwrap(),
+                GuestTracking::new().unwrap(),
+                red_zone_size,
+                IF::DEFAULT_QUARANTINE_SIZE,
+            )
+        };
+        /* The red-zone must be a multiple of the granularity of the shadow */
+        assert!(matches!(
+            new_frontend(0x30),
+            Err(InbandFrontendError::InvalidRedZoneSize(0x30))
+        ));
+
+        let mut frontend = new_frontend(0x40).unwrap();
+        assert_eq!(frontend.alloc_align_size(), 32);
+        for len in [1, 20, 32, 100] {
+            let buf = frontend.alloc(len, 8).unwrap();
+            assert_eq!(buf % 32, 0);
+            assert_eq!(frontend.get_size(buf).unwrap(), len);
+            for i in buf - 0x40..buf + len.next_multiple_of(32) + 0x40 {
+                let expected = i < buf || i >= buf + len;
+                assert_eq!(frontend.shadow().is_poison(i, 1).unwrap(), expected);
+            }
+            frontend.dealloc(buf).unwrap();
+            assert!(frontend.shadow().is_poison(buf, len).unwrap());
+        }
+    }
+
+    #[cfg(feature = "dlmalloc")]
+    fn check_cached_frontend<L: ShadowLayout + Sync>() {
+        use asan::{
+            allocator::{
+                backend::dlmalloc::DlmallocBackend,
+                frontend::{cached::CachedFrontend, default::DefaultFrontend},
+            },
+            tracking::guest::GuestTracking,
+        };
+
+        let granularity = L::ALLOC_ALIGN_SIZE;
+        let shadow = GuestShadow::<LinuxMmap, L>::new().unwrap();
+        /* The freed buffers must remain in the quarantine to be found */
+        let frontend = DefaultFrontend::new(
+            DlmallocBackend::<LinuxMmap>::new(0x1000),
+            &shadow,
+            GuestTracking::new().unwrap(),
+            0x40,
+            1 << 20,
+        )
+        .unwrap();
+        let frontend = CachedFrontend::new(frontend, &shadow, 4).unwrap();
+        /* Both cached and direct allocations, whose ends fall within a granule */
+        for len in [1, 16, 17, 100, 5000] {
+            let buf = frontend.alloc(len, 8).unwrap();
+            assert_eq!(buf % granularity, 0);
+            assert_eq!(frontend.get_size(buf).unwrap(), len);
+            assert!(!shadow.is_poison(buf, len).unwrap());
+            assert!(shadow.is_poison(buf + len, 1).unwrap());
+            assert!(shadow.is_poison(buf - 1, 1).unwrap());
+            frontend.dealloc(buf).unwrap();
+            assert!(shadow.is_poison(buf, len).unwrap());
+            assert_eq!(frontend.find_allocation(buf).map(|a| a.freed), Some(true));
+        }
+        frontend.flush().unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "dlmalloc")]
+    fn test_cached_frontend_granularity_16() {
+        check_cached_frontend::<L16>();
+    }
+
+    #[test]
+    #[cfg(feature = "dlmalloc")]
+    fn test_cached_frontend_granularity_32() {
+        check_cached_frontend::<L32>();
+    }
+}
@@ -0,0 +1,250 @@
+#[cfg(test)]
+#[cfg(all(feature = "guest", feature = "linux", target_pointer_width = "64"))]
+mod tests {
+    use {
+        asan::{
+            maps::{entry::MapEntry, linux::LinuxMapReader},
+            mmap::linux::LinuxMmap,
+            options::Options,
+            shadow::{
+                dynamic::{
+                    DynamicGuestShadow, DynamicGuestShadowError, ShadowCollision, ShadowLayoutKind,
+                },
+                guest::{
+                    Aarch64Va39ShadowLayout, Aarch64Va42ShadowLayout, Aarch64Va48ShadowLayout,
+                    ScaledShadowLayout, ShadowLayout, X86_64HighShadowLayout, X86_64ShadowLayout,
+                },
+                PoisonType, Shadow,
+            },
+            GuestAddr,
+        },
+        core::str::FromStr,
+    };
+
+    type Dgs = DynamicGuestShadow<LinuxMmap>;
+
+    fn mapping(base: GuestAddr, limit: GuestAddr, path: &str) -> MapEntry {
+        MapEntry::new(
+            base,
+            limit,
+            true,
+            true,
+            false,
+            true,
+            0,
+            0,
+            0,
+            0,
+            path.to_string(),
+        )
+    }
+
+    fn x86_64_mappings() -> Vec<MapEntry> {
+        vec![
+            mapping(0x555555554000, 0x555555556000, "/usr/bin/test"),
+            mapping(0x7ffff7dd3000, 0x7ffff7dfc000, "/usr/lib/libc.so.6"),
+            mapping(0x7ffffffde000, 0x7ffffffff000, "[stack]"),
+            mapping(0xffffffffff600000, 0xffffffffff601000, "[vsyscall]"),
+        ]
+    }
+
+    fn aarch64_mappings(stack: GuestAddr) -> Vec<MapEntry> {
+        vec![
+            mapping(0xaaaaaaaa0000, 0xaaaaaaab0000, "/usr/bin/test"),
+            mapping(stack - 0x21000, stack, "[stack]"),
+        ]
+    }
+
+    #[test]
+    fn test_collision_x86_64() {
+        let mappings = x86_64_mappings();
+        assert!(X86_64ShadowLayout::collision(&mappings).is_none());
+        assert!(X86_64HighShadowLayout::collision(&mappings).is_none());
+        /* The [vsyscall] page is ignored, though it lies beyond each layout */
+        assert!(Aarch64Va48ShadowLayout::collision(&mappings).is_none());
+        assert_eq!(
+            Aarch64Va39ShadowLayout::collision(&mappings).map(|m| m.path()),
+            Some("/usr/bin/test")
+        );
+    }
+
+    #[test]
+    fn test_collision_shadow_gap() {
+        /* A PIE placed within the shadow gap of the x86-64 layout */
+        let mut mappings = x86_64_mappings();
+        mappings.insert(0, mapping(0x4000000000, 0x4000002000, "/usr/bin/pie"));
+        assert_eq!(
+            ShadowLayoutKind::X86_64.collision(&mappings),
+            Some(ShadowCollision {
+                layout: ShadowLayoutKind::X86_64,
+                base: 0x4000000000,
+                limit: 0x4000002000,
+                path: "/usr/bin/pie".to_string(),
+            })
+        );
+        assert_eq!(ShadowLayoutKind::X86_64High.collision(&mappings), None);
+        assert_eq!(
+            ShadowLayoutKind::probe(
+                &[ShadowLayoutKind::X86_64, ShadowLayoutKind::X86_64High],
+                &mappings
+            ),
+            Ok(ShadowLayoutKind::X86_64High)
+        );
+    }
+
+    #[test]
+    fn test_collision_shadow() {
+        /* A mapping overlapping the end of the low shadow */
+        let mappings = [mapping(0x8fff6000, 0x8fff8000, "")];
+        assert!(X86_64ShadowLayout::collision(&mappings).is_some());
+        let mappings = [mapping(0x8fff7000, 0x8fff8000, "")];
+        assert!(X86_64ShadowLayout::collision(&mappings).is_some());
+        /* A mapping which ends where the low shadow begins */
+        let mappings = [mapping(0x7fff7000, 0x7fff8000, "")];
+        assert!(X86_64ShadowLayout::collision(&mappings).is_none());
+        /* A mapping which begins where the high memory begins */
+        let mappings = [mapping(0x10007fff8000, 0x10007fff9000, "")];
+        assert!(X86_64ShadowLayout::collision(&mappings).is_none());
+    }
+
+    #[test]
+    fn test_probe_aarch64() {
+        let candidates = [
+            ShadowLayoutKind::Aarch64Va39,
+            ShadowLayoutKind::Aarch64Va42,
+            ShadowLayoutKind::Aarch64Va48,
+        ];
+        /* The PIE lies beyond the 39 and 42-bit address spaces */
+        assert_eq!(
+            ShadowLayoutKind::probe(&candidates, &aarch64_mappings(0xfffffffff000)),
+            Ok(ShadowLayoutKind::Aarch64Va48)
+        );
+        let mappings = [
+            mapping(0x5555550000, 0x5555560000, "/usr/bin/test"),
+            mapping(0x7ffffdf000, 0x8000000000, "[stack]"),
+        ];
+        assert_eq!(
+            ShadowLayoutKind::probe(&candidates, &mappings),
+            Ok(ShadowLayoutKind::Aarch64Va39)
+        );
+        let mappings = [
+            mapping(0xaaaaaa0000, 0xaaaaab0000, "/usr/bin/test"),
+            mapping(0x3ffffdf0000, 0x40000000000, "[stack]"),
+        ];
+        assert_eq!(
+            ShadowLayoutKind::probe(&candidates, &mappings),
+            Ok(ShadowLayoutKind::Aarch64Va42)
+        );
+        assert!(Aarch64Va42ShadowLayout::collision(&mappings).is_none());
+    }
+
+    #[test]
+    fn test_probe_none() {
+        let candidates = [ShadowLayoutKind::Aarch64Va39, ShadowLayoutKind::Aarch64Va48];
+        let mappings = aarch64_mappings(0x1000000100000);
+        assert_eq!(
+            ShadowLayoutKind::probe(&candidates, &mappings),
+            Err(vec![
+                ShadowCollision {
+                    layout: ShadowLayoutKind::Aarch64Va39,
+                    base: 0xaaaaaaaa0000,
+                    limit: 0xaaaaaaab0000,
+                    path: "/usr/bin/test".to_string(),
+                },
+                ShadowCollision {
+                    layout: ShadowLayoutKind::Aarch64Va48,
+                    base: 0x10000000df000,
+                    limit: 0x1000000100000,
+                    path: "[stack]".to_string(),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_layout_kind_names() {
+        for kind in [
+            ShadowLayoutKind::X86_64,
+            ShadowLayoutKind::X86_64High,
+            ShadowLayoutKind::Aarch64Va39,
+            ShadowLayoutKind::Aarch64Va42,
+            ShadowLayoutKind::Aarch64Va48,
+            ShadowLayoutKind::X86_64Granule16,
+            ShadowLayoutKind::X86_64Granule32,
+        ] {
+            assert_eq!(ShadowLayoutKind::from_str(&kind.to_string()), Ok(kind));
+        }
+        assert!(ShadowLayoutKind::from_str("sparc").is_err());
+        assert_eq!(
+            Options::parse("shadow_layout=aarch64_42")
+                .unwrap()
+                .shadow_layout,
+            Some(ShadowLayoutKind::Aarch64Va42)
+        );
+        assert!(Options::parse("shadow_layout=sparc").is_err());
+        assert_eq!(
+            Options::parse("shadow_layout=x86_64_g32")
+                .unwrap()
+                .shadow_layout,
+            Some(ShadowLayoutKind::X86_64Granule32)
+        );
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn test_dynamic_probe() {
+        let mut shadow = Dgs::new::<LinuxMapReader>(None).unwrap();
+        assert_eq!(shadow.kind(), ShadowLayoutKind::X86_64);
+        assert_eq!(
+            shadow.regions(),
+            [
+                X86_64ShadowLayout::LOW_SHADOW_OFFSET..0x8fff7000,
+                X86_64ShadowLayout::HIGH_SHADOW_OFFSET..0x10007fff8000
+            ]
+        );
+        let addr = 0x7fff2c000000;
+        shadow
+            .poison(addr, 0x10, PoisonType::AsanHeapLeftRz)
+            .unwrap();
+        assert!(shadow.is_poison(addr + 8, 1).unwrap());
+        assert!(shadow.load(addr, 0x8).is_err());
+        shadow.unpoison(addr, 0x10).unwrap();
+        assert!(shadow.load(addr, 0x10).is_ok());
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn test_dynamic_scaled() {
+        type L32 = ScaledShadowLayout<X86_64ShadowLayout, 5>;
+        let mut shadow =
+            Dgs::new::<LinuxMapReader>(Some(ShadowLayoutKind::X86_64Granule32)).unwrap();
+        assert_eq!(shadow.kind(), ShadowLayoutKind::X86_64Granule32);
+        assert_eq!(shadow.granularity(), 32);
+        assert_eq!(
+            shadow.regions(),
+            [
+                L32::LOW_SHADOW_OFFSET..L32::LOW_SHADOW_OFFSET + L32::LOW_SHADOW_SIZE,
+                L32::HIGH_SHADOW_OFFSET..L32::HIGH_MEM_OFFSET
+            ]
+        );
+        let addr = 0x7fff2c000000;
+        shadow.unpoison(addr, 0x14).unwrap();
+        assert!(!shadow.is_poison(addr, 0x14).unwrap());
+        assert!(shadow.is_poison(addr + 0x14, 1).unwrap());
+        assert_eq!(shadow.get_poison(addr).unwrap(), PoisonType::AsanPartial20);
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn test_dynamic_collision() {
+        /* The stack of the process lies beyond the 39-bit address space */
+        let result = Dgs::new::<LinuxMapReader>(Some(ShadowLayoutKind::Aarch64Va39));
+        assert!(matches!(
+            result,
+            Err(DynamicGuestShadowError::LayoutCollision(ShadowCollision {
+                layout: ShadowLayoutKind::Aarch64Va39,
+                ..
+            }))
+        ));
+    }
+}
@@ -0,0 +1,215 @@
+#[cfg(test)]
+#[cfg(feature = "guest")]
+mod tests {
+    use {
+        asan::{
+            mmap::linux::LinuxMmap,
+            shadow::{
+                guest::{DefaultShadowLayout, GuestShadow, GuestShadowError},
+                PoisonType, Shadow, ShadowFault,
+            },
+            GuestAddr,
+        },
+        spin::Lazy,
+        std::sync::Mutex,
+    };
+
+    type GS = GuestShadow<LinuxMmap, DefaultShadowLayout>;
+
+    static INIT_ONCE: Lazy<Mutex<()>> = Lazy::new(|| {
+        {
+            env_logger::init();
+        };
+        Mutex::new(())
+    });
+
+    fn get_shadow() -> GuestShadow<LinuxMmap, DefaultShadowLayout> {
+        drop(INIT_ONCE.lock().unwrap());
+        GS::new().unwrap()
+    }
+
+    /// Exposes only the shadow bytes of the underlying shadow, so that the
+    /// default implementation of `find_poison` is used (as by `HostShadow`)
+    #[derive(Debug)]
+    struct ReadOnlyShadow<'a>(&'a GS);
+
+    impl Shadow for ReadOnlyShadow<'_> {
+        type Error = GuestShadowError<LinuxMmap>;
+
+        fn load(&self, start: GuestAddr, len: usize) -> Result<(), Self::Error> {
+            self.0.load(start, len)
+        }
+
+        fn store(&self, start: GuestAddr, len: usize) -> Result<(), Self::Error> {
+            self.0.store(start, len)
+        }
+
+        fn poison(
+            &mut self,
+            _start: GuestAddr,
+            _len: usize,
+            _val: PoisonType,
+        ) -> Result<(), Self::Error> {
+            unimplemented!()
+        }
+
+        fn unpoison(&mut self, _start: GuestAddr, _len: usize) -> Result<(), Self::Error> {
+            unimplemented!()
+        }
+
+        fn is_poison(&self, start: GuestAddr, len: usize) -> Result<bool, Self::Error> {
+            self.0.is_poison(start, len)
+        }
+
+        fn read_shadow(&self, start: GuestAddr, buf: &mut [u8]) -> Result<(), Self::Error> {
+            self.0.read_shadow(start, buf)
+        }
+
+        fn granularity(&self) -> usize {
+            self.0.granularity()
+        }
+    }
+
+    /* A page aligned address in high memory, whatever the layout */
+    const BASE: GuestAddr = GS::HIGH_MEM_OFFSET.next_multiple_of(0x1000);
+
+    #[test]
+    fn test_find_poison_none() {
+        let mut shadow = get_shadow();
+        shadow.unpoison(BASE, 0x14).unwrap();
+        assert_eq!(shadow.find_poison(BASE, 0x14), Ok(None));
+        assert_eq!(shadow.find_poison(BASE + 0x3, 0x11), Ok(None));
+        assert_eq!(shadow.find_poison(BASE, 0), Ok(None));
+    }
+
+    #[test]
+    fn test_find_poison_right_red_zone() {
+        let mut shadow = get_shadow();
+        shadow.unpoison(BASE, 0x14).unwrap();
+        shadow
+            .poison(BASE + 0x18, 0x28, PoisonType::AsanHeapRightRz)
+            .unwrap();
+        let expected = ShadowFault {
+            addr: BASE + 0x14,
+            value: 0x04,
+            poison: Some(PoisonType::AsanHeapRightRz),
+        };
+        assert_eq!(shadow.find_poison(BASE, 0x40), Ok(Some(expected)));
+        assert_eq!(shadow.find_poison(BASE + 0x11, 4), Ok(Some(expected)));
+        assert_eq!(
+            shadow.find_poison(BASE + 0x16, 1),
+            Ok(Some(ShadowFault {
+                addr: BASE + 0x16,
+                ..expected
+            }))
+        );
+        assert_eq!(
+            shadow.find_poison(BASE + 0x20, 8),
+            Ok(Some(ShadowFault {
+                addr: BASE + 0x20,
+                value: 0xfb,
+                poison: Some(PoisonType::AsanHeapRightRz),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_find_poison_left_red_zone() {
+        let mut shadow = get_shadow();
+        shadow
+            .poison(BASE + 0x1000, 0x10, PoisonType::AsanHeapLeftRz)
+            .unwrap();
+        shadow.unpoison(BASE + 0x1010, 0x10).unwrap();
+        assert_eq!(
+            shadow.find_poison(BASE + 0x100c, 0x8),
+            Ok(Some(ShadowFault {
+                addr: BASE + 0x100c,
+                value: 0xfa,
+                poison: Some(PoisonType::AsanHeapLeftRz),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_find_poison_freed() {
+        let mut shadow = get_shadow();
+        shadow.unpoison(BASE + 0x2000, 0x10).unwrap();
+        shadow
+            .poison(BASE + 0x2010, 0x20, PoisonType::AsanHeapFreed)
+            .unwrap();
+        assert_eq!(
+            shadow.find_poison(BASE + 0x2000, 0x100),
+            Ok(Some(ShadowFault {
+                addr: BASE + 0x2010,
+                value: 0xfd,
+                poison: Some(PoisonType::AsanHeapFreed),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_load_store_poisoned() {
+        let mut shadow = get_shadow();
+        shadow.unpoison(BASE + 0x3000, 0x10).unwrap();
+        shadow
+            .poison(BASE + 0x3010, 0x10, PoisonType::AsanHeapRightRz)
+            .unwrap();
+        let fault = ShadowFault {
+            addr: BASE + 0x3010,
+            value: 0xfb,
+            poison: Some(PoisonType::AsanHeapRightRz),
+        };
+        assert_eq!(
+            shadow.load(BASE + 0x3008, 0x10),
+            Err(GuestShadowError::Poisoned(fault))
+        );
+        assert_eq!(
+            shadow.store(BASE + 0x3000, 0x11),
+            Err(GuestShadowError::Poisoned(fault))
+        );
+        assert_eq!(shadow.load(BASE + 0x3000, 0x10), Ok(()));
+    }
+
+    #[test]
+    fn test_find_poison_invalid() {
+        let shadow = get_shadow();
+        assert_eq!(
+            shadow.find_poison(GS::LOW_SHADOW_OFFSET, 0x8),
+            Err(GuestShadowError::InvalidMemoryAddress(
+                GS::LOW_SHADOW_OFFSET
+            ))
+        );
+        assert_eq!(
+            shadow.find_poison(GuestAddr::MAX, 0x8),
+            Err(GuestShadowError::AddressRangeOverflow(GuestAddr::MAX, 0x8))
+        );
+    }
+
+    #[test]
+    fn test_find_poison_default() {
+        let mut shadow = get_shadow();
+        let base = BASE + 0x10000;
+        /* The partial granule is the last of the first chunk read */
+        shadow.unpoison(base, 0xffc).unwrap();
+        shadow
+            .poison(base + 0x1000, 0x20, PoisonType::AsanHeapRightRz)
+            .unwrap();
+        let expected = ShadowFault {
+            addr: base + 0xffc,
+            value: 0x04,
+            poison: Some(PoisonType::AsanHeapRightRz),
+        };
+        let read_only = ReadOnlyShadow(&shadow);
+        assert_eq!(read_only.find_poison(base, 0x2000), Ok(Some(expected)));
+        assert_eq!(shadow.find_poison(base, 0x2000), Ok(Some(expected)));
+        assert_eq!(read_only.find_poison(base + 0x3, 0xff9), Ok(None));
+        assert_eq!(
+            read_only.find_poison(base + 0x1008, 0x4),
+            Ok(Some(ShadowFault {
+                addr: base + 0x1008,
+                value: 0xfb,
+                poison: Some(PoisonType::AsanHeapRightRz),
+            }))
+        );
+    }
+}
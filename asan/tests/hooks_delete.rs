@@ -0,0 +1,28 @@
+#[cfg(test)]
+#[cfg(feature = "hooks")]
+mod tests {
+    use {
+        asan::hooks::{
+            delete::{
+                operator_delete, operator_delete_array, operator_delete_array_sized,
+                operator_delete_sized,
+            },
+            new::{operator_new, operator_new_array},
+        },
+        core::ptr::null_mut,
+    };
+
+    #[test]
+    fn test_delete_null() {
+        unsafe { operator_delete(null_mut()) };
+        unsafe { operator_delete_array(null_mut()) };
+    }
+
+    #[test]
+    fn test_delete_sized() {
+        let p = unsafe { operator_new(24) };
+        unsafe { operator_delete_sized(p, 24) };
+        let q = unsafe { operator_new_array(24) };
+        unsafe { operator_delete_array_sized(q, 24) };
+    }
+}
@@ -2,7 +2,7 @@
 #[cfg(all(feature = "hooks"))]
 mod tests {
     use {
-        asan::hooks::malloc::malloc,
+        asan::{allocator::set_allocator_may_return_null, hooks::malloc::malloc},
         core::{ptr::null_mut, slice::from_raw_parts_mut},
     };
 
@@ -22,4 +22,15 @@ mod tests {
                 .for_each(|x| *x = 0)
         };
     }
+
+    #[test]
+    #[cfg(feature = "libc")]
+    fn test_malloc_too_big() {
+        set_allocator_may_return_null(true);
+        unsafe { *libc::__errno_location() = 0 };
+        let p = unsafe { malloc(usize::MAX) };
+        assert_eq!(p, null_mut());
+        assert_eq!(unsafe { *libc::__errno_location() }, libc::ENOMEM);
+        set_allocator_may_return_null(false);
+    }
 }
@@ -0,0 +1,55 @@
+#[cfg(test)]
+#[cfg(feature = "hooks")]
+mod tests {
+    use {
+        asan::hooks::{
+            delete::{operator_delete, operator_delete_aligned, operator_delete_array},
+            new::{
+                operator_new, operator_new_aligned, operator_new_array, operator_new_array_nothrow,
+            },
+        },
+        core::{
+            ptr::{null, null_mut},
+            slice::from_raw_parts_mut,
+        },
+    };
+
+    #[test]
+    fn test_new_zero() {
+        let p = unsafe { operator_new(0) };
+        assert_ne!(p, null_mut());
+        let q = unsafe { operator_new(0) };
+        assert_ne!(p, q);
+        unsafe { operator_delete(p) };
+        unsafe { operator_delete(q) };
+    }
+
+    #[test]
+    fn test_new_buff() {
+        let p = unsafe { operator_new(10) };
+        assert_ne!(p, null_mut());
+        unsafe {
+            from_raw_parts_mut(p as *mut u8, 10)
+                .iter_mut()
+                .for_each(|x| *x = 0)
+        };
+        unsafe { operator_delete(p) };
+    }
+
+    #[test]
+    fn test_new_array() {
+        let p = unsafe { operator_new_array(10) };
+        assert_ne!(p, null_mut());
+        unsafe { operator_delete_array(p) };
+        let q = unsafe { operator_new_array_nothrow(10, null()) };
+        assert_ne!(q, null_mut());
+        unsafe { operator_delete_array(q) };
+    }
+
+    #[test]
+    fn test_new_aligned() {
+        let p = unsafe { operator_new_aligned(10, 0x100) };
+        assert_eq!(p as usize % 0x100, 0);
+        unsafe { operator_delete_aligned(p, 0x100) };
+    }
+}
@@ -2,7 +2,7 @@
 #[cfg(all(feature = "hooks"))]
 mod tests {
     use {
-        asan::hooks::realloc::realloc,
+        asan::{allocator::set_allocator_may_return_null, hooks::realloc::realloc},
         core::{ptr::null_mut, slice::from_raw_parts_mut},
     };
 
@@ -68,4 +68,18 @@ mod tests {
                 .for_each(|x| assert_eq!(*x, 0x88));
         };
     }
+
+    #[test]
+    fn test_realloc_too_big() {
+        set_allocator_may_return_null(true);
+        let p = unsafe { realloc(null_mut(), 10) };
+        assert_ne!(p, null_mut());
+        unsafe { from_raw_parts_mut(p as *mut u8, 10).fill(0xaa) };
+        let q = unsafe { realloc(p, usize::MAX) };
+        assert_eq!(q, null_mut());
+        /* The original buffer is still valid */
+        let buf = unsafe { from_raw_parts_mut(p as *mut u8, 10) };
+        assert!(buf.iter().all(|b| *b == 0xaa));
+        set_allocator_may_return_null(false);
+    }
 }
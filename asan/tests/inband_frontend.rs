@@ -0,0 +1,204 @@
+#[cfg(test)]
+#[cfg(all(feature = "linux", feature = "dlmalloc"))]
+mod tests {
+
+    use {
+        asan::{
+            allocator::{
+                backend::dlmalloc::DlmallocBackend,
+                frontend::{
+                    inband::{InbandFrontend, InbandFrontendError},
+                    AllocationInfo, AllocatorFrontend,
+                },
+            },
+            mmap::linux::LinuxMmap,
+            shadow::{
+                guest::{DefaultShadowLayout, GuestShadow},
+                Shadow,
+            },
+            tracking::guest::GuestTracking,
+        },
+        spin::{Lazy, Mutex, MutexGuard},
+    };
+
+    const PAGE_SIZE: usize = 4096;
+
+    static INIT_ONCE: Lazy<Mutex<IF>> = Lazy::new(|| {
+        Mutex::new({
+            env_logger::init();
+            let backend = DlmallocBackend::<LinuxMmap>::new(PAGE_SIZE);
+            let shadow = GuestShadow::<LinuxMmap, DefaultShadowLayout>::new().unwrap();
+            let tracking = GuestTracking::new().unwrap();
+            IF::new(
+                backend,
+                shadow,
+                tracking,
+                IF::DEFAULT_REDZONE_SIZE,
+                IF::DEFAULT_QUARANTINE_SIZE,
+            )
+            .unwrap()
+        })
+    });
+
+    type IF = InbandFrontend<
+        DlmallocBackend<LinuxMmap>,
+        GuestShadow<LinuxMmap, DefaultShadowLayout>,
+        GuestTracking,
+    >;
+
+    fn frontend() -> MutexGuard<'static, IF> {
+        INIT_ONCE.lock()
+    }
+
+    #[test]
+    fn test_allocate() {
+        let mut frontend = frontend();
+        let buf = frontend.alloc(16, 8).unwrap();
+        assert_eq!(frontend.get_size(buf).unwrap(), 16);
+        frontend.dealloc(buf).unwrap();
+    }
+
+    #[test]
+    fn test_allocate_is_poisoned() {
+        let mut frontend = frontend();
+        let len = 20;
+        let buf = frontend.alloc(len, 8).unwrap();
+        for i in buf - IF::DEFAULT_REDZONE_SIZE..buf + len + IF::DEFAULT_REDZONE_SIZE {
+            let expected = i < buf || i >= buf + len;
+            let poisoned = frontend.shadow().is_poison(i, 1).unwrap();
+            assert_eq!(expected, poisoned);
+        }
+        frontend.dealloc(buf).unwrap();
+    }
+
+    #[test]
+    fn test_allocate_aligned() {
+        let mut frontend = frontend();
+        let buf = frontend.alloc(24, 0x1000).unwrap();
+        assert_eq!(buf % 0x1000, 0);
+        assert_eq!(frontend.get_size(buf).unwrap(), 24);
+        frontend.dealloc(buf).unwrap();
+    }
+
+    #[test]
+    fn test_double_free() {
+        let mut frontend = frontend();
+        let buf = frontend.alloc(16, 8).unwrap();
+        frontend.dealloc(buf).unwrap();
+        assert!(matches!(
+            frontend.dealloc(buf),
+            Err(InbandFrontendError::DoubleFree(a)) if a == buf
+        ));
+        assert!(matches!(
+            frontend.get_size(buf),
+            Err(InbandFrontendError::InvalidAddress(a)) if a == buf
+        ));
+    }
+
+    #[test]
+    fn test_invalid_free() {
+        let mut frontend = frontend();
+        let buf = frontend.alloc(64, 8).unwrap();
+        assert!(matches!(
+            frontend.dealloc(buf + 16),
+            Err(InbandFrontendError::InvalidAddress(a)) if a == buf + 16
+        ));
+        assert!(matches!(
+            frontend.dealloc(buf + 1),
+            Err(InbandFrontendError::InvalidAddress(a)) if a == buf + 1
+        ));
+        let local = [0u64; 16];
+        let addr = local.as_ptr() as usize + 64;
+        assert!(matches!(
+            frontend.dealloc(addr),
+            Err(InbandFrontendError::InvalidAddress(a)) if a == addr
+        ));
+        frontend.dealloc(buf).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_header() {
+        let mut frontend = frontend();
+        let buf = frontend.alloc(16, 8).unwrap();
+        let header = buf - IF::HEADER_SIZE;
+        /* Corrupt the recorded size */
+        unsafe { *((header + 2 * size_of::<usize>()) as *mut usize) = 0x1000 };
+        assert!(matches!(
+            frontend.get_size(buf),
+            Err(InbandFrontendError::CorruptHeader(a)) if a == buf
+        ));
+    }
+
+    #[test]
+    fn test_find_allocation() {
+        let mut frontend = frontend();
+        let len = 16;
+        let others = (0..4)
+            .map(|_| frontend.alloc(len, 8).unwrap())
+            .collect::<Vec<_>>();
+        let buf = frontend.alloc(len, 8).unwrap();
+        let expected = frontend.find_allocation(buf);
+        assert!(matches!(
+            expected,
+            Some(AllocationInfo {
+                addr,
+                len: 16,
+                freed: false,
+                free_stack: None,
+                ..
+            }) if addr == buf
+        ));
+        assert_eq!(frontend.find_allocation(buf + len), expected);
+        assert_eq!(frontend.find_allocation(buf - 1), expected);
+        frontend.dealloc(buf).unwrap();
+        let freed = frontend.find_allocation(buf + 4).unwrap();
+        assert_eq!(freed.addr, buf);
+        assert!(freed.freed);
+        assert!(!freed.free_stack.unwrap().is_empty());
+        for b in others {
+            assert_eq!(frontend.find_allocation(b).unwrap().addr, b);
+            frontend.dealloc(b).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_quarantine_purged() {
+        let backend = DlmallocBackend::<LinuxMmap>::new(PAGE_SIZE);
+        let shadow = GuestShadow::<LinuxMmap, DefaultShadowLayout>::new().unwrap();
+        let tracking = GuestTracking::new().unwrap();
+        let mut frontend = IF::new(backend, shadow, tracking, IF::DEFAULT_REDZONE_SIZE, 0).unwrap();
+        let buf = frontend.alloc(16, 8).unwrap();
+        frontend.dealloc(buf).unwrap();
+        assert_eq!(frontend.find_allocation(buf), None);
+        assert!(frontend.dealloc(buf).is_err());
+    }
+
+    #[test]
+    fn test_allocate_too_big() {
+        let mut frontend = frontend();
+        assert!(matches!(
+            frontend.alloc(usize::MAX - 8, 8),
+            Err(InbandFrontendError::AllocationSizeTooBig(len)) if len == usize::MAX - 8
+        ));
+    }
+
+    #[test]
+    fn test_allocate_non_power_of_two_alignment() {
+        let mut frontend = frontend();
+        assert!(matches!(
+            frontend.alloc(16, 24),
+            Err(InbandFrontendError::InvalidAlignment(24))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_red_zone_size() {
+        let backend = DlmallocBackend::<LinuxMmap>::new(PAGE_SIZE);
+        let shadow = GuestShadow::<LinuxMmap, DefaultShadowLayout>::new().unwrap();
+        let tracking = GuestTracking::new().unwrap();
+        assert!(matches!(
+            IF::new(backend, shadow, tracking, 16, 0),
+            Err(InbandFrontendError::InvalidRedZoneSize(16))
+        ));
+    }
+}
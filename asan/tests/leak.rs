@@ -0,0 +1,133 @@
+#[cfg(test)]
+#[cfg(all(feature = "linux", feature = "dlmalloc"))]
+mod tests {
+    use {
+        asan::{
+            allocator::{
+                backend::dlmalloc::{regions, DlmallocBackend},
+                frontend::{default::DefaultFrontend, AllocatorFrontend},
+            },
+            backtrace::depot::StackDepot,
+            leak::{roots, Chunk, LeakChecker, LeakReport},
+            maps::linux::LinuxMapReader,
+            mmap::linux::LinuxMmap,
+            shadow::guest::{DefaultShadowLayout, GuestShadow},
+            tracking::guest::GuestTracking,
+            GuestAddr,
+        },
+        std::hint::black_box,
+    };
+
+    const PAGE_SIZE: usize = 4096;
+
+    type GS = GuestShadow<LinuxMmap, DefaultShadowLayout>;
+
+    type DF = DefaultFrontend<DlmallocBackend<LinuxMmap>, GS, GuestTracking>;
+
+    fn chunk(depot: &mut StackDepot, buf: &[GuestAddr], pc: GuestAddr) -> Chunk {
+        Chunk {
+            addr: buf.as_ptr() as GuestAddr,
+            len: size_of_val(buf),
+            stack: depot.insert(&[pc]),
+        }
+    }
+
+    #[test]
+    fn test_reachable() {
+        let mut depot = StackDepot::new();
+        let b = vec![0 as GuestAddr; 4];
+        let a = vec![b.as_ptr() as GuestAddr + 8, 0];
+        let chunks = vec![chunk(&mut depot, &a, 0x1000), chunk(&mut depot, &b, 0x2000)];
+        let mut checker = LeakChecker::new(chunks);
+        checker.scan_words(&[a.as_ptr() as GuestAddr]);
+        assert!(checker.leaks(&depot).is_empty());
+    }
+
+    #[test]
+    fn test_direct_and_indirect() {
+        let mut depot = StackDepot::new();
+        let b = vec![0 as GuestAddr; 4];
+        let a = vec![b.as_ptr() as GuestAddr, 0];
+        let c = vec![0 as GuestAddr; 2];
+        let chunks = vec![
+            chunk(&mut depot, &a, 0x1000),
+            chunk(&mut depot, &b, 0x2000),
+            chunk(&mut depot, &c, 0x1000),
+        ];
+        let mut checker = LeakChecker::new(chunks);
+        checker.scan_words(&[0x1234]);
+        let leaks = checker.leaks(&depot);
+        assert_eq!(leaks.len(), 2);
+        assert!(leaks[0].direct);
+        assert_eq!(leaks[0].count, 2);
+        assert_eq!(leaks[0].bytes, size_of_val(&a[..]) + size_of_val(&c[..]));
+        assert_eq!(leaks[0].stack, vec![0x1000]);
+        assert!(!leaks[1].direct);
+        assert_eq!(leaks[1].count, 1);
+        assert_eq!(leaks[1].stack, vec![0x2000]);
+
+        let msg = format!("{}", LeakReport::new(leaks));
+        assert!(msg
+            .contains("Direct leak of 32 byte(s) in 2 object(s) allocated from:\n    #0 0x1000\n"));
+        assert!(msg.contains(
+            "Indirect leak of 32 byte(s) in 1 object(s) allocated from:\n    #0 0x2000\n"
+        ));
+        assert!(msg.contains("SUMMARY: AddressSanitizer: 64 byte(s) leaked in 3 allocation(s)."));
+    }
+
+    #[test]
+    fn test_scan_range() {
+        let mut depot = StackDepot::new();
+        let a = vec![0 as GuestAddr; 2];
+        let root = [0, a.as_ptr() as GuestAddr];
+        let mut checker = LeakChecker::new(vec![chunk(&mut depot, &a, 0x1000)]);
+        let start = root.as_ptr() as GuestAddr;
+        unsafe { checker.scan_range(start, start + size_of_val(&root)) };
+        assert!(checker.leaks(&depot).is_empty());
+    }
+
+    #[test]
+    fn test_roots() {
+        let local = black_box([0 as GuestAddr; 4]);
+        let addr = local.as_ptr() as GuestAddr;
+        let all = roots::<LinuxMapReader>(&[]).unwrap();
+        assert!(all.iter().any(|r| r.contains(&addr)));
+        let exclude = addr..addr + 1;
+        let excluded = roots::<LinuxMapReader>(core::slice::from_ref(&exclude)).unwrap();
+        assert!(!excluded.iter().any(|r| r.contains(&addr)));
+        assert!(excluded.iter().any(|r| r.contains(&(addr + 1))));
+    }
+
+    #[test]
+    fn test_find_leaks() {
+        let backend = DlmallocBackend::<LinuxMmap>::new(PAGE_SIZE);
+        let shadow = GS::new().unwrap();
+        let tracking = GuestTracking::new().unwrap();
+        let mut frontend = DF::new(
+            backend,
+            shadow,
+            tracking,
+            DF::DEFAULT_REDZONE_SIZE,
+            DF::DEFAULT_QUARANTINE_SIZE,
+        )
+        .unwrap();
+        let len = 0x321;
+        let buf = frontend.alloc(len, 8).unwrap();
+        let root = Box::new(buf);
+        let start = &*root as *const GuestAddr as GuestAddr;
+        let exclude = regions()
+            .chain([
+                GS::LOW_SHADOW_OFFSET..GS::LOW_SHADOW_LIMIT + 1,
+                GS::HIGH_SHADOW_OFFSET..GS::HIGH_SHADOW_LIMIT + 1,
+            ])
+            .collect::<Vec<_>>();
+        assert!(roots::<LinuxMapReader>(&exclude)
+            .unwrap()
+            .iter()
+            .all(|r| !r.contains(&buf)));
+        let root_range = start..start + size_of::<GuestAddr>();
+        let leaks = unsafe { frontend.find_leaks(core::slice::from_ref(&root_range)) };
+        assert!(leaks.iter().all(|l| l.bytes != len));
+        frontend.dealloc(buf).unwrap();
+    }
+}
@@ -0,0 +1,105 @@
+#[cfg(test)]
+#[cfg(all(feature = "libc", feature = "linux"))]
+mod tests {
+
+    use {
+        asan::{
+            allocator::{
+                backend::{
+                    libc::{LibcBackend, LibcBackendError},
+                    AllocatorBackend,
+                },
+                frontend::{default::DefaultFrontend, AllocatorFrontend},
+            },
+            mmap::linux::LinuxMmap,
+            shadow::{
+                guest::{DefaultShadowLayout, GuestShadow},
+                Shadow,
+            },
+            symbols::dlsym::{DlSymSymbols, LookupTypeNext},
+            tracking::guest::GuestTracking,
+        },
+        libc::{c_void, malloc_usable_size},
+        spin::{Lazy, Mutex, MutexGuard},
+    };
+
+    type LB = LibcBackend<DlSymSymbols<LookupTypeNext>>;
+
+    type DF = DefaultFrontend<LB, GuestShadow<LinuxMmap, DefaultShadowLayout>, GuestTracking>;
+
+    static INIT_ONCE: Lazy<Mutex<DF>> = Lazy::new(|| {
+        Mutex::new({
+            env_logger::init();
+            let shadow = GuestShadow::<LinuxMmap, DefaultShadowLayout>::new().unwrap();
+            let tracking = GuestTracking::new().unwrap();
+            DF::new(
+                LB::new(),
+                shadow,
+                tracking,
+                DF::DEFAULT_REDZONE_SIZE,
+                DF::DEFAULT_QUARANTINE_SIZE,
+            )
+            .unwrap()
+        })
+    });
+
+    fn frontend() -> MutexGuard<'static, DF> {
+        INIT_ONCE.lock()
+    }
+
+    #[test]
+    fn test_allocate() {
+        let mut allocator = LB::new();
+        let buf = allocator.alloc(100, 8).unwrap();
+        assert_eq!(buf % LB::MALLOC_ALIGNMENT, 0);
+        /* The buffer is owned by the libc heap */
+        assert!(unsafe { malloc_usable_size(buf as *mut c_void) } >= 100);
+        unsafe { (buf as *mut u8).write_bytes(0xaa, 100) };
+        allocator.dealloc(buf, 100, 8).unwrap();
+    }
+
+    #[test]
+    fn test_allocate_aligned() {
+        let mut allocator = LB::new();
+        for align in [32, 0x1000, 0x10000] {
+            let buf = allocator.alloc(24, align).unwrap();
+            assert_eq!(buf % align, 0);
+            assert!(unsafe { malloc_usable_size(buf as *mut c_void) } >= 24);
+            allocator.dealloc(buf, 24, align).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_invalid_alignment() {
+        let mut allocator = LB::new();
+        assert_eq!(
+            allocator.alloc(16, 24),
+            Err(LibcBackendError::InvalidAlignment(24))
+        );
+    }
+
+    #[test]
+    fn test_allocation_failure() {
+        let mut allocator = LB::new();
+        let len = usize::MAX - 0x1000;
+        assert_eq!(
+            allocator.alloc(len, 8),
+            Err(LibcBackendError::FailedToAllocate(len, 8))
+        );
+    }
+
+    #[test]
+    fn test_frontend() {
+        let mut frontend = frontend();
+        let len = 20;
+        let buf = frontend.alloc(len, 8).unwrap();
+        assert_eq!(frontend.get_size(buf).unwrap(), len);
+        for i in buf - DF::DEFAULT_REDZONE_SIZE..buf + len + DF::DEFAULT_REDZONE_SIZE {
+            let expected = i < buf || i >= buf + len;
+            let poisoned = frontend.shadow().is_poison(i, 1).unwrap();
+            assert_eq!(expected, poisoned);
+        }
+        frontend.dealloc(buf).unwrap();
+        assert!(frontend.shadow().is_poison(buf, len).unwrap());
+    }
+}
@@ -0,0 +1,85 @@
+#[cfg(test)]
+mod tests {
+    use {
+        asan::options::{Options, ParseError},
+        log::Level,
+    };
+
+    #[test]
+    fn test_parse_empty() {
+        assert_eq!(Options::parse(""), Ok(Options::default()));
+    }
+
+    #[test]
+    fn test_parse() {
+        let options = Options::parse(
+            "redzone=64:max_redzone=1024:quarantine_size_mb=16,log_level=debug halt_on_error=0:exitcode=0x17:malloc_fill_byte=0xbe:max_malloc_fill_size=64:free_fill_byte=0x55:check_free_fill=1:alloc_dealloc_mismatch=0:new_delete_type_mismatch=false:forward_foreign_free=true:max_allocation_size_mb=1024:allocator_may_return_null=1:arena_size_mb=512:heap_base=0x60000000:print_stats=1:malloc_context_size=8:detect_leaks=false:leak_exitcode=23:detect_stack_use_after_return=1",
+        )
+        .unwrap();
+        assert_eq!(
+            options,
+            Options {
+                redzone: Some(64),
+                max_redzone: Some(1024),
+                quarantine_size_mb: Some(16),
+                malloc_context_size: Some(8),
+                malloc_fill_byte: Some(0xbe),
+                max_malloc_fill_size: Some(64),
+                free_fill_byte: Some(0x55),
+                check_free_fill: Some(true),
+                alloc_dealloc_mismatch: Some(false),
+                new_delete_type_mismatch: Some(false),
+                forward_foreign_free: Some(true),
+                max_allocation_size_mb: Some(1024),
+                allocator_may_return_null: Some(true),
+                arena_size_mb: Some(512),
+                heap_base: Some(0x6000_0000),
+                #[cfg(feature = "guest")]
+                shadow_layout: None,
+                print_stats: Some(true),
+                log_level: Some(Level::Debug),
+                halt_on_error: Some(false),
+                exitcode: Some(0x17),
+                detect_leaks: Some(false),
+                leak_exitcode: Some(23),
+                detect_stack_use_after_return: Some(true),
+                unknown_keys: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_unknown_key() {
+        let options =
+            Options::parse("redzone=64:foo=1:abort_on_error=1:symbolize=0:handle_segv=1").unwrap();
+        assert_eq!(options.redzone, Some(64));
+        assert_eq!(
+            options.unknown_keys,
+            vec!["foo", "abort_on_error", "symbolize", "handle_segv"]
+        );
+        /* Malformed values are still rejected */
+        assert!(Options::parse("foo=1:redzone=bar").is_err());
+    }
+
+    #[test]
+    fn test_parse_missing_value() {
+        assert_eq!(
+            Options::parse("redzone"),
+            Err(ParseError::MissingValue("redzone".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_value() {
+        assert_eq!(
+            Options::parse("malloc_fill_byte=0x100"),
+            Err(ParseError::InvalidValue(
+                "malloc_fill_byte".to_string(),
+                "0x100".to_string()
+            ))
+        );
+        assert!(Options::parse("halt_on_error=yes").is_err());
+        assert!(Options::parse("log_level=loud").is_err());
+        assert!(Options::parse("heap_base=0").is_err());
+    }
+}
@@ -0,0 +1,401 @@
+#[cfg(test)]
+#[cfg(all(feature = "guest", feature = "linux", target_pointer_width = "64"))]
+mod tests {
+    use {
+        asan::{
+            allocator::frontend::{AllocationInfo, AllocationType},
+            globals::Global,
+            mmap::linux::LinuxMmap,
+            report::{find_fault, set_halt_on_error, summary, AccessType, ErrorType, Report},
+            shadow::{
+                guest::{DefaultShadowLayout, GuestShadow},
+                PoisonType, Shadow,
+            },
+        },
+        spin::Lazy,
+        std::sync::Mutex,
+    };
+
+    type GS = GuestShadow<LinuxMmap, DefaultShadowLayout>;
+
+    static INIT_ONCE: Lazy<Mutex<()>> = Lazy::new(|| {
+        {
+            env_logger::init();
+        };
+        Mutex::new(())
+    });
+
+    fn get_shadow() -> GS {
+        drop(INIT_ONCE.lock().unwrap());
+        GS::new().unwrap()
+    }
+
+    fn allocation(freed: bool) -> AllocationInfo {
+        AllocationInfo {
+            addr: 0x1000,
+            len: 0x10,
+            freed,
+            alloc_stack: vec![0x4000, 0x5000],
+            free_stack: freed.then(|| vec![0x6000]),
+        }
+    }
+
+    #[test]
+    fn test_heap_buffer_overflow() {
+        let report = Report::access(
+            0x100c,
+            8,
+            AccessType::Read,
+            0x1010,
+            Some(PoisonType::AsanHeapRightRz),
+            Some(allocation(false)),
+        );
+        assert_eq!(report.error_type(), ErrorType::HeapBufferOverflow);
+        let msg = format!("{report}");
+        assert!(msg.contains("heap-buffer-overflow on address 0x1010"));
+        assert!(msg.contains("READ of size 8 at 0x1010"));
+        assert!(msg.contains("0x1010 is located 0 bytes after 16-byte region [0x1000,0x1010)"));
+        assert!(msg.contains("allocated here:\n    #0 0x4000\n    #1 0x5000\n"));
+        assert!(!msg.contains("freed here:"));
+    }
+
+    #[test]
+    fn test_heap_buffer_underflow() {
+        let report = Report::access(
+            0xff8,
+            1,
+            AccessType::Write,
+            0xff8,
+            Some(PoisonType::AsanHeapLeftRz),
+            Some(allocation(false)),
+        );
+        assert_eq!(report.error_type(), ErrorType::HeapBufferOverflow);
+        let msg = format!("{report}");
+        assert!(msg.contains("WRITE of size 1 at 0xff8"));
+        assert!(msg.contains("0xff8 is located 8 bytes before 16-byte region"));
+    }
+
+    #[test]
+    fn test_heap_use_after_free() {
+        let report = Report::access(
+            0x1004,
+            4,
+            AccessType::Read,
+            0x1004,
+            Some(PoisonType::AsanHeapFreed),
+            Some(allocation(true)),
+        );
+        assert_eq!(report.error_type(), ErrorType::HeapUseAfterFree);
+        let msg = format!("{report}");
+        assert!(msg.contains("heap-use-after-free"));
+        assert!(msg.contains("0x1004 is located 4 bytes inside of 16-byte region"));
+        assert!(msg.contains("freed here:\n    #0 0x6000\n"));
+        assert!(msg.contains("previously allocated here:\n    #0 0x4000\n    #1 0x5000\n"));
+    }
+
+    #[test]
+    fn test_classify_without_poison() {
+        let overflow = Report::access(
+            0x1010,
+            1,
+            AccessType::Read,
+            0x1010,
+            None,
+            Some(allocation(false)),
+        );
+        assert_eq!(overflow.error_type(), ErrorType::HeapBufferOverflow);
+        let uaf = Report::access(
+            0x1000,
+            1,
+            AccessType::Read,
+            0x1000,
+            None,
+            Some(allocation(true)),
+        );
+        assert_eq!(uaf.error_type(), ErrorType::HeapUseAfterFree);
+    }
+
+    #[test]
+    fn test_wild_access() {
+        let report = Report::access(0x1234, 2, AccessType::Write, 0x1234, None, None);
+        assert_eq!(report.error_type(), ErrorType::WildAccess);
+        let msg = format!("{report}");
+        assert!(msg.contains("SUMMARY: AddressSanitizer: wild-access"));
+    }
+
+    #[test]
+    fn test_double_free() {
+        let report = Report::free(
+            0x1000,
+            Some(PoisonType::AsanHeapFreed),
+            Some(allocation(true)),
+        );
+        assert_eq!(report.error_type(), ErrorType::DoubleFree);
+        assert!(format!("{report}").contains("attempting double-free on 0x1000"));
+        let evicted = Report::free(0x1000, Some(PoisonType::AsanHeapFreed), None);
+        assert_eq!(evicted.error_type(), ErrorType::DoubleFree);
+    }
+
+    #[test]
+    fn test_invalid_free() {
+        let interior = Report::free(0x1008, Some(PoisonType::AsanValid), Some(allocation(false)));
+        assert_eq!(interior.error_type(), ErrorType::InvalidFree);
+        let unknown = Report::free(0x1234, None, None);
+        assert_eq!(unknown.error_type(), ErrorType::InvalidFree);
+        assert!(format!("{unknown}").contains("which was not malloc()-ed: 0x1234"));
+    }
+
+    #[test]
+    fn test_write_after_free() {
+        let report = Report::recycle(0x1004, allocation(true)).with_stack(vec![0x7000]);
+        assert_eq!(report.error_type(), ErrorType::WriteAfterFree);
+        assert_eq!(report.stack(), &[0x7000]);
+        let msg = format!("{report}");
+        assert!(msg.contains("write-after-free detected on recycle at 0x1004"));
+        assert!(msg.contains("4 bytes inside of 16-byte region"));
+        assert!(msg.contains("SUMMARY: AddressSanitizer: write-after-free"));
+    }
+
+    #[test]
+    fn test_alloc_dealloc_mismatch() {
+        let report = Report::mismatch(
+            0x1000,
+            AllocationType::NewArray,
+            AllocationType::Malloc,
+            allocation(false),
+        );
+        assert_eq!(report.error_type(), ErrorType::AllocDeallocMismatch);
+        let msg = format!("{report}");
+        assert!(msg.contains("alloc-dealloc-mismatch (operator new [] vs free) on 0x1000"));
+        assert!(msg.contains("SUMMARY: AddressSanitizer: alloc-dealloc-mismatch"));
+
+        let report = Report::size_mismatch(0x1000, 8, allocation(false));
+        assert_eq!(report.error_type(), ErrorType::AllocDeallocMismatch);
+        let msg = format!("{report}");
+        assert!(msg.contains("size of the allocated type: 16 bytes"));
+        assert!(msg.contains("size of the deallocated type: 8 bytes"));
+    }
+
+    #[test]
+    fn test_allocation_failure() {
+        let report =
+            Report::allocation_size_too_big(0x20000000000, 0x10000000000).with_stack(vec![0x7000]);
+        assert_eq!(report.error_type(), ErrorType::AllocationSizeTooBig);
+        assert_eq!(report.pc(), Some(0x7000));
+        let msg = format!("{report}");
+        assert!(msg.contains(
+            "requested allocation size 0x20000000000 exceeds maximum supported size of 0x10000000000"
+        ));
+        assert!(msg.contains("SUMMARY: AddressSanitizer: allocation-size-too-big"));
+
+        let report = Report::out_of_memory(0x1000);
+        assert_eq!(report.error_type(), ErrorType::OutOfMemory);
+        let msg = format!("{report}");
+        assert!(msg.contains("allocator is trying to allocate 0x1000 bytes"));
+        assert!(msg.contains("SUMMARY: AddressSanitizer: out-of-memory"));
+    }
+
+    #[test]
+    fn test_free_foreign() {
+        let global = Global {
+            addr: 0x2000,
+            size: 0x20,
+            size_with_redzone: 0x40,
+            name: "buffer".to_string(),
+            module: "test.c".to_string(),
+        };
+        let report =
+            Report::free(0x2008, Some(PoisonType::AsanValid), None).with_global(Some(global));
+        assert_eq!(report.error_type(), ErrorType::InvalidFree);
+        let msg = format!("{report}");
+        assert!(msg.contains("which was not malloc()-ed: 0x2008"));
+        assert!(msg.contains("Shadow value: AsanValid (0x00)"));
+        assert!(msg.contains("8 bytes inside of global variable 'buffer'"));
+    }
+
+    #[test]
+    fn test_global_buffer_overflow() {
+        let global = Global {
+            addr: 0x2000,
+            size: 0x14,
+            size_with_redzone: 0x40,
+            name: "table".to_string(),
+            module: "test.c".to_string(),
+        };
+        let report = Report::access(
+            0x2010,
+            8,
+            AccessType::Write,
+            0x2014,
+            Some(PoisonType::AsanGlobalRz),
+            None,
+        )
+        .with_global(Some(global));
+        assert_eq!(report.error_type(), ErrorType::GlobalBufferOverflow);
+        let msg = format!("{report}");
+        assert!(msg.contains("global-buffer-overflow on address 0x2014"));
+        assert!(msg.contains(
+            "0x2014 is located 0 bytes after global variable 'table' defined in 'test.c' (0x2000) of size 20"
+        ));
+    }
+
+    #[test]
+    fn test_stack_errors() {
+        let error_type = |poison| {
+            Report::access(0x3000, 1, AccessType::Read, 0x3000, Some(poison), None).error_type()
+        };
+        assert_eq!(
+            error_type(PoisonType::AsanStackLeftRz),
+            ErrorType::StackBufferOverflow
+        );
+        assert_eq!(
+            error_type(PoisonType::AsanStackMidRz),
+            ErrorType::StackBufferOverflow
+        );
+        assert_eq!(
+            error_type(PoisonType::AsanAllocaRightRz),
+            ErrorType::DynamicStackBufferOverflow
+        );
+        assert_eq!(
+            error_type(PoisonType::AsanStackFreed),
+            ErrorType::StackUseAfterReturn
+        );
+        assert_eq!(
+            error_type(PoisonType::AsanStackOoscope),
+            ErrorType::StackUseAfterScope
+        );
+        assert_eq!(
+            ErrorType::StackUseAfterReturn.name(),
+            "stack-use-after-return"
+        );
+    }
+
+    #[test]
+    fn test_poison_type_from_shadow_value() {
+        assert_eq!(PoisonType::try_from(0x00), Ok(PoisonType::AsanValid));
+        assert_eq!(PoisonType::try_from(0x07), Ok(PoisonType::AsanPartial7));
+        assert_eq!(PoisonType::try_from(0xfa), Ok(PoisonType::AsanHeapLeftRz));
+        assert_eq!(PoisonType::try_from(0xfd), Ok(PoisonType::AsanHeapFreed));
+        assert_eq!(PoisonType::try_from(0xca), Ok(PoisonType::AsanAllocaLeftRz));
+        assert!(PoisonType::try_from(0x42).is_err());
+    }
+
+    #[test]
+    fn test_find_fault() {
+        let mut shadow = get_shadow();
+        let base = 0x7fff2bffff00;
+        shadow.unpoison(base, 0x20).unwrap();
+        shadow
+            .poison(base + 0x20, 0x20, PoisonType::AsanHeapRightRz)
+            .unwrap();
+        assert_eq!(find_fault(&shadow, base + 0x10, 0x20), base + 0x20);
+        assert_eq!(
+            shadow.get_poison(base + 0x24),
+            Ok(PoisonType::AsanHeapRightRz)
+        );
+        assert_eq!(shadow.get_poison(base), Ok(PoisonType::AsanValid));
+    }
+
+    #[test]
+    fn test_shadow_dump() {
+        let mut shadow = get_shadow();
+        let base = 0x7fff2bffff00;
+        shadow.unpoison(base, 0x14).unwrap();
+        shadow
+            .poison(base + 0x18, 0x68, PoisonType::AsanHeapRightRz)
+            .unwrap();
+        let mut bytes = [0xff; 4];
+        shadow.read_shadow(base, &mut bytes).unwrap();
+        assert_eq!(bytes, [0x00, 0x00, 0x04, 0xfb]);
+        assert!(shadow.read_shadow(base + 1, &mut bytes).is_err());
+
+        let dump = shadow.dump(base + 0x14).unwrap();
+        assert_eq!(dump.granularity, 8);
+        assert_eq!(dump.rows.len(), 11);
+        assert_eq!(dump.rows[5].addr, base);
+        assert_eq!(dump.value(), Some(0x04));
+        let msg = format!("{dump}");
+        assert!(msg.starts_with("Shadow bytes around the buggy address:\n"));
+        assert!(
+            msg.contains("\n=>0x7fff2bffff00: 00 00[04]fb fb fb fb fb fb fb fb fb fb fb fb fb\n")
+        );
+        assert!(msg.contains("\n  0x7fff2bffff80: 00 00"));
+        assert!(msg.contains("(one shadow byte represents 8 application bytes)"));
+        assert!(msg.contains("Partially addressable: 01 02 03 04 05 06 07\n"));
+        assert!(msg.contains("\n    Heap right redzone:    fb"));
+
+        let last = format!("{}", shadow.dump(base + 0x78).unwrap());
+        assert!(last.contains("fb[fb]\n"));
+
+        let report = Report::access(
+            base + 0x14,
+            4,
+            AccessType::Read,
+            base + 0x14,
+            Some(PoisonType::AsanPartial4),
+            None,
+        )
+        .with_shadow(Some(dump.clone()));
+        assert_eq!(report.shadow(), Some(&dump));
+        let msg = format!("{report}");
+        assert!(msg.contains(&format!("{dump}\nSUMMARY: AddressSanitizer: ")));
+        assert_eq!(
+            Report::out_of_memory(8).with_shadow(Some(dump)).shadow(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_stack() {
+        let report = Report::access(
+            0x1010,
+            1,
+            AccessType::Write,
+            0x1010,
+            None,
+            Some(allocation(false)),
+        )
+        .with_stack(vec![0x7000, 0x8000]);
+        assert_eq!(report.pc(), Some(0x7000));
+        let msg = format!("{report}");
+        assert!(msg.contains(
+            "WRITE of size 1 at 0x1010 (access: 0x1010-0x1011)\n    #0 0x7000\n    #1 0x8000\n"
+        ));
+    }
+
+    #[test]
+    fn test_recover_deduplicates() {
+        set_halt_on_error(false);
+        let report = |pc, freed| {
+            Report::access(
+                0x1004,
+                4,
+                AccessType::Read,
+                0x1004,
+                None,
+                Some(allocation(freed)),
+            )
+            .with_stack(vec![pc])
+        };
+        report(0x7000, false).emit();
+        report(0x7000, false).emit();
+        report(0x7004, false).emit();
+        report(0x7000, true).emit();
+
+        let summary = summary();
+        let errors = summary.errors();
+        assert_eq!(errors.len(), 3);
+        assert_eq!(errors.iter().map(|e| e.count).sum::<usize>(), 4);
+        assert!(errors
+            .iter()
+            .any(|e| e.error_type == ErrorType::HeapBufferOverflow
+                && e.pc == Some(0x7000)
+                && e.count == 2));
+        assert!(errors
+            .iter()
+            .any(|e| e.error_type == ErrorType::HeapUseAfterFree && e.count == 1));
+        let msg = format!("{summary}");
+        assert!(msg.contains("3 unique error(s) detected (4 in total)"));
+        assert!(msg.contains("heap-buffer-overflow at pc 0x7000 (2 time(s))"));
+    }
+}
@@ -0,0 +1,62 @@
+#[cfg(test)]
+mod tests {
+    use {
+        asan::shadow::{find_nonzero, scan_shadow, PoisonType, ShadowFault},
+        core::mem::size_of,
+    };
+
+    const WORD_SIZE: usize = size_of::<usize>();
+
+    #[test]
+    fn test_find_nonzero_empty() {
+        assert_eq!(find_nonzero(&[]), None);
+    }
+
+    #[test]
+    fn test_find_nonzero_zero() {
+        let buf = [0u8; 0x100];
+        for offset in 0..WORD_SIZE {
+            for len in 0..buf.len() - offset {
+                assert_eq!(find_nonzero(&buf[offset..offset + len]), None);
+            }
+        }
+    }
+
+    #[test]
+    fn test_find_nonzero() {
+        /* Test every position relative to the word boundaries of the slice */
+        for offset in 0..WORD_SIZE {
+            for len in 1..4 * WORD_SIZE {
+                for i in 0..len {
+                    let mut buf = [0u8; 8 * WORD_SIZE];
+                    buf[offset + i] = PoisonType::AsanHeapRightRz as u8;
+                    /* Any subsequent non-zero bytes must not be reported */
+                    buf[offset + len - 1] |= PoisonType::AsanPartial1 as u8;
+                    assert_eq!(find_nonzero(&buf[offset..offset + len]), Some(i));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_scan_shadow() {
+        let base = 0x1000;
+        let mut buf = [0u8; 4 * WORD_SIZE];
+        buf[3 * WORD_SIZE] = PoisonType::AsanPartial4 as u8;
+        buf[3 * WORD_SIZE + 1] = PoisonType::AsanHeapRightRz as u8;
+        let granule = base + 3 * WORD_SIZE * 8;
+        /* The range ends within the addressable part of the partial granule */
+        assert_eq!(
+            scan_shadow(base + 3, granule + 4 - base - 3, 8, &buf, None),
+            None
+        );
+        assert_eq!(
+            scan_shadow(base + 3, granule + 5 - base - 3, 8, &buf, None),
+            Some(ShadowFault {
+                addr: granule + 4,
+                value: 0x04,
+                poison: Some(PoisonType::AsanHeapRightRz),
+            })
+        );
+    }
+}
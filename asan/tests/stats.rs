@@ -0,0 +1,106 @@
+#[cfg(test)]
+mod tests {
+    use {
+        asan::{
+            asan_alloc, asan_dealloc,
+            stats::{
+                __sanitizer_get_allocated_size, __sanitizer_get_current_allocated_bytes,
+                __sanitizer_get_free_bytes, __sanitizer_get_heap_size, __sanitizer_get_ownership,
+                AtomicHeapStats, HeapStats, NUM_SIZE_CLASSES,
+            },
+        },
+        core::ptr::null,
+    };
+
+    #[test]
+    fn test_size_class() {
+        assert_eq!(HeapStats::size_class(0), 0);
+        assert_eq!(HeapStats::size_class(1), 0);
+        assert_eq!(HeapStats::size_class(2), 1);
+        assert_eq!(HeapStats::size_class(16), 4);
+        assert_eq!(HeapStats::size_class(17), 5);
+        assert_eq!(HeapStats::size_class(usize::MAX), NUM_SIZE_CLASSES - 1);
+    }
+
+    #[test]
+    fn test_record() {
+        let mut stats = HeapStats::default();
+        stats.record_alloc(16, 64);
+        stats.record_alloc(100, 256);
+        assert_eq!(stats.allocated_bytes, 116);
+        assert_eq!(stats.allocated_chunks, 2);
+        assert_eq!(stats.red_zone_bytes, 48 + 156);
+        assert_eq!(stats.heap_size, 320);
+        assert_eq!(stats.size_classes[4], 1);
+        assert_eq!(stats.size_classes[7], 1);
+
+        stats.record_free(100, 256);
+        assert_eq!(stats.allocated_bytes, 16);
+        assert_eq!(stats.peak_allocated_bytes, 116);
+        assert_eq!(stats.quarantine_bytes, 256);
+        assert_eq!(stats.quarantine_chunks, 1);
+        assert_eq!(stats.free_bytes(), 256);
+        assert_eq!(stats.heap_size, 320);
+        assert_eq!(stats.size_classes[7], 0);
+
+        stats.record_release(256);
+        assert_eq!(stats.quarantine_bytes, 0);
+        assert_eq!(stats.quarantine_chunks, 0);
+        assert_eq!(stats.heap_size, 64);
+        assert_eq!(stats.total_allocs, 2);
+        assert_eq!(stats.total_frees, 1);
+
+        let msg = format!("{stats}");
+        assert!(msg.contains("16 bytes in 1 chunks allocated (116 bytes peak)"));
+        assert!(msg.contains("<= 16: 1"));
+        assert!(!msg.contains("<= 128"));
+    }
+
+    #[test]
+    fn test_record_atomic() {
+        let mut expected = HeapStats::default();
+        let stats = AtomicHeapStats::default();
+        stats.record_alloc(16, 64);
+        expected.record_alloc(16, 64);
+        stats.record_alloc(100, 256);
+        expected.record_alloc(100, 256);
+        stats.record_free(100, 256);
+        expected.record_free(100, 256);
+        assert_eq!(stats.load(), expected);
+
+        stats.record_release(256);
+        expected.record_release(256);
+        assert_eq!(stats.load(), expected);
+
+        stats.record_reset();
+        expected.record_reset();
+        assert_eq!(stats.load(), expected);
+        assert_eq!(stats.load().peak_allocated_bytes, 116);
+    }
+
+    #[test]
+    fn test_interface() {
+        let allocated = unsafe { __sanitizer_get_current_allocated_bytes() };
+        let heap_size = unsafe { __sanitizer_get_heap_size() };
+        let free_bytes = unsafe { __sanitizer_get_free_bytes() };
+
+        let p = unsafe { asan_alloc(40, 8) };
+        assert_eq!(unsafe { __sanitizer_get_ownership(p) }, 1);
+        assert_eq!(unsafe { __sanitizer_get_allocated_size(p) }, 40);
+        assert_eq!(
+            unsafe { __sanitizer_get_current_allocated_bytes() },
+            allocated + 40
+        );
+        assert!(unsafe { __sanitizer_get_heap_size() } > heap_size + 40);
+
+        unsafe { asan_dealloc(p) };
+        assert_eq!(unsafe { __sanitizer_get_ownership(p) }, 0);
+        assert_eq!(unsafe { __sanitizer_get_allocated_size(p) }, 0);
+        assert_eq!(
+            unsafe { __sanitizer_get_current_allocated_bytes() },
+            allocated
+        );
+        assert!(unsafe { __sanitizer_get_free_bytes() } > free_bytes);
+        assert_eq!(unsafe { __sanitizer_get_ownership(null()) }, 0);
+    }
+}
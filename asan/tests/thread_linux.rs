@@ -0,0 +1,26 @@
+#[cfg(test)]
+#[cfg(feature = "linux")]
+mod tests {
+    use {
+        asan::thread::linux::{thread_exited, thread_id},
+        std::thread,
+    };
+
+    #[test]
+    fn test_linux_thread_id() {
+        let tid = thread_id();
+        assert_eq!(thread_id(), tid);
+        let other = thread::spawn(thread_id).join().unwrap();
+        assert_ne!(other, tid);
+    }
+
+    #[test]
+    fn test_linux_thread_exited() {
+        assert!(!thread_exited(thread_id()));
+        let tid = thread::spawn(thread_id).join().unwrap();
+        /* The thread may be briefly visible after it has been joined */
+        while !thread_exited(tid) {
+            thread::yield_now();
+        }
+    }
+}
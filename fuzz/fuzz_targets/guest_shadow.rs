@@ -64,6 +64,12 @@ fuzz_target!(|data: Vec<GuestAddr>| {
     };
 
     let test_result = shadow.is_poison(test_start, test_len);
+    assert_eq!(
+        shadow
+            .find_poison(test_start, test_len)
+            .map(|fault| fault.is_some()),
+        test_result
+    );
     if !GS::is_memory(test_start, test_len) {
         assert_eq!(
             test_result,
@@ -2,88 +2,176 @@
 extern crate alloc;
 
 use {
+    alloc::vec::Vec,
     asan::{
         allocator::{
-            backend::{dlmalloc::DlmallocBackend, mimalloc::MimallocBackend, GlobalAllocator},
-            frontend::{default::DefaultFrontend, AllocatorFrontend},
+            allocator_may_return_null,
+            backend::dlmalloc::regions,
+            frontend::{
+                cached::{CachedFrontend, CachedFrontendError},
+                default::{DefaultFrontend, DefaultFrontendError},
+                AllocationInfo, AllocationType,
+            },
         },
+        backtrace::stack,
+        env::{libc::LibcEnv, Env},
+        fake_stack::{FakeStacks, DEFAULT_CLASS_SIZE},
+        globals::find_global,
+        leak::{self, detect_leaks, LeakReport},
         logger::libc::LibcLogger,
-        maps::libc::LibcMapReader,
+        maps::{iterator::MapIterator, libc::LibcMapReader, MapReader},
         mmap::libc::LibcMmap,
+        options::Options,
         patch::{hooks::PatchedHooks, raw::RawPatch},
-        shadow::{
-            guest::{DefaultShadowLayout, GuestShadow},
-            Shadow,
-        },
+        report::{emit_summary, fatal, AccessType, Report},
+        shadow::{dynamic::DynamicGuestShadow, PoisonType, Shadow},
+        stats::{emit_stats, print_stats, HeapStats},
         symbols::{
             dlsym::{DlSymSymbols, LookupTypeNext},
-            Symbols,
+            AtomicGuestAddr, Function, FunctionPointer, Symbols, SymbolsLookupStr,
         },
         tracking::{guest::GuestTracking, Tracking},
         GuestAddr,
     },
-    core::ffi::{c_char, c_void, CStr},
+    core::{
+        ffi::{c_char, c_int, c_void, CStr},
+        ptr::null_mut,
+    },
     ctor::ctor,
     log::{info, trace, Level},
-    spin::{mutex::Mutex, Lazy},
+    spin::Lazy,
 };
 
 type Syms = DlSymSymbols<LookupTypeNext>;
 
 type GasanMmap = LibcMmap<Syms>;
 
+#[cfg(all(feature = "arena", feature = "libc"))]
+compile_error!("The arena and libc features are mutually exclusive");
+
+#[cfg(not(any(feature = "arena", feature = "libc")))]
+use asan::allocator::backend::{
+    dlmalloc::DlmallocBackend, mimalloc::MimallocBackend, GlobalAllocator,
+};
+
+#[cfg(feature = "arena")]
+use asan::allocator::backend::{arena::ArenaBackend, ResettableBackend};
+
+#[cfg(feature = "libc")]
+use asan::allocator::backend::libc::LibcBackend;
+
+#[cfg(not(any(feature = "arena", feature = "libc")))]
 type GasanBackend = MimallocBackend<GlobalAllocator<DlmallocBackend<GasanMmap>>>;
 
-pub type GasanFrontend =
-    DefaultFrontend<GasanBackend, GuestShadow<GasanMmap, DefaultShadowLayout>, GuestTracking>;
+/// The heap is allocated from a single arena which can be discarded in its
+/// entirety using `__asan_reset_heap`
+#[cfg(feature = "arena")]
+type GasanBackend = ArenaBackend<GasanMmap>;
+
+/// The heap is allocated by the original `libc` implementation of `malloc`
+#[cfg(feature = "libc")]
+type GasanBackend = LibcBackend<Syms>;
+
+/// The layout of the shadow is chosen at runtime to fit the address space of
+/// the process (see `Options::shadow_layout`)
+type GasanShadow = DynamicGuestShadow<GasanMmap>;
+
+/// Each thread allocates from its own cache (see `CachedFrontend`), so the
+/// frontend is shared without a lock, as is the shadow it poisons
+pub type GasanFrontend = CachedFrontend<GasanBackend, &'static GasanShadow, GuestTracking>;
 
 pub type GasanSyms = DlSymSymbols<LookupTypeNext>;
 
-const PAGE_SIZE: usize = 4096;
+type GasanEnv = LibcEnv<GasanSyms>;
+
+static OPTIONS: Lazy<Options> = Lazy::new(|| {
+    let options = Options::from_env::<GasanEnv>();
+    let level = options
+        .as_ref()
+        .ok()
+        .and_then(|o| o.log_level)
+        .unwrap_or(Level::Trace);
+    LibcLogger::initialize::<GasanSyms>(level);
+    let options = options.unwrap_or_else(|e| fatal(format_args!("options - {:?}", e)));
+    options.apply();
+    options
+});
 
-static FRONTEND: Lazy<Mutex<GasanFrontend>> = Lazy::new(|| {
-    LibcLogger::initialize::<GasanSyms>(Level::Trace);
+static PAGE_SIZE: Lazy<usize> = Lazy::new(|| {
+    GasanEnv::page_size().unwrap_or_else(|e| fatal(format_args!("page_size - {:?}", e)))
+});
+
+static SHADOW: Lazy<GasanShadow> = Lazy::new(|| {
+    GasanShadow::with_options::<LibcMapReader<GasanSyms>>(&OPTIONS)
+        .unwrap_or_else(|e| fatal(format_args!("shadow - {}", e)))
+});
+
+static FRONTEND: Lazy<GasanFrontend> = Lazy::new(|| {
+    let options = &*OPTIONS;
     info!("init");
-    let backend = GasanBackend::new(GlobalAllocator::new(DlmallocBackend::new(PAGE_SIZE)));
-    let shadow = GuestShadow::<GasanMmap, DefaultShadowLayout>::new().unwrap();
+    let backend = backend(options);
     let tracking = GuestTracking::new().unwrap();
-    let frontend = GasanFrontend::new(
-        backend,
-        shadow,
-        tracking,
-        GasanFrontend::DEFAULT_REDZONE_SIZE,
-        GasanFrontend::DEFAULT_QUARANTINE_SIZE,
-    )
-    .unwrap();
+    let frontend = DefaultFrontend::with_options(backend, &*SHADOW, tracking, options)
+        .unwrap_or_else(|e| fatal(format_args!("frontend - {:?}", e)));
+    let frontend = GasanFrontend::with_options(frontend, &*SHADOW, options)
+        .unwrap_or_else(|e| fatal(format_args!("frontend - {:?}", e)));
     PatchedHooks::init::<GasanSyms, RawPatch, LibcMapReader<GasanSyms>, GasanMmap>().unwrap();
-    Mutex::new(frontend)
+    frontend
 });
 
+#[cfg(not(any(feature = "arena", feature = "libc")))]
+fn backend(options: &Options) -> GasanBackend {
+    let backend = DlmallocBackend::with_options(*PAGE_SIZE, options)
+        .unwrap_or_else(|e| fatal(format_args!("backend - {:?}", e)));
+    GasanBackend::new(GlobalAllocator::new(backend))
+}
+
+#[cfg(feature = "libc")]
+fn backend(_options: &Options) -> GasanBackend {
+    GasanBackend::new()
+}
+
+#[cfg(feature = "arena")]
+fn backend(options: &Options) -> GasanBackend {
+    GasanBackend::with_options(*PAGE_SIZE, options)
+        .unwrap_or_else(|e| fatal(format_args!("backend - {:?}", e)))
+}
+
 #[no_mangle]
 /// # Safety
 pub unsafe extern "C" fn asan_load(addr: *const c_void, size: usize) {
     trace!("load - addr: 0x{:x}, size: {:#x}", addr as GuestAddr, size);
-    if FRONTEND
-        .lock()
-        .shadow()
-        .is_poison(addr as GuestAddr, size)
-        .unwrap()
-    {
-        panic!("Poisoned - addr: {:p}, size: 0x{:x}", addr, size);
-    }
+    check(addr as GuestAddr, size, AccessType::Read);
 }
 
 #[no_mangle]
 /// # Safety
 pub unsafe extern "C" fn asan_store(addr: *const c_void, size: usize) {
     trace!("store - addr: 0x{:x}, size: {:#x}", addr as GuestAddr, size);
-    if FRONTEND
-        .lock()
-        .shadow()
-        .is_poison(addr as GuestAddr, size)
-        .unwrap()
-    {
-        panic!("Poisoned - addr: {:p}, size: 0x{:x}", addr, size);
+    check(addr as GuestAddr, size, AccessType::Write);
+}
+
+/// Always inlined so that the first frame of the captured stack is the caller
+/// of `asan_load` or `asan_store`
+#[inline(always)]
+fn check(addr: GuestAddr, size: usize, access: AccessType) {
+    match SHADOW.is_poison(addr, size) {
+        Ok(false) => (),
+        Ok(true) => {
+            let (fault, poison) = match SHADOW.find_poison(addr, size) {
+                Ok(Some(fault)) => (fault.addr, fault.poison),
+                _ => (addr, None),
+            };
+            let allocation = FRONTEND.find_allocation(fault);
+            Report::access(addr, size, access, fault, poison, allocation)
+                .with_shadow(SHADOW.dump(fault).ok())
+                .with_global(find_global(fault))
+                .with_stack(stack(1))
+                .emit();
+        }
+        Err(_) => Report::access(addr, size, access, addr, None, None)
+            .with_stack(stack(1))
+            .emit(),
     }
 }
 
@@ -91,7 +179,7 @@ pub unsafe extern "C" fn asan_store(addr: *const c_void, size: usize) {
 /// # Safety
 pub unsafe extern "C" fn asan_alloc(len: usize, align: usize) -> *mut c_void {
     trace!("alloc - len: {:#x}, align: {:#x}", len, align);
-    let ptr = FRONTEND.lock().alloc(len, align).unwrap() as *mut c_void;
+    let ptr = alloc(len, align, AllocationType::Malloc, true);
     trace!(
         "alloc - len: {:#x}, align: {:#x}, ptr: {:p}",
         len,
@@ -101,18 +189,196 @@ pub unsafe extern "C" fn asan_alloc(len: usize, align: usize) -> *mut c_void {
     ptr
 }
 
+#[no_mangle]
+/// # Safety
+pub unsafe extern "C" fn asan_new(
+    len: usize,
+    align: usize,
+    alloc_type: AllocationType,
+    nothrow: bool,
+) -> *mut c_void {
+    trace!(
+        "new - len: {:#x}, align: {:#x}, type: {:?}, nothrow: {}",
+        len,
+        align,
+        alloc_type,
+        nothrow
+    );
+    /* Since we can't throw std::bad_alloc, only nothrow new may return null */
+    alloc(len, align, alloc_type, nothrow)
+}
+
+/// Always inlined so that the first frame of the captured stack is the caller
+/// of `asan_alloc` or `asan_new`
+#[inline(always)]
+fn alloc(
+    len: usize,
+    align: usize,
+    alloc_type: AllocationType,
+    may_return_null: bool,
+) -> *mut c_void {
+    let report = match FRONTEND.alloc_typed(len, align, alloc_type) {
+        Ok(addr) => return addr as *mut c_void,
+        Err(
+            CachedFrontendError::AllocationSizeTooBig(len, max)
+            | CachedFrontendError::FrontendError(DefaultFrontendError::AllocationSizeTooBig(
+                len,
+                max,
+            )),
+        ) => Report::allocation_size_too_big(len, max),
+        Err(CachedFrontendError::FrontendError(DefaultFrontendError::AllocatorError(_))) => {
+            Report::out_of_memory(len)
+        }
+        Err(e) => fatal(format_args!("alloc - {:?}", e)),
+    };
+    /* The option is only consulted once the allocation has failed */
+    if !may_return_null || !allocator_may_return_null() {
+        report.with_stack(stack(1)).emit();
+    }
+    null_mut()
+}
+
 #[no_mangle]
 /// # Safety
 pub unsafe extern "C" fn asan_dealloc(addr: *const c_void) {
     trace!("free - addr: {:p}", addr);
-    FRONTEND.lock().dealloc(addr as GuestAddr).unwrap();
+    dealloc(addr as GuestAddr, AllocationType::Malloc, None);
+}
+
+#[no_mangle]
+/// # Safety
+/// A `len` of zero indicates that the size of the buffer is not known
+pub unsafe extern "C" fn asan_delete(
+    addr: *const c_void,
+    dealloc_type: AllocationType,
+    len: usize,
+) {
+    trace!(
+        "delete - addr: {:p}, type: {:?}, len: {:#x}",
+        addr,
+        dealloc_type,
+        len
+    );
+    dealloc(addr as GuestAddr, dealloc_type, (len != 0).then_some(len));
+}
+
+#[inline(always)]
+fn dealloc(addr: GuestAddr, dealloc_type: AllocationType, len: Option<usize>) {
+    match FRONTEND.dealloc_typed(addr, dealloc_type, len) {
+        Ok(()) => (),
+        Err(CachedFrontendError::DoubleFree(addr) | CachedFrontendError::InvalidAddress(addr)) => {
+            let poison = SHADOW.get_poison(addr).ok();
+            match FRONTEND.find_allocation(addr) {
+                Some(allocation) => Report::free(addr, poison, Some(allocation))
+                    .with_shadow(SHADOW.dump(addr).ok())
+                    .with_stack(stack(1))
+                    .emit(),
+                None if OPTIONS.forward_foreign_free.unwrap_or(false)
+                    && poison != Some(PoisonType::AsanHeapFreed) =>
+                {
+                    forward_free(addr)
+                }
+                None => Report::free(addr, poison, None)
+                    .with_shadow(SHADOW.dump(addr).ok())
+                    .with_global(find_global(addr))
+                    .with_stack(stack(1))
+                    .emit(),
+            }
+        }
+        Err(CachedFrontendError::FrontendError(DefaultFrontendError::WriteAfterFree(
+            addr,
+            allocation,
+        ))) => {
+            Report::recycle(addr, allocation)
+                .with_shadow(SHADOW.dump(addr).ok())
+                .with_stack(stack(1))
+                .emit();
+        }
+        Err(CachedFrontendError::AllocDeallocMismatch(addr, alloc_type, dealloc_type)) => {
+            Report::mismatch(addr, alloc_type, dealloc_type, allocation(addr))
+                .with_shadow(SHADOW.dump(addr).ok())
+                .with_stack(stack(1))
+                .emit();
+        }
+        Err(CachedFrontendError::NewDeleteSizeMismatch(addr, len)) => {
+            Report::size_mismatch(addr, len, allocation(addr))
+                .with_shadow(SHADOW.dump(addr).ok())
+                .with_stack(stack(1))
+                .emit();
+        }
+        Err(e) => fatal(format_args!("dealloc - {:?}", e)),
+    }
+}
+
+/// The allocation (which must exist) containing the given address
+fn allocation(addr: GuestAddr) -> AllocationInfo {
+    FRONTEND
+        .find_allocation(addr)
+        .unwrap_or_else(|| fatal(format_args!("allocation - not found: 0x{:x}", addr)))
+}
+
+#[derive(Debug)]
+struct FunctionFree;
+
+impl Function for FunctionFree {
+    type Func = unsafe extern "C" fn(*mut c_void);
+    const NAME: &'static CStr = c"free";
+}
+
+static FREE_ADDR: AtomicGuestAddr = AtomicGuestAddr::new();
+
+/// Release a buffer which wasn't allocated by the runtime using the original
+/// libc `free`
+fn forward_free(addr: GuestAddr) {
+    trace!("forward_free - addr: 0x{:x}", addr);
+    let free_addr = FREE_ADDR
+        .try_get_or_insert_with(|| GasanSyms::lookup_str(FunctionFree::NAME))
+        .unwrap_or_else(|e| fatal(format_args!("forward_free - {:?}", e)));
+    let fn_free = FunctionFree::as_ptr(free_addr)
+        .unwrap_or_else(|e| fatal(format_args!("forward_free - {:?}", e)));
+    unsafe { fn_free(addr as *mut c_void) };
 }
 
 #[no_mangle]
 /// # Safety
 pub unsafe extern "C" fn asan_get_size(addr: *const c_void) -> usize {
     trace!("get_size - addr: {:p}", addr);
-    FRONTEND.lock().get_size(addr as GuestAddr).unwrap()
+    FRONTEND
+        .get_size(addr as GuestAddr)
+        .unwrap_or_else(|e| fatal(format_args!("get_size - {:?}", e)))
+}
+
+#[no_mangle]
+/// # Safety
+pub unsafe extern "C" fn asan_get_ownership(addr: *const c_void) -> bool {
+    trace!("get_ownership - addr: {:p}", addr);
+    FRONTEND.owns(addr as GuestAddr)
+}
+
+#[no_mangle]
+/// # Safety
+pub unsafe extern "C" fn asan_heap_stats() -> HeapStats {
+    FRONTEND.stats()
+}
+
+#[no_mangle]
+/// Discard the whole heap (e.g. between the iterations of a persistent
+/// fuzzing loop). Returns zero on success, or -1 if the runtime was not built
+/// with the `arena` feature.
+///
+/// # Safety
+/// None of the buffers previously allocated by the application may be used
+/// after the heap is reset
+pub unsafe extern "C" fn __asan_reset_heap() -> c_int {
+    #[cfg(feature = "arena")]
+    {
+        FRONTEND
+            .reset()
+            .unwrap_or_else(|e| fatal(format_args!("reset_heap - {:?}", e)));
+        0
+    }
+    #[cfg(not(feature = "arena"))]
+    -1
 }
 
 #[no_mangle]
@@ -124,18 +390,105 @@ pub unsafe extern "C" fn asan_sym(name: *const c_char) -> GuestAddr {
 #[no_mangle]
 /// # Safety
 pub unsafe extern "C" fn asan_page_size() -> usize {
-    PAGE_SIZE
+    *PAGE_SIZE
 }
 
 #[no_mangle]
 /// # Safety
 pub unsafe extern "C" fn asan_unpoison(addr: *const c_void, len: usize) {
     trace!("unpoison - addr: {:p}, len: {:#x}", addr, len);
-    FRONTEND
-        .lock()
-        .shadow_mut()
+    let mut shadow = &*SHADOW;
+    shadow
         .unpoison(addr as GuestAddr, len)
-        .unwrap();
+        .unwrap_or_else(|e| fatal(format_args!("unpoison - {:?}", e)));
+}
+
+#[no_mangle]
+/// # Safety
+pub unsafe extern "C" fn asan_poison(addr: *const c_void, len: usize, poison: PoisonType) {
+    trace!(
+        "poison - addr: {:p}, len: {:#x}, poison: {:?}",
+        addr,
+        len,
+        poison
+    );
+    let mut shadow = &*SHADOW;
+    shadow
+        .poison(addr as GuestAddr, len, poison)
+        .unwrap_or_else(|e| fatal(format_args!("poison - {:?}", e)));
+}
+
+#[no_mangle]
+/// Addresses which can't be described by the shadow map (e.g. those within
+/// the shadow map itself) are considered to be poisoned
+///
+/// # Safety
+pub unsafe extern "C" fn asan_is_poison(addr: *const c_void, len: usize) -> bool {
+    trace!("is_poison - addr: {:p}, len: {:#x}", addr, len);
+    SHADOW.is_poison(addr as GuestAddr, len).unwrap_or(true)
+}
+
+#[no_mangle]
+/// # Safety
+pub unsafe extern "C" fn asan_shadow_granularity() -> usize {
+    SHADOW.granularity()
+}
+
+#[no_mangle]
+/// Returns the address of the first poisoned byte in the given range, or null
+/// if there is none. As with `asan_is_poison`, addresses which can't be
+/// described by the shadow map are considered to be poisoned.
+///
+/// # Safety
+pub unsafe extern "C" fn asan_find_poison(addr: *const c_void, len: usize) -> *mut c_void {
+    trace!("find_poison - addr: {:p}, len: {:#x}", addr, len);
+    match SHADOW.find_poison(addr as GuestAddr, len) {
+        Ok(Some(fault)) => fault.addr as *mut c_void,
+        Ok(None) => null_mut(),
+        Err(_) => addr as *mut c_void,
+    }
+}
+
+#[no_mangle]
+/// Returns the top of the stack containing the given address (i.e. the limit
+/// of the mapping which contains it), or zero if it isn't mapped
+///
+/// # Safety
+pub unsafe extern "C" fn asan_stack_top(addr: GuestAddr) -> GuestAddr {
+    trace!("stack_top - addr: 0x{:x}", addr);
+    let reader = LibcMapReader::<GasanSyms>::new()
+        .unwrap_or_else(|e| fatal(format_args!("stack_top - {:?}", e)));
+    MapIterator::new(reader)
+        .find(|m| m.contains(addr))
+        .map_or(0, |m| m.limit())
+}
+
+#[no_mangle]
+/// # Safety
+pub unsafe extern "C" fn asan_init() {
+    trace!("init");
+    Lazy::force(&FRONTEND);
+}
+
+static FAKE_STACKS: FakeStacks<GasanMmap> = FakeStacks::new(DEFAULT_CLASS_SIZE);
+
+#[no_mangle]
+/// # Safety
+pub unsafe extern "C" fn asan_fake_stack_alloc(class: usize, real: GuestAddr) -> *mut c_void {
+    trace!("fake_stack_alloc - class: {}, real: 0x{:x}", class, real);
+    FAKE_STACKS
+        .alloc(class, real)
+        .unwrap_or_else(|e| fatal(format_args!("fake_stack_alloc - {:?}", e)))
+        .unwrap_or(0) as *mut c_void
+}
+
+#[no_mangle]
+/// # Safety
+pub unsafe extern "C" fn asan_fake_stack_dealloc(addr: *mut c_void, class: usize) {
+    trace!("fake_stack_dealloc - addr: {:p}, class: {}", addr, class);
+    FAKE_STACKS
+        .dealloc(class, addr as GuestAddr)
+        .unwrap_or_else(|e| fatal(format_args!("fake_stack_dealloc - {:?}", e)));
 }
 
 #[no_mangle]
@@ -143,10 +496,10 @@ pub unsafe extern "C" fn asan_unpoison(addr: *const c_void, len: usize) {
 pub unsafe extern "C" fn asan_track(addr: *const c_void, len: usize) {
     trace!("track - addr: {:p}, len: {:#x}", addr, len);
     FRONTEND
-        .lock()
+        .frontend()
         .tracking_mut()
         .alloc(addr as GuestAddr, len)
-        .unwrap();
+        .unwrap_or_else(|e| fatal(format_args!("track - {:?}", e)));
 }
 
 #[no_mangle]
@@ -154,10 +507,10 @@ pub unsafe extern "C" fn asan_track(addr: *const c_void, len: usize) {
 pub unsafe extern "C" fn asan_untrack(addr: *const c_void) {
     trace!("untrack - addr: {:p}", addr);
     FRONTEND
-        .lock()
+        .frontend()
         .tracking_mut()
         .dealloc(addr as GuestAddr)
-        .unwrap();
+        .unwrap_or_else(|e| fatal(format_args!("untrack - {:?}", e)));
 }
 
 #[no_mangle]
@@ -177,5 +530,35 @@ pub unsafe extern "C" fn asan_swap(_enabled: bool) {
 #[no_mangle]
 #[ctor]
 fn ctor() {
-    drop(FRONTEND.lock());
+    Lazy::force(&FRONTEND);
 }
+
+/// Check for leaks at exit
+fn check_leaks() {
+    if !detect_leaks() {
+        return;
+    }
+    #[cfg_attr(not(feature = "arena"), allow(unused_mut))]
+    let mut exclude = regions().chain(SHADOW.regions()).collect::<Vec<_>>();
+    #[cfg(feature = "arena")]
+    exclude.push(FRONTEND.frontend().backend().range());
+    let roots = leak::roots::<LibcMapReader<GasanSyms>>(&exclude)
+        .unwrap_or_else(|e| fatal(format_args!("leak roots - {:?}", e)));
+    let leaks = unsafe { FRONTEND.find_leaks(&roots) };
+    LeakReport::new(leaks).emit();
+}
+
+/// Called from `.fini_array` so that it runs after the application's own
+/// destructors
+extern "C" fn fini() {
+    emit_summary();
+    check_leaks();
+    if print_stats() {
+        let stats = FRONTEND.stats();
+        emit_stats(&stats);
+    }
+}
+
+#[used]
+#[link_section = ".fini_array"]
+static FINI: extern "C" fn() = fini;